@@ -0,0 +1,97 @@
+//! Bundles all the state relevant for debugging an emulator crash into one report, which a
+//! frontend can save to disk for bug reports.
+
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+use crate::gb_emu::GameBoyEmulator;
+use crate::hardware::cpu::registers::Registers;
+use crate::hardware::cpu::trace::TraceEntry;
+use crate::hardware::cpu::CpuFault;
+use crate::hardware::mmu::MemoryMapper;
+
+#[derive(Debug, Clone)]
+pub struct CrashReport {
+    pub fault: CpuFault,
+    pub game_title: Option<String>,
+    pub cycles_performed: u64,
+    pub registers: Registers,
+    /// The instructions leading up to the crash, oldest first.
+    pub recent_instructions: Vec<TraceEntry>,
+    /// The ROM banks mapped to `0x0000..=0x3FFF` and `0x4000..=0x7FFF` respectively.
+    pub rom_banks: Option<(usize, usize)>,
+    pub ram_bank: Option<usize>,
+}
+
+impl CrashReport {
+    /// Create a report for the current state of the `emulator`, should there have been a crash.
+    pub fn new(emulator: &GameBoyEmulator) -> Option<Self> {
+        let cpu = &emulator.cpu;
+        let cartridge = cpu.mmu.cartridge();
+
+        Some(CrashReport {
+            fault: cpu.fault?,
+            game_title: emulator.game_title().map(|title| title.trim().to_string()),
+            cycles_performed: emulator.cycles_performed(),
+            registers: cpu.registers().clone(),
+            recent_instructions: cpu.trace().to_vec(),
+            rom_banks: cartridge.map(|cart| cart.rom_banks()),
+            ram_bank: cartridge.map(|cart| cart.ram_bank()),
+        })
+    }
+}
+
+impl Display for CrashReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.fault {
+            CpuFault::IllegalOpcode { pc, opcode } => {
+                writeln!(f, "Illegal opcode {:#04X} executed at PC: {:#06X}", opcode, pc)?
+            }
+        }
+        writeln!(f, "Game: {}", self.game_title.as_deref().unwrap_or("Unknown"))?;
+        writeln!(f, "Cycles performed: {}", self.cycles_performed)?;
+        writeln!(f, "Registers: {}", self.registers)?;
+        if let Some((lower, higher)) = self.rom_banks {
+            writeln!(f, "ROM banks: {:#X} - {:#X}", lower, higher)?;
+        }
+        if let Some(ram_bank) = self.ram_bank {
+            writeln!(f, "RAM bank: {:#X}", ram_bank)?;
+        }
+        writeln!(f, "Recent instructions (oldest first):")?;
+        for entry in &self.recent_instructions {
+            writeln!(f, "    {:04X}: {:02X}", entry.pc, entry.opcode)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Error for CrashReport {}
+
+#[cfg(test)]
+mod tests {
+    use crate::gb_emu::GameBoyEmulator;
+    use crate::hardware::cpu::CpuFault;
+    use crate::EmulatorOptionsBuilder;
+
+    #[test]
+    fn test_illegal_opcode_report() {
+        let mut rom = vec![0; 0x8000];
+        // Two NOPs followed by an illegal opcode.
+        rom[0x102] = 0xD3;
+        let mut emulator = GameBoyEmulator::new(&rom, EmulatorOptionsBuilder::new().build());
+
+        assert!(emulator.step_instruction().is_ok());
+        assert!(emulator.step_instruction().is_ok());
+        let report = emulator.step_instruction().unwrap_err();
+
+        assert_eq!(report.fault, CpuFault::IllegalOpcode { pc: 0x102, opcode: 0xD3 });
+        assert_eq!(report.recent_instructions.len(), 3);
+        assert_eq!(report.recent_instructions[0].pc, 0x100);
+        assert_eq!(report.rom_banks, Some((0, 1)));
+        // The CPU should stay locked up, without panicking.
+        emulator.run_to_vblank();
+        assert_eq!(emulator.crash_report().unwrap().registers.pc, 0x103);
+    }
+}
@@ -0,0 +1,173 @@
+//! A human readable snapshot of the most relevant machine state, meant for diffing two emulator
+//! states (e.g, against a trace log of another emulator) rather than restoring them.
+//!
+//! Only available with the `debug-json` feature.
+
+use serde::Serialize;
+
+use crate::gb_emu::GameBoyEmulator;
+use crate::hardware::cartridge::mbc::MBC;
+use crate::hardware::cartridge::Cartridge;
+use crate::hardware::cpu::registers::Registers;
+use crate::hardware::mmu::*;
+use crate::hardware::ppu::memory_binds::*;
+use crate::hardware::ppu::PPU;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MachineStateDump {
+    pub model: String,
+    pub cycles_performed: u64,
+    pub cpu: CpuDump,
+    pub io: IoDump,
+    pub ppu: PpuDump,
+    pub banking: Option<BankingDump>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CpuDump {
+    pub af: u16,
+    pub bc: u16,
+    pub de: u16,
+    pub hl: u16,
+    pub sp: u16,
+    pub pc: u16,
+    pub ime: bool,
+    pub halted: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IoDump {
+    pub interrupt_enable: u8,
+    pub interrupt_flag: u8,
+    pub div: u8,
+    pub tima: u8,
+    pub tma: u8,
+    pub tac: u8,
+    pub double_speed: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PpuDump {
+    pub mode: String,
+    pub lcdc: u8,
+    pub stat: u8,
+    pub ly: u8,
+    pub lyc: u8,
+    pub scx: u8,
+    pub scy: u8,
+    pub wx: u8,
+    pub wy: u8,
+    pub window_line_counter: u8,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BankingDump {
+    pub mbc: String,
+    pub rom_bank_0000: usize,
+    pub rom_bank_4000: usize,
+    pub ram_bank: usize,
+    pub ram_enabled: bool,
+}
+
+impl MachineStateDump {
+    pub fn new(emulator: &GameBoyEmulator) -> Self {
+        let mmu = &emulator.cpu.mmu;
+
+        MachineStateDump {
+            model: format!("{:?}", mmu.emulated_model),
+            cycles_performed: emulator.cycles_performed(),
+            cpu: CpuDump::new(emulator.cpu.registers(), emulator.cpu.ime, emulator.cpu.halted),
+            io: IoDump {
+                interrupt_enable: mmu.interrupts.interrupt_enable.bits(),
+                interrupt_flag: mmu.interrupts.interrupt_flag.bits(),
+                div: mmu.timers.divider_register(&mmu.scheduler),
+                tima: mmu.timers.timer_counter,
+                tma: mmu.timers.timer_modulo,
+                tac: mmu.timers.timer_control.to_bits(),
+                double_speed: mmu.cgb_data.double_speed,
+            },
+            ppu: PpuDump::new(&mmu.ppu),
+            banking: mmu.cartridge().map(BankingDump::new),
+        }
+    }
+}
+
+impl CpuDump {
+    fn new(registers: &Registers, ime: bool, halted: bool) -> Self {
+        CpuDump {
+            af: registers.af(),
+            bc: registers.bc(),
+            de: registers.de(),
+            hl: registers.hl(),
+            sp: registers.sp,
+            pc: registers.pc,
+            ime,
+            halted,
+        }
+    }
+}
+
+impl PpuDump {
+    fn new(ppu: &PPU) -> Self {
+        PpuDump {
+            mode: format!("{:?}", ppu.get_current_mode()),
+            lcdc: ppu.read_vram(LCD_CONTROL_REGISTER),
+            stat: ppu.read_vram(LCD_STATUS_REGISTER),
+            ly: ppu.read_vram(LY_REGISTER),
+            lyc: ppu.read_vram(LYC_REGISTER),
+            scx: ppu.read_vram(SCX_REGISTER),
+            scy: ppu.read_vram(SCY_REGISTER),
+            wx: ppu.window_x(),
+            wy: ppu.window_y(),
+            window_line_counter: ppu.window_line_counter(),
+        }
+    }
+}
+
+impl BankingDump {
+    fn new(cartridge: &Cartridge) -> Self {
+        let (mbc, ram_enabled) = match cartridge.mbc() {
+            MBC::MBC0 => ("MBC0", true),
+            MBC::MBC1(state) => ("MBC1", state.ram_enabled),
+            MBC::MBC2(state) => ("MBC2", state.ram_enabled),
+            MBC::MBC3(state) => ("MBC3", state.ram_enabled),
+            MBC::MBC5(state) => ("MBC5", state.ram_enabled),
+        };
+        let (rom_bank_0000, rom_bank_4000) = cartridge.rom_banks();
+
+        BankingDump {
+            mbc: mbc.to_string(),
+            rom_bank_0000,
+            rom_bank_4000,
+            ram_bank: cartridge.ram_bank(),
+            ram_enabled,
+        }
+    }
+}
+
+impl GameBoyEmulator {
+    /// Returns a pretty printed JSON dump of the CPU registers, key I/O registers, PPU state and
+    /// MBC banking state. See [`MachineStateDump`] for the full contents.
+    ///
+    /// Note that this can *not* be used to restore the emulator state.
+    pub fn debug_dump_json(&self) -> String {
+        serde_json::to_string_pretty(&MachineStateDump::new(self)).expect("Failed to serialise machine state")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gb_emu::GameBoyEmulator;
+    use crate::EmulatorOptionsBuilder;
+
+    #[test]
+    fn test_debug_dump_json() {
+        let emulator = GameBoyEmulator::new(&vec![0; 0x8000], EmulatorOptionsBuilder::new().build());
+        let dump: serde_json::Value = serde_json::from_str(&emulator.debug_dump_json()).unwrap();
+
+        assert_eq!(dump["cpu"]["pc"], 0x100);
+        assert_eq!(dump["cpu"]["af"], 0x01B0);
+        assert_eq!(dump["banking"]["mbc"], "MBC0");
+        assert_eq!(dump["banking"]["rom_bank_4000"], 1);
+    }
+}
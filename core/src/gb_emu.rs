@@ -1,15 +1,104 @@
-use crate::hardware::cpu::CPU;
+use crate::hardware::cartridge::bank_switches::BankSwitch;
+use crate::hardware::cartridge::header::CartridgeHeader;
+use crate::hardware::cartridge::mbc::{MbcKind, ROM_BANK_SIZE};
+use crate::hardware::cpu::registers::Registers;
+use crate::hardware::cpu::{StepKind, CPU};
+use crate::hardware::mmu::cheats::CheatParseError;
 use crate::hardware::mmu::{Memory, MemoryMapper};
+use crate::hardware::ppu::debugging_features::FrameHeatmap;
 use crate::hardware::ppu::palette::{DisplayColour, RGB};
 use crate::hardware::ppu::{FRAMEBUFFER_SIZE, PPU};
+use crate::io::infrared::InfraredDevice;
 use crate::io::interrupts::InterruptFlags;
 use crate::io::joypad::*;
+use crate::io::serial::SerialLink;
+use crate::patch::{apply_patch, PatchError};
+use crate::state::{
+    decompress_state, CompactState, CompressionLevel, FullState, SaveState, StateError, StateReader, StateWriter,
+};
 use crate::EmulatorOptions;
+use std::error::Error;
+use std::fmt;
 
 /// A DMG runs at `4.194304 MHz` with a Vsync of `59.7275 Hz`, so that would be
 /// `4194304 / 59.7275 = 70224 cycles/frame`
 pub const CYCLES_PER_FRAME: u64 = 70224;
+/// The amount of t-cycles a DMG (or CGB in normal speed mode) performs per second.
 pub const DMG_CLOCK_SPEED: u64 = 4194304;
+/// The amount of t-cycles a CGB in double speed mode performs per second.
+///
+/// Note that the PPU and APU still run at [DMG_CLOCK_SPEED](constant.DMG_CLOCK_SPEED.html),
+/// so a frame will take twice as many CPU cycles in this mode.
+pub const CGB_DOUBLE_SPEED_CLOCK_SPEED: u64 = DMG_CLOCK_SPEED * 2;
+/// The refresh rate of the LCD, roughly `59.7275 Hz`.
+pub const FRAMES_PER_SECOND: f64 = DMG_CLOCK_SPEED as f64 / CYCLES_PER_FRAME as f64;
+/// The default amount of frames without external ram writes before the save callback is called.
+pub const DEFAULT_SAVE_DEBOUNCE_FRAMES: u32 = 60;
+
+/// Called with the battery ram, see [set_save_callback](struct.GameBoyEmulator.html#method.set_save_callback).
+pub type SaveCallback = Box<dyn FnMut(&[u8]) + Send>;
+/// Called with every completed frame, see [set_vblank_callback](struct.GameBoyEmulator.html#method.set_vblank_callback).
+pub type FrameCallback = Box<dyn FnMut(&[RGB]) + Send>;
+
+/// Returned by [run_to_first_input_poll](struct.GameBoyEmulator.html#method.run_to_first_input_poll)
+/// if the game didn't poll the joypad within the given amount of frames.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct InputPollTimeout {
+    pub frames: u32,
+}
+
+impl fmt::Display for InputPollTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The joypad wasn't polled within {} frames", self.frames)
+    }
+}
+
+impl Error for InputPollTimeout {}
+
+/// Returned by [try_new](struct.GameBoyEmulator.html#method.try_new) if the ROM can't be run
+/// with the provided options.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum EmulatorError {
+    /// The ROM only runs on a CGB, but `DMG` mode was requested without
+    /// [upgrade_cgb_only_roms](../struct.EmulatorOptions.html#structfield.upgrade_cgb_only_roms).
+    CgbRequired,
+    /// The [mbc_override](../struct.EmulatorOptions.html#structfield.mbc_override) can't address
+    /// the entire ROM.
+    IncompatibleMbc { mbc: MbcKind, rom_banks: usize },
+    /// The patch passed to [new_patched](struct.GameBoyEmulator.html#method.new_patched) couldn't be applied.
+    Patch(PatchError),
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmulatorError::CgbRequired => write!(f, "The ROM can only be run in CGB mode"),
+            EmulatorError::IncompatibleMbc { mbc, rom_banks } => write!(
+                f,
+                "The {:?} can address at most {} ROM banks, but the ROM has {}",
+                mbc,
+                mbc.max_rom_banks(),
+                rom_banks
+            ),
+            EmulatorError::Patch(e) => write!(f, "The patch couldn't be applied: {}", e),
+        }
+    }
+}
+
+impl Error for EmulatorError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            EmulatorError::Patch(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<PatchError> for EmulatorError {
+    fn from(e: PatchError) -> Self {
+        EmulatorError::Patch(e)
+    }
+}
 
 #[derive(Debug, Clone, Copy, PartialOrd, PartialEq)]
 pub enum GameBoyModel {
@@ -33,17 +122,203 @@ impl GameBoyModel {
     }
 }
 
+/// The specific hardware revision to emulate while in `DMG` mode.
+///
+/// These only differ in the register state their bootroms hand over to the game, which some
+/// games use to detect the hardware they're running on (e.g, `A == 0xFF` for the Pocket).
+#[derive(Debug, Clone, Copy, PartialOrd, PartialEq)]
+pub enum DmgRevision {
+    /// The very first revision of the original Game Boy.
+    DMG0,
+    DMG,
+    /// The Game Boy Pocket (and Light).
+    MGB,
+    SGB,
+    SGB2,
+}
+
+impl Default for DmgRevision {
+    fn default() -> Self {
+        DmgRevision::DMG
+    }
+}
+
+impl DmgRevision {
+    /// Returns the register state right after the bootrom of this revision finished.
+    ///
+    /// Values taken from the [pandocs](https://gbdev.io/pandocs/Power_Up_Sequence.html#cpu-registers).
+    /// Note that for the `DMG` and `MGB` the `H` and `C` flags actually depend on the header
+    /// checksum, we always assume a non-zero checksum.
+    pub fn post_boot_registers(self) -> Registers {
+        let mut registers = Registers::new();
+        let (af, bc, de, hl) = match self {
+            DmgRevision::DMG0 => (0x0100, 0xFF13, 0x00C1, 0x8403),
+            DmgRevision::DMG => (0x01B0, 0x0013, 0x00D8, 0x014D),
+            DmgRevision::MGB => (0xFFB0, 0x0013, 0x00D8, 0x014D),
+            DmgRevision::SGB => (0x0100, 0x0014, 0x0000, 0xC060),
+            DmgRevision::SGB2 => (0xFF00, 0x0014, 0x0000, 0xC060),
+        };
+
+        registers.set_af(af);
+        registers.set_bc(bc);
+        registers.set_de(de);
+        registers.set_hl(hl);
+        registers.sp = 0xFFFE;
+        registers.pc = 0x100;
+        registers
+    }
+}
+
 pub struct GameBoyEmulator {
     pub(super) cpu: CPU<Memory>,
+    save_callback: Option<SaveCallback>,
+    vblank_callback: Option<FrameCallback>,
+    /// Only present while the heatmap is enabled, see `set_frame_heatmap()`.
+    pub(super) frame_heatmap: Option<FrameHeatmap>,
+    save_debounce_frames: u32,
+    /// The amount of frames since the last external ram write, `None` if already saved.
+    frames_since_ram_write: Option<u32>,
+    /// The amount of V-blanks which have occurred since the emulator was created (or reset).
+    frames_emulated: u64,
+    /// The options used to (re)create the system on a reset or power cycle, without saved ram.
+    options: EmulatorOptions,
+    /// The most cycles a single `emulate_cycle()` call took since the last reset.
+    max_cycles_per_call: u64,
 }
 
 impl GameBoyEmulator {
-    pub fn new(cartridge: &[u8], options: EmulatorOptions) -> Self {
+    /// Create a new emulator for the provided `cartridge`.
+    ///
+    /// Unlike [try_new](#method.try_new) a CGB-only ROM will still be run in `DMG` mode
+    /// (most likely showing garbage) if so requested.
+    pub fn new(cartridge: &[u8], mut options: EmulatorOptions) -> Self {
+        if let Err(e) = check_cgb_required(cartridge, &mut options) {
+            log::warn!("{}, running it in DMG mode regardless.", e);
+        }
+        if let Err(e) = check_mbc_override(cartridge, &options) {
+            log::warn!("{}, using the MBC from the header instead.", e);
+            options.mbc_override = None;
+        }
+
+        Self::create(cartridge, options)
+    }
+
+    /// Create a new emulator like [try_new](#method.try_new), after applying the IPS or BPS `patch`
+    /// to the `cartridge`, see [apply_patch](../patch/fn.apply_patch.html).
+    pub fn new_patched(cartridge: &[u8], patch: &[u8], options: EmulatorOptions) -> Result<Self, EmulatorError> {
+        let patched = apply_patch(cartridge, patch)?;
+
+        Self::try_new(&patched, options)
+    }
+
+    /// Create a new emulator for the provided `cartridge`, refusing to run a CGB-only ROM in
+    /// `DMG` mode unless the `upgrade_cgb_only_roms` option is set, or with an `mbc_override`
+    /// which doesn't fit the ROM.
+    pub fn try_new(cartridge: &[u8], mut options: EmulatorOptions) -> Result<Self, EmulatorError> {
+        check_cgb_required(cartridge, &mut options)?;
+        check_mbc_override(cartridge, &options)?;
+
+        Ok(Self::create(cartridge, options))
+    }
+
+    fn create(cartridge: &[u8], options: EmulatorOptions) -> Self {
+        let mut stored_options = options.clone();
+        stored_options.saved_ram = None;
+        let memory = Memory::new(cartridge, options);
+
         GameBoyEmulator {
-            cpu: CPU::new(Memory::new(cartridge, options)),
+            cpu: create_cpu(memory, &stored_options),
+            save_callback: None,
+            vblank_callback: None,
+            frame_heatmap: None,
+            save_debounce_frames: DEFAULT_SAVE_DEBOUNCE_FRAMES,
+            frames_since_ram_write: None,
+            frames_emulated: 0,
+            options: stored_options,
+            max_cycles_per_call: 0,
         }
     }
 
+    /// Soft reset the system, as if the reset line was pulled.
+    ///
+    /// Re-runs the bootrom (if any) and resets the CPU, PPU, APU, timers and MBC banking, while
+    /// preserving the work ram, high ram, all external ram and the RTC.
+    pub fn reset(&mut self) {
+        self.restart(false);
+    }
+
+    /// Turn the system off and on again.
+    ///
+    /// Like a [reset](#method.reset), except that the work ram and high ram are re-initialised
+    /// and only battery backed external ram survives. The RTC is battery backed, and thus kept.
+    ///
+    /// The RTC keeps running through the restart, only its latch sequence starts over.
+    pub fn power_cycle(&mut self) {
+        self.restart(true);
+    }
+
+    fn restart(&mut self, power_cycle: bool) {
+        let memory = self.cpu.mmu.restart(self.options.clone(), power_cycle);
+
+        self.cpu = create_cpu(memory, &self.options);
+        self.frames_since_ram_write = None;
+        self.frames_emulated = 0;
+        self.max_cycles_per_call = 0;
+    }
+
+    /// Create a snapshot of the entire machine (including the external ram and RTC), which can
+    /// be restored with [load_state](#method.load_state).
+    ///
+    /// Anything configured through the `EmulatorOptions` (e.g, the display colours or audio
+    /// settings) is not part of the state, see the [state](../state/index.html) module for the format.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new(self.cpu.mmu.emulated_model, self.rom_checksum());
+
+        self.cpu.save_state(&mut state);
+        state.write_u64(self.frames_emulated);
+        state.finish()
+    }
+
+    /// Create a snapshot like [save_state](#method.save_state), compressed to a fraction of the
+    /// size at the cost of CPU time depending on the `level`.
+    pub fn save_state_compact(&self, level: CompressionLevel) -> Vec<u8> {
+        let state = FullState::from_bytes(&self.save_state()).expect("Our own state should always be valid");
+        CompactState::new(&state, level).to_bytes()
+    }
+
+    /// Restore a snapshot created by [save_state](#method.save_state) or
+    /// [save_state_compact](#method.save_state_compact).
+    ///
+    /// States created by a different version of the emulator, for a different ROM, or while
+    /// emulating a different model are rejected before any of the current state is touched.
+    /// Should a component reject its part later on (e.g, an unknown scheduler event) the emulator
+    /// is restored to how it was before the call.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let data = decompress_state(data)?;
+        let state = StateReader::new(&data, self.cpu.mmu.emulated_model, self.rom_checksum())?;
+        let snapshot = self.save_state();
+
+        if let Err(e) = self.read_state(state) {
+            let snapshot = StateReader::new(&snapshot, self.cpu.mmu.emulated_model, self.rom_checksum())?;
+            self.read_state(snapshot)
+                .expect("Our own snapshot should always be restorable");
+            return Err(e);
+        }
+        self.frames_since_ram_write = None;
+
+        Ok(())
+    }
+
+    fn read_state(&mut self, mut state: StateReader) -> Result<(), StateError> {
+        self.cpu.load_state(&mut state)?;
+        self.frames_emulated = state.read_u64()?;
+        state.finish()
+    }
+
+    fn rom_checksum(&self) -> u16 {
+        self.cpu.mmu.cartridge().map_or(0, |cartridge| cartridge.cartridge_header().global_checksum)
+    }
+
     /// Return how many cycles the CPU has performed so far.
     ///
     /// Mainly useful for timing.
@@ -51,14 +326,59 @@ impl GameBoyEmulator {
         self.cpu.cycles_performed
     }
 
-    /// Returns the current `frame buffer` from the `PPU`.
+    /// Returns the most cycles a single [emulate_cycle](#method.emulate_cycle) call took since
+    /// the emulator was created (or reset).
+    pub fn max_cycles_per_call(&self) -> u64 {
+        self.max_cycles_per_call
+    }
+
+    /// Returns the amount of frames (V-blanks) which have occurred so far.
+    pub fn frames_emulated(&self) -> u64 {
+        self.frames_emulated
+    }
+
+    /// Returns how far the emulation is into the current frame, in the range
+    /// `0..CYCLES_PER_FRAME`, e.g. for beam racing or injecting input mid-frame.
     ///
-    /// Should only be called on multiples of [CYCLES_PER_FRAME](constant.CYCLES_PER_FRAME.html)
-    /// otherwise the data will be only partially complete.
+    /// A frame starts at V-blank (LY 144), the same point at which [run_to_vblank](#method.run_to_vblank)
+    /// returns, so the cycles until the next V-blank are `CYCLES_PER_FRAME - cycles_into_frame()`.
+    /// Counted in PPU cycles, thus unaffected by CGB double speed mode. Always `0` while the
+    /// LCD is off.
+    pub fn cycles_into_frame(&self) -> u32 {
+        let mmu = &self.cpu.mmu;
+        mmu.ppu.cycles_into_frame(&mmu.scheduler, mmu.get_speed_shift()) as u32
+    }
+
+    /// Returns the amount of cycles per second the CPU is currently running at, depending on
+    /// whether CGB double speed mode is enabled.
+    ///
+    /// Can be combined with [cycles_performed](#method.cycles_performed) to calculate the
+    /// emulated speed relative to real hardware.
+    pub fn current_clock_speed(&self) -> u64 {
+        if self.cpu.mmu.cgb_data.double_speed {
+            CGB_DOUBLE_SPEED_CLOCK_SPEED
+        } else {
+            DMG_CLOCK_SPEED
+        }
+    }
+
+    /// Returns the last completed frame from the `PPU`, which is swapped in on every V-blank.
+    ///
+    /// The `PPU` renders into a separate back buffer, so this never returns a partially drawn
+    /// frame, even when called in the middle of a frame.
     pub fn frame_buffer(&self) -> &[RGB; FRAMEBUFFER_SIZE] {
         self.cpu.mmu.ppu.frame_buffer()
     }
 
+    /// **Experimental**, returns the last completed frame at twice the native resolution
+    /// (`320x288`), or `None` if [supersample_2x](../struct.EmulatorOptions.html#structfield.supersample_2x)
+    /// is disabled.
+    ///
+    /// This is a non-authentic enhancement, useful for comparing against integer scaled output.
+    pub fn frame_buffer_2x(&self) -> Option<&[RGB]> {
+        self.cpu.mmu.ppu.supersampled_frame_buffer()
+    }
+
     pub fn audio_buffer(&self) -> &[f32] {
         self.cpu.mmu.apu.get_audio_buffer()
     }
@@ -67,6 +387,73 @@ impl GameBoyEmulator {
         self.cpu.mmu.apu.clear_audio_buffer();
     }
 
+    /// Reset just the sound output, useful to recover from audio desyncs (e.g, after a long pause).
+    ///
+    /// Buffered samples and the high-pass filter state are discarded and the APU is resynchronised
+    /// to the current time. The sound registers are left as is.
+    pub fn reset_audio(&mut self) {
+        self.cpu.mmu.apu.reset_audio(&self.cpu.mmu.scheduler);
+    }
+
+    /// Returns the amount of stereo frames (left/right pairs) currently buffered, without
+    /// draining them.
+    ///
+    /// Allows a frontend to pace the emulation based on the audio, see [drain_audio](#method.drain_audio).
+    pub fn audio_samples_available(&self) -> usize {
+        self.cpu.mmu.apu.samples_available()
+    }
+
+    /// Remove and return up to `max_samples` (interleaved stereo) of the oldest audio samples,
+    /// allowing a frontend to pull exactly what its audio callback needs.
+    pub fn drain_audio(&mut self, max_samples: usize) -> Vec<f32> {
+        self.cpu.mmu.apu.drain_audio(max_samples)
+    }
+
+    /// Switch between stereo and mono audio output at runtime.
+    pub fn set_mono_audio(&mut self, mono: bool) {
+        self.options.mono_audio = mono;
+        self.cpu.mmu.apu.set_mono(mono);
+    }
+
+    /// Change the stereo separation of the audio at runtime, see
+    /// [set_stereo_width](hardware/apu/struct.APU.html#method.set_stereo_width).
+    pub fn set_stereo_width(&mut self, width: f32) {
+        self.options.stereo_width = width;
+        self.cpu.mmu.apu.set_stereo_width(width);
+    }
+
+    /// Change the gain stage of the audio at runtime, see
+    /// [set_gain](hardware/apu/struct.APU.html#method.set_gain).
+    pub fn set_audio_gain(&mut self, master_gain: f32, volume_step: f32) {
+        self.options.audio_master_gain = master_gain;
+        self.options.audio_volume_step = volume_step;
+        self.cpu.mmu.apu.set_gain(master_gain, volume_step);
+    }
+
+    /// Mute the voice `channel` (`0-3` for voice 1-4) regardless of what the ROM wrote to NR51,
+    /// see [set_channel_masked](hardware/apu/struct.APU.html#method.set_channel_masked).
+    pub fn set_audio_channel_masked(&mut self, channel: usize, masked: bool) {
+        self.cpu.mmu.apu.set_channel_masked(channel, masked);
+    }
+
+    /// Enable or disable the audio high-pass filter at runtime.
+    pub fn set_highpass_filter(&mut self, enabled: bool) {
+        self.options.highpass_filter = enabled;
+        self.cpu.mmu.apu.set_highpass_filter(enabled);
+    }
+
+    /// Enable or disable recording of every MBC bank switch at runtime, see `take_bank_switches()`.
+    pub fn set_log_bank_switches(&mut self, enabled: bool) {
+        self.options.log_bank_switches = enabled;
+        self.cpu.mmu.set_log_bank_switches(enabled);
+    }
+
+    /// Returns every write to the MBC registers which changed the mapped ROM or RAM banks since
+    /// the last call, oldest first. Only recorded while enabled, see `set_log_bank_switches()`.
+    pub fn take_bank_switches(&mut self) -> Vec<BankSwitch> {
+        self.cpu.mmu.take_bank_switches()
+    }
+
     /// Returns, if the current `ROM` has a battery, the contents of the External Ram.
     ///
     /// Should be used for saving functionality.
@@ -74,6 +461,38 @@ impl GameBoyEmulator {
         self.cpu.mmu.cartridge()?.battery_ram()
     }
 
+    /// Set a `callback` which will be called with the battery ram once the game has stopped
+    /// writing to the external ram for a while, see
+    /// [set_save_debounce_frames](#method.set_save_debounce_frames).
+    ///
+    /// For cartridges with an RTC the clock state is appended to the ram, see
+    /// [battery_save](../hardware/cartridge/struct.Cartridge.html#method.battery_save).
+    ///
+    /// This allows frontends to persist saves right after the player saved in-game.
+    /// Never called for cartridges without a battery.
+    pub fn set_save_callback(&mut self, callback: SaveCallback) {
+        self.save_callback = Some(callback);
+    }
+
+    /// Set a `callback` which will be called with the completed frame on every V-blank, for
+    /// frame-synced logic such as recording or frame hashing.
+    ///
+    /// Called for every emulated frame, so also for frames a frontend might skip while fast
+    /// forwarding. The frozen frames reported while in STOP mode are excluded.
+    pub fn set_vblank_callback(&mut self, callback: FrameCallback) {
+        self.vblank_callback = Some(callback);
+    }
+
+    pub fn clear_vblank_callback(&mut self) {
+        self.vblank_callback = None;
+    }
+
+    /// Set the amount of frames without any external ram writes which have to pass before
+    /// the save callback is called. Defaults to [DEFAULT_SAVE_DEBOUNCE_FRAMES](constant.DEFAULT_SAVE_DEBOUNCE_FRAMES.html).
+    pub fn set_save_debounce_frames(&mut self, frames: u32) {
+        self.save_debounce_frames = frames;
+    }
+
     pub fn game_title(&self) -> Option<&str> {
         Some(self.cpu.mmu.cartridge()?.cartridge_header().title.as_str())
     }
@@ -82,24 +501,109 @@ impl GameBoyEmulator {
     /// This can be changed while the emulator is running (though if done mid-frame will produce
     /// artifacts for that one frame)
     pub fn set_dmg_display_colour(&mut self, bg_palette: DisplayColour, sp0_palette: DisplayColour, sp1_palette: DisplayColour) {
+        self.options.bg_display_colour = bg_palette;
+        self.options.sp0_display_colour = sp0_palette;
+        self.options.sp1_display_colour = sp1_palette;
         self.cpu.mmu.ppu.update_display_colours(bg_palette, sp0_palette, sp1_palette);
     }
 
     /// Run the emulator until it has reached Vblank (every 70224 t-cycles)
+    ///
+    /// In STOP mode this returns right away, as nothing advances until the joypad input which
+    /// wakes the CPU up is provided.
     pub fn run_to_vblank(&mut self) {
-        while !self.emulate_cycle() {}
+        loop {
+            match self.emulate_step() {
+                (_, true, _) | (StepKind::Stopped, ..) => return,
+                _ => {}
+            }
+        }
+    }
+
+    /// Run until the game first reads the joypad register after having selected a line, which
+    /// is usually the title/menu screen. The frame in which this happens is still completed.
+    ///
+    /// Handy for getting a meaningful screenshot without relying on a fixed frame count.
+    ///
+    /// # Returns
+    ///
+    /// The amount of frames it took, or an error if no poll happened within `max_frames`.
+    pub fn run_to_first_input_poll(&mut self, max_frames: u32) -> Result<u32, InputPollTimeout> {
+        // Ignore any polls which may have happened before this call.
+        self.cpu.mmu.take_input_polled();
+
+        for frame in 1..=max_frames {
+            let mut polled = false;
+
+            loop {
+                let (kind, vblank, _) = self.emulate_step();
+                polled |= self.cpu.mmu.take_input_polled();
+
+                if vblank || kind == StepKind::Stopped {
+                    break;
+                }
+            }
+
+            if polled || self.cpu.mmu.take_input_polled() {
+                return Ok(frame);
+            }
+        }
+
+        Err(InputPollTimeout { frames: max_frames })
     }
 
     /// Emulate one CPU cycle, and any other things that need to happen.
     ///
+    /// Usually this is a single instruction (at most 24 cycles), but a few operations advance
+    /// the system a lot further in one call:
+    /// * Halt skipping, which jumps straight to the next scheduled event (e.g, a timer overflow).
+    /// * A GDMA transfer, which stalls the CPU for up to
+    ///   [max_gdma_stall_cycles](../struct.EmulatorOptions.html#structfield.max_gdma_stall_cycles).
+    /// * The CGB speed switch, which takes `131072` cycles.
+    ///
+    /// In STOP mode no cycles pass at all, see [halt_reason](#method.halt_reason).
+    /// See [max_cycles_per_call](#method.max_cycles_per_call) for the largest call so far.
+    ///
     /// # Returns
     ///
-    /// Returns whether VBlank occurred in this emulator cycle.
+    /// Whether VBlank occurred in this emulator cycle, and the amount of cycles which passed.
+    #[inline(always)]
+    pub fn emulate_cycle(&mut self) -> (bool, u64) {
+        let (_, vblank, cycles) = self.emulate_step();
+        (vblank, cycles)
+    }
+
+    /// Like [emulate_cycle](#method.emulate_cycle), but also returns what the CPU did.
+    #[inline(always)]
+    pub(crate) fn emulate_step(&mut self) -> (StepKind, bool, u64) {
+        let start_time = self.cpu.mmu.scheduler.current_time;
+        let (kind, vblank) = self.cpu.step_cycle();
+        let cycles = self.cpu.mmu.scheduler.current_time - start_time;
+        self.finish_cycle(vblank, cycles);
+
+        (kind, vblank, cycles)
+    }
+
+    /// The bookkeeping after every step of the CPU, which took `cycles`.
     #[inline(always)]
-    pub fn emulate_cycle(&mut self) -> bool {
-        self.cpu.step_cycle();
+    pub(crate) fn finish_cycle(&mut self, vblank: bool, cycles: u64) {
+        self.max_cycles_per_call = self.max_cycles_per_call.max(cycles);
+
+        if vblank {
+            self.frames_emulated += 1;
+
+            if self.save_callback.is_some() {
+                self.check_battery_save();
+            }
+
+            if let Some(callback) = self.vblank_callback.as_mut() {
+                callback(self.cpu.mmu.ppu.frame_buffer());
+            }
 
-        self.cpu.added_vblank()
+            if let Some(heatmap) = self.frame_heatmap.as_mut() {
+                heatmap.add_frame(self.cpu.mmu.ppu.frame_buffer());
+            }
+        }
     }
 
     /// Pass the provided `InputKey` to the emulator and ensure it's `pressed` state
@@ -109,10 +613,113 @@ impl GameBoyEmulator {
         self.cpu.mmu.add_new_interrupts(result);
     }
 
+    /// Connect a `device` to the serial port, replacing any previously connected device.
+    ///
+    /// To keep access to the device (e.g, a [PrinterDevice](../struct.PrinterDevice.html))
+    /// one can connect it wrapped in an `Arc<Mutex<_>>`.
+    pub fn connect_serial_link(&mut self, device: Box<dyn SerialLink + Send>) {
+        self.cpu.mmu.serial_link = Some(device);
+    }
+
+    /// Disconnect the current serial device, if any.
+    pub fn disconnect_serial_link(&mut self) {
+        self.cpu.mmu.serial_link = None;
+    }
+
+    /// Exchange a byte with the serial port as clocked by the other side of the link cable,
+    /// e.g. another emulator whose game started a transfer with its internal clock.
+    ///
+    /// The `received` byte is only shifted in (and the SERIAL interrupt requested) if the game
+    /// is awaiting a transfer with the external clock, otherwise `0xFF` is returned.
+    ///
+    /// # Returns
+    ///
+    /// The byte shifted out to the other side.
+    pub fn external_serial_transfer(&mut self, received: u8) -> u8 {
+        self.cpu.mmu.external_serial_transfer(received)
+    }
+
+    /// Apply a Game Genie `code` (e.g, `010-00A-C49`) to all subsequent ROM reads, or a
+    /// GameShark `code` (e.g, `01FF16D0`) to memory every V-blank.
+    ///
+    /// GameShark codes can only poke RAM (`0xA000-0xDFFF` and `0xFF80-0xFFFE`), in whichever
+    /// bank is mapped at the time.
+    ///
+    /// Game Genie codes for the switchable ROM bank should include the compare value (the last
+    /// group), as that's what limits them to the bank they were meant for.
+    pub fn add_cheat(&mut self, code: &str) -> Result<(), CheatParseError> {
+        self.cpu.mmu.cheats.add_code(code.parse()?);
+        Ok(())
+    }
+
+    /// Stop applying the cheat `code`, does nothing if it was never added.
+    pub fn remove_cheat(&mut self, code: &str) {
+        if let Ok(code) = code.parse() {
+            self.cpu.mmu.cheats.remove_code(code);
+        }
+    }
+
+    /// Connect a `device` to the CGB infrared port, replacing any previously connected device.
+    ///
+    /// Two emulators can be linked by connecting both ends of an [InfraredLink](../struct.InfraredLink.html).
+    pub fn connect_infrared_device(&mut self, device: Box<dyn InfraredDevice + Send>) {
+        self.cpu.mmu.infrared.device = Some(device);
+    }
+
+    /// Disconnect the current infrared device, if any.
+    pub fn disconnect_infrared_device(&mut self) {
+        self.cpu.mmu.infrared.device = None;
+    }
+
     pub fn ppu(&mut self) -> &mut PPU {
         &mut self.cpu.mmu.ppu
     }
 
+    /// Prepare the emulator for exiting, ensuring no battery ram writes are lost.
+    ///
+    /// Any pending save is immediately passed to the save callback (if set) instead of waiting
+    /// for the debounce period, and the final battery ram (including the RTC, if any) is returned
+    /// so the frontend can persist it. Buffered audio is left untouched, and can still be drained afterwards.
+    ///
+    /// Calling this multiple times is safe, the callback is only called if there are new writes.
+    pub fn shutdown(&mut self) -> Option<Vec<u8>> {
+        // Writes made since the last V-blank haven't been noticed yet.
+        if self.cpu.mmu.take_external_ram_dirty() {
+            self.frames_since_ram_write = Some(0);
+        }
+
+        if self.frames_since_ram_write.take().is_some() {
+            let battery_save = self.cpu.mmu.cartridge().and_then(|cartridge| cartridge.battery_save());
+
+            if let (Some(callback), Some(save)) = (self.save_callback.as_mut(), battery_save) {
+                callback(&save);
+            }
+        }
+
+        self.cpu.mmu.cartridge()?.battery_save()
+    }
+
+    /// Called every frame to call the save callback once the external ram has settled.
+    fn check_battery_save(&mut self) {
+        if self.cpu.mmu.take_external_ram_dirty() {
+            self.frames_since_ram_write = Some(0);
+            return;
+        }
+
+        if let Some(frames) = self.frames_since_ram_write.as_mut() {
+            *frames += 1;
+
+            if *frames >= self.save_debounce_frames {
+                self.frames_since_ram_write = None;
+                let battery_save = self.cpu.mmu.cartridge().and_then(|cartridge| cartridge.battery_save());
+
+                if let (Some(callback), Some(save)) = (self.save_callback.as_mut(), battery_save) {
+                    callback(&save);
+                }
+            }
+        }
+    }
+
     fn handle_external_input(&mut self, input: InputKey, pressed: bool) -> Option<InterruptFlags> {
         let inputs = &mut self.cpu.mmu.joypad_register;
 
@@ -125,3 +732,725 @@ impl GameBoyEmulator {
         }
     }
 }
+
+/// Upgrades the `options` to `CGB` mode for CGB-only ROMs if allowed, otherwise returns an error.
+fn check_cgb_required(cartridge: &[u8], options: &mut EmulatorOptions) -> Result<(), EmulatorError> {
+    if options.emulator_mode.is_cgb() || !CartridgeHeader::new(cartridge).cgb_only() {
+        return Ok(());
+    }
+
+    if options.upgrade_cgb_only_roms {
+        log::info!("The provided ROM only supports the CGB, switching to CGB mode instead of DMG.");
+        options.emulator_mode = GameBoyModel::CGB;
+        Ok(())
+    } else {
+        Err(EmulatorError::CgbRequired)
+    }
+}
+
+fn check_mbc_override(cartridge: &[u8], options: &EmulatorOptions) -> Result<(), EmulatorError> {
+    match options.mbc_override {
+        Some(mbc) if !mbc.supports_rom_size(cartridge.len()) => Err(EmulatorError::IncompatibleMbc {
+            mbc,
+            rom_banks: cartridge.len() / ROM_BANK_SIZE,
+        }),
+        _ => Ok(()),
+    }
+}
+
+fn create_cpu(memory: Memory, options: &EmulatorOptions) -> CPU<Memory> {
+    // The CPU already defaults to the DMG/CGB registers, so we only need to override the others.
+    let initial_registers = options.initial_registers.clone().or_else(|| {
+        let other_revision = options.emulator_mode.is_dmg() && options.dmg_revision != DmgRevision::DMG;
+        if other_revision && options.boot_rom.is_none() {
+            Some(options.dmg_revision.post_boot_registers())
+        } else {
+            None
+        }
+    });
+    let mut cpu = CPU::new(memory);
+
+    if let Some(registers) = initial_registers {
+        cpu.set_initial_registers(registers);
+    }
+
+    cpu
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::gb_emu::{
+        DmgRevision, EmulatorError, GameBoyEmulator, GameBoyModel, InputPollTimeout, CYCLES_PER_FRAME,
+    };
+    use crate::hardware::cartridge::mbc::{MbcKind, MBC, ROM_BANK_SIZE};
+    use crate::hardware::mmu::cheats::CheatParseError;
+    use crate::hardware::mmu::{
+        Memory, MemoryMapper, BOOT_ROM_REGISTER, CGB_SWITCH_MODE, CGB_WRAM_BANK, SIO_CONT, SIO_DATA,
+    };
+    use crate::hardware::ppu::memory_binds::{LCD_CONTROL_REGISTER, LCD_STATUS_REGISTER, LY_REGISTER, OB_PALETTE_0};
+    use crate::hardware::ppu::palette::{DisplayColour, DisplayColourPreset, PaletteTables, RGB};
+    use crate::hardware::ppu::FRAMEBUFFER_SIZE;
+    use crate::io::interrupts::InterruptFlags;
+    use crate::io::joypad::InputKey;
+    use crate::io::serial::SERIAL_TRANSFER_CYCLES;
+    use crate::patch::PatchError;
+    use crate::{EmulatorOptionsBuilder, PrinterDevice, SerialLink};
+    use std::sync::{Arc, Mutex};
+
+    /// Builds a 32KB ROM without an MBC for tests, along with the emulator (or just the `Memory`)
+    /// running it.
+    pub(crate) struct TestRom {
+        rom: Vec<u8>,
+        options: EmulatorOptionsBuilder,
+    }
+
+    impl TestRom {
+        pub(crate) fn new() -> Self {
+            TestRom {
+                rom: vec![0; 0x8000],
+                options: EmulatorOptionsBuilder::new(),
+            }
+        }
+
+        /// Place the `program` at the entry point (`0x100`).
+        pub(crate) fn with_program(self, program: &[u8]) -> Self {
+            self.with_bytes(0x100, program)
+        }
+
+        /// Overwrite the ROM starting at `address` with `bytes`, e.g. for header fields.
+        pub(crate) fn with_bytes(mut self, address: usize, bytes: &[u8]) -> Self {
+            self.rom[address..address + bytes.len()].copy_from_slice(bytes);
+            self
+        }
+
+        /// Replaces any options set before, including the mode.
+        pub(crate) fn with_options(mut self, options: EmulatorOptionsBuilder) -> Self {
+            self.options = options;
+            self
+        }
+
+        pub(crate) fn with_mode(mut self, mode: GameBoyModel) -> Self {
+            self.options = self.options.with_mode(mode);
+            self
+        }
+
+        pub(crate) fn emulator(self) -> GameBoyEmulator {
+            GameBoyEmulator::new(&self.rom, self.options.build())
+        }
+
+        pub(crate) fn try_emulator(self) -> Result<GameBoyEmulator, EmulatorError> {
+            GameBoyEmulator::try_new(&self.rom, self.options.build())
+        }
+
+        /// Create just the `Memory`, with the initial scheduler events already running.
+        pub(crate) fn memory(self) -> Memory {
+            let mut memory = Memory::new(&self.rom, self.options.build());
+            memory.do_m_cycle();
+            memory
+        }
+    }
+
+    #[test]
+    fn test_post_boot_registers() {
+        let dmg = DmgRevision::DMG.post_boot_registers();
+        assert_eq!(dmg.af(), 0x01B0);
+        assert_eq!(dmg.hl(), 0x014D);
+        assert_eq!(dmg.pc, 0x100);
+        assert_eq!(dmg.sp, 0xFFFE);
+
+        // Games detect the Pocket and SGB2 through register A.
+        assert_eq!(DmgRevision::MGB.post_boot_registers().a, 0xFF);
+        assert_eq!(DmgRevision::SGB2.post_boot_registers().a, 0xFF);
+
+        let dmg0 = DmgRevision::DMG0.post_boot_registers();
+        assert_eq!(dmg0.af(), 0x0100);
+        assert_eq!(dmg0.bc(), 0xFF13);
+        assert_eq!(DmgRevision::SGB.post_boot_registers().hl(), 0xC060);
+    }
+
+    #[test]
+    fn test_save_callback_debounce() {
+        let mut rom = vec![0; 0x8000];
+        // MBC1 + RAM + Battery, with 8KB of RAM.
+        rom[0x147] = 0x03;
+        rom[0x149] = 0x02;
+        let mut emulator = GameBoyEmulator::new(&rom, EmulatorOptionsBuilder::new().build());
+        let saves = Arc::new(Mutex::new(Vec::new()));
+        let saves_clone = saves.clone();
+        emulator.set_save_debounce_frames(3);
+        emulator.set_save_callback(Box::new(move |ram| saves_clone.lock().unwrap().push(ram[0])));
+
+        emulator.cpu.mmu.write_byte(0x0000, 0x0A);
+        emulator.cpu.mmu.write_byte(0xA000, 0x42);
+        emulator.run_to_vblank();
+        emulator.run_to_vblank();
+        emulator.run_to_vblank();
+        assert!(saves.lock().unwrap().is_empty());
+
+        emulator.run_to_vblank();
+        emulator.run_to_vblank();
+        // Should only be called once per batch of writes.
+        assert_eq!(*saves.lock().unwrap(), vec![0x42]);
+    }
+
+    #[test]
+    fn test_vblank_callback() {
+        let mut rom = vec![0; 0x8000];
+        // NOP; JR -3
+        rom[0x100..0x103].copy_from_slice(&[0x00, 0x18, 0xFD]);
+        let mut emulator = GameBoyEmulator::new(&rom, EmulatorOptionsBuilder::new().build());
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let frames_clone = frames.clone();
+        emulator.set_vblank_callback(Box::new(move |frame| frames_clone.lock().unwrap().push(frame.len())));
+
+        for _ in 0..3 {
+            emulator.run_to_vblank();
+        }
+        assert_eq!(*frames.lock().unwrap(), vec![FRAMEBUFFER_SIZE; 3]);
+
+        emulator.clear_vblank_callback();
+        emulator.run_to_vblank();
+        assert_eq!(frames.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_shutdown_flushes_save() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x147] = 0x03;
+        rom[0x149] = 0x02;
+        let mut emulator = GameBoyEmulator::new(&rom, EmulatorOptionsBuilder::new().build());
+        let saves = Arc::new(Mutex::new(Vec::new()));
+        let saves_clone = saves.clone();
+        emulator.set_save_callback(Box::new(move |ram| saves_clone.lock().unwrap().push(ram[0])));
+
+        emulator.cpu.mmu.write_byte(0x0000, 0x0A);
+        emulator.cpu.mmu.write_byte(0xA000, 0x42);
+
+        assert_eq!(emulator.shutdown().map(|ram| ram[0]), Some(0x42));
+        assert_eq!(*saves.lock().unwrap(), vec![0x42]);
+        // Nothing new to save, so the callback shouldn't be called again.
+        assert_eq!(emulator.shutdown().map(|ram| ram[0]), Some(0x42));
+        assert_eq!(*saves.lock().unwrap(), vec![0x42]);
+    }
+
+    #[test]
+    fn test_serial_transfer() {
+        let mut emulator = GameBoyEmulator::new(&vec![0; 0x8000], EmulatorOptionsBuilder::new().build());
+        let printer = Arc::new(Mutex::new(PrinterDevice::new()));
+        emulator.connect_serial_link(Box::new(printer.clone()));
+
+        // Walk the printer up to the alive byte of an init packet.
+        for &byte in &[0x88, 0x33, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00] {
+            printer.lock().unwrap().transfer(byte);
+        }
+        emulator.cpu.mmu.write_byte(SIO_DATA, 0x00);
+        emulator.cpu.mmu.write_byte(SIO_CONT, 0x81);
+        assert_eq!(emulator.cpu.mmu.read_byte(SIO_CONT), 0x81);
+
+        emulator.run_to_vblank();
+
+        assert_eq!(emulator.cpu.mmu.read_byte(SIO_DATA), 0x81);
+        assert_eq!(emulator.cpu.mmu.read_byte(SIO_CONT), 0x01);
+        assert!(emulator.cpu.mmu.interrupts().interrupt_flag.contains(InterruptFlags::SERIAL));
+    }
+
+    #[test]
+    fn test_external_serial_transfer() {
+        let mut emulator = GameBoyEmulator::new(&vec![0; 0x8000], EmulatorOptionsBuilder::new().build());
+        emulator.cpu.mmu.write_byte(SIO_DATA, 0x42);
+
+        // Without a pending transfer nothing should be shifted.
+        assert_eq!(emulator.external_serial_transfer(0x13), 0xFF);
+        assert_eq!(emulator.cpu.mmu.read_byte(SIO_DATA), 0x42);
+
+        emulator.cpu.mmu.write_byte(SIO_CONT, 0x80);
+        assert_eq!(emulator.external_serial_transfer(0x13), 0x42);
+        assert_eq!(emulator.cpu.mmu.read_byte(SIO_DATA), 0x13);
+        assert_eq!(emulator.cpu.mmu.read_byte(SIO_CONT), 0x00);
+        assert!(emulator.cpu.mmu.interrupts().interrupt_flag.contains(InterruptFlags::SERIAL));
+    }
+
+    /// The other side of the link cable, which only ever uses the external clock.
+    struct LinkedMemory(Arc<Mutex<Memory>>);
+
+    impl SerialLink for LinkedMemory {
+        fn transfer(&mut self, out: u8) -> u8 {
+            self.0.lock().unwrap().external_serial_transfer(out)
+        }
+    }
+
+    #[test]
+    fn test_linked_emulators() {
+        // Send 0x42 with the internal clock.
+        let program = [0x3E, 0x42, 0xE0, 0x01, 0x3E, 0x81, 0xE0, 0x02, 0x18, 0xFE];
+        let link = Arc::new(Mutex::new(TestRom::new().memory()));
+        {
+            let mut other = link.lock().unwrap();
+            other.write_byte(SIO_DATA, 0x24);
+            other.write_byte(SIO_CONT, 0x80);
+        }
+
+        // Once the other side stops waiting for a transfer nothing is shifted anymore.
+        for &(expected, other_interrupt) in [(0x24, true), (0xFF, false)].iter() {
+            let mut emulator = TestRom::new().with_program(&program).emulator();
+            emulator.connect_serial_link(Box::new(LinkedMemory(link.clone())));
+            emulator.run_to_vblank();
+
+            let mut other = link.lock().unwrap();
+            assert_eq!(emulator.cpu.mmu.read_byte(SIO_DATA), expected);
+            assert_eq!(emulator.cpu.mmu.read_byte(SIO_CONT) & 0x80, 0);
+            assert_eq!(other.read_byte(SIO_DATA), 0x42);
+            assert_eq!(other.read_byte(SIO_CONT) & 0x80, 0);
+            let serial_requested = other.interrupts().interrupt_flag.contains(InterruptFlags::SERIAL);
+            assert_eq!(serial_requested, other_interrupt);
+            other.interrupts_mut().overwrite_if(0);
+        }
+    }
+
+    #[test]
+    fn test_serial_interrupt_timing() {
+        let mut emulator = GameBoyEmulator::new(&vec![0; 0x8000], EmulatorOptionsBuilder::new().build());
+        let cpu = &mut emulator.cpu;
+        let serial_requested = |mmu: &Memory| mmu.interrupts().interrupt_flag.contains(InterruptFlags::SERIAL);
+        cpu.add_cycles();
+
+        // With the external clock the transfer should never complete.
+        cpu.mmu.write_byte(SIO_CONT, 0x80);
+        for _ in 0..(SERIAL_TRANSFER_CYCLES / 2) {
+            cpu.add_cycles();
+        }
+        assert!(!serial_requested(&cpu.mmu));
+
+        cpu.mmu.write_byte(SIO_DATA, 0x42);
+        cpu.mmu.write_byte(SIO_CONT, 0x81);
+        let start_cycles = cpu.cycles_performed;
+        while cpu.cycles_performed - start_cycles < SERIAL_TRANSFER_CYCLES - 4 {
+            cpu.add_cycles();
+            assert!(!serial_requested(&cpu.mmu));
+        }
+
+        cpu.add_cycles();
+        assert_eq!(cpu.cycles_performed - start_cycles, SERIAL_TRANSFER_CYCLES);
+        assert!(serial_requested(&cpu.mmu));
+        // Nothing is connected, so we should've received 0xFF.
+        assert_eq!(cpu.mmu.read_byte(SIO_DATA), 0xFF);
+        assert_eq!(cpu.mmu.read_byte(SIO_CONT), 0x01);
+    }
+
+    #[test]
+    fn test_cycles_into_frame() {
+        let rom = vec![0; 0x8000];
+        let mut emulator = GameBoyEmulator::new(&rom, EmulatorOptionsBuilder::new().build());
+        emulator.run_to_vblank();
+        assert!(emulator.cycles_into_frame() < 8);
+
+        let mut previous = 0;
+        while !emulator.emulate_cycle().0 {
+            let cycles = emulator.cycles_into_frame();
+            let line = (cycles as u64 / 456 + 144) % 154;
+            assert!(cycles > previous && (cycles as u64) < CYCLES_PER_FRAME);
+            // LY reads 0 for most of line 153.
+            if line != 153 {
+                assert_eq!(emulator.cpu.mmu.ppu.current_y as u64, line);
+            }
+            previous = cycles;
+        }
+
+        assert!(emulator.cycles_into_frame() < 8);
+    }
+
+    #[test]
+    fn test_run_to_first_input_poll() {
+        let mut rom = vec![0; 0x8000];
+        // Spend ~6.5 frames (0x4013 iterations of 28 cycles) before selecting the buttons and
+        // reading the joypad register.
+        // LD B, 0x40; DEC BC; LD A, B; OR C; JR NZ, -5
+        rom[0x100..0x109].copy_from_slice(&[0x06, 0x40, 0x0B, 0x78, 0xB1, 0x20, 0xFB, 0x00, 0x00]);
+        // LD A, 0x10; LDH (0x00), A; LDH A, (0x00); JR -2
+        rom[0x109..0x111].copy_from_slice(&[0x3E, 0x10, 0xE0, 0x00, 0xF0, 0x00, 0x18, 0xFE]);
+        let mut emulator = GameBoyEmulator::new(&rom, EmulatorOptionsBuilder::new().build());
+
+        assert_eq!(emulator.run_to_first_input_poll(10), Ok(7));
+
+        // Reading the joypad without ever selecting a line shouldn't count.
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x104].copy_from_slice(&[0xF0, 0x00, 0x18, 0xFC]);
+        let mut emulator = GameBoyEmulator::new(&rom, EmulatorOptionsBuilder::new().build());
+
+        assert_eq!(emulator.run_to_first_input_poll(3), Err(InputPollTimeout { frames: 3 }));
+    }
+
+    #[test]
+    fn test_stop_freezes_ppu() {
+        let mut rom = vec![0; 0x8000];
+        // STOP; INC A; JR -3
+        rom[0x100..0x105].copy_from_slice(&[0x10, 0x00, 0x3C, 0x18, 0xFD]);
+        let mut emulator = GameBoyEmulator::new(&rom, EmulatorOptionsBuilder::new().build());
+        emulator.run_to_vblank();
+        assert!(emulator.cpu.stopped);
+
+        let time = emulator.cpu.mmu.scheduler.current_time;
+        let ly = emulator.cpu.mmu.ppu.current_y;
+        let frame = emulator.frame_buffer().to_vec();
+        let frames = emulator.frames_emulated;
+        for _ in 0..5 {
+            emulator.run_to_vblank();
+        }
+        // No fake V-blanks are reported while stopped.
+        assert_eq!(emulator.emulate_cycle(), (false, 0));
+        assert_eq!(emulator.frames_emulated, frames);
+        assert_eq!(emulator.cpu.mmu.scheduler.current_time, time);
+        assert_eq!(emulator.cpu.mmu.ppu.current_y, ly);
+        assert_eq!(emulator.frame_buffer(), &frame[..]);
+
+        emulator.handle_input(InputKey::A, true);
+        emulator.run_to_vblank();
+        assert!(!emulator.cpu.stopped);
+        assert!(emulator.cpu.registers().pc > 0x101);
+        assert_ne!(emulator.cpu.registers().a, 0x01);
+    }
+
+    #[test]
+    fn test_lcd_on_first_line_timing() {
+        let mut memory = TestRom::new().memory();
+        memory.write_byte(LCD_CONTROL_REGISTER, 0x11);
+        // Only the mode 2 STAT interrupt.
+        memory.write_byte(LCD_STATUS_REGISTER, 0x20);
+        memory.interrupts_mut().overwrite_if(0);
+
+        memory.write_byte(LCD_CONTROL_REGISTER, 0x91);
+        let start = memory.scheduler.current_time;
+        let mut modes = Vec::new();
+
+        while !memory.interrupts().interrupt_flag.contains(InterruptFlags::LCD) {
+            memory.do_m_cycle();
+            let mode = memory.read_byte(LCD_STATUS_REGISTER) & 0x3;
+            modes.push((memory.scheduler.current_time - start, mode));
+        }
+
+        // The first line has no OAM search, and is 4 cycles shorter than a normal line.
+        assert_eq!(modes.last(), Some(&(452, 2)));
+        assert_eq!(memory.read_byte(LY_REGISTER), 1);
+        assert!(modes.contains(&(76, 3)));
+        for &(cycle, mode) in &modes[..modes.len() - 1] {
+            assert_ne!(mode, 2);
+            assert!(cycle >= 76 || mode == 0);
+        }
+    }
+
+    #[test]
+    fn test_max_cycles_per_call() {
+        // NOP; JR -3
+        let mut emulator = TestRom::new().with_program(&[0x00, 0x18, 0xFD]).emulator();
+        emulator.run_to_vblank();
+        assert_eq!(emulator.max_cycles_per_call(), 12);
+
+        // HALT, which we never leave as no interrupts are enabled, so every call skips to the next event.
+        let mut emulator = TestRom::new().with_program(&[0x76]).emulator();
+        emulator.run_to_vblank();
+        assert!(emulator.max_cycles_per_call() > 24);
+
+        emulator.reset();
+        assert_eq!(emulator.max_cycles_per_call(), 0);
+    }
+
+    #[test]
+    fn test_disable_shortcuts() {
+        let options = EmulatorOptionsBuilder::new().with_disable_shortcuts(true);
+        let mut emulator = TestRom::new().with_program(&[0x76]).with_options(options).emulator();
+        emulator.run_to_vblank();
+
+        // Without halt skipping every call should be exactly one M-cycle, except for the HALT itself.
+        assert_eq!(emulator.max_cycles_per_call(), 8);
+        assert_eq!(emulator.emulate_cycle(), (false, 4));
+    }
+
+    #[test]
+    fn test_reset_and_power_cycle() {
+        let mut rom = vec![0; 0x8000];
+        // MBC1 + RAM (no battery), with 8KB of RAM.
+        rom[0x147] = 0x02;
+        rom[0x149] = 0x02;
+        let mut emulator = GameBoyEmulator::new(&rom, EmulatorOptionsBuilder::new().build());
+        emulator.cpu.mmu.write_byte(0xC000, 0x42);
+        emulator.cpu.mmu.write_byte(0xFF80, 0x43);
+        emulator.cpu.mmu.write_byte(0x0000, 0x0A);
+        emulator.cpu.mmu.write_byte(0xA000, 0x44);
+        emulator.run_to_vblank();
+
+        emulator.reset();
+        assert_eq!(emulator.cpu.registers().pc, 0x100);
+        assert_eq!(emulator.cpu.mmu.read_byte(0xC000), 0x42);
+        assert_eq!(emulator.cpu.mmu.read_byte(0xFF80), 0x43);
+        // The MBC should be reset, so the RAM is disabled again.
+        assert_eq!(emulator.cpu.mmu.read_byte(0xA000), 0xFF);
+        emulator.cpu.mmu.write_byte(0x0000, 0x0A);
+        assert_eq!(emulator.cpu.mmu.read_byte(0xA000), 0x44);
+
+        emulator.power_cycle();
+        emulator.cpu.mmu.write_byte(0x0000, 0x0A);
+        assert_eq!(emulator.cpu.mmu.read_byte(0xC000), 0xFF);
+        assert_eq!(emulator.cpu.mmu.read_byte(0xFF80), 0xFF);
+        assert_eq!(emulator.cpu.mmu.read_byte(0xA000), 0xFF);
+
+        // Battery backed ram should survive a power cycle.
+        rom[0x147] = 0x03;
+        let mut emulator = GameBoyEmulator::new(&rom, EmulatorOptionsBuilder::new().build());
+        emulator.cpu.mmu.write_byte(0x0000, 0x0A);
+        emulator.cpu.mmu.write_byte(0xA000, 0x44);
+        emulator.power_cycle();
+        emulator.cpu.mmu.write_byte(0x0000, 0x0A);
+        assert_eq!(emulator.cpu.mmu.read_byte(0xA000), 0x44);
+
+        // As should the RTC, MBC3 + Timer + RAM + Battery.
+        rom[0x147] = 0x10;
+        let mut emulator = GameBoyEmulator::new(&rom, EmulatorOptionsBuilder::new().build());
+        emulator.cpu.mmu.write_byte(0x0000, 0x0A);
+        emulator.cpu.mmu.write_byte(0x4000, 0x09);
+        emulator.cpu.mmu.write_byte(0xA000, 42);
+        emulator.power_cycle();
+        let rtc = emulator.cpu.mmu.cartridge().and_then(|cartridge| cartridge.rtc_live());
+        assert_eq!(rtc.map(|rtc| rtc.minutes), Some(42));
+    }
+
+    #[test]
+    fn test_echo_ram_banking() {
+        let mut emulator = TestRom::new().with_mode(GameBoyModel::CGB).emulator();
+        let mmu = &mut emulator.cpu.mmu;
+        mmu.write_byte(CGB_WRAM_BANK, 3);
+        mmu.write_byte(0xDDFF, 0x33);
+        mmu.write_byte(CGB_WRAM_BANK, 4);
+        mmu.write_byte(0xDDFF, 0x44);
+        assert_eq!(mmu.read_byte(0xFDFF), 0x44);
+        mmu.write_byte(CGB_WRAM_BANK, 3);
+        assert_eq!(mmu.read_byte(0xFDFF), 0x33);
+        // Echo RAM ends right before OAM, which is only accessible with the LCD off.
+        mmu.write_byte(LCD_CONTROL_REGISTER, 0x00);
+        mmu.write_byte(0xFE00, 0xAB);
+        assert_eq!(mmu.read_byte(0xFE00), 0xAB);
+        assert_eq!(mmu.read_byte(0xFDFF), 0x33);
+
+        // The DMG has no WRAM banking, so both writes go to the same bank.
+        let mut emulator = TestRom::new().emulator();
+        let mmu = &mut emulator.cpu.mmu;
+        mmu.write_byte(CGB_WRAM_BANK, 3);
+        mmu.write_byte(0xDDFF, 0x33);
+        mmu.write_byte(CGB_WRAM_BANK, 4);
+        mmu.write_byte(0xDDFF, 0x44);
+        mmu.write_byte(CGB_WRAM_BANK, 3);
+        assert_eq!(mmu.read_byte(0xFDFF), 0x44);
+    }
+
+    #[test]
+    fn test_cgb_only_rom_in_dmg_mode() {
+        // The CGB flag in the header marks the ROM as CGB-only.
+        let cgb_only_rom = || TestRom::new().with_bytes(0x143, &[0xC0]);
+
+        let mode = cgb_only_rom().try_emulator().map(|emulator| emulator.emulator_mode());
+        assert_eq!(mode, Err(EmulatorError::CgbRequired));
+
+        let options = EmulatorOptionsBuilder::new().with_upgrade_cgb_only_roms(true);
+        let mode = cgb_only_rom().with_options(options).try_emulator().map(|emulator| emulator.emulator_mode());
+        assert_eq!(mode, Ok(GameBoyModel::CGB));
+
+        // Without the check a DMG emulator should still be created.
+        assert_eq!(cgb_only_rom().emulator().emulator_mode(), GameBoyModel::DMG);
+    }
+
+    #[test]
+    fn test_new_patched() {
+        let rom = vec![0; 0x8000];
+        // An IPS patch marking the ROM as CGB-only.
+        let patch = [&b"PATCH"[..], &[0x00, 0x01, 0x43, 0x00, 0x01, 0xC0], &b"EOF"[..]].concat();
+        let mode = |patch: &[u8], options: EmulatorOptionsBuilder| {
+            GameBoyEmulator::new_patched(&rom, patch, options.build()).map(|emulator| emulator.emulator_mode())
+        };
+
+        assert_eq!(mode(&patch, EmulatorOptionsBuilder::new()), Err(EmulatorError::CgbRequired));
+        let options = EmulatorOptionsBuilder::new().with_upgrade_cgb_only_roms(true);
+        assert_eq!(mode(&patch, options), Ok(GameBoyModel::CGB));
+        assert_eq!(
+            mode(b"NOT A PATCH", EmulatorOptionsBuilder::new()),
+            Err(EmulatorError::Patch(PatchError::UnknownFormat))
+        );
+    }
+
+    #[test]
+    fn test_mbc_override() {
+        let mut rom = vec![0; 4 * ROM_BANK_SIZE];
+        // MMM01, which isn't supported.
+        rom[0x147] = 0x0B;
+        let options = EmulatorOptionsBuilder::new().with_mbc_override(Some(MbcKind::MBC1)).build();
+        let emulator = GameBoyEmulator::try_new(&rom, options).unwrap();
+        assert!(matches!(emulator.cpu.mmu.cartridge().unwrap().mbc(), MBC::MBC1(_)));
+
+        let options = EmulatorOptionsBuilder::new().with_mbc_override(Some(MbcKind::MBC0)).build();
+        let expected = EmulatorError::IncompatibleMbc {
+            mbc: MbcKind::MBC0,
+            rom_banks: 4,
+        };
+        assert_eq!(GameBoyEmulator::try_new(&rom, options.clone()).err(), Some(expected));
+
+        // Without the check the header should be used instead.
+        rom[0x147] = 0x19;
+        let emulator = GameBoyEmulator::new(&rom, options);
+        assert!(matches!(emulator.cpu.mmu.cartridge().unwrap().mbc(), MBC::MBC5(_)));
+    }
+
+    #[test]
+    fn test_cheats() {
+        let mut rom = vec![0; 4 * ROM_BANK_SIZE];
+        rom[0x147] = 0x19;
+        rom[0x148] = 0x01;
+        rom[ROM_BANK_SIZE + 0x1000] = 0x33;
+        rom[2 * ROM_BANK_SIZE + 0x1000] = 0xC8;
+        let mut emulator = GameBoyEmulator::new(&rom, EmulatorOptionsBuilder::new().build());
+
+        assert_eq!(emulator.add_cheat("010-00A-C4"), Err(CheatParseError::InvalidLength));
+        emulator.add_cheat("010-00A-C49").unwrap();
+        // The code was meant for bank 2, so it shouldn't fire in bank 1.
+        assert_eq!(emulator.cpu.mmu.read_byte(0x5000), 0x33);
+        emulator.cpu.mmu.write_byte(0x2000, 0x02);
+        assert_eq!(emulator.cpu.mmu.read_byte(0x5000), 0x01);
+
+        emulator.remove_cheat("010-00A-C49");
+        assert_eq!(emulator.cpu.mmu.read_byte(0x5000), 0xC8);
+    }
+
+    #[test]
+    fn test_gameshark_cheats() {
+        let mut emulator = GameBoyEmulator::new(&vec![0; 0x8000], EmulatorOptionsBuilder::new().build());
+        emulator.add_cheat("01FF16D0").unwrap();
+        emulator.run_to_vblank();
+        assert_eq!(emulator.cpu.mmu.read_byte(0xD016), 0xFF);
+
+        // The poke keeps being reasserted, even after the game overwrites it.
+        emulator.cpu.mmu.write_byte(0xD016, 0x03);
+        emulator.run_to_vblank();
+        assert_eq!(emulator.cpu.mmu.read_byte(0xD016), 0xFF);
+
+        emulator.remove_cheat("01FF16D0");
+        emulator.cpu.mmu.write_byte(0xD016, 0x03);
+        emulator.run_to_vblank();
+        assert_eq!(emulator.cpu.mmu.read_byte(0xD016), 0x03);
+    }
+
+    #[test]
+    fn test_cgb_switch_mode() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x143] = 0x80;
+        let options = EmulatorOptionsBuilder::new()
+            .with_mode(GameBoyModel::CGB)
+            .with_boot_rom(Some(vec![0; 0x900]))
+            .build();
+
+        let mut memory = Memory::new(&rom, options.clone());
+        assert_eq!(memory.read_byte(CGB_SWITCH_MODE), 0x80);
+        // The boot rom selects DMG compatibility mode, even though this is a CGB cartridge.
+        memory.write_byte(CGB_SWITCH_MODE, 0x04);
+        assert_eq!(memory.read_byte(CGB_SWITCH_MODE), 0x04);
+        assert!(memory.ppu.cgb_rendering());
+        memory.write_byte(BOOT_ROM_REGISTER, 0x11);
+        assert!(!memory.ppu.cgb_rendering());
+
+        // After the boot rom the register is locked.
+        memory.write_byte(CGB_SWITCH_MODE, 0x80);
+        assert_eq!(memory.read_byte(CGB_SWITCH_MODE), 0xFF);
+        assert!(!memory.ppu.cgb_rendering());
+
+        // Without the write the mode is taken from the cartridge header.
+        let mut memory = Memory::new(&rom, options);
+        memory.write_byte(BOOT_ROM_REGISTER, 0x11);
+        assert!(memory.ppu.cgb_rendering());
+    }
+
+    #[test]
+    fn test_blank_initial_frame() {
+        let rom = vec![0; 0x8000];
+        let options = EmulatorOptionsBuilder::new().build();
+        let white = options.bg_display_colour.white;
+        let emulator = GameBoyEmulator::new(&rom, options);
+        assert!(emulator.frame_buffer().iter().all(|&colour| colour == white));
+
+        let options = EmulatorOptionsBuilder::new().with_blank_initial_frame(false).build();
+        let emulator = GameBoyEmulator::new(&rom, options);
+        assert!(emulator.frame_buffer().iter().all(|&colour| colour == RGB::default()));
+    }
+
+    #[test]
+    fn test_shared_palette_tables() {
+        let rom = vec![0; 0x8000];
+        let grayscale = DisplayColour::from_preset(DisplayColourPreset::Grayscale);
+        let tritanopia = DisplayColour::from_preset(DisplayColourPreset::Tritanopia);
+        let tables = Arc::new(PaletteTables::new(grayscale, grayscale, grayscale));
+        assert!(tables.is_created_from(grayscale, grayscale, grayscale));
+        assert!(!tables.is_created_from(grayscale, tritanopia, grayscale));
+
+        let options = EmulatorOptionsBuilder::new()
+            .with_display_colour(grayscale)
+            .with_palette_tables(tables.clone())
+            .build();
+        let emulator = GameBoyEmulator::new(&rom, options);
+        assert_eq!(emulator.frame_buffer()[0], grayscale.white);
+
+        // Tables created for other display colours shouldn't override the chosen colours.
+        let options = EmulatorOptionsBuilder::new()
+            .with_display_colour(tritanopia)
+            .with_palette_tables(tables)
+            .build();
+        let mut emulator = GameBoyEmulator::new(&rom, options);
+        assert_eq!(emulator.frame_buffer()[0], tritanopia.white);
+        emulator.run_to_vblank();
+        assert!(emulator.frame_buffer().iter().all(|&colour| colour == tritanopia.white));
+    }
+
+    #[test]
+    fn test_frame_buffer_2x() {
+        let rom = TestRom::new().with_program(&[0x18, 0xFE]);
+        assert!(rom.emulator().frame_buffer_2x().is_none());
+
+        let rom = TestRom::new().with_program(&[0x18, 0xFE]);
+        let options = EmulatorOptionsBuilder::new().with_supersample_2x(true);
+        let mut emulator = rom.with_options(options).emulator();
+        let DisplayColour {
+            white, light_grey, black, ..
+        } = emulator.options.bg_display_colour;
+        emulator.run_to_vblank();
+        let mmu = &mut emulator.cpu.mmu;
+        // Tile 0 (the entire background) as alternating black and white columns, tile 1 as a light grey sprite.
+        for row in 0..8 {
+            mmu.write_byte(0x8000 + row * 2, 0xAA);
+            mmu.write_byte(0x8001 + row * 2, 0xAA);
+            mmu.write_byte(0x8010 + row * 2, 0xFF);
+            mmu.write_byte(0x8011 + row * 2, 0xFF);
+        }
+        // A sprite at (16, 16) on the screen.
+        for (i, &value) in [32, 24, 1, 0].iter().enumerate() {
+            mmu.write_byte(0xFE00 + i as u16, value);
+        }
+        mmu.write_byte(OB_PALETTE_0, 0x40);
+        mmu.write_byte(LCD_CONTROL_REGISTER, 0x93);
+        emulator.run_to_vblank();
+        emulator.run_to_vblank();
+
+        let frame = emulator.frame_buffer_2x().unwrap();
+        let pixel = |x: usize, y: usize| frame[y * 320 + x];
+        let grey = RGB(
+            ((white.0 as u16 + black.0 as u16) / 2) as u8,
+            ((white.1 as u16 + black.1 as u16) / 2) as u8,
+            ((white.2 as u16 + black.2 as u16) / 2) as u8,
+        );
+        assert_eq!(frame.len(), FRAMEBUFFER_SIZE * 4);
+        // The background is interpolated at the half-pixel offsets.
+        assert_eq!(&frame[..4], &[black, grey, white, grey]);
+        assert_eq!(pixel(2, 1), white);
+        // While the sprite keeps its hard edges.
+        assert_eq!(pixel(31, 34), white);
+        assert_eq!(pixel(32, 34), light_grey);
+        assert_eq!(pixel(47, 34), light_grey);
+        assert_eq!(pixel(34, 31), white);
+        assert_eq!(pixel(34, 47), light_grey);
+        assert_eq!(pixel(48, 34), black);
+        assert_eq!(pixel(49, 34), grey);
+    }
+}
@@ -1,24 +1,515 @@
+use crate::crash_report::CrashReport;
 use crate::gb_emu::{GameBoyEmulator, GameBoyModel};
-use crate::hardware::ppu::debugging_features::PaletteDebugInfo;
+use crate::hardware::cartridge::mbc::RtcView;
+use crate::hardware::cartridge::BankInfo;
+use crate::hardware::cpu::overrides::OpcodeHandler;
+use crate::hardware::cpu::power_events::PowerEvent;
+use crate::hardware::cpu::registers::Registers;
+use crate::hardware::cpu::{HaltReason, StepKind};
+use crate::hardware::mmu::breakpoints::{BreakpointHit, Breakpoints};
+use crate::hardware::mmu::{Memory, MemoryMapper};
+use crate::hardware::ppu::cgb_vram::CgbTileAttribute;
+use crate::hardware::ppu::debugging_features::{
+    BackgroundSnapshot, FrameHeatmap, PaletteDebugInfo, PpuRegisters, TileMapSelect, WindowDebugInfo,
+};
+use crate::hardware::ppu::memory_binds::LY_REGISTER;
 use crate::hardware::ppu::palette::RGB;
 use crate::hardware::ppu::tiledata::SpriteAttribute;
+use crate::hardware::ppu::FRAMEBUFFER_SIZE;
+use crate::io::timer::TimerState;
+use crate::scheduler::Event;
+
+/// A request for debug information, see [debug_query](gb_emu/struct.GameBoyEmulator.html#method.debug_query).
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DebugRequest {
+    Palette,
+    Oam,
+    Tiles,
+    Registers,
+    Scheduler,
+}
+
+/// A single step of the emulator, see [step_instruction](gb_emu/struct.GameBoyEmulator.html#method.step_instruction).
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct StepResult {
+    pub kind: StepKind,
+    /// The `PC` before the step, so the address of the executed instruction.
+    pub pc: u16,
+    /// The amount of t-cycles the step took.
+    pub cycles: u64,
+    /// Whether V-blank occurred during the step.
+    pub vblank: bool,
+}
+
+impl StepResult {
+    /// The opcode of the executed instruction, if any.
+    pub fn opcode(&self) -> Option<u8> {
+        match self.kind {
+            StepKind::Instruction(opcode) => Some(opcode),
+            _ => None,
+        }
+    }
+}
+
+/// The answer to a [DebugRequest], with the variant matching the request.
+#[derive(Debug, Clone)]
+pub enum DebugResponse {
+    Palette(PaletteDebugInfo),
+    Oam(Vec<SpriteAttribute>),
+    /// All 768 tiles (both VRAM banks) as 8x8 RGB pixels, see `vram_tiles()`.
+    Tiles(Vec<RGB>),
+    Registers(Registers),
+    Scheduler {
+        /// The current time of the scheduler, in t-cycles.
+        current_time: u64,
+        pending_events: Vec<Event>,
+    },
+}
 
 impl GameBoyEmulator {
+    /// Gather the debug information for the `request` right away.
+    ///
+    /// A synchronous alternative to the frontend's debug channel, for frontends (or tests)
+    /// which own the emulator directly.
+    pub fn debug_query(&self, request: DebugRequest) -> DebugResponse {
+        match request {
+            DebugRequest::Palette => DebugResponse::Palette(self.get_palette_info()),
+            DebugRequest::Oam => DebugResponse::Oam(self.oam().to_vec()),
+            DebugRequest::Tiles => DebugResponse::Tiles(self.vram_tiles().to_vec()),
+            DebugRequest::Registers => DebugResponse::Registers(self.cpu.registers().clone()),
+            DebugRequest::Scheduler => DebugResponse::Scheduler {
+                current_time: self.cpu.mmu.scheduler.current_time,
+                pending_events: self.pending_events(),
+            },
+        }
+    }
+
     /// Retrieves and returns all palette info from the `PPU`
     /// Strips out all unnecessary information, only leaving colour info.
     pub fn get_palette_info(&self) -> PaletteDebugInfo {
         PaletteDebugInfo::new(&self.cpu.mmu.ppu, self.emulator_mode())
     }
 
+    /// Retrieves the current window registers and internal window state from the `PPU`.
+    pub fn get_window_info(&self) -> WindowDebugInfo {
+        WindowDebugInfo::new(&self.cpu.mmu.ppu)
+    }
+
+    /// Returns the current values of all PPU registers.
+    pub fn ppu_registers(&self) -> PpuRegisters {
+        self.cpu.mmu.ppu.register_snapshot()
+    }
+
+    /// Returns the currently selected background tile map together with the scroll and window
+    /// positions, e.g. for drawing a minimap.
+    pub fn background_snapshot(&self) -> BackgroundSnapshot {
+        BackgroundSnapshot::new(&self.cpu.mmu.ppu)
+    }
+
+    /// Returns the selected tile map rendered as a 256x256 RGB pixel array, see
+    /// [render_tilemap](../hardware/ppu/struct.PPU.html#method.render_tilemap).
+    pub fn render_tilemap(&self, which: TileMapSelect) -> Vec<RGB> {
+        self.cpu.mmu.ppu.render_tilemap(which)
+    }
+
     pub fn vram_tiles(&self) -> [RGB; 8 * 8 * 768] {
         self.cpu.mmu.ppu.tiles_cgb()
     }
 
+    /// Returns the CGB attributes (palette, bank, flips and priority) for every tile in the selected tile map.
+    pub fn cgb_tilemap_attributes(&self, which: TileMapSelect) -> Vec<CgbTileAttribute> {
+        self.cpu.mmu.ppu.cgb_tilemap_attributes(which)
+    }
+
+    /// Returns the current frame with an outline around every sprite, see
+    /// [render_with_sprite_boxes](../hardware/ppu/struct.PPU.html#method.render_with_sprite_boxes).
+    pub fn render_with_sprite_boxes(&self) -> Vec<RGB> {
+        self.cpu.mmu.ppu.render_with_sprite_boxes()
+    }
+
+    /// Returns the banking layout of the cartridge, see
+    /// [bank_info](../hardware/cartridge/struct.Cartridge.html#method.bank_info).
+    pub fn bank_info(&self) -> Option<BankInfo> {
+        self.cpu.mmu.cartridge().map(|cartridge| cartridge.bank_info())
+    }
+
+    /// Returns the current real time clock values, if the cartridge has one, see
+    /// [rtc_live](../hardware/cartridge/struct.Cartridge.html#method.rtc_live).
+    pub fn rtc_live(&self) -> Option<RtcView> {
+        self.cpu.mmu.cartridge().and_then(|cartridge| cartridge.rtc_live())
+    }
+
     pub fn oam(&self) -> &[SpriteAttribute; 40] {
         &self.cpu.mmu.ppu.oam
     }
 
+    /// Returns the current timer registers, with `DIV` reflecting the current cycle.
+    pub fn timer_state(&self) -> TimerState {
+        let mmu = &self.cpu.mmu;
+        mmu.timers.timer_state(&mmu.scheduler)
+    }
+
+    /// Returns all scheduled events which have yet to fire, soonest first.
+    ///
+    /// See [EventType::description](../enum.EventType.html#method.description) for what
+    /// each of them means.
+    pub fn pending_events(&self) -> Vec<Event> {
+        self.cpu.mmu.scheduler.pending_events()
+    }
+
+    /// Execute `handler` instead of the normal implementation of `opcode`, see
+    /// [override_opcode](../hardware/cpu/struct.CPU.html#method.override_opcode).
+    ///
+    /// Overrides don't survive a reset or power cycle.
+    pub fn override_opcode(&mut self, opcode: u8, handler: OpcodeHandler<Memory>) {
+        self.cpu.override_opcode(opcode, handler);
+    }
+
+    pub fn remove_opcode_override(&mut self, opcode: u8) {
+        self.cpu.remove_opcode_override(opcode);
+    }
+
     pub fn emulator_mode(&self) -> GameBoyModel {
         self.cpu.mmu.emulated_model
     }
+
+    /// Run exactly until the next V-blank and return the newly completed frame.
+    ///
+    /// Stops early if a breakpoint is hit (see [take_breakpoint_hit](#method.take_breakpoint_hit)),
+    /// or the CPU is in STOP mode, in which case the frame is still the previous one.
+    /// As we only ever stop in between instructions the emulator can safely be left paused
+    /// afterwards, or be stepped again.
+    pub fn frame_step(&mut self) -> &[RGB; FRAMEBUFFER_SIZE] {
+        loop {
+            let (kind, vblank, _) = self.emulate_step();
+
+            if vblank || kind == StepKind::Stopped || self.cpu.mmu.breakpoints.has_hit() {
+                return self.frame_buffer();
+            }
+        }
+    }
+
+    /// Execute exactly one instruction (or skip ahead to the next event while halted).
+    ///
+    /// Unlike [emulate_cycle](../gb_emu/struct.GameBoyEmulator.html#method.emulate_cycle) an
+    /// interrupt dispatch is reported as its own step, with the first instruction of the handler
+    /// only executed by the next call. This doesn't affect the timing in any way.
+    ///
+    /// # Returns
+    ///
+    /// What was executed, or a `CrashReport` if the CPU has locked up.
+    pub fn step_instruction(&mut self) -> Result<StepResult, CrashReport> {
+        let pc = self.cpu.registers().pc;
+        let start_time = self.cpu.mmu.scheduler.current_time;
+        let (kind, vblank) = self.cpu.step_instruction();
+        let cycles = self.cpu.mmu.scheduler.current_time - start_time;
+        self.finish_cycle(vblank, cycles);
+
+        match self.crash_report() {
+            Some(report) => Err(report),
+            None => Ok(StepResult {
+                kind,
+                pc,
+                cycles,
+                vblank,
+            }),
+        }
+    }
+
+    /// Returns a single line describing the current machine state, meant to be logged once per
+    /// V-blank and diffed against the trace of another emulator to find the first divergence.
+    ///
+    /// The format is stable, with all values in upper case hexadecimal except for the frame:
+    ///
+    /// `{frame} PC:{pc:04X} AF:{af:04X} BC:{bc:04X} DE:{de:04X} HL:{hl:04X} SP:{sp:04X} LY:{ly:02X} DIV:{div:02X}`
+    pub fn golden_log_line(&self) -> String {
+        let registers = self.cpu.registers();
+        let mmu = &self.cpu.mmu;
+
+        format!(
+            "{} PC:{:04X} AF:{:04X} BC:{:04X} DE:{:04X} HL:{:04X} SP:{:04X} LY:{:02X} DIV:{:02X}",
+            self.frames_emulated(),
+            registers.pc,
+            registers.af(),
+            registers.bc(),
+            registers.de(),
+            registers.hl(),
+            registers.sp,
+            mmu.ppu.read_vram(LY_REGISTER),
+            mmu.timers.divider_register(&mmu.scheduler)
+        )
+    }
+
+    /// The memory breakpoints and value watches, checked on every memory access.
+    pub fn breakpoints_mut(&mut self) -> &mut Breakpoints {
+        &mut self.cpu.mmu.breakpoints
+    }
+
+    /// Returns the first breakpoint hit since the last call, if any.
+    ///
+    /// The emulator won't pause by itself, so this should be checked after every instruction
+    /// (see [step_instruction](#method.step_instruction)) to stop as close to the hit as possible.
+    pub fn take_breakpoint_hit(&mut self) -> Option<BreakpointHit> {
+        self.cpu.mmu.breakpoints.take_hit()
+    }
+
+    /// Returns why the CPU is currently halted, handy for diagnosing a game which appears frozen.
+    pub fn halt_reason(&self) -> Option<HaltReason> {
+        self.cpu.halt_reason()
+    }
+
+    /// Start (or stop) accumulating how often every pixel changes between frames, see
+    /// `frame_diff_heatmap()`. Disabling it also discards the accumulated changes.
+    pub fn set_frame_heatmap(&mut self, enabled: bool) {
+        if !enabled {
+            self.frame_heatmap = None;
+        } else if self.frame_heatmap.is_none() {
+            self.frame_heatmap = Some(FrameHeatmap::new());
+        }
+    }
+
+    /// Returns, for every pixel, how often it changed between `0` (never) and `255` (every frame)
+    /// since the heatmap was enabled or last reset. `None` if the heatmap isn't enabled.
+    pub fn frame_diff_heatmap(&self) -> Option<Vec<u8>> {
+        self.frame_heatmap.as_ref().map(FrameHeatmap::heatmap)
+    }
+
+    pub fn reset_frame_heatmap(&mut self) {
+        if let Some(heatmap) = self.frame_heatmap.as_mut() {
+            heatmap.reset();
+        }
+    }
+
+    /// Start (or stop) recording the CPU's HALT/STOP transitions, see `take_power_events()`.
+    ///
+    /// Like opcode overrides this doesn't survive a reset or power cycle.
+    pub fn set_power_event_logging(&mut self, enabled: bool) {
+        self.cpu.power_events_mut().set_enabled(enabled);
+    }
+
+    /// Returns all HALT/STOP transitions recorded since the last call, oldest first.
+    pub fn take_power_events(&mut self) -> Vec<PowerEvent> {
+        self.cpu.power_events_mut().take_events()
+    }
+
+    /// Returns a `CrashReport` if the emulator ended up in an unrecoverable state.
+    pub fn crash_report(&self) -> Option<CrashReport> {
+        CrashReport::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gb_emu::{GameBoyEmulator, CYCLES_PER_FRAME};
+    use crate::gb_emu_debug::{DebugRequest, DebugResponse};
+    use crate::hardware::cpu::power_events::PowerEventKind::{HaltBug, HaltEntered, HaltExited};
+    use crate::hardware::cpu::{HaltReason, StepKind};
+    use crate::hardware::mmu::breakpoints::BreakpointHit;
+    use crate::hardware::ppu::FRAMEBUFFER_SIZE;
+    use crate::io::interrupts::InterruptFlags;
+    use crate::io::timer::{TIMER_CONTROL, TIMER_MODULO};
+    use crate::EmulatorOptionsBuilder;
+
+    #[test]
+    fn test_frame_step() {
+        // A ROM consisting of only NOPs.
+        let mut emulator = GameBoyEmulator::new(&vec![0; 0x8000], EmulatorOptionsBuilder::new().build());
+        emulator.frame_step();
+        let first_frame = emulator.cycles_performed();
+        emulator.frame_step();
+
+        assert_eq!(emulator.cycles_performed() - first_frame, CYCLES_PER_FRAME);
+
+        // LD (0xC000), A; JR -5
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x105].copy_from_slice(&[0xEA, 0x00, 0xC0, 0x18, 0xFB]);
+        let mut emulator = GameBoyEmulator::new(&rom, EmulatorOptionsBuilder::new().build());
+        emulator.breakpoints_mut().add_breakpoint(0xC000);
+        emulator.frame_step();
+
+        assert!(emulator.cycles_performed() < CYCLES_PER_FRAME);
+        assert_eq!(emulator.cpu.registers().pc, 0x103);
+        assert!(emulator.take_breakpoint_hit().is_some());
+    }
+
+    #[test]
+    fn test_debug_query() {
+        let mut emulator = GameBoyEmulator::new(&vec![0; 0x8000], EmulatorOptionsBuilder::new().build());
+        emulator.frame_step();
+
+        match emulator.debug_query(DebugRequest::Registers) {
+            DebugResponse::Registers(registers) => assert_eq!(registers.pc, emulator.cpu.registers().pc),
+            other => panic!("Unexpected response: {:?}", other),
+        }
+        match emulator.debug_query(DebugRequest::Oam) {
+            DebugResponse::Oam(oam) => assert_eq!(oam.len(), 40),
+            other => panic!("Unexpected response: {:?}", other),
+        }
+        match emulator.debug_query(DebugRequest::Tiles) {
+            DebugResponse::Tiles(tiles) => assert_eq!(tiles.len(), 8 * 8 * 768),
+            other => panic!("Unexpected response: {:?}", other),
+        }
+        match emulator.debug_query(DebugRequest::Scheduler) {
+            DebugResponse::Scheduler {
+                current_time,
+                pending_events,
+            } => {
+                assert_eq!(current_time, emulator.cpu.mmu.scheduler.current_time);
+                assert!(pending_events.windows(2).all(|e| e[0].timestamp <= e[1].timestamp));
+                assert!(pending_events.iter().all(|e| e.timestamp >= current_time));
+            }
+            other => panic!("Unexpected response: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_timer_state() {
+        let mut emulator = GameBoyEmulator::new(&vec![0; 0x8000], EmulatorOptionsBuilder::new().build());
+        emulator.cpu.mmu.write_byte(TIMER_MODULO, 0x42);
+        emulator.cpu.mmu.write_byte(TIMER_CONTROL, 0x05);
+        let state = emulator.timer_state();
+
+        assert_eq!(state.tma, 0x42);
+        assert!(state.enabled);
+        assert_eq!(state.frequency, 262144);
+        assert_eq!(state.div, emulator.cpu.mmu.read_byte(0xFF04));
+    }
+
+    #[test]
+    fn test_golden_log_line() {
+        let mut emulator = GameBoyEmulator::new(&vec![0; 0x8000], EmulatorOptionsBuilder::new().build());
+        let expected = "0 PC:0100 AF:01B0 BC:0013 DE:00D8 HL:014D SP:FFFE LY:00 DIV:00";
+        assert_eq!(emulator.golden_log_line(), expected);
+
+        emulator.frame_step();
+        let line = emulator.golden_log_line();
+        assert!(line.starts_with("1 PC:"));
+        assert!(line.contains(" LY:90 "));
+    }
+
+    #[test]
+    fn test_value_watch() {
+        let mut rom = vec![0; 0x8000];
+        // LD A, 0x42; LD (0xC000), A; INC A; LD (0xC000), A
+        rom[0x100..0x109].copy_from_slice(&[0x3E, 0x42, 0xEA, 0x00, 0xC0, 0x3C, 0xEA, 0x00, 0xC0]);
+        let mut emulator = GameBoyEmulator::new(&rom, EmulatorOptionsBuilder::new().build());
+        emulator.cpu.mmu.write_byte(0xC000, 0x10);
+        emulator.breakpoints_mut().add_value_watch(0xC000, 0x43);
+
+        emulator.step_instruction().unwrap();
+        emulator.step_instruction().unwrap();
+        assert_eq!(emulator.take_breakpoint_hit(), None);
+
+        emulator.step_instruction().unwrap();
+        emulator.step_instruction().unwrap();
+        let expected = BreakpointHit::Value {
+            address: 0xC000,
+            old_value: 0x42,
+            new_value: 0x43,
+            write: true,
+        };
+        assert_eq!(emulator.take_breakpoint_hit(), Some(expected));
+        assert_eq!(emulator.take_breakpoint_hit(), None);
+
+        // Watching the joypad shouldn't make the write count as a poll.
+        emulator.cpu.mmu.write_byte(0xFF00, 0x10);
+        emulator.cpu.mmu.take_input_polled();
+        emulator.breakpoints_mut().add_value_watch(0xFF00, 0x20);
+        emulator.cpu.mmu.write_byte(0xFF00, 0x20);
+        assert!(emulator.take_breakpoint_hit().is_some());
+        assert!(!emulator.cpu.mmu.take_input_polled());
+    }
+
+    #[test]
+    fn test_step_instruction() {
+        let mut rom = vec![0; 0x8000];
+        // EI; NOP; NOP, with INC A as the V-blank handler.
+        rom[0x100..0x103].copy_from_slice(&[0xFB, 0x00, 0x00]);
+        rom[0x40] = 0x3C;
+        let mut emulator = GameBoyEmulator::new(&rom, EmulatorOptionsBuilder::new().build());
+        emulator.cpu.mmu.write_byte(0xFFFF, 0x01);
+        emulator.cpu.mmu.write_byte(0xFF0F, 0x01);
+
+        let step = emulator.step_instruction().unwrap();
+        assert_eq!(step.opcode(), Some(0xFB));
+        assert_eq!((step.pc, step.cycles), (0x100, 4));
+        assert!(!emulator.cpu.ime);
+
+        // IME is only set after the next instruction was fetched, so it can't be interrupted.
+        let step = emulator.step_instruction().unwrap();
+        assert_eq!(step.kind, StepKind::Instruction(0x00));
+        assert_eq!((step.pc, step.cycles), (0x101, 4));
+        assert!(emulator.cpu.ime);
+
+        let step = emulator.step_instruction().unwrap();
+        assert_eq!(step.kind, StepKind::InterruptDispatch);
+        assert_eq!((step.pc, step.cycles), (0x102, 20));
+        assert_eq!(emulator.cpu.registers().pc, 0x40);
+
+        let step = emulator.step_instruction().unwrap();
+        assert_eq!(step.kind, StepKind::Instruction(0x3C));
+        assert_eq!((step.pc, step.cycles), (0x40, 4));
+    }
+
+    #[test]
+    fn test_halt_reason() {
+        let mut rom = vec![0; 0x8000];
+        // HALT, while IE is still 0 after the boot.
+        rom[0x100] = 0x76;
+        let mut emulator = GameBoyEmulator::new(&rom, EmulatorOptionsBuilder::new().build());
+        assert_eq!(emulator.halt_reason(), None);
+        emulator.step_instruction().unwrap();
+        assert_eq!(emulator.halt_reason(), Some(HaltReason::NoInterruptsEnabled));
+
+        // EI; LD A, 0x04; LDH (0xFF), A; HALT
+        rom[0x100..0x106].copy_from_slice(&[0xFB, 0x3E, 0x04, 0xE0, 0xFF, 0x76]);
+        let mut emulator = GameBoyEmulator::new(&rom, EmulatorOptionsBuilder::new().build());
+        for _ in 0..4 {
+            emulator.step_instruction().unwrap();
+        }
+        let expected = HaltReason::WaitingForInterrupt {
+            enabled: InterruptFlags::TIMER,
+            ime: true,
+        };
+        assert_eq!(emulator.halt_reason(), Some(expected));
+    }
+
+    #[test]
+    fn test_power_events() {
+        let mut rom = vec![0; 0x8000];
+        // LD A, 0x01; LDH (0xFF), A; XOR A; LDH (0x0F), A; HALT; HALT
+        rom[0x100..0x109].copy_from_slice(&[0x3E, 0x01, 0xE0, 0xFF, 0xAF, 0xE0, 0x0F, 0x76, 0x76]);
+        let mut emulator = GameBoyEmulator::new(&rom, EmulatorOptionsBuilder::new().build());
+        emulator.set_power_event_logging(true);
+        emulator.run_to_vblank();
+        // Wake up from the V-blank interrupt, after which the second HALT triggers the halt bug
+        // as the interrupt is still pending.
+        emulator.step_instruction().unwrap();
+        emulator.step_instruction().unwrap();
+
+        let events = emulator.take_power_events();
+        let kinds: Vec<_> = events.iter().map(|event| event.kind).collect();
+        let pcs: Vec<_> = events.iter().map(|event| event.pc).collect();
+
+        assert_eq!(kinds, [HaltEntered, HaltExited, HaltBug]);
+        assert_eq!(pcs, [0x107, 0x108, 0x108]);
+        assert!(events[1].cycle > events[0].cycle);
+    }
+
+    #[test]
+    fn test_frame_diff_heatmap() {
+        let mut emulator = GameBoyEmulator::new(&vec![0; 0x8000], EmulatorOptionsBuilder::new().build());
+        assert_eq!(emulator.frame_diff_heatmap(), None);
+
+        emulator.set_frame_heatmap(true);
+        emulator.run_to_vblank();
+        emulator.run_to_vblank();
+        // A ROM of only NOPs never changes the screen.
+        let heatmap = emulator.frame_diff_heatmap().unwrap();
+        assert_eq!(heatmap.len(), FRAMEBUFFER_SIZE);
+        assert!(heatmap.iter().all(|&count| count == 0));
+
+        emulator.set_frame_heatmap(false);
+        assert_eq!(emulator.frame_diff_heatmap(), None);
+    }
 }
@@ -1,4 +1,5 @@
 use crate::hardware::apu::test_bit;
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
 
 #[derive(Default, Debug, Copy, Clone)]
 pub struct EnvelopeFeature {
@@ -242,3 +243,60 @@ impl SweepFeature {
         self.sweep_shift = value & 0x7;
     }
 }
+
+impl SaveState for EnvelopeFeature {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.volume);
+        state.write_u8(self.volume_load);
+        state.write_bool(self.envelope_add_mode);
+        state.write_bool(self.envelope_enabled);
+        state.write_u8(self.envelope_period);
+        state.write_u8(self.envelope_timer);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.volume = state.read_u8()?;
+        self.volume_load = state.read_u8()?;
+        self.envelope_add_mode = state.read_bool()?;
+        self.envelope_enabled = state.read_bool()?;
+        self.envelope_period = state.read_u8()?;
+        self.envelope_timer = state.read_u8()?;
+        Ok(())
+    }
+}
+
+impl SaveState for LengthFeature {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.length_enable);
+        state.write_u16(self.length_timer);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.length_enable = state.read_bool()?;
+        self.length_timer = state.read_u16()?;
+        Ok(())
+    }
+}
+
+impl SaveState for SweepFeature {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.sweep_period);
+        state.write_bool(self.sweep_negate);
+        state.write_u8(self.sweep_shift);
+        state.write_bool(self.sweep_enabled);
+        state.write_bool(self.done_negate_calc);
+        state.write_u8(self.sweep_timer);
+        state.write_u16(self.sweep_frequency_shadow);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.sweep_period = state.read_u8()?;
+        self.sweep_negate = state.read_bool()?;
+        self.sweep_shift = state.read_u8()?;
+        self.sweep_enabled = state.read_bool()?;
+        self.done_negate_calc = state.read_bool()?;
+        self.sweep_timer = state.read_u8()?;
+        self.sweep_frequency_shadow = state.read_u16()?;
+        Ok(())
+    }
+}
@@ -10,25 +10,45 @@ use crate::hardware::apu::square_channel::SquareWaveChannel;
 use crate::hardware::apu::wave_channel::WaveformChannel;
 use crate::hardware::mmu::INVALID_READ;
 use crate::scheduler::Scheduler;
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
 
 mod channel_features;
 mod noise_channel;
 mod square_channel;
 mod wave_channel;
 
-// Currently assumes 44100 Hz
+/// The sample rate (in Hz) the audio is generated at, unless configured otherwise.
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+/// The amount of samples (roughly two frames worth) after which the output buffer is compacted,
+/// at the `DEFAULT_SAMPLE_RATE`. Scaled for other sample rates, see `sample_buffer_size()`.
 pub const SAMPLE_SIZE_BUFFER: usize = 1480;
 /// The frame sequencer component clocks at 512Hz.
 /// 4194304/512 = 8192 cycles
 pub const FRAME_SEQUENCE_CYCLES: u64 = 8192;
-/// The amount of cycles (normalised to 4Mhz) between every sample.
-pub const SAMPLE_CYCLES: u64 = 95;
+/// The amount of cycles (normalised to 4Mhz) between every sample at the `DEFAULT_SAMPLE_RATE`.
+pub const SAMPLE_CYCLES: u64 = DMG_CLOCK_SPEED / DEFAULT_SAMPLE_RATE as u64;
+/// The largest width accepted by `APU::set_stereo_width()`, twice the hardware separation.
+pub const MAX_STEREO_WIDTH: f32 = 2.0;
+/// The default gain applied to the sum of the four channels (each outputting `0..=15`).
+pub const DEFAULT_MASTER_GAIN: f32 = 1.0 / 100.0;
+/// The default gain for every step of the `NR50` master volume (`0..=7`).
+pub const DEFAULT_VOLUME_STEP: f32 = 1.0 / 6.0;
 
 pub const APU_MEM_START: u16 = 0xFF10;
 pub const APU_MEM_END: u16 = 0xFF2F;
 pub const WAVE_SAMPLE_START: u16 = 0xFF30;
 pub const WAVE_SAMPLE_END: u16 = 0xFF3F;
 
+/// The values of `0xFF10..=0xFF25` while the APU is disabled, where every register reads as if
+/// it were cleared. Only the unused/write-only bits still read as 1.
+const DISABLED_READ_VALUES: [u8; 0x16] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, // NR10-NR14
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF, // NR20-NR24
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF, // NR30-NR34
+    0xFF, 0xFF, 0x00, 0x00, 0xBF, // NR40-NR44
+    0x00, 0x00, // NR50-NR51
+];
+
 #[derive(Debug)]
 pub struct APU {
     voice1: SquareWaveChannel,
@@ -44,8 +64,22 @@ pub struct APU {
     // 0-3 will represent voice 1-4 enable respectively.
     left_channel_enable: [bool; 4],
     right_channel_enable: [bool; 4],
+    /// See `set_channel_masked()`, independent of the above as those are controlled by the ROM.
+    channel_masked: [bool; 4],
     global_sound_enable: bool,
+    /// Whether to downmix the output to mono (still outputting two, identical, channels).
+    mono: bool,
+    /// See `set_stereo_width()`.
+    stereo_width: f32,
+    /// See `set_gain()`.
+    master_gain: f32,
+    volume_step: f32,
     output_buffer: Vec<f32>,
+    /// See `SAMPLE_SIZE_BUFFER`.
+    sample_buffer_size: usize,
+    /// The start of the samples in `output_buffer` which haven't been drained yet.
+    /// Avoids shifting the entire buffer on every partial drain.
+    output_start: usize,
     frame_sequencer_step: u8,
     // Used for synchronisation
     last_synchronise_time: u64,
@@ -53,22 +87,33 @@ pub struct APU {
 }
 
 impl APU {
-    pub fn new() -> Self {
+    /// Create an APU generating `sample_rate` (in Hz) samples per second.
+    pub fn new(sample_rate: u32) -> Self {
+        let sample_buffer_size = sample_buffer_size(sample_rate);
+
         APU {
             voice1: SquareWaveChannel::default(),
             voice2: SquareWaveChannel::default(),
             voice3: WaveformChannel::new(),
             voice4: NoiseChannel::new(),
-            audio_output: AudioOutput::default(),
+            audio_output: AudioOutput::new(sample_rate),
             vin_l_enable: false,
             vin_r_enable: false,
             left_volume: 7,
             right_volume: 7,
             left_channel_enable: [true; 4],
             right_channel_enable: [true, true, false, false],
-            // Start the APU with 2 frames of audio buffered
-            output_buffer: Vec::with_capacity(SAMPLE_SIZE_BUFFER * 2),
+            channel_masked: [false; 4],
+            // Reserve room for 2 frames of audio, see `prebuffer_silence()` for actually
+            // starting with audio buffered.
+            output_buffer: Vec::with_capacity(sample_buffer_size * 2),
+            sample_buffer_size,
+            output_start: 0,
             global_sound_enable: true,
+            mono: false,
+            stereo_width: 1.0,
+            master_gain: DEFAULT_MASTER_GAIN,
+            volume_step: DEFAULT_VOLUME_STEP,
             frame_sequencer_step: 0,
             last_synchronise_time: 0,
             last_frame_sequence_tick: 0,
@@ -140,8 +185,8 @@ impl APU {
         }
     }
 
-    /// Ticked by the `synchronise()` method every `95` cycles.
-    /// This is a close enough value such that we get one sample every ~1/44100 seconds
+    /// Ticked by the `synchronise()` method every `cycles_per_sample` cycles (e.g, `95`, which is
+    /// close enough to get one sample every ~1/44100 seconds).
     fn generate_sample(&mut self) {
         // TODO: Add actual downsampling instead of the selective audio pick.
         // Refer to: https://www.reddit.com/r/EmuDev/comments/g5czyf/sound_emulation/
@@ -151,32 +196,150 @@ impl APU {
         // If we ever want to implement a low pass filter we would probably have to generate
         // samples at native rate (so every 4/8 clocks) in each individual channel.
 
-        // These values are purely personal preference, may even want to defer this to the emulator
-        // consumer.
-        let left_final_volume = self.left_volume as f32 / 6.0;
-        let right_final_volume = self.right_volume as f32 / 6.0;
+        // The defaults for these values are purely personal preference, see `set_gain()`.
+        let left_final_volume = self.left_volume as f32 * self.volume_step;
+        let right_final_volume = self.right_volume as f32 * self.volume_step;
 
         let left_sample = self.generate_audio(self.left_channel_enable, left_final_volume);
         let right_sample = self.generate_audio(self.right_channel_enable, right_final_volume);
 
-        let (left_sample, right_sample) = self.audio_output.apply_highpass_filter(left_sample, right_sample);
+        let (mut left_sample, mut right_sample) = self.audio_output.apply_highpass_filter(left_sample, right_sample);
+
+        if self.stereo_width != 1.0 {
+            let (left, right) = apply_stereo_width(left_sample, right_sample, self.stereo_width);
+            left_sample = left;
+            right_sample = right;
+        }
+
+        if self.mono {
+            // Otherwise channels which are only panned to one side would be lost on mono speakers.
+            left_sample = (left_sample + right_sample) / 2.0;
+            right_sample = left_sample;
+        }
 
         self.output_buffer.push(left_sample);
         self.output_buffer.push(right_sample);
     }
 
+    /// Force the voice `channel` (`0-3` for voice 1-4) to output silence, regardless of what the
+    /// ROM wrote to NR51. Meant for debugging, as the voice itself keeps running as usual.
+    ///
+    /// Invalid channels are ignored.
+    pub fn set_channel_masked(&mut self, channel: usize, masked: bool) {
+        if let Some(channel_masked) = self.channel_masked.get_mut(channel) {
+            *channel_masked = masked;
+        }
+    }
+
+    /// Whether the voice `channel` (`0-3` for voice 1-4) is masked, see `set_channel_masked()`.
+    pub fn channel_masked(&self, channel: usize) -> bool {
+        self.channel_masked.get(channel).copied().unwrap_or(false)
+    }
+
+    /// Whether to downmix the audio to mono. The audio buffer will still contain both
+    /// channels, but they'll be identical.
+    pub fn set_mono(&mut self, mono: bool) {
+        self.mono = mono;
+    }
+
+    /// Set the stereo separation, where `0.0` is mono, `1.0` is the hard panning of real
+    /// hardware, and anything above exaggerates it. Clamped to `[0.0, MAX_STEREO_WIDTH]`.
+    ///
+    /// Softening the separation can make headphone listening less fatiguing. Independent of
+    /// `set_mono()`, which always downmixes regardless of the width.
+    pub fn set_stereo_width(&mut self, width: f32) {
+        self.stereo_width = if width.is_nan() {
+            1.0
+        } else {
+            width.clamp(0.0, MAX_STEREO_WIDTH)
+        };
+    }
+
+    /// Set the gain stage of the output, where every sample is
+    /// `sum of channels * master_gain * (NR50 volume * volume_step)`.
+    ///
+    /// With the defaults ([DEFAULT_MASTER_GAIN] and [DEFAULT_VOLUME_STEP]) the output stays
+    /// within `[-1.0, 1.0]`, higher values make the output louder but may clip. Negative values
+    /// are treated as `0.0`, and `NaN` as the default.
+    pub fn set_gain(&mut self, master_gain: f32, volume_step: f32) {
+        let sanitise = |value: f32, default: f32| if value.is_nan() { default } else { value.max(0.0) };
+
+        self.master_gain = sanitise(master_gain, DEFAULT_MASTER_GAIN);
+        self.volume_step = sanitise(volume_step, DEFAULT_VOLUME_STEP);
+    }
+
+    /// Whether to apply the high-pass filter (removing the DC offset) to the output.
+    /// Disabling it allows for analysis of the raw DAC output.
+    pub fn set_highpass_filter(&mut self, enabled: bool) {
+        self.audio_output.set_highpass_enabled(enabled);
+    }
+
     pub fn get_audio_buffer(&self) -> &[f32] {
-        &self.output_buffer
+        &self.output_buffer[self.output_start..]
+    }
+
+    /// Fill the output buffer with `milliseconds` of silence, so that an audio callback has data
+    /// to play right away instead of underrunning while the first frames are emulated.
+    pub fn prebuffer_silence(&mut self, milliseconds: u32) {
+        let sample_rate = DMG_CLOCK_SPEED / self.audio_output.cycles_per_sample;
+        let samples = (sample_rate * milliseconds as u64 / 1000) as usize;
+
+        self.output_buffer.extend(std::iter::repeat_n(0.0, samples * 2));
     }
 
     pub fn clear_audio_buffer(&mut self) {
         self.output_buffer.clear();
+        self.output_start = 0;
+    }
+
+    /// Reset only the audio output, while leaving the sound registers (and thus the game's
+    /// configuration) untouched.
+    ///
+    /// Clears the output buffer and high-pass filter state, and resynchronises to the current
+    /// time of the `scheduler` so that no backlog of samples is generated afterwards.
+    pub fn reset_audio(&mut self, scheduler: &Scheduler) {
+        self.clear_audio_buffer();
+        self.audio_output.reset();
+        self.last_synchronise_time = scheduler.current_time;
+        self.last_frame_sequence_tick = scheduler.current_time;
+    }
+
+    /// The amount of buffered stereo frames (left/right pairs) which have yet to be drained.
+    pub fn samples_available(&self) -> usize {
+        (self.output_buffer.len() - self.output_start) / 2
+    }
+
+    /// Remove and return up to `max_samples` of the oldest samples, leaving the rest buffered.
+    ///
+    /// `max_samples` is rounded down to an even number so that a left/right pair is never split.
+    pub fn drain_audio(&mut self, max_samples: usize) -> Vec<f32> {
+        let end = self
+            .output_start
+            .saturating_add(max_samples & !1)
+            .min(self.output_buffer.len());
+        let result = self.output_buffer[self.output_start..end].to_vec();
+        self.output_start = end;
+
+        if self.output_start == self.output_buffer.len() {
+            self.clear_audio_buffer();
+        } else if self.output_start >= self.sample_buffer_size {
+            // Only occasionally shift the remaining samples to the front.
+            self.output_buffer.drain(..self.output_start);
+            self.output_start = 0;
+        }
+
+        result
     }
 
     pub fn read_register(&mut self, address: u16, scheduler: &mut Scheduler, speed_multiplier: u64) -> u8 {
         self.synchronise(scheduler, speed_multiplier);
         let address = address & 0xFF;
 
+        // The DMG can still write to the length registers while disabled, but those are write-only.
+        if !self.global_sound_enable && address < 0x26 {
+            return DISABLED_READ_VALUES[(address - 0x10) as usize];
+        }
+
         match address {
             0x10..=0x14 => self.voice1.read_register(address),
             0x15..=0x19 => self.voice2.read_register(address),
@@ -287,8 +450,12 @@ impl APU {
         self.voice3.write_register(address & 0xFF, value, self.frame_sequencer_step)
     }
 
-    fn generate_audio(&mut self, voice_enables: [bool; 4], final_volume: f32) -> f32 {
+    fn generate_audio(&mut self, mut voice_enables: [bool; 4], final_volume: f32) -> f32 {
         let mut result = 0f32;
+
+        for (enabled, &masked) in voice_enables.iter_mut().zip(self.channel_masked.iter()) {
+            *enabled &= !masked;
+        }
         // Voice 1 (Square wave)
         if voice_enables[0] {
             result += self.voice1.output_volume() as f32;
@@ -305,8 +472,8 @@ impl APU {
         if voice_enables[3] {
             result += self.voice4.output_volume() as f32;
         }
-        //TODO: Move / 100.0 after high pass.
-        (result / 100.0) * final_volume
+        //TODO: Move the master gain after high pass.
+        (result * self.master_gain) * final_volume
     }
 
     fn tick_length(&mut self) {
@@ -343,28 +510,84 @@ impl APU {
     }
 }
 
+impl SaveState for APU {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.voice1.save_state(state);
+        self.voice2.save_state(state);
+        self.voice3.save_state(state);
+        self.voice4.save_state(state);
+        state.write_u64(self.audio_output.remainder_cycles_sample);
+        state.write_f32(self.audio_output.highpass_diff.0);
+        state.write_f32(self.audio_output.highpass_diff.1);
+        state.write_bool(self.vin_l_enable);
+        state.write_bool(self.vin_r_enable);
+        state.write_u8(self.left_volume);
+        state.write_u8(self.right_volume);
+        for &enabled in self.left_channel_enable.iter().chain(self.right_channel_enable.iter()) {
+            state.write_bool(enabled);
+        }
+        state.write_bool(self.global_sound_enable);
+        state.write_u8(self.frame_sequencer_step);
+        state.write_u64(self.last_synchronise_time);
+        state.write_u64(self.last_frame_sequence_tick);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.voice1.load_state(state)?;
+        self.voice2.load_state(state)?;
+        self.voice3.load_state(state)?;
+        self.voice4.load_state(state)?;
+        self.audio_output.remainder_cycles_sample = state.read_u64()?;
+        self.audio_output.highpass_diff = (state.read_f32()?, state.read_f32()?);
+        self.vin_l_enable = state.read_bool()?;
+        self.vin_r_enable = state.read_bool()?;
+        self.left_volume = state.read_u8()?;
+        self.right_volume = state.read_u8()?;
+        for enabled in self.left_channel_enable.iter_mut().chain(self.right_channel_enable.iter_mut()) {
+            *enabled = state.read_bool()?;
+        }
+        self.global_sound_enable = state.read_bool()?;
+        self.frame_sequencer_step = state.read_u8()?;
+        self.last_synchronise_time = state.read_u64()?;
+        self.last_frame_sequence_tick = state.read_u64()?;
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 pub struct AudioOutput {
     remainder_cycles_sample: u64,
     cycles_per_sample: u64,
     highpass_rate: f32,
     highpass_diff: (f32, f32),
+    /// When disabled the raw DAC output is passed through, including its DC offset.
+    highpass_enabled: bool,
 }
 
 impl Default for AudioOutput {
     fn default() -> Self {
-        AudioOutput {
+        AudioOutput::new(DEFAULT_SAMPLE_RATE)
+    }
+}
+
+impl AudioOutput {
+    pub fn new(sample_rate: u32) -> Self {
+        let mut output = AudioOutput {
             remainder_cycles_sample: 0,
             cycles_per_sample: SAMPLE_CYCLES,
             highpass_rate: get_highpass_rate(SAMPLE_CYCLES),
             highpass_diff: (0.0, 0.0),
-        }
+            highpass_enabled: true,
+        };
+        output.set_sample_rate(sample_rate as u64);
+        output
     }
-}
 
-impl AudioOutput {
     #[inline]
     pub fn apply_highpass_filter(&mut self, left_in: f32, right_in: f32) -> (f32, f32) {
+        if !self.highpass_enabled {
+            return (left_in, right_in);
+        }
         // Credits to SameBoy since I looked at their implementation for this.
         let (high_left, high_right) = self.highpass_diff;
         let (filt_left, filt_right) = (left_in - high_left, right_in - high_right);
@@ -375,8 +598,23 @@ impl AudioOutput {
         (filt_left, filt_right)
     }
 
+    pub fn set_highpass_enabled(&mut self, enabled: bool) {
+        if self.highpass_enabled != enabled {
+            // Stale filter state would otherwise cause a pop when the filter is re-enabled.
+            self.highpass_diff = (0.0, 0.0);
+        }
+        self.highpass_enabled = enabled;
+    }
+
+    /// Clear the filter state and any partially accumulated sample.
+    pub fn reset(&mut self) {
+        self.remainder_cycles_sample = 0;
+        self.highpass_diff = (0.0, 0.0);
+    }
+
+    /// Rates above the clock speed are treated as a sample every cycle.
     pub fn set_sample_rate(&mut self, sample_rate_in_hz: u64) {
-        self.cycles_per_sample = DMG_CLOCK_SPEED / sample_rate_in_hz;
+        self.cycles_per_sample = (DMG_CLOCK_SPEED / sample_rate_in_hz.max(1)).max(1);
         self.highpass_rate = get_highpass_rate(self.cycles_per_sample);
     }
 }
@@ -386,6 +624,18 @@ fn no_length_tick_next_step(next_frame_sequence_val: u8) -> bool {
     (next_frame_sequence_val & 0x1) == 1
 }
 
+/// Scale the difference between the channels by `width`, while keeping their sum (the mono mix) intact.
+fn apply_stereo_width(left: f32, right: f32, width: f32) -> (f32, f32) {
+    let mid = (left + right) / 2.0;
+    let side = (left - right) / 2.0 * width;
+
+    (mid + side, mid - side)
+}
+
+fn sample_buffer_size(sample_rate: u32) -> usize {
+    (SAMPLE_SIZE_BUFFER as u64 * sample_rate as u64 / DEFAULT_SAMPLE_RATE as u64).max(1) as usize
+}
+
 fn get_highpass_rate(cycles_per_sample: u64) -> f32 {
     0.999958f32.powf(cycles_per_sample as f32)
 }
@@ -400,3 +650,206 @@ fn test_bit(value: u8, bit: u8) -> bool {
     let mask = 1 << bit;
     (value & mask) == mask
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::gb_emu::GameBoyModel;
+    use crate::hardware::apu::{
+        apply_stereo_width, AudioOutput, APU, DEFAULT_MASTER_GAIN, DEFAULT_SAMPLE_RATE, DEFAULT_VOLUME_STEP,
+        DISABLED_READ_VALUES, MAX_STEREO_WIDTH, SAMPLE_CYCLES, SAMPLE_SIZE_BUFFER,
+    };
+    use crate::scheduler::Scheduler;
+
+    #[test]
+    fn test_prebuffer_silence() {
+        let mut apu = APU::new(DEFAULT_SAMPLE_RATE);
+        apu.prebuffer_silence(0);
+        assert_eq!(apu.samples_available(), 0);
+
+        // 4194304 / 95 = 44150 samples per second.
+        apu.prebuffer_silence(20);
+        assert_eq!(apu.samples_available(), 883);
+        assert!(apu.get_audio_buffer().iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn test_sample_rate() {
+        let mut apu = APU::new(48000);
+        // 4194304 / 87 = 48210 samples per second.
+        assert_eq!(apu.audio_output.cycles_per_sample, 87);
+        assert!(apu.audio_output.highpass_rate > AudioOutput::default().highpass_rate);
+        assert_eq!(apu.sample_buffer_size, 1610);
+        apu.prebuffer_silence(20);
+        assert_eq!(apu.samples_available(), 964);
+
+        let mut scheduler = Scheduler::new();
+        scheduler.current_time = 87 * 10;
+        apu.clear_audio_buffer();
+        apu.synchronise(&mut scheduler, 0);
+        assert_eq!(apu.samples_available(), 10);
+
+        assert_eq!(APU::new(0).audio_output.cycles_per_sample, 4194304);
+        assert_eq!(APU::new(u32::MAX).audio_output.cycles_per_sample, 1);
+    }
+
+    #[test]
+    fn test_stereo_width() {
+        assert_eq!(apply_stereo_width(1.0, 0.0, 1.0), (1.0, 0.0));
+        assert_eq!(apply_stereo_width(1.0, 0.0, 0.0), (0.5, 0.5));
+        assert_eq!(apply_stereo_width(1.0, 0.0, 0.5), (0.75, 0.25));
+        assert_eq!(apply_stereo_width(1.0, 0.0, 2.0), (1.5, -0.5));
+
+        let mut apu = APU::new(DEFAULT_SAMPLE_RATE);
+        apu.set_stereo_width(-1.0);
+        assert_eq!(apu.stereo_width, 0.0);
+        apu.set_stereo_width(10.0);
+        assert_eq!(apu.stereo_width, MAX_STEREO_WIDTH);
+        apu.set_stereo_width(f32::NAN);
+        assert_eq!(apu.stereo_width, 1.0);
+    }
+
+    #[test]
+    fn test_gain() {
+        let mut apu = APU::new(DEFAULT_SAMPLE_RATE);
+        apu.set_gain(0.02, -1.0);
+        assert_eq!((apu.master_gain, apu.volume_step), (0.02, 0.0));
+        apu.set_gain(f32::NAN, f32::NAN);
+        assert_eq!(apu.master_gain, DEFAULT_MASTER_GAIN);
+        assert_eq!(apu.volume_step, DEFAULT_VOLUME_STEP);
+    }
+
+    #[test]
+    fn test_drain_audio() {
+        let mut apu = APU::new(DEFAULT_SAMPLE_RATE);
+        apu.output_buffer.extend((0..SAMPLE_SIZE_BUFFER * 2).map(|i| i as f32));
+
+        assert_eq!(apu.samples_available(), SAMPLE_SIZE_BUFFER);
+        assert_eq!(apu.drain_audio(3), vec![0.0, 1.0]);
+        assert_eq!(apu.samples_available(), SAMPLE_SIZE_BUFFER - 1);
+        assert_eq!(apu.get_audio_buffer()[0], 2.0);
+        assert_eq!(apu.get_audio_buffer().len(), SAMPLE_SIZE_BUFFER * 2 - 2);
+
+        let drained = apu.drain_audio(SAMPLE_SIZE_BUFFER);
+        assert_eq!(drained.len(), SAMPLE_SIZE_BUFFER);
+        assert_eq!(drained[0], 2.0);
+        assert_eq!(apu.get_audio_buffer()[0], (SAMPLE_SIZE_BUFFER + 2) as f32);
+
+        assert_eq!(apu.drain_audio(usize::MAX).len(), SAMPLE_SIZE_BUFFER - 2);
+        assert!(apu.get_audio_buffer().is_empty());
+        assert_eq!(apu.samples_available(), 0);
+        assert!(apu.drain_audio(10).is_empty());
+
+        // Draining everything while part of the buffer was already drained shouldn't overflow.
+        apu.output_buffer.extend((0..8).map(|i| i as f32));
+        apu.drain_audio(2);
+        assert_eq!(apu.drain_audio(usize::MAX), vec![2.0, 3.0, 4.0, 5.0, 6.0, 7.0]);
+    }
+
+    #[test]
+    fn test_read_while_disabled() {
+        let mut apu = APU::new(DEFAULT_SAMPLE_RATE);
+        let mut scheduler = Scheduler::new();
+        for address in 0xFF10..=0xFF25 {
+            apu.write_register(address, 0xFF, &mut scheduler, GameBoyModel::DMG, 0);
+        }
+        apu.write_register(0xFF26, 0x00, &mut scheduler, GameBoyModel::DMG, 0);
+        // Length registers can still be written on the DMG.
+        apu.write_register(0xFF20, 0x3F, &mut scheduler, GameBoyModel::DMG, 0);
+        apu.write_register(0xFF1B, 0xFF, &mut scheduler, GameBoyModel::DMG, 0);
+
+        let expected = [
+            0x80, 0x3F, 0x00, 0xFF, 0xBF, 0xFF, 0x3F, 0x00, 0xFF, 0xBF, 0x7F, 0xFF, 0x9F, 0xFF, 0xBF, 0xFF, 0xFF, 0x00,
+            0x00, 0xBF, 0x00, 0x00, 0x70,
+        ];
+        for (i, &value) in expected.iter().enumerate() {
+            let address = 0xFF10 + i as u16;
+            assert_eq!(
+                apu.read_register(address, &mut scheduler, 0),
+                value,
+                "Invalid read at 0x{:04X}",
+                address
+            );
+        }
+        assert_eq!(&expected[..0x16], &DISABLED_READ_VALUES[..]);
+
+        apu.write_register(0xFF26, 0x80, &mut scheduler, GameBoyModel::DMG, 0);
+        apu.write_register(0xFF24, 0x77, &mut scheduler, GameBoyModel::DMG, 0);
+        assert_eq!(apu.read_register(0xFF24, &mut scheduler, 0), 0x77);
+    }
+
+    #[test]
+    fn test_reset_audio() {
+        let mut apu = APU::new(DEFAULT_SAMPLE_RATE);
+        let mut scheduler = Scheduler::new();
+        apu.left_volume = 3;
+        scheduler.current_time = 20_000;
+        apu.synchronise(&mut scheduler, 0);
+        assert!(apu.samples_available() > 0);
+        apu.audio_output.apply_highpass_filter(1.0, 1.0);
+
+        // Simulate a long pause, after which we don't want to catch up.
+        scheduler.current_time = 10_000_000;
+        apu.reset_audio(&scheduler);
+
+        assert_eq!(apu.samples_available(), 0);
+        assert_eq!(apu.audio_output.highpass_diff, (0.0, 0.0));
+        assert_eq!(apu.last_synchronise_time, 10_000_000);
+        assert_eq!(apu.last_frame_sequence_tick, 10_000_000);
+        assert_eq!(apu.left_volume, 3);
+
+        scheduler.current_time += SAMPLE_CYCLES;
+        apu.synchronise(&mut scheduler, 0);
+        assert_eq!(apu.samples_available(), 1);
+    }
+
+    #[test]
+    fn test_channel_masked() {
+        let play_wave = |masked: bool| {
+            let mut apu = APU::new(DEFAULT_SAMPLE_RATE);
+            let mut scheduler = Scheduler::new();
+            apu.set_channel_masked(2, masked);
+            for address in 0xFF30..=0xFF3F {
+                apu.write_wave_sample(address, 0xF0, &mut scheduler, 0);
+            }
+            // Voice 3 at full volume on both sides, the only voice which is triggered.
+            let registers = [(0xFF24, 0x77), (0xFF25, 0xFF), (0xFF1A, 0x80), (0xFF1C, 0x20), (0xFF1E, 0x87)];
+            for &(address, value) in registers.iter() {
+                apu.write_register(address, value, &mut scheduler, GameBoyModel::DMG, 0);
+            }
+            scheduler.current_time = 10_000;
+            let wave_sample = apu.read_wave_sample(0xFF30, &mut scheduler, 0);
+
+            (apu.get_audio_buffer().to_vec(), wave_sample)
+        };
+
+        let (unmasked_audio, unmasked_wave_sample) = play_wave(false);
+        let (masked_audio, masked_wave_sample) = play_wave(true);
+
+        assert!(unmasked_audio.iter().any(|&sample| sample != 0.0));
+        assert!(masked_audio.iter().all(|&sample| sample == 0.0));
+        // The voice should keep running as usual, so the wave RAM accesses stay in sync.
+        assert_eq!(masked_wave_sample, unmasked_wave_sample);
+
+        let mut apu = APU::new(DEFAULT_SAMPLE_RATE);
+        apu.set_channel_masked(3, true);
+        apu.set_channel_masked(4, true);
+        assert!(apu.channel_masked(3));
+        assert!(!apu.channel_masked(4));
+    }
+
+    #[test]
+    fn test_highpass_bypass() {
+        let mut output = AudioOutput::default();
+        let (filtered, _) = output.apply_highpass_filter(1.0, 1.0);
+        let (filtered_next, _) = output.apply_highpass_filter(1.0, 1.0);
+        // The DC offset should slowly be removed.
+        assert!(filtered_next < filtered);
+
+        output.set_highpass_enabled(false);
+        assert_eq!(output.apply_highpass_filter(1.0, 0.5), (1.0, 0.5));
+
+        // Should start from a clean slate, as if no samples had been filtered yet.
+        output.set_highpass_enabled(true);
+        assert_eq!(output.apply_highpass_filter(1.0, 1.0).0, filtered);
+    }
+}
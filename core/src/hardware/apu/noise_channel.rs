@@ -2,6 +2,7 @@ use crate::gb_emu::GameBoyModel;
 use crate::hardware::apu::channel_features::{EnvelopeFeature, LengthFeature};
 use crate::hardware::apu::{no_length_tick_next_step, test_bit};
 use crate::hardware::mmu::INVALID_READ;
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
 
 /// Relevant for voice 4 for the DMG.
 ///
@@ -220,3 +221,32 @@ impl NoiseChannel {
         }
     }
 }
+
+impl SaveState for NoiseChannel {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.length.save_state(state);
+        self.envelope.save_state(state);
+        state.write_bool(self.trigger);
+        state.write_u8(self.output_volume);
+        state.write_u16(self.timer);
+        state.write_u16(self.timer_load_value);
+        state.write_bool(self.width_mode);
+        state.write_u8(self.clock_shift);
+        state.write_u8(self.divisor_code);
+        state.write_u16(self.lfsr);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.length.load_state(state)?;
+        self.envelope.load_state(state)?;
+        self.trigger = state.read_bool()?;
+        self.output_volume = state.read_u8()?;
+        self.timer = state.read_u16()?;
+        self.timer_load_value = state.read_u16()?;
+        self.width_mode = state.read_bool()?;
+        self.clock_shift = state.read_u8()?;
+        self.divisor_code = state.read_u8()?;
+        self.lfsr = state.read_u16()?;
+        Ok(())
+    }
+}
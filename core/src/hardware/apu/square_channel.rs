@@ -2,6 +2,7 @@ use crate::gb_emu::GameBoyModel;
 use crate::hardware::apu::channel_features::{EnvelopeFeature, LengthFeature, SweepFeature};
 use crate::hardware::apu::{no_length_tick_next_step, test_bit};
 use crate::hardware::mmu::INVALID_READ;
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
 
 /// Relevant for voice 1 and 2 for the DMG.
 /// This is a rather dirty implementation where voice 1 and 2 are merged, the latter
@@ -191,3 +192,32 @@ impl SquareWaveChannel {
         self.sweep.tick(&mut self.trigger, &mut self.frequency);
     }
 }
+
+impl SaveState for SquareWaveChannel {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.length.save_state(state);
+        self.envelope.save_state(state);
+        self.sweep.save_state(state);
+        state.write_bool(self.trigger);
+        state.write_u8(self.output_volume);
+        state.write_u16(self.frequency);
+        state.write_u16(self.timer);
+        state.write_u16(self.timer_load_value);
+        state.write_u8(self.wave_table_index as u8);
+        state.write_u8(self.duty_select as u8);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.length.load_state(state)?;
+        self.envelope.load_state(state)?;
+        self.sweep.load_state(state)?;
+        self.trigger = state.read_bool()?;
+        self.output_volume = state.read_u8()?;
+        self.frequency = state.read_u16()?;
+        self.timer = state.read_u16()?;
+        self.timer_load_value = state.read_u16()?;
+        self.wave_table_index = (state.read_u8()? & 0x7) as usize;
+        self.duty_select = (state.read_u8()? & 0x3) as usize;
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
 use crate::hardware::apu::channel_features::LengthFeature;
 use crate::hardware::apu::{no_length_tick_next_step, test_bit};
 use crate::hardware::mmu::INVALID_READ;
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
 
 /// Relevant for voice 3 for the DMG.
 ///
@@ -270,3 +271,36 @@ impl WaveformChannel {
         self.update_sample();
     }
 }
+
+impl SaveState for WaveformChannel {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.length.save_state(state);
+        state.write_u16(self.timer);
+        state.write_u16(self.timer_load_value);
+        state.write_u16(self.frequency);
+        state.write_bool(self.trigger);
+        state.write_u8(self.output_volume);
+        state.write_bool(self.dac_power);
+        state.write_u8(self.volume_load);
+        state.write_u8(self.volume);
+        state.write_bytes(&self.sample_buffer);
+        state.write_bytes(&self.wave_ram);
+        state.write_u8(self.sample_pointer as u8);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.length.load_state(state)?;
+        self.timer = state.read_u16()?;
+        self.timer_load_value = state.read_u16()?;
+        self.frequency = state.read_u16()?;
+        self.trigger = state.read_bool()?;
+        self.output_volume = state.read_u8()?;
+        self.dac_power = state.read_bool()?;
+        self.volume_load = state.read_u8()?;
+        self.volume = state.read_u8()?;
+        state.read_into(&mut self.sample_buffer)?;
+        state.read_into(&mut self.wave_ram)?;
+        self.sample_pointer = (state.read_u8()? & 0x1F) as usize;
+        Ok(())
+    }
+}
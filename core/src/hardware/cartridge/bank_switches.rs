@@ -0,0 +1,63 @@
+//! A log of the MBC bank switches, e.g, for finding out why a game ends up reading from the wrong bank.
+
+use std::collections::VecDeque;
+
+/// The amount of bank switches kept before the oldest ones are dropped.
+pub const MAX_BANK_SWITCHES: usize = 1024;
+
+/// A write to the MBC registers which changed the mapped ROM and/or RAM banks.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BankSwitch {
+    /// The address of the MBC register which was written to.
+    pub address: u16,
+    pub value: u8,
+    /// The ROM banks mapped to `0x0000..=0x3FFF` and `0x4000..=0x7FFF` after the write.
+    pub rom_banks: (usize, usize),
+    /// The offsets into the ROM of the above banks.
+    pub rom_offsets: (usize, usize),
+    /// The external RAM bank mapped to `0xA000..=0xBFFF` after the write.
+    pub ram_bank: usize,
+    pub ram_offset: usize,
+}
+
+/// Only records bank switches while enabled, to avoid any overhead otherwise.
+#[derive(Debug, Default, Clone)]
+pub struct BankSwitchLog {
+    enabled: bool,
+    switches: VecDeque<BankSwitch>,
+}
+
+impl BankSwitchLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline(always)]
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+
+        if !enabled {
+            self.switches.clear();
+        }
+    }
+
+    pub fn record(&mut self, switch: BankSwitch) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.switches.len() == MAX_BANK_SWITCHES {
+            self.switches.pop_front();
+        }
+        self.switches.push_back(switch);
+    }
+
+    /// Returns all bank switches since the last call, oldest first.
+    pub fn take_switches(&mut self) -> Vec<BankSwitch> {
+        self.switches.drain(..).collect()
+    }
+}
@@ -7,14 +7,26 @@ use crate::hardware::cartridge::header::RamSizes::{KB128, KB2, KB32, KB64, KB8,
 pub const HEADER_START: u16 = 0x0100;
 pub const HEADER_END: u16 = 0x014F;
 
+/// The Nintendo logo as it should be present at `0x0104..=0x0133` in every ROM.
+pub const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D, 0x00, 0x08, 0x11,
+    0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99, 0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E,
+    0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
 #[derive(Debug)]
 pub struct CartridgeHeader {
+    /// Whether the bitmap of the Nintendo logo shown during boot is correct, see `logo_valid()`.
+    logo_valid: bool,
     /// Upper case ASCII, 16 characters in DMG, zero filled if less than that.
     /// In CGB it's either 15 or 11 characters instead
     pub title: String,
     /// In DMG this is still part of the title bytes, in CGB it contains a flag for determining
     /// The relevant mode.
     pub cgb_flag: bool,
+    /// Whether the game only runs on a CGB (`0xC0`) rather than just having CGB enhancements (`0x80`).
+    /// Always `false` if `cgb_flag` isn't set, see `cgb_only()`.
+    cgb_exclusive: bool,
     /// Two character ASCII code, this one is for newer games only. Older games use the other header.
     pub new_licensee_code: u16,
     /// Specifies whether the game supports SGB functions.
@@ -29,6 +41,8 @@ pub struct CartridgeHeader {
     /// Specifies if this version of the game is supposed to be sold in Japan,
     /// or anywhere else. Only two values are defined.
     pub is_japanese: bool,
+    /// The raw destination code, `0x00` for Japan and `0x01` for anywhere else.
+    pub destination_code: u8,
     /// Specifies the games company/publisher code in range 00-FFh.
     /// A value of 0x33 signalizes that the New License Code in header bytes 0144-0145 is used instead.
     pub old_licensee_code: u8,
@@ -50,20 +64,39 @@ impl CartridgeHeader {
     pub fn new(rom: &[u8]) -> Self {
         let is_cgb_rom = read_cgb_flag(rom);
         CartridgeHeader {
+            logo_valid: rom[0x104..=0x133] == NINTENDO_LOGO[..],
             title: read_title(rom, is_cgb_rom),
             cgb_flag: is_cgb_rom,
+            cgb_exclusive: read_cgb_exclusive(rom),
             new_licensee_code: read_new_licensee(rom),
             sgb_flag: read_sgb_flag(rom),
             cartridge_type: read_cartridge_type(rom),
             rom_size: read_rom_size(rom),
             ram_size: read_ram_size(rom),
-            is_japanese: read_dest_code(rom),
+            is_japanese: read_dest_code(rom) == 0x00,
+            destination_code: read_dest_code(rom),
             old_licensee_code: read_old_licensee(rom),
             mask_rom_version_number: read_mask_rom_version(rom),
             header_checksum: read_header_checksum(rom),
             global_checksum: read_global_checksum(rom),
         }
     }
+
+    /// Whether the game refuses to run on anything but a CGB, as opposed to merely having
+    /// CGB enhancements. Such games shouldn't be run in `DMG` mode.
+    pub fn cgb_only(&self) -> bool {
+        self.cgb_exclusive
+    }
+
+    /// Whether the ROM contains the proper Nintendo logo.
+    ///
+    /// The boot ROM verifies the logo and locks up if it doesn't match, so ROMs with an invalid
+    /// logo (often bad dumps) can only run when the boot ROM is skipped.
+    /// Note that the CGB boot ROM only checks the first half of the logo, whereas we always
+    /// check all of it.
+    pub fn logo_valid(&self) -> bool {
+        self.logo_valid
+    }
 }
 
 fn read_title(rom: &[u8], cgb_mode: bool) -> String {
@@ -86,6 +119,10 @@ fn read_cgb_flag(rom: &[u8]) -> bool {
     cgb_flag == 0x80 || cgb_flag == 0xC0
 }
 
+fn read_cgb_exclusive(rom: &[u8]) -> bool {
+    rom[0x143] == 0xC0
+}
+
 fn read_new_licensee(rom: &[u8]) -> u16 {
     ((rom[0x144] as u16) << 8) | rom[0x145] as u16
 }
@@ -124,8 +161,8 @@ fn read_ram_size(rom: &[u8]) -> RamSizes {
     }
 }
 
-fn read_dest_code(rom: &[u8]) -> bool {
-    rom[0x14A] == 0x00
+fn read_dest_code(rom: &[u8]) -> u8 {
+    rom[0x14A]
 }
 
 fn read_old_licensee(rom: &[u8]) -> u8 {
@@ -231,16 +268,18 @@ back_to_enum! {
 
 impl CartridgeType {
     pub fn has_battery(&self) -> bool {
-        match *self as u8 {
-            0x3 | 0x6 | 0x9 | 0xD | 0xF | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFF => true,
-            _ => false,
-        }
+        matches!(*self as u8, 0x3 | 0x6 | 0x9 | 0xD | 0xF | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFF)
+    }
+
+    /// Whether the cartridge contains a real time clock (MBC3 with timer).
+    pub fn has_rtc(&self) -> bool {
+        matches!(*self as u8, 0xF | 0x10)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::hardware::cartridge::header::{read_cgb_flag, read_title, CartridgeHeader};
+    use crate::hardware::cartridge::header::{read_cgb_flag, read_title, CartridgeHeader, NINTENDO_LOGO};
 
     #[test]
     fn test_read_title() {
@@ -253,4 +292,33 @@ mod tests {
         }
         assert_eq!("Hello Wor", read_title(&test, false))
     }
+
+    #[test]
+    fn test_cgb_only() {
+        let mut rom = vec![0u8; 0x8000];
+        rom[0x143] = 0x80;
+        rom[0x14A] = 0x01;
+        let header = CartridgeHeader::new(&rom);
+        assert!(header.cgb_flag);
+        assert!(!header.cgb_only());
+        assert!(!header.is_japanese);
+        assert_eq!(header.destination_code, 0x01);
+
+        rom[0x143] = 0xC0;
+        let header = CartridgeHeader::new(&rom);
+        assert!(header.cgb_flag);
+        assert!(header.cgb_only());
+    }
+
+    #[test]
+    fn test_logo_valid() {
+        let mut rom = vec![0u8; 0x8000];
+        assert!(!CartridgeHeader::new(&rom).logo_valid());
+
+        rom[0x104..=0x133].copy_from_slice(&NINTENDO_LOGO);
+        assert!(CartridgeHeader::new(&rom).logo_valid());
+
+        rom[0x133] ^= 0x01;
+        assert!(!CartridgeHeader::new(&rom).logo_valid());
+    }
 }
@@ -1,14 +1,133 @@
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
+
 pub const EXTERNAL_RAM_SIZE: usize = 8192;
 pub const ROM_BANK_SIZE: usize = 16384;
+/// The size of a single switchable bank of the external RAM.
+pub const RAM_BANK_SIZE: usize = EXTERNAL_RAM_SIZE;
+/// MBC2 has 512x4 bits of built-in RAM, stored as one nibble per byte.
+pub const MBC2_RAM_SIZE: usize = 512;
+/// The size of the RTC state appended to the battery ram, see [RtcSave].
+pub const RTC_SAVE_SIZE: usize = 48;
+
+/// The kinds of memory bank controllers which are supported, without any of their state.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MbcKind {
+    /// No MBC, a plain 32KB ROM with optional RAM.
+    MBC0,
+    MBC1,
+    MBC2,
+    MBC3,
+    MBC5,
+}
+
+impl MbcKind {
+    /// The maximum amount of ROM banks the MBC can address.
+    pub const fn max_rom_banks(self) -> usize {
+        match self {
+            MbcKind::MBC0 => 2,
+            MbcKind::MBC1 => 128,
+            MbcKind::MBC2 => 16,
+            MbcKind::MBC3 => 128,
+            MbcKind::MBC5 => 512,
+        }
+    }
+
+    /// Whether a ROM of `rom_length` bytes fits within the banks the MBC can address.
+    pub fn supports_rom_size(self, rom_length: usize) -> bool {
+        rom_length / ROM_BANK_SIZE <= self.max_rom_banks()
+    }
+
+    pub fn create(self) -> MBC {
+        match self {
+            MbcKind::MBC0 => MBC::MBC0,
+            MbcKind::MBC1 => MBC::MBC1(MBC1State::default()),
+            MbcKind::MBC2 => MBC::MBC2(MBC2State::default()),
+            MbcKind::MBC3 => MBC::MBC3(MBC3State::default()),
+            MbcKind::MBC5 => MBC::MBC5(MBC5State::default()),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub enum MBC {
     MBC0,
     MBC1(MBC1State),
+    MBC2(MBC2State),
     MBC3(MBC3State),
     MBC5(MBC5State),
 }
 
+impl SaveState for MBC {
+    fn save_state(&self, state: &mut StateWriter) {
+        match self {
+            MBC::MBC0 => {}
+            MBC::MBC1(mbc) => {
+                state.write_bool(mbc.ram_enabled);
+                state.write_bool(mbc.banking_mode_select);
+                state.write_bytes(&[mbc.rom_bank, mbc.bank1, mbc.bank2]);
+            }
+            MBC::MBC2(mbc) => {
+                state.write_bool(mbc.ram_enabled);
+                state.write_u8(mbc.rom_bank);
+            }
+            MBC::MBC3(mbc) => {
+                let rtc = &mbc.rtc_registers;
+                state.write_bool(mbc.ram_enabled);
+                state.write_u8(mbc.ram_bank);
+                state.write_u16(mbc.rom_bank);
+                state.write_bytes(&[rtc.seconds, rtc.minutes, rtc.hours]);
+                state.write_bytes(&[rtc.day_counter_lower, rtc.day_counter_upper]);
+                state.write_bool(rtc.latched);
+                state.write_u64(mbc.rtc_timestamp);
+            }
+            MBC::MBC5(mbc) => {
+                state.write_bool(mbc.ram_enabled);
+                state.write_u16(mbc.rom_bank);
+                state.write_u8(mbc.ram_bank);
+            }
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        match self {
+            MBC::MBC0 => {}
+            MBC::MBC1(mbc) => {
+                mbc.ram_enabled = state.read_bool()?;
+                mbc.banking_mode_select = state.read_bool()?;
+                mbc.rom_bank = state.read_u8()?;
+                mbc.bank1 = state.read_u8()?;
+                mbc.bank2 = state.read_u8()?;
+            }
+            MBC::MBC2(mbc) => {
+                mbc.ram_enabled = state.read_bool()?;
+                mbc.rom_bank = state.read_u8()?;
+            }
+            MBC::MBC3(mbc) => {
+                mbc.ram_enabled = state.read_bool()?;
+                // Only RAM banks 0-7 and RTC registers 8-C are valid.
+                mbc.ram_bank = state.read_u8()?.min(0xC);
+                mbc.rom_bank = state.read_u16()?;
+                mbc.rtc_registers = RTCRegisters {
+                    seconds: state.read_u8()?,
+                    minutes: state.read_u8()?,
+                    hours: state.read_u8()?,
+                    day_counter_lower: state.read_u8()?,
+                    day_counter_upper: state.read_u8()?,
+                    latched: state.read_bool()?,
+                };
+                // Like a battery save the clock catches up on the time since the state was made.
+                mbc.rtc_timestamp = state.read_u64()?;
+            }
+            MBC::MBC5(mbc) => {
+                mbc.ram_enabled = state.read_bool()?;
+                mbc.rom_bank = state.read_u16()?;
+                mbc.ram_bank = state.read_u8()?;
+            }
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct MBC1State {
     pub ram_enabled: bool,
@@ -66,20 +185,64 @@ impl MBC1State {
             self.bank1 = 0x1;
         }
 
-        self.rom_bank = self.bank2 | self.bank1;
-        self.rom_bank %= effective_rom_banks as u8;
+        self.update_rom_bank(effective_rom_banks);
     }
 
     pub fn set_higher_rom_bank(&mut self, value: u8, effective_rom_banks: usize) {
         // Preemptively shift the bank 2 bits 5 bits to the left.
         // Done because every operation after this will have them as such anyway.
         self.bank2 = (value & 0x03) << 5;
-        self.rom_bank = self.bank2 | self.bank1;
-        self.rom_bank %= effective_rom_banks as u8;
+        self.update_rom_bank(effective_rom_banks);
     }
 
     pub fn set_bank_mode_select(&mut self, value: u8) {
-        self.banking_mode_select = value == 1
+        // Only the lowest bit is connected.
+        self.banking_mode_select = (value & 0x1) == 1
+    }
+
+    fn update_rom_bank(&mut self, effective_rom_banks: usize) {
+        // Done in usize, as `effective_rom_banks` could exceed u8::MAX for (mislabeled) huge ROMs.
+        self.rom_bank = ((self.bank2 | self.bank1) as usize % effective_rom_banks) as u8;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MBC2State {
+    pub ram_enabled: bool,
+    rom_bank: u8,
+}
+
+impl Default for MBC2State {
+    fn default() -> Self {
+        MBC2State {
+            ram_enabled: false,
+            rom_bank: 1,
+        }
+    }
+}
+
+impl MBC2State {
+    pub fn get_7fff_offset(&self) -> usize {
+        (self.rom_bank as usize) << 14
+    }
+
+    /// Both registers live in `0x0000..=0x3FFF`, bit 8 of the `address` selects between
+    /// enabling the RAM (reset) and the ROM bank (set).
+    pub fn write_register(&mut self, address: u16, value: u8, effective_rom_banks: usize) {
+        if address & 0x100 == 0 {
+            self.ram_enabled = value & 0xF == 0b0000_1010;
+        } else {
+            self.rom_bank = ((value & 0xF).max(1) as usize % effective_rom_banks) as u8;
+        }
+    }
+
+    /// The built-in RAM echoes throughout `0xA000..=0xBFFF`, with the upper nibble undefined.
+    pub fn read_external_ram(&self, ram: &[u8], address: u16) -> u8 {
+        0xF0 | ram[address as usize % MBC2_RAM_SIZE]
+    }
+
+    pub fn write_external_ram(&self, ram: &mut [u8], address: u16, value: u8) {
+        ram[address as usize % MBC2_RAM_SIZE] = value & 0xF;
     }
 }
 
@@ -88,7 +251,11 @@ pub struct MBC3State {
     pub ram_enabled: bool,
     pub ram_bank: u8,
     rom_bank: u16,
+    /// The latched clock, which is what the game reads.
     rtc_registers: RTCRegisters,
+    /// The unix timestamp at which the `rtc_registers` were last brought up to date, the live
+    /// clock is ahead of them by the time passed since (unless halted).
+    rtc_timestamp: u64,
 }
 
 impl Default for MBC3State {
@@ -98,6 +265,7 @@ impl Default for MBC3State {
             rom_bank: 1,
             ram_bank: 0,
             rtc_registers: RTCRegisters::default(),
+            rtc_timestamp: 0,
         }
     }
 }
@@ -134,7 +302,10 @@ impl MBC3State {
         self.rtc_registers.read_rtc(self.ram_bank)
     }
 
-    pub fn write_rtc_register(&mut self, value: u8) {
+    /// Write to the clock register selected by the RAM bank, at the unix timestamp `now`.
+    pub fn write_rtc_register(&mut self, value: u8, now: u64) {
+        // Writes go to the live clock, so catch up first lest the write is overwritten.
+        self.sync_rtc(now);
         self.rtc_registers.write_rtc(self.ram_bank, value)
     }
 
@@ -142,11 +313,143 @@ impl MBC3State {
         self.ram_bank = value & 0xF;
     }
 
-    pub fn write_latch_data(&mut self, value: u8) {
+    /// Carry the clock over from `other`, but not the latch sequence, which a restart starts over.
+    pub fn copy_rtc_from(&mut self, other: &MBC3State) {
+        self.rtc_registers = RTCRegisters {
+            latched: false,
+            ..other.rtc_registers
+        };
+        self.rtc_timestamp = other.rtc_timestamp;
+    }
+
+    /// Start a new clock at the unix timestamp `now`.
+    pub fn start_rtc(&mut self, now: u64) {
+        self.rtc_timestamp = now;
+    }
+
+    pub fn rtc_save(&self, now: u64) -> RtcSave {
+        RtcSave {
+            rtc: self.rtc_view(now),
+            timestamp: now,
+        }
+    }
+
+    /// Restore the RTC from the `save`, advancing it by the time passed between the save and `now`.
+    pub fn load_rtc(&mut self, save: &RtcSave, now: u64) {
+        let rtc = &save.rtc;
+        self.rtc_registers = RTCRegisters {
+            seconds: rtc.seconds,
+            minutes: rtc.minutes,
+            hours: rtc.hours,
+            day_counter_lower: rtc.days as u8,
+            day_counter_upper: rtc.day_counter_upper(),
+            latched: false,
+        };
+        self.rtc_timestamp = save.timestamp;
+        self.sync_rtc(now);
+    }
+
+    /// Latch the live clock into the registers on a `0x00` -> `0x01` write, at the unix timestamp `now`.
+    pub fn write_latch_data(&mut self, value: u8, now: u64) {
         if self.ram_enabled {
+            if self.rtc_registers.latched && value == 0x01 {
+                self.sync_rtc(now);
+            }
             self.rtc_registers.latch_rtc(value);
         }
     }
+
+    /// The live clock at the unix timestamp `now`, the latched registers plus the time passed since.
+    pub fn rtc_view(&self, now: u64) -> RtcView {
+        let mut rtc = self.rtc_registers;
+        rtc.advance(now.saturating_sub(self.rtc_timestamp));
+        RtcView {
+            seconds: rtc.seconds,
+            minutes: rtc.minutes,
+            hours: rtc.hours,
+            days: rtc.days(),
+            halted: rtc.clock_halt(),
+            day_carry: rtc.day_overflow(),
+        }
+    }
+
+    /// Bring the latched registers up to date with the live clock.
+    fn sync_rtc(&mut self, now: u64) {
+        self.rtc_registers.advance(now.saturating_sub(self.rtc_timestamp));
+        self.rtc_timestamp = now;
+    }
+}
+
+/// A read-only view of the MBC3 real time clock registers.
+#[derive(Debug, Default, Copy, Clone, PartialOrd, PartialEq)]
+pub struct RtcView {
+    pub seconds: u8,
+    pub minutes: u8,
+    pub hours: u8,
+    /// The 9 bit day counter.
+    pub days: u16,
+    /// Whether the clock is stopped (bit 6 of the upper day counter).
+    pub halted: bool,
+    /// Set once the day counter overflowed, until the game clears it.
+    pub day_carry: bool,
+}
+
+impl RtcView {
+    fn day_counter_upper(&self) -> u8 {
+        ((self.days >> 8) as u8 & 0x1) | ((self.halted as u8) << 6) | ((self.day_carry as u8) << 7)
+    }
+}
+
+/// The MBC3 real time clock as it's persisted alongside the battery ram, so that the clock
+/// keeps running while the emulator is closed.
+///
+/// Serialized in the 48 byte format shared by BGB and VBA-M: the five clock registers as
+/// little endian `u32`s, the same five again as the latched registers, and the unix timestamp
+/// as a little endian `u64`.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct RtcSave {
+    pub rtc: RtcView,
+    /// The unix timestamp (in seconds) at which the state was saved.
+    pub timestamp: u64,
+}
+
+impl RtcSave {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let rtc = &self.rtc;
+        let registers = [rtc.seconds, rtc.minutes, rtc.hours, rtc.days as u8, rtc.day_counter_upper()];
+        let mut result = Vec::with_capacity(RTC_SAVE_SIZE);
+
+        // We don't keep separate latched registers, so both sets are identical.
+        for &register in registers.iter().chain(registers.iter()) {
+            result.extend_from_slice(&(register as u32).to_le_bytes());
+        }
+        result.extend_from_slice(&self.timestamp.to_le_bytes());
+
+        result
+    }
+
+    /// Parse the `data` created by [to_bytes](#method.to_bytes), `None` if it's not exactly
+    /// `RTC_SAVE_SIZE` bytes.
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        if data.len() != RTC_SAVE_SIZE {
+            return None;
+        }
+        let register = |index: usize| data[index * 4];
+        let mut timestamp = [0; 8];
+        timestamp.copy_from_slice(&data[40..]);
+
+        Some(RtcSave {
+            rtc: RtcView {
+                seconds: register(0),
+                minutes: register(1),
+                hours: register(2),
+                days: ((register(4) as u16 & 0x1) << 8) | register(3) as u16,
+                halted: register(4) & 0x40 != 0,
+                day_carry: register(4) & 0x80 != 0,
+            },
+            timestamp: u64::from_le_bytes(timestamp),
+        })
+    }
 }
 
 //TODO: Check if we should use user system time to populate these values?
@@ -161,14 +464,28 @@ struct RTCRegisters {
 }
 
 impl RTCRegisters {
-    #[inline]
-    fn latch_rtc(&mut self, value: u8) {
-        if !self.latched && value != 0 {
-            //TODO: Implement actual timekeeping, look at this:
-            // https://web.archive.org/web/20150110235712/https://github.com/supergameherm/supergameherm/blob/df158781fcb85693b3d10fe2f40ea0010573fa5e/src/mbc.c#L378-430
-            // for reference.
+    /// Advance the clock by `seconds`, unless it's halted.
+    fn advance(&mut self, seconds: u64) {
+        if self.clock_halt() {
+            return;
+        }
+        let total = self.seconds as u64 + self.minutes as u64 * 60 + self.hours as u64 * 3600 + seconds;
+        let days = self.days() as u64 + total / 86400;
+
+        self.seconds = (total % 60) as u8;
+        self.minutes = (total / 60 % 60) as u8;
+        self.hours = (total / 3600 % 24) as u8;
+        self.day_counter_lower = days as u8;
+        self.day_counter_upper = (self.day_counter_upper & 0xFE) | ((days >> 8) & 0x1) as u8;
+
+        if days > 0x1FF {
+            self.day_counter_upper |= 0b1000_0000;
         }
+    }
 
+    /// Arm the latch on a `0x00` write, the following `0x01` write latches the live clock.
+    #[inline]
+    fn latch_rtc(&mut self, value: u8) {
         self.latched = value == 0;
     }
 
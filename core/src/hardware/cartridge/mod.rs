@@ -1,14 +1,31 @@
 use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use bitflags::_core::fmt::{Debug, Formatter};
+use num_integer::Integer;
 
+use crate::hardware::cartridge::bank_switches::{BankSwitch, BankSwitchLog};
 use crate::hardware::cartridge::header::CartridgeHeader;
-use crate::hardware::cartridge::mbc::{MBC1State, MBC3State, MBC5State, MBC, ROM_BANK_SIZE};
+use crate::hardware::cartridge::mbc::{
+    MbcKind, RtcSave, RtcView, MBC, MBC2_RAM_SIZE, RAM_BANK_SIZE, ROM_BANK_SIZE, RTC_SAVE_SIZE,
+};
 use crate::hardware::mmu::INVALID_READ;
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
 
+pub mod bank_switches;
 pub mod header;
 pub mod mbc;
 
+/// A summary of the cartridge's banking layout, and the banks currently mapped in.
+#[derive(Debug, Default, Copy, Clone, PartialOrd, PartialEq)]
+pub struct BankInfo {
+    pub rom_banks: usize,
+    pub ram_banks: usize,
+    /// The ROM bank mapped to `0x4000..=0x7FFF`.
+    pub current_rom_bank: usize,
+    pub current_ram_bank: usize,
+}
+
 pub struct Cartridge {
     header: CartridgeHeader,
     has_battery: bool,
@@ -18,24 +35,77 @@ pub struct Cartridge {
     ram_offset: usize,
     rom: Vec<u8>,
     ram: Vec<u8>,
+    /// Set whenever the external ram is written to, cleared by `take_ram_dirty()`
+    ram_dirty: bool,
+    /// Every write which changed the mapped banks, see `set_log_bank_switches()`
+    bank_switches: BankSwitchLog,
     mbc: MBC,
+    /// Returns the current unix timestamp for the RTC, replaceable for tests.
+    clock: fn() -> u64,
 }
 
 impl Cartridge {
+    /// Create a cartridge for the `rom`, where the `saved_ram` may contain the state of the RTC
+    /// as created by [battery_save](#method.battery_save).
+    ///
+    /// A restored RTC is advanced by the real time which passed since it was saved.
     pub fn new(rom: &[u8], saved_ram: Option<Vec<u8>>) -> Self {
+        Self::with_mbc_override(rom, saved_ram, None)
+    }
+
+    /// Create a cartridge like [new](#method.new), but use the `mbc_override` (if any) instead of
+    /// the MBC specified by the header.
+    ///
+    /// The override should support the size of the `rom`, see [MbcKind::supports_rom_size].
+    pub fn with_mbc_override(rom: &[u8], saved_ram: Option<Vec<u8>>, mbc_override: Option<MbcKind>) -> Self {
+        Self::restore(rom, saved_ram, mbc_override, unix_timestamp)
+    }
+
+    /// Create a cartridge like [with_mbc_override](#method.with_mbc_override), where the RTC
+    /// reads the current unix timestamp from the `clock`.
+    fn restore(rom: &[u8], saved_ram: Option<Vec<u8>>, mbc_override: Option<MbcKind>, clock: fn() -> u64) -> Self {
         let header = CartridgeHeader::new(rom);
-        let mbc = create_mbc(&header);
+        let mut mbc = mbc_override.unwrap_or_else(|| header_mbc_kind(&header)).create();
         let has_battery = header.cartridge_type.has_battery();
-        let mut ex_ram = vec![INVALID_READ; header.ram_size.to_usize()];
+        let mut rtc_save = None;
+        // MBC2 has its RAM built-in, regardless of what the header claims.
+        let ram_size = match mbc {
+            MBC::MBC2(_) => MBC2_RAM_SIZE,
+            _ => header.ram_size.to_usize(),
+        };
+        let mut ex_ram = vec![INVALID_READ; ram_size];
 
         if let Some(mut ram) = saved_ram {
-            if ram.len() < header.ram_size.to_usize() {
-                ram.extend_from_slice(&vec![INVALID_READ; header.ram_size.to_usize() - ram.len()]);
+            // The RAM itself is always a multiple of 512 bytes, so the RTC is easy to distinguish.
+            if header.cartridge_type.has_rtc() && ram.len() % MBC2_RAM_SIZE == RTC_SAVE_SIZE {
+                rtc_save = RtcSave::from_bytes(&ram.split_off(ram.len() - RTC_SAVE_SIZE));
+            }
+
+            if ram.len() < ram_size {
+                ram.extend_from_slice(&vec![INVALID_READ; ram_size - ram.len()]);
+            } else if matches!(mbc, MBC::MBC2(_)) {
+                ram.truncate(ram_size);
             }
             ex_ram = ram;
         }
 
+        if let MBC::MBC3(state) = &mut mbc {
+            let now = clock();
+            state.start_rtc(now);
+
+            if let Some(save) = rtc_save {
+                log::info!(
+                    "Restoring the RTC, advancing it by {} seconds",
+                    now.saturating_sub(save.timestamp)
+                );
+                state.load_rtc(&save, now);
+            }
+        }
+
         log::info!("Loading ROM with header: {:#X?}", header);
+        if !header.logo_valid() {
+            log::warn!("ROM has an invalid Nintendo logo, a boot ROM would lock up while verifying it");
+        }
 
         Cartridge {
             header,
@@ -46,10 +116,19 @@ impl Cartridge {
             effective_rom_banks: rom.len() / ROM_BANK_SIZE,
             rom: rom.to_vec(),
             ram: ex_ram,
+            ram_dirty: false,
+            bank_switches: BankSwitchLog::new(),
             mbc,
+            clock,
         }
     }
 
+    /// Returns whether the provided `cartridge_type` (byte `0x147` of the ROM header) is
+    /// supported, allowing a frontend to check compatibility before creating an emulator.
+    pub fn is_supported(cartridge_type: u8) -> bool {
+        mbc_kind_for_type(cartridge_type).is_some()
+    }
+
     pub fn read_0000_3fff(&self, address: u16) -> u8 {
         self.rom[(address & 0x3FFF) as usize | self.lower_bank_offset]
     }
@@ -64,6 +143,7 @@ impl Cartridge {
         match &self.mbc {
             MBC::MBC0 if self.ram.len() > 0 => self.ram[address],
             MBC::MBC1(state) if state.ram_enabled => self.ram[address | self.ram_offset],
+            MBC::MBC2(state) if state.ram_enabled => state.read_external_ram(&self.ram, address as u16),
             MBC::MBC3(state) if state.ram_enabled => match state.ram_bank {
                 0x0..=0x7 => self.ram[address + self.ram_offset],
                 0x8..=0xC => state.read_rtc_register(),
@@ -79,23 +159,73 @@ impl Cartridge {
         match &mut self.mbc {
             MBC::MBC0 if self.ram.len() > 0 => {
                 self.ram[address] = value;
+                self.ram_dirty = true;
             }
             MBC::MBC1(state) if state.ram_enabled => {
                 self.ram[address | self.ram_offset] = value;
+                self.ram_dirty = true;
+            }
+            MBC::MBC2(state) if state.ram_enabled => {
+                state.write_external_ram(&mut self.ram, address as u16, value);
+                self.ram_dirty = true;
             }
             MBC::MBC3(state) if state.ram_enabled => match state.ram_bank {
-                0x0..=0x7 => self.ram[address + self.ram_offset] = value,
-                0x8..=0xC => state.write_rtc_register(value),
+                0x0..=0x7 => {
+                    self.ram[address + self.ram_offset] = value;
+                    self.ram_dirty = true;
+                }
+                0x8..=0xC => {
+                    // The RTC is persisted alongside the ram, see `battery_save()`.
+                    state.write_rtc_register(value, (self.clock)());
+                    self.ram_dirty = true;
+                }
                 _ => unreachable!(),
             },
             MBC::MBC5(state) if state.ram_enabled => {
                 self.ram[address + self.ram_offset] = value;
+                self.ram_dirty = true;
             }
             _ => {}
         }
     }
 
+    /// Returns whether the external ram was written to since the last call.
+    pub fn take_ram_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.ram_dirty, false)
+    }
+
     pub fn write_byte(&mut self, address: u16, value: u8) {
+        if !self.bank_switches.is_enabled() {
+            return self.write_mbc_register(address, value);
+        }
+
+        let previous_offsets = (self.lower_bank_offset, self.higher_bank_offset, self.ram_offset);
+        self.write_mbc_register(address, value);
+
+        if (self.lower_bank_offset, self.higher_bank_offset, self.ram_offset) != previous_offsets {
+            self.bank_switches.record(BankSwitch {
+                address,
+                value,
+                rom_banks: self.rom_banks(),
+                rom_offsets: (self.lower_bank_offset, self.higher_bank_offset),
+                ram_bank: self.ram_bank(),
+                ram_offset: self.ram_offset,
+            });
+        }
+    }
+
+    /// Enable or disable recording every write to the MBC registers which changed the mapped
+    /// ROM or RAM banks, see `take_bank_switches()`. Disabling it discards any recorded switches.
+    pub fn set_log_bank_switches(&mut self, enabled: bool) {
+        self.bank_switches.set_enabled(enabled);
+    }
+
+    /// Returns all bank switches since the last call, oldest first.
+    pub fn take_bank_switches(&mut self) -> Vec<BankSwitch> {
+        self.bank_switches.take_switches()
+    }
+
+    fn write_mbc_register(&mut self, address: u16, value: u8) {
         match &mut self.mbc {
             MBC::MBC0 => {}
             MBC::MBC1(state) => match address {
@@ -117,6 +247,12 @@ impl Cartridge {
                 }
                 _ => {}
             },
+            MBC::MBC2(state) => {
+                if address <= 0x3FFF {
+                    state.write_register(address, value, self.effective_rom_banks);
+                    self.higher_bank_offset = state.get_7fff_offset();
+                }
+            }
             MBC::MBC3(state) => match address {
                 0x0000..=0x1FFF => state.enable_ram(value),
                 0x2000..=0x3FFF => {
@@ -125,10 +261,13 @@ impl Cartridge {
                 }
                 0x4000..=0x5FFF => {
                     state.write_ram_bank(value);
-                    self.ram_offset = state.get_ram_offset();
+                    // Selecting an RTC register only unmaps the RAM, the RAM bank stays as-is.
+                    if state.ram_bank <= 0x7 {
+                        self.ram_offset = state.get_ram_offset();
+                    }
                 }
                 0x6000..=0x7FFF => {
-                    state.write_latch_data(value);
+                    state.write_latch_data(value, (self.clock)());
                 }
                 _ => {}
             },
@@ -155,6 +294,67 @@ impl Cartridge {
         &self.header
     }
 
+    pub fn mbc(&self) -> &MBC {
+        &self.mbc
+    }
+
+    /// The ROM banks currently mapped to `0x0000..=0x3FFF` and `0x4000..=0x7FFF` respectively.
+    pub fn rom_banks(&self) -> (usize, usize) {
+        (self.lower_bank_offset / ROM_BANK_SIZE, self.higher_bank_offset / ROM_BANK_SIZE)
+    }
+
+    /// The external RAM bank currently mapped to `0xA000..=0xBFFF`.
+    pub fn ram_bank(&self) -> usize {
+        self.ram_offset / RAM_BANK_SIZE
+    }
+
+    /// Returns the amount of (effective) banks and the currently mapped ones.
+    ///
+    /// Cartridges with less than one full bank of RAM (2KB) are reported as having one RAM bank.
+    pub fn bank_info(&self) -> BankInfo {
+        BankInfo {
+            rom_banks: self.effective_rom_banks,
+            ram_banks: Integer::div_ceil(&self.ram.len(), &RAM_BANK_SIZE),
+            current_rom_bank: self.rom_banks().1,
+            current_ram_bank: self.ram_bank(),
+        }
+    }
+
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    /// The entire external ram, regardless of whether it's battery backed.
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    /// Returns the current values of the real time clock, if the cartridge has one.
+    ///
+    /// These are the values the game last latched, advanced by the real time passed since.
+    /// The game itself only sees the new values once it latches the clock again.
+    pub fn rtc_live(&self) -> Option<RtcView> {
+        match &self.mbc {
+            MBC::MBC3(state) if self.header.cartridge_type.has_rtc() => Some(state.rtc_view((self.clock)())),
+            _ => None,
+        }
+    }
+
+    /// Returns the RTC state to persist alongside the battery ram, if the cartridge has one.
+    pub fn rtc_state(&self) -> Option<RtcSave> {
+        match &self.mbc {
+            MBC::MBC3(state) if self.header.cartridge_type.has_rtc() => Some(state.rtc_save((self.clock)())),
+            _ => None,
+        }
+    }
+
+    /// Copy the RTC state from `other`, should both cartridges have one.
+    pub fn copy_rtc_from(&mut self, other: &Cartridge) {
+        if let (MBC::MBC3(state), MBC::MBC3(other_state)) = (&mut self.mbc, &other.mbc) {
+            state.copy_rtc_from(other_state);
+        }
+    }
+
     /// Retrieves the current battery ram state.
     /// Ideally this would be done via an MMAP so that the battery ram is always saved,
     /// even in the case of an emulator crash.
@@ -165,6 +365,50 @@ impl Cartridge {
             None
         }
     }
+
+    /// The [battery_ram](#method.battery_ram) with the [rtc_state](#method.rtc_state) appended
+    /// (if any), which is what should be persisted and passed back as the `saved_ram`.
+    pub fn battery_save(&self) -> Option<Vec<u8>> {
+        let mut result = self.battery_ram()?.to_vec();
+
+        if let Some(rtc) = self.rtc_state() {
+            result.extend_from_slice(&rtc.to_bytes());
+        }
+
+        Some(result)
+    }
+}
+
+impl SaveState for Cartridge {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.mbc.save_state(state);
+        state.write_usize(self.lower_bank_offset);
+        state.write_usize(self.higher_bank_offset);
+        state.write_usize(self.ram_offset);
+        state.write_cartridge_ram(&self.ram);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.mbc.load_state(state)?;
+        let lower_bank_offset = state.read_usize()?;
+        let higher_bank_offset = state.read_usize()?;
+        let ram_offset = state.read_usize()?;
+        let ram = state.read_cartridge_ram()?;
+
+        // Even though the ROM checksum matched, out of range offsets would panic on the next read.
+        if lower_bank_offset >= self.rom.len()
+            || higher_bank_offset >= self.rom.len()
+            || ram.len() != self.ram.len()
+            || (ram_offset != 0 && ram_offset >= ram.len())
+        {
+            return Err(StateError::Corrupt);
+        }
+        self.lower_bank_offset = lower_bank_offset;
+        self.higher_bank_offset = higher_bank_offset;
+        self.ram_offset = ram_offset;
+        self.ram.copy_from_slice(ram);
+        Ok(())
+    }
 }
 
 impl Debug for Cartridge {
@@ -173,17 +417,314 @@ impl Debug for Cartridge {
     }
 }
 
-fn create_mbc(header: &CartridgeHeader) -> MBC {
-    use MBC::*;
-    match header.cartridge_type as u8 {
-        0x0 => MBC0,
-        0x1..=0x3 => MBC1(MBC1State::default()),
-        0xF..=0x13 => MBC3(MBC3State::default()),
-        // 1C..=1E technically contain a rumble feature, to be implemented.
-        0x19..=0x1E => MBC5(MBC5State::default()),
-        _ => panic!(
+fn unix_timestamp() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs())
+}
+
+fn header_mbc_kind(header: &CartridgeHeader) -> MbcKind {
+    mbc_kind_for_type(header.cartridge_type as u8).unwrap_or_else(|| {
+        panic!(
             "Unsupported cartridge type, please add support for: {:#?}",
             header.cartridge_type
-        ),
+        )
+    })
+}
+
+/// The single source of truth for which cartridge types are supported, `None` if unsupported.
+fn mbc_kind_for_type(cartridge_type: u8) -> Option<MbcKind> {
+    use MbcKind::*;
+    match cartridge_type {
+        0x0 => Some(MBC0),
+        0x1..=0x3 => Some(MBC1),
+        0x5..=0x6 => Some(MBC2),
+        0xF..=0x13 => Some(MBC3),
+        // 1C..=1E technically contain a rumble feature, to be implemented.
+        0x19..=0x1E => Some(MBC5),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hardware::cartridge::bank_switches::BankSwitch;
+    use crate::hardware::cartridge::mbc::{RtcView, RAM_BANK_SIZE, RTC_SAVE_SIZE, ROM_BANK_SIZE};
+    use crate::hardware::cartridge::{BankInfo, Cartridge};
+
+    /// Create an MBC1 cartridge where the first byte of every bank contains its bank number.
+    fn mbc1_cartridge(rom_banks: usize) -> Cartridge {
+        let mut rom = vec![0; rom_banks * ROM_BANK_SIZE];
+        for bank in 0..rom_banks {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+        }
+        rom[0x147] = 0x01;
+
+        Cartridge::new(&rom, None)
+    }
+
+    #[test]
+    fn test_is_supported() {
+        assert!(Cartridge::is_supported(0x00));
+        assert!(Cartridge::is_supported(0x03));
+        assert!(Cartridge::is_supported(0x13));
+        assert!(Cartridge::is_supported(0x1E));
+        assert!(Cartridge::is_supported(0x06));
+        // MMM01
+        assert!(!Cartridge::is_supported(0x0B));
+        assert!(!Cartridge::is_supported(0xFC));
+    }
+
+    #[test]
+    fn test_bank_info() {
+        let mut rom = vec![0; 32 * ROM_BANK_SIZE];
+        // MBC5 + RAM, 32KB of RAM.
+        rom[0x147] = 0x1A;
+        rom[0x149] = 0x03;
+        let mut cartridge = Cartridge::new(&rom, None);
+        cartridge.write_byte(0x2000, 0x05);
+        cartridge.write_byte(0x4000, 0x02);
+
+        let expected = BankInfo {
+            rom_banks: 32,
+            ram_banks: 4,
+            current_rom_bank: 5,
+            current_ram_bank: 2,
+        };
+        assert_eq!(cartridge.bank_info(), expected);
+        // Writes beyond the ROM size should wrap around.
+        cartridge.write_byte(0x2000, 0x25);
+        assert_eq!(cartridge.bank_info().current_rom_bank, 5);
+    }
+
+    #[test]
+    fn test_bank_switch_log() {
+        let mut rom = vec![0; 4 * ROM_BANK_SIZE];
+        // MBC3 + Timer + RAM + Battery, 64KB ROM and 32KB RAM.
+        rom[0x147] = 0x10;
+        rom[0x148] = 0x01;
+        rom[0x149] = 0x03;
+        let mut cartridge = Cartridge::restore(&rom, None, None, || 1000);
+        cartridge.write_byte(0x2000, 0x02);
+        assert!(cartridge.take_bank_switches().is_empty());
+
+        cartridge.set_log_bank_switches(true);
+        cartridge.write_byte(0x2000, 0x03);
+        // Neither re-selecting the same bank, the RTC latch, nor selecting an RTC register
+        // changes the mapped banks.
+        cartridge.write_byte(0x2000, 0x03);
+        cartridge.write_byte(0x6000, 0x00);
+        cartridge.write_byte(0x6000, 0x01);
+        cartridge.write_byte(0x4000, 0x08);
+        cartridge.write_byte(0x4000, 0x02);
+
+        let expected = [
+            BankSwitch {
+                address: 0x2000,
+                value: 0x03,
+                rom_banks: (0, 3),
+                rom_offsets: (0, 3 * ROM_BANK_SIZE),
+                ram_bank: 0,
+                ram_offset: 0,
+            },
+            BankSwitch {
+                address: 0x4000,
+                value: 0x02,
+                rom_banks: (0, 3),
+                rom_offsets: (0, 3 * ROM_BANK_SIZE),
+                ram_bank: 2,
+                ram_offset: 2 * RAM_BANK_SIZE,
+            },
+        ];
+        assert_eq!(cartridge.take_bank_switches(), expected);
+        assert!(cartridge.take_bank_switches().is_empty());
+    }
+
+    #[test]
+    fn test_mbc2() {
+        let mut rom = vec![0; 16 * ROM_BANK_SIZE];
+        for bank in 0..16 {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+        }
+        // MBC2 + Battery, which doesn't declare its built-in RAM.
+        rom[0x147] = 0x06;
+        let mut cartridge = Cartridge::new(&rom, None);
+
+        // Bit 8 set selects the ROM bank instead, bank 0 is treated as 1.
+        assert_eq!(cartridge.read_4000_7fff(0x4000), 1);
+        cartridge.write_byte(0x0100, 0x0A);
+        assert_eq!(cartridge.read_4000_7fff(0x4000), 10);
+        cartridge.write_external_ram(0xA000, 0x03);
+        cartridge.write_byte(0x2100, 0x37);
+        assert_eq!(cartridge.read_4000_7fff(0x4000), 7);
+        cartridge.write_byte(0x3FFF, 0x00);
+        assert_eq!(cartridge.read_4000_7fff(0x4000), 1);
+
+        cartridge.write_byte(0x0000, 0x1A);
+        assert_eq!(cartridge.read_external_ram(0xA000), 0xFF);
+        cartridge.write_external_ram(0xA001, 0x35);
+        assert_eq!(cartridge.read_external_ram(0xA001), 0xF5);
+        // The 512 nibbles echo throughout the entire RAM region.
+        assert_eq!(cartridge.read_external_ram(0xA201), 0xF5);
+        assert_eq!(cartridge.read_external_ram(0xBE01), 0xF5);
+
+        let battery_ram = cartridge.battery_ram().unwrap();
+        assert_eq!(battery_ram.len(), 512);
+        assert_eq!(battery_ram[1], 0x05);
+        assert_eq!(Cartridge::new(&rom, Some(vec![0; 8192])).battery_ram().unwrap().len(), 512);
+    }
+
+    #[test]
+    fn test_rtc_live() {
+        let mut rom = vec![0; 2 * ROM_BANK_SIZE];
+        // MBC3 + Timer + RAM + Battery
+        rom[0x147] = 0x10;
+        let mut cartridge = Cartridge::restore(&rom, None, None, || 1000);
+        cartridge.write_byte(0x0000, 0x0A);
+        for &(register, value) in [(0x8, 30), (0x9, 15), (0xA, 7), (0xB, 0x2C), (0xC, 0xC1)].iter() {
+            cartridge.write_byte(0x4000, register);
+            cartridge.write_external_ram(0xA000, value);
+        }
+        // The RTC is part of the battery save, so the frontend should be told to save it.
+        assert!(cartridge.take_ram_dirty());
+
+        let expected = RtcView {
+            seconds: 30,
+            minutes: 15,
+            hours: 7,
+            days: 0x12C,
+            halted: true,
+            day_carry: true,
+        };
+        assert_eq!(cartridge.rtc_live(), Some(expected));
+
+        // Resume the clock, it should now run in real time.
+        cartridge.write_external_ram(0xA000, 0x01);
+        cartridge.clock = || 1000 + 3725;
+        let expected = RtcView {
+            seconds: 35,
+            minutes: 17,
+            hours: 8,
+            halted: false,
+            day_carry: false,
+            ..expected
+        };
+        assert_eq!(cartridge.rtc_live(), Some(expected));
+        // The game only sees the new time once it latches the clock.
+        cartridge.write_byte(0x4000, 0x8);
+        assert_eq!(cartridge.read_external_ram(0xA000), 30);
+        cartridge.write_byte(0x6000, 0x00);
+        cartridge.write_byte(0x6000, 0x01);
+        assert_eq!(cartridge.read_external_ram(0xA000), 35);
+        assert_eq!(cartridge.rtc_live(), Some(expected));
+
+        // MBC3 without a timer.
+        rom[0x147] = 0x11;
+        assert_eq!(Cartridge::new(&rom, None).rtc_live(), None);
+    }
+
+    #[test]
+    fn test_rtc_persistence() {
+        const SAVE_TIME: u64 = 1_600_000_000;
+        let mut rom = vec![0; 2 * ROM_BANK_SIZE];
+        // MBC3 + Timer + RAM + Battery, 8KB of RAM.
+        rom[0x147] = 0x10;
+        rom[0x149] = 0x02;
+        let mut cartridge = Cartridge::restore(&rom, None, None, || SAVE_TIME);
+        cartridge.write_byte(0x0000, 0x0A);
+        cartridge.write_external_ram(0xA000, 0x42);
+        for &(register, value) in [(0x8, 10), (0x9, 30), (0xA, 23), (0xB, 0xFF), (0xC, 0x01)].iter() {
+            cartridge.write_byte(0x4000, register);
+            cartridge.write_external_ram(0xA000, value);
+        }
+
+        // Simulate 1 hour, 2 minutes and 5 seconds passing while the emulator was closed.
+        let rtc = cartridge.rtc_state().unwrap();
+        let mut saved_ram = cartridge.battery_ram().unwrap().to_vec();
+        saved_ram.extend_from_slice(&rtc.to_bytes());
+        assert_eq!(cartridge.battery_save().unwrap().len(), 8192 + RTC_SAVE_SIZE);
+
+        let reloaded = Cartridge::restore(&rom, Some(saved_ram), None, || SAVE_TIME + 3725);
+        assert_eq!(reloaded.ram().len(), 8192);
+        assert_eq!(reloaded.ram()[0], 0x42);
+        // The day counter overflowed.
+        let expected = RtcView {
+            seconds: 15,
+            minutes: 32,
+            hours: 0,
+            days: 0,
+            halted: false,
+            day_carry: true,
+        };
+        assert_eq!(reloaded.rtc_live(), Some(expected));
+
+        // A halted clock shouldn't advance.
+        let mut rtc = cartridge.rtc_state().unwrap();
+        rtc.rtc.halted = true;
+        let mut saved_ram = vec![0; 8192];
+        saved_ram.extend_from_slice(&rtc.to_bytes());
+        let expected = RtcView {
+            seconds: 10,
+            minutes: 30,
+            hours: 23,
+            days: 0x1FF,
+            halted: true,
+            day_carry: false,
+        };
+        assert_eq!(Cartridge::restore(&rom, Some(saved_ram), None, || SAVE_TIME + 3725).rtc_live(), Some(expected));
+    }
+
+    #[test]
+    fn test_mbc1_bank2_mode_0() {
+        // 2MB
+        let mut cartridge = mbc1_cartridge(128);
+        cartridge.write_byte(0x4000, 0x02);
+        cartridge.write_byte(0x2000, 0x00);
+
+        // In mode 0 bank 2 should only apply to the 0x4000-0x7FFF region.
+        assert_eq!(cartridge.read_0000_3fff(0x0000), 0x00);
+        assert_eq!(cartridge.read_4000_7fff(0x4000), 0x41);
+        // A value of 0x20 for bank 1 is treated as 0x00, and thus becomes 0x01.
+        cartridge.write_byte(0x2000, 0x20);
+        assert_eq!(cartridge.read_4000_7fff(0x4000), 0x41);
+    }
+
+    #[test]
+    fn test_mbc1_bank2_mode_1() {
+        let mut cartridge = mbc1_cartridge(128);
+        cartridge.write_byte(0x4000, 0x03);
+        cartridge.write_byte(0x2000, 0x04);
+        cartridge.write_byte(0x6000, 0x01);
+
+        assert_eq!(cartridge.read_0000_3fff(0x0000), 0x60);
+        assert_eq!(cartridge.read_4000_7fff(0x4000), 0x64);
+        // Switching back to mode 0 should restore bank 0.
+        cartridge.write_byte(0x6000, 0x00);
+        assert_eq!(cartridge.read_0000_3fff(0x0000), 0x00);
+        // Only the lowest bit of the mode select is connected.
+        cartridge.write_byte(0x6000, 0xFF);
+        assert_eq!(cartridge.read_0000_3fff(0x0000), 0x60);
+    }
+
+    #[test]
+    fn test_mbc1_bank2_masking() {
+        // 1MB, only the lowest bit of bank 2 is connected.
+        let mut cartridge = mbc1_cartridge(64);
+        cartridge.write_byte(0x6000, 0x01);
+        cartridge.write_byte(0x4000, 0x03);
+        assert_eq!(cartridge.read_0000_3fff(0x0000), 0x20);
+        assert_eq!(cartridge.read_4000_7fff(0x4000), 0x21);
+
+        // 512KB, bank 2 should be ignored entirely.
+        let mut cartridge = mbc1_cartridge(32);
+        cartridge.write_byte(0x6000, 0x01);
+        cartridge.write_byte(0x4000, 0x03);
+        cartridge.write_byte(0x2000, 0x1F);
+        assert_eq!(cartridge.read_0000_3fff(0x0000), 0x00);
+        assert_eq!(cartridge.read_4000_7fff(0x4000), 0x1F);
+
+        // 4MB, larger than any real MBC1 cart, but shouldn't overflow the bank arithmetic.
+        let mut cartridge = mbc1_cartridge(256);
+        cartridge.write_byte(0x4000, 0x03);
+        cartridge.write_byte(0x2000, 0x1F);
+        assert_eq!(cartridge.read_4000_7fff(0x4000), 0x7F);
     }
 }
@@ -9,10 +9,7 @@ impl<M: MemoryMapper> CPU<M> {
     /// Add 4 cycles to the internal counter
     pub fn add_cycles(&mut self) {
         self.cycles_performed += 4;
-
-        if self.mmu.do_m_cycle() {
-            self.had_vblank = true;
-        }
+        self.vblank_in_step |= self.mmu.do_m_cycle();
     }
 
     /// Read the next opcode, advance the PC, and call the execute function for
@@ -43,6 +40,23 @@ impl<M: MemoryMapper> CPU<M> {
         opcode
     }
 
+    /// Like `get_next_opcode()`, but stops right after launching the interrupt routine.
+    ///
+    /// # Returns
+    ///
+    /// The opcode, or `None` if an interrupt was dispatched instead.
+    pub fn fetch_opcode_or_dispatch(&mut self) -> Option<u8> {
+        let opcode = self.read_byte_cycle(self.registers.pc);
+
+        if self.mmu.interrupts().should_check && self.handle_interrupts() {
+            return None;
+        }
+
+        self.registers.pc = self.registers.pc.wrapping_add(1);
+
+        Some(opcode)
+    }
+
     pub fn handle_interrupts(&mut self) -> bool {
         if self.mmu.interrupts().interrupts_pending() {
             if !self.ime {
@@ -103,18 +117,6 @@ impl<M: MemoryMapper> CPU<M> {
         self.write_byte_cycle(address.wrapping_add(1), (value >> 8) as u8);
     }
 
-    /// Temporary hack to see if we rendered `VBlank` this execution cycle.
-    ///
-    /// Resets `VBlank` to `false` if it was `true`.
-    pub fn added_vblank(&mut self) -> bool {
-        if self.had_vblank {
-            self.had_vblank = false;
-            true
-        } else {
-            false
-        }
-    }
-
     pub fn log_instr(&mut self) {
         let ie = self.mmu.read_byte(INTERRUPTS_ENABLE);
         let if_flag = self.mmu.read_byte(INTERRUPTS_FLAG);
@@ -6,11 +6,16 @@ use std::fmt::*;
 use registers::Reg8::A;
 use registers::{Reg16, Registers};
 
+use crate::gb_emu::DmgRevision;
 use crate::hardware::cpu::execute::JumpModifier;
 use crate::hardware::cpu::execute::JumpModifier::Always;
+use crate::hardware::cpu::overrides::{OpcodeHandler, OpcodeOverrides};
+use crate::hardware::cpu::power_events::{PowerEventKind, PowerEventLog};
+use crate::hardware::cpu::trace::InstructionTrace;
 use crate::hardware::cpu::traits::{SetU16, SetU8, ToU16, ToU8};
 use crate::hardware::mmu::*;
 use crate::io::interrupts::InterruptFlags;
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
 
 #[cfg(test)]
 mod tests;
@@ -19,19 +24,68 @@ mod alu;
 mod execute;
 mod fetch;
 mod instructions;
+pub mod overrides;
+pub mod power_events;
 pub mod registers;
+pub mod trace;
 mod traits;
 
+/// An unrecoverable state the CPU ended up in.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CpuFault {
+    /// One of the unused opcodes was executed, which locks up the CPU on real hardware.
+    IllegalOpcode { pc: u16, opcode: u8 },
+}
+
+/// Why the CPU currently isn't executing instructions, see `CPU::halt_reason()`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum HaltReason {
+    /// Halted until one of the `enabled` interrupts is requested. Should `ime` be reset the CPU
+    /// will resume without servicing the interrupt.
+    WaitingForInterrupt { enabled: InterruptFlags, ime: bool },
+    /// Halted without any interrupts enabled in `IE`, which on real hardware hangs forever.
+    NoInterruptsEnabled,
+    /// In STOP mode, waiting for a joypad button to be pressed.
+    Stopped,
+}
+
+/// What a single step of the CPU did, see `CPU::step_instruction()`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StepKind {
+    /// The instruction with the given opcode was executed, for `0xCB` prefixed instructions
+    /// this is just `0xCB`.
+    Instruction(u8),
+    /// An interrupt was serviced, after which `PC` is at the interrupt's vector.
+    InterruptDispatch,
+    /// Skipped ahead to the next event while halted, without servicing an interrupt.
+    Halted,
+    /// Nothing happened, as we're still waiting for a joypad press in STOP mode.
+    Stopped,
+    /// The CPU has locked up, so only the rest of the system advanced.
+    Locked,
+}
+
 #[derive(Debug)]
 pub struct CPU<M: MemoryMapper> {
     pub cycles_performed: u64,
     pub ime: bool,
+    /// Set by EI, as `ime` is only set once the next instruction has been fetched.
+    ime_scheduled: bool,
     pub halted: bool,
+    /// Set while in STOP mode, during which the entire system (including the PPU) is frozen
+    /// until a selected joypad button is pressed.
+    pub stopped: bool,
+    /// Set once the CPU locked up, after which no more instructions are executed.
+    pub fault: Option<CpuFault>,
     pub mmu: M,
     opcode: u8,
     registers: Registers,
-    /// Temporary hack to determine when VBLANK occurred for rendering.
-    had_vblank: bool,
+    trace: InstructionTrace,
+    opcode_overrides: OpcodeOverrides<M>,
+    power_events: PowerEventLog,
+    /// Whether V-blank occurred in any of the M-cycles of the current step, as reported by the
+    /// `MemoryMapper`. Always `false` in between steps.
+    vblank_in_step: bool,
 }
 
 impl<M: MemoryMapper> CPU<M> {
@@ -41,20 +95,21 @@ impl<M: MemoryMapper> CPU<M> {
             registers: Registers::new(),
             mmu,
             halted: false,
+            stopped: false,
+            fault: None,
             cycles_performed: 0,
             ime: false,
-            had_vblank: false,
+            ime_scheduled: false,
+            trace: InstructionTrace::new(),
+            opcode_overrides: OpcodeOverrides::new(),
+            power_events: PowerEventLog::new(),
+            vblank_in_step: false,
         };
 
         if result.mmu.boot_rom_finished() {
-            result.registers.pc = 0x100;
             // Set the registers to the state they would
             // have if we used the bootrom, missing MEM values
-            result.registers.set_af(0x01B0);
-            result.registers.set_bc(0x0013);
-            result.registers.set_de(0x00D8);
-            result.registers.set_hl(0x014D);
-            result.registers.sp = 0xFFFE;
+            result.registers = DmgRevision::DMG.post_boot_registers();
         }
         if result.mmu.get_mode().is_cgb() {
             // 0x11 indicates CGB hardware for games.
@@ -66,25 +121,173 @@ impl<M: MemoryMapper> CPU<M> {
         result
     }
 
+    /// Override the default post-bootrom register state with `registers`.
+    ///
+    /// Only honoured if there is no bootrom to run, as it would overwrite the registers anyway.
+    pub fn set_initial_registers(&mut self, registers: Registers) {
+        if self.mmu.boot_rom_finished() {
+            self.registers = registers;
+        } else {
+            log::warn!("Ignoring initial registers, as a bootrom is going to be executed");
+        }
+    }
+
+    pub fn registers(&self) -> &Registers {
+        &self.registers
+    }
+
+    /// Mutable access to the registers, mainly meant for opcode overrides.
+    pub fn registers_mut(&mut self) -> &mut Registers {
+        &mut self.registers
+    }
+
+    /// Execute `handler` instead of the normal implementation whenever `opcode` is executed,
+    /// replacing any earlier override for the same opcode.
+    ///
+    /// The handler is called after the opcode was fetched, so `PC` already points past it. It's
+    /// responsible for reading any operands and for the cycle accounting (see `add_cycles()`),
+    /// otherwise the rest of the system won't advance. CB-prefixed instructions can only be
+    /// overridden as a whole, by overriding `0xCB`.
+    pub fn override_opcode(&mut self, opcode: u8, handler: OpcodeHandler<M>) {
+        self.opcode_overrides.insert(opcode, handler);
+    }
+
+    /// Restore the normal implementation of `opcode`.
+    pub fn remove_opcode_override(&mut self, opcode: u8) {
+        self.opcode_overrides.remove(opcode);
+    }
+
+    /// Returns why the CPU is currently halted or stopped, or `None` if it's running.
+    ///
+    /// Note that the halt bug never leaves the CPU halted, as it executes the next instruction
+    /// immediately.
+    pub fn halt_reason(&self) -> Option<HaltReason> {
+        if self.stopped {
+            return Some(HaltReason::Stopped);
+        } else if !self.halted {
+            return None;
+        }
+        let enabled = self.mmu.interrupts().interrupt_enable;
+
+        if enabled.is_empty() {
+            Some(HaltReason::NoInterruptsEnabled)
+        } else {
+            Some(HaltReason::WaitingForInterrupt { enabled, ime: self.ime })
+        }
+    }
+
+    /// The log of HALT/STOP transitions, which only records once enabled, see [`PowerEventLog`].
+    pub fn power_events_mut(&mut self) -> &mut PowerEventLog {
+        &mut self.power_events
+    }
+
+    /// The most recently executed instructions, see [`InstructionTrace`].
+    pub fn trace(&self) -> &InstructionTrace {
+        &self.trace
+    }
+
     /// Fetches the next instruction and executes it as well.
-    pub fn step_cycle(&mut self) {
-        if self.halted {
+    ///
+    /// # Returns
+    ///
+    /// What this step did, and whether V-blank occurred during the execution of this instruction.
+    pub fn step_cycle(&mut self) -> (StepKind, bool) {
+        self.step(false)
+    }
+
+    /// Like `step_cycle()`, but an interrupt dispatch is performed as its own step instead of
+    /// being merged with the first instruction of the interrupt handler.
+    ///
+    /// The timing is identical to `step_cycle()`, as the dispatch is only split off after the
+    /// (aborted) opcode fetch.
+    ///
+    /// # Returns
+    ///
+    /// What this step did, and whether V-blank occurred during it.
+    pub fn step_instruction(&mut self) -> (StepKind, bool) {
+        self.step(true)
+    }
+
+    #[inline(always)]
+    fn step(&mut self, split_dispatch: bool) -> (StepKind, bool) {
+        if self.stopped {
+            // Nothing advances in STOP mode, so the framebuffer stays as-is and the PPU's scheduled
+            // events remain valid for when we resume.
+            if !self.mmu.joypad_pressed() {
+                return (StepKind::Stopped, false);
+            }
+            self.stopped = false;
+            self.power_events
+                .record(PowerEventKind::StopExited, self.registers.pc, self.mmu.current_cycle());
+        }
+
+        let kind = if self.halted {
             // We know only interrupts can affect halt, and all interrupts are on the scheduler.
             // It's therefore safe to just skip to the next event until an interrupt occurs.
-            if self.mmu.execute_next_event() {
-                self.had_vblank = true;
-            }
+            self.vblank_in_step |= self.mmu.execute_next_event();
+            let pc = self.registers.pc;
             // Since we don't call for an opcode we'll have to handle interrupts here.
-            self.handle_interrupts();
-            return;
-        }
+            let dispatched = self.handle_interrupts();
 
-        self.opcode = self.get_next_opcode();
+            if !self.halted {
+                self.power_events
+                    .record(PowerEventKind::HaltExited, pc, self.mmu.current_cycle());
+            }
 
-        #[cfg(feature = "cpu-logging")]
-        self.log_instr();
+            if dispatched {
+                StepKind::InterruptDispatch
+            } else {
+                StepKind::Halted
+            }
+        } else if self.fault.is_some() {
+            // A locked up CPU will never execute anything again, but the rest of the system keeps going.
+            self.vblank_in_step |= self.mmu.execute_next_event();
+            StepKind::Locked
+        } else {
+            let opcode = if split_dispatch {
+                self.fetch_opcode_or_dispatch()
+            } else {
+                Some(self.get_next_opcode())
+            };
+
+            match opcode {
+                Some(opcode) => {
+                    self.opcode = opcode;
+                    // The instruction after EI can't be interrupted yet, as it was fetched before
+                    // `ime` was set.
+                    self.ime |= std::mem::take(&mut self.ime_scheduled);
+                    self.trace.record(self.registers.pc.wrapping_sub(1), self.opcode);
+
+                    #[cfg(feature = "cpu-logging")]
+                    self.log_instr();
+
+                    if self.opcode_overrides.is_empty() || !self.execute_override(self.opcode) {
+                        self.execute(self.opcode);
+                    }
+
+                    StepKind::Instruction(opcode)
+                }
+                None => StepKind::InterruptDispatch,
+            }
+        };
 
-        self.execute(self.opcode);
+        (kind, std::mem::take(&mut self.vblank_in_step))
+    }
+
+    /// Run the registered override for `opcode`, if any.
+    ///
+    /// # Returns
+    ///
+    /// Whether an override was executed.
+    fn execute_override(&mut self, opcode: u8) -> bool {
+        match self.opcode_overrides.take(opcode) {
+            Some(mut handler) => {
+                handler(self);
+                self.opcode_overrides.restore(opcode, handler);
+                true
+            }
+            None => false,
+        }
     }
 
     /// The routine to be used whenever any kind of `interrupt` is called.
@@ -95,6 +298,7 @@ impl<M: MemoryMapper> CPU<M> {
         self.add_cycles();
 
         self.ime = false;
+        self.ime_scheduled = false;
         self.halted = false;
 
         // Stack push, MSB can cancel interrupt if it overwrites IE
@@ -253,7 +457,7 @@ impl<M: MemoryMapper> CPU<M> {
             log::warn!("Starting speed switch");
 
             for _ in 0..32768 {
-                self.mmu.do_m_cycle();
+                self.vblank_in_step |= self.mmu.do_m_cycle();
             }
 
             log::info!(
@@ -265,7 +469,15 @@ impl<M: MemoryMapper> CPU<M> {
                 }
             );
         } else {
-            unimplemented!("STOP called, implement!");
+            // STOP always resets DIV, but won't be entered if a button is already held.
+            self.mmu.write_byte(DIVIDER_REGISTER, 0);
+            self.stopped = !self.mmu.joypad_pressed();
+
+            if self.stopped {
+                let pc = self.registers.pc.wrapping_sub(1);
+                self.power_events
+                    .record(PowerEventKind::StopEntered, pc, self.mmu.current_cycle());
+            }
         }
     }
 
@@ -363,6 +575,9 @@ impl<M: MemoryMapper> CPU<M> {
     fn halt(&mut self) {
         // Halt bug
         if !self.ime && self.mmu.interrupts().interrupts_pending() {
+            let pc = self.registers.pc.wrapping_sub(1);
+            self.power_events
+                .record(PowerEventKind::HaltBug, pc, self.mmu.current_cycle());
             // We execute the next opcode immediately without incrementing PC.
             // No need to check for interrupts since ime is disabled anyway.
             self.opcode = self.get_instr_u8();
@@ -373,6 +588,9 @@ impl<M: MemoryMapper> CPU<M> {
             // Normal halt
             self.add_cycles();
             self.halted = true;
+            let pc = self.registers.pc.wrapping_sub(1);
+            self.power_events
+                .record(PowerEventKind::HaltEntered, pc, self.mmu.current_cycle());
         }
     }
 
@@ -614,10 +832,12 @@ impl<M: MemoryMapper> CPU<M> {
     }
 
     /// There are a few instructions in the GameBoy's instruction set which are not used.
-    /// For now we'll panic, but it may be that some games call them erroneously, so consider
-    /// just returning instead.
+    /// On real hardware these lock up the CPU, so we do the same and record the fault
+    /// so that a `CrashReport` can be made.
     fn unknown(&mut self) {
-        panic!("Unknown function was called, opcode: {}", self.opcode)
+        let pc = self.registers.pc.wrapping_sub(1);
+        log::error!("Unknown function was called, opcode: {:#04X} at PC: {:#06X}", self.opcode, pc);
+        self.fault = Some(CpuFault::IllegalOpcode { pc, opcode: self.opcode });
     }
 
     /// Return from subroutine and enable interrupts.
@@ -642,6 +862,8 @@ impl<M: MemoryMapper> CPU<M> {
 
         self.registers.set_zf(false);
         self.registers.set_n(false);
+        // The flags are based on the unsigned immediate, which the masks of the sign extended
+        // value preserve, even for negative offsets.
         self.registers.set_h((self.registers.sp & 0xF) + (value & 0xF) > 0xF);
         self.registers
             .set_cf((self.registers.sp & 0xFF) + (value & 0xFF) > 0xFF);
@@ -695,13 +917,8 @@ impl<M: MemoryMapper> CPU<M> {
     /// Enable Interrupts by setting the IME flag.
     /// The flag is only set after the instruction following EI.
     fn ei(&mut self) {
-        // First execute the next instruction, since we're setting IME here it's reasonable to
-        // assume that IME is false, so that no interrupt will be fired.
-        self.opcode = self.get_next_opcode();
-        // Set ime to true after we have delayed one cycle.
-        self.ime = true;
-        // Execute the opcode, since we have no way of only retrieving an opcode this'll have to do.
-        self.execute(self.opcode);
+        // IME is set with a delay of one instruction, see `step()`.
+        self.ime_scheduled = true;
     }
 
     /*
@@ -882,3 +1099,55 @@ impl<M: MemoryMapper> CPU<M> {
         self.set_u8_value(target, value & !bit_mask);
     }
 }
+
+impl<M: MemoryMapper + SaveState> SaveState for CPU<M> {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.registers.af());
+        state.write_u16(self.registers.bc());
+        state.write_u16(self.registers.de());
+        state.write_u16(self.registers.hl());
+        state.write_u16(self.registers.sp);
+        state.write_u16(self.registers.pc);
+        state.write_u8(self.opcode);
+        state.write_u64(self.cycles_performed);
+        state.write_bool(self.ime);
+        state.write_bool(self.ime_scheduled);
+        state.write_bool(self.halted);
+        state.write_bool(self.stopped);
+        match self.fault {
+            Some(CpuFault::IllegalOpcode { pc, opcode }) => {
+                state.write_bool(true);
+                state.write_u16(pc);
+                state.write_u8(opcode);
+            }
+            None => state.write_bool(false),
+        }
+
+        self.mmu.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> std::result::Result<(), StateError> {
+        self.registers.set_af(state.read_u16()?);
+        self.registers.set_bc(state.read_u16()?);
+        self.registers.set_de(state.read_u16()?);
+        self.registers.set_hl(state.read_u16()?);
+        self.registers.sp = state.read_u16()?;
+        self.registers.pc = state.read_u16()?;
+        self.opcode = state.read_u8()?;
+        self.cycles_performed = state.read_u64()?;
+        self.ime = state.read_bool()?;
+        self.ime_scheduled = state.read_bool()?;
+        self.halted = state.read_bool()?;
+        self.stopped = state.read_bool()?;
+        self.fault = if state.read_bool()? {
+            Some(CpuFault::IllegalOpcode {
+                pc: state.read_u16()?,
+                opcode: state.read_u8()?,
+            })
+        } else {
+            None
+        };
+
+        self.mmu.load_state(state)
+    }
+}
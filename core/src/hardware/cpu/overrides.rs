@@ -0,0 +1,103 @@
+//! User registered handlers which replace the implementation of individual opcodes, for HLE of
+//! specific routines or logging particular instructions.
+
+use std::fmt;
+
+use crate::hardware::cpu::CPU;
+use crate::hardware::mmu::MemoryMapper;
+
+/// A replacement implementation of an opcode, see [`CPU::override_opcode`].
+pub type OpcodeHandler<M> = Box<dyn FnMut(&mut CPU<M>) + Send>;
+
+pub struct OpcodeOverrides<M: MemoryMapper> {
+    /// Stays empty until the first override is registered, so that the check in the
+    /// fast path is just a length comparison.
+    handlers: Vec<Option<OpcodeHandler<M>>>,
+    /// The opcodes whose handlers are currently taken out to run (a handler could step the CPU
+    /// itself), and whether that slot was replaced or removed in the meantime, in which case the
+    /// handler shouldn't be put back.
+    running: Vec<(u8, bool)>,
+}
+
+impl<M: MemoryMapper> Default for OpcodeOverrides<M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M: MemoryMapper> OpcodeOverrides<M> {
+    pub fn new() -> Self {
+        OpcodeOverrides {
+            handlers: Vec::new(),
+            running: Vec::new(),
+        }
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+
+    pub fn insert(&mut self, opcode: u8, handler: OpcodeHandler<M>) {
+        if self.handlers.is_empty() {
+            self.handlers.resize_with(256, || None);
+        }
+        self.handlers[opcode as usize] = Some(handler);
+        self.mark_changed(opcode);
+    }
+
+    pub fn remove(&mut self, opcode: u8) {
+        if let Some(handler) = self.handlers.get_mut(opcode as usize) {
+            *handler = None;
+        }
+        self.mark_changed(opcode);
+        self.shrink();
+    }
+
+    /// Temporarily take the handler for `opcode` out, as it needs mutable access to the `CPU`
+    /// which owns it. Should be put back with `restore()` afterwards.
+    pub fn take(&mut self, opcode: u8) -> Option<OpcodeHandler<M>> {
+        let handler = self.handlers.get_mut(opcode as usize).and_then(Option::take);
+        if handler.is_some() {
+            self.running.push((opcode, false));
+        }
+        handler
+    }
+
+    pub fn restore(&mut self, opcode: u8, handler: OpcodeHandler<M>) {
+        // The handler may have replaced (or removed) itself while it was running.
+        if let Some((running, false)) = self.running.pop() {
+            if running == opcode {
+                self.handlers[opcode as usize] = Some(handler);
+            }
+        }
+        self.shrink();
+    }
+
+    fn mark_changed(&mut self, opcode: u8) {
+        for (running, changed) in &mut self.running {
+            *changed |= *running == opcode;
+        }
+    }
+
+    /// Drop the table once the last override is gone, unless a handler is still checked out, as
+    /// its slot would otherwise disappear from under it.
+    fn shrink(&mut self) {
+        if self.running.is_empty() && self.handlers.iter().all(Option::is_none) {
+            self.handlers.clear();
+        }
+    }
+
+    fn opcodes(&self) -> Vec<u8> {
+        (0..self.handlers.len())
+            .filter(|&opcode| self.handlers[opcode].is_some())
+            .map(|opcode| opcode as u8)
+            .collect()
+    }
+}
+
+impl<M: MemoryMapper> fmt::Debug for OpcodeOverrides<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "OpcodeOverrides: {:02X?}", self.opcodes())
+    }
+}
@@ -0,0 +1,87 @@
+//! A log of the CPU's low power transitions (HALT and STOP), e.g, for a timeline in a debug GUI.
+
+use std::collections::VecDeque;
+
+/// The amount of events kept before the oldest ones are dropped.
+pub const MAX_POWER_EVENTS: usize = 1024;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PowerEventKind {
+    HaltEntered,
+    HaltExited,
+    /// HALT was executed with `ime` reset while an interrupt was pending, so the CPU never
+    /// halted and the next byte was read twice.
+    HaltBug,
+    StopEntered,
+    StopExited,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PowerEvent {
+    pub kind: PowerEventKind,
+    /// For entering HALT/STOP (and the halt bug) the address of the instruction, when exiting
+    /// the address of the next instruction to be executed.
+    pub pc: u16,
+    /// The amount of cycles since the emulator started.
+    pub cycle: u64,
+}
+
+/// Only records events while enabled, to avoid any overhead otherwise.
+#[derive(Debug, Default, Clone)]
+pub struct PowerEventLog {
+    enabled: bool,
+    events: VecDeque<PowerEvent>,
+}
+
+impl PowerEventLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+
+        if !enabled {
+            self.events.clear();
+        }
+    }
+
+    #[inline(always)]
+    pub fn record(&mut self, kind: PowerEventKind, pc: u16, cycle: u64) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.events.len() == MAX_POWER_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(PowerEvent { kind, pc, cycle });
+    }
+
+    /// Returns all events since the last call, oldest first.
+    pub fn take_events(&mut self) -> Vec<PowerEvent> {
+        self.events.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hardware::cpu::power_events::{PowerEventKind, PowerEventLog, MAX_POWER_EVENTS};
+
+    #[test]
+    fn test_only_records_while_enabled() {
+        let mut log = PowerEventLog::new();
+        log.record(PowerEventKind::HaltEntered, 0x100, 0);
+        assert!(log.take_events().is_empty());
+
+        log.set_enabled(true);
+        for i in 0..(MAX_POWER_EVENTS as u64 + 2) {
+            log.record(PowerEventKind::HaltExited, 0x101, i);
+        }
+        let events = log.take_events();
+
+        assert_eq!(events.len(), MAX_POWER_EVENTS);
+        assert_eq!(events[0].cycle, 2);
+        assert!(log.take_events().is_empty());
+    }
+}
@@ -22,3 +22,26 @@ fn basic_cycle_test() {
 
     assert_eq!(cpu.cycles_performed, 32);
 }
+
+#[test]
+fn test_forced_interrupt() {
+    let mut cpu = initial_cpu();
+    cpu.registers.sp = 0xD000;
+    cpu.mmu.interrupts.overwrite_ie(0x1F);
+    cpu.mmu.interrupts.insert_interrupt(InterruptFlags::VBLANK | InterruptFlags::TIMER);
+    cpu.mmu.interrupts.force_next(Some(InterruptFlags::TIMER));
+
+    cpu.interrupts_routine();
+    assert_eq!(cpu.registers.pc, 0x0050);
+    assert_eq!(cpu.mmu.interrupts.interrupt_flag, InterruptFlags::VBLANK);
+
+    // The forced interrupt isn't pending, so the hardware priority applies.
+    cpu.interrupts_routine();
+    assert_eq!(cpu.registers.pc, 0x0040);
+
+    let mut interrupts = Interrupts::default();
+    interrupts.insert_interrupt(InterruptFlags::SERIAL);
+    interrupts.force_next(Some(InterruptFlags::SERIAL));
+    // Not enabled, so it should not be returned.
+    assert_eq!(interrupts.get_highest_priority(), InterruptFlags::NONE);
+}
@@ -293,6 +293,85 @@ fn test_daa() {
     assert!(cpu.registers.cf());
 }
 
+#[test]
+fn test_daa_edge_cases() {
+    // Hardware results, including the inputs which no valid BCD operation could produce.
+    // (A, N, H, C, result, Z, C)
+    let cases = [
+        (0x00, false, false, false, 0x00, true, false),
+        (0x0A, false, false, false, 0x10, false, false),
+        (0x9A, false, false, false, 0x00, true, true),
+        (0x99, false, false, false, 0x99, false, false),
+        (0xA0, false, false, false, 0x00, true, true),
+        (0x3C, false, true, false, 0x42, false, false),
+        (0x00, false, true, true, 0x66, false, true),
+        (0xFA, false, false, false, 0x60, false, true),
+        (0x9F, false, true, false, 0x05, false, true),
+        (0x66, false, false, true, 0xC6, false, true),
+        (0x00, true, false, false, 0x00, true, false),
+        // After a subtraction only the (half) carry matters, never the value of A itself.
+        (0x0A, true, false, false, 0x0A, false, false),
+        (0x0F, true, true, false, 0x09, false, false),
+        (0x40, true, true, false, 0x3A, false, false),
+        (0x06, true, true, false, 0x00, true, false),
+        (0xA0, true, false, true, 0x40, false, true),
+        (0x60, true, false, true, 0x00, true, true),
+        (0x9A, true, true, true, 0x34, false, true),
+        (0x00, true, true, true, 0x9A, false, true),
+    ];
+
+    for &(a, n, half_carry, carry, result, zero, carry_out) in cases.iter() {
+        let mut cpu = initial_cpu();
+        cpu.registers.a = a;
+        cpu.registers.set_n(n);
+        cpu.registers.set_h(half_carry);
+        cpu.registers.set_cf(carry);
+
+        cpu.daa();
+
+        let input = format!("A: {:#04X} N: {} H: {} C: {}", a, n, half_carry, carry);
+        assert_eq!(cpu.registers.a, result, "{}", input);
+        assert_eq!(cpu.registers.zf(), zero, "{}", input);
+        assert_eq!(cpu.registers.cf(), carry_out, "{}", input);
+        assert_eq!(cpu.registers.n(), n, "{}", input);
+        assert!(!cpu.registers.hf(), "{}", input);
+    }
+}
+
+#[test]
+fn test_daa_bcd_arithmetic() {
+    let to_bcd = |value: u32| (((value / 10) << 4) | (value % 10)) as u8;
+
+    for left in 0..100 {
+        for right in 0..100 {
+            for &carry in [false, true].iter() {
+                let mut cpu = initial_cpu();
+                cpu.registers.a = to_bcd(left);
+                cpu.registers.b = to_bcd(right);
+                cpu.registers.set_cf(carry);
+
+                cpu.adc(B);
+                cpu.daa();
+
+                let sum = left + right + carry as u32;
+                assert_eq!(cpu.registers.a, to_bcd(sum % 100), "{} + {} + {}", left, right, carry);
+                assert_eq!(cpu.registers.cf(), sum >= 100, "{} + {} + {}", left, right, carry);
+
+                cpu.registers.a = to_bcd(left);
+                cpu.registers.set_cf(carry);
+
+                cpu.sbc(B);
+                cpu.daa();
+
+                let difference = (left + 100 - right - carry as u32) % 100;
+                let borrow = left < right + carry as u32;
+                assert_eq!(cpu.registers.a, to_bcd(difference), "{} - {} - {}", left, right, carry);
+                assert_eq!(cpu.registers.cf(), borrow, "{} - {} - {}", left, right, carry);
+            }
+        }
+    }
+}
+
 #[test]
 fn test_cpl() {
     let mut cpu = initial_cpu();
@@ -524,7 +603,9 @@ fn test_add_sp() {
 
     assert_eq!(cpu.registers.sp, 30);
     assert_eq!(cpu.registers.pc, 2);
-    //TODO: Add flag tests once we figure out what they actually are supposed to be ._.
+    assert!(!cpu.registers.zf() && !cpu.registers.n());
+    assert!(!cpu.registers.hf());
+    assert!(cpu.registers.cf());
 }
 
 #[test]
@@ -537,7 +618,45 @@ fn test_load_sp() {
 
     assert_eq!(cpu.registers.hl(), 20);
     assert_eq!(cpu.registers.sp, 50);
-    //TODO: Add flag tests once we figure out what they actually are supposed to be ._.
+    assert!(!cpu.registers.zf() && !cpu.registers.n());
+    assert!(!cpu.registers.hf());
+    assert!(cpu.registers.cf());
+}
+
+#[test]
+fn test_sp_offset_flags() {
+    // The flags come from adding the unsigned immediate to the low byte of SP, regardless of
+    // the sign of the offset.
+    // (SP, e8, result, half carry, carry)
+    let cases = [
+        (0x0000, -1, 0xFFFF, false, false),
+        (0x0001, -1, 0x0000, true, true),
+        (0x1000, -1, 0x0FFF, false, false),
+        (0x0080, -128, 0x0000, false, true),
+        (0x000F, 1, 0x0010, true, false),
+        (0x00FF, 1, 0x0100, true, true),
+        (0xFFFF, 1, 0x0000, true, true),
+    ];
+
+    for &(sp, offset, result, half_carry, carry) in cases.iter() {
+        // ADD SP, e8 and LD HL, SP+e8
+        for &opcode in [0xE8, 0xF8].iter() {
+            let mut cpu = initial_cpu();
+            cpu.registers.sp = sp;
+            cpu.registers.set_zf(true);
+            cpu.registers.set_n(true);
+            cpu.set_instruction(opcode);
+            cpu.mmu.write_byte(1, offset as i8 as u8);
+
+            cpu.step_cycle();
+
+            let actual = if opcode == 0xE8 { cpu.registers.sp } else { cpu.registers.hl() };
+            assert_eq!(actual, result, "SP: {:#06X} e8: {}", sp, offset);
+            assert!(!cpu.registers.zf() && !cpu.registers.n());
+            assert_eq!(cpu.registers.hf(), half_carry, "SP: {:#06X} e8: {}", sp, offset);
+            assert_eq!(cpu.registers.cf(), carry, "SP: {:#06X} e8: {}", sp, offset);
+        }
+    }
 }
 
 #[test]
@@ -705,3 +824,79 @@ fn test_res() {
 
     assert_eq!(cpu.registers.b, 0b0010_1001);
 }
+
+#[test]
+fn test_opcode_override() {
+    let mut cpu = initial_cpu();
+    // LD B, 0x42 is replaced by a handler which loads its operand into C instead.
+    cpu.mmu.write_byte(0, 0x06);
+    cpu.mmu.write_byte(1, 0x42);
+    cpu.mmu.write_byte(2, 0x06);
+    cpu.mmu.write_byte(3, 0x42);
+    cpu.override_opcode(
+        0x06,
+        Box::new(|cpu| {
+            let operand = cpu.get_instr_u8();
+            cpu.registers_mut().c = operand;
+        }),
+    );
+
+    cpu.step_cycle();
+    assert_eq!(cpu.registers.b, 0x00);
+    assert_eq!(cpu.registers.c, 0x42);
+    assert_eq!(cpu.registers.pc, 2);
+    assert_eq!(cpu.cycles_performed, 8);
+
+    cpu.remove_opcode_override(0x06);
+    cpu.step_cycle();
+    assert_eq!(cpu.registers.b, 0x42);
+}
+
+#[test]
+fn test_opcode_override_removes_itself() {
+    let mut cpu = initial_cpu();
+    cpu.override_opcode(
+        0x00,
+        Box::new(|cpu| {
+            cpu.registers_mut().c += 1;
+            cpu.remove_opcode_override(0x00);
+        }),
+    );
+
+    cpu.step_cycle();
+    assert_eq!(cpu.registers.c, 1);
+    // The override shouldn't have been put back after it ran.
+    cpu.step_cycle();
+    assert_eq!(cpu.registers.c, 1);
+
+    // Same, but with another override keeping the table alive.
+    cpu.override_opcode(0x01, Box::new(|_| {}));
+    cpu.override_opcode(0x00, Box::new(|cpu| cpu.remove_opcode_override(0x00)));
+    cpu.step_cycle();
+    cpu.override_opcode(0x00, Box::new(|cpu| cpu.registers_mut().c += 1));
+    cpu.step_cycle();
+    assert_eq!(cpu.registers.c, 2);
+}
+
+#[test]
+fn test_opcode_override_removes_other() {
+    let mut cpu = initial_cpu();
+    cpu.mmu.write_byte(0, 0x00);
+    cpu.mmu.write_byte(1, 0x04);
+    cpu.mmu.write_byte(2, 0x00);
+    cpu.override_opcode(0x04, Box::new(|cpu| cpu.registers_mut().c += 1));
+    cpu.override_opcode(
+        0x00,
+        Box::new(|cpu| {
+            cpu.registers_mut().c += 0x10;
+            cpu.remove_opcode_override(0x04);
+        }),
+    );
+
+    cpu.step_cycle();
+    cpu.step_cycle();
+    // INC B ran normally, while the NOP override stayed in place.
+    assert_eq!(cpu.registers.b, 1);
+    cpu.step_cycle();
+    assert_eq!(cpu.registers.c, 0x20);
+}
@@ -1,6 +1,6 @@
 use crate::gb_emu::GameBoyModel;
 use crate::gb_emu::GameBoyModel::DMG;
-use crate::hardware::apu::APU;
+use crate::hardware::apu::{APU, DEFAULT_SAMPLE_RATE};
 use crate::hardware::cartridge::Cartridge;
 use crate::hardware::cpu::registers::Registers;
 use crate::hardware::cpu::CPU;
@@ -78,6 +78,14 @@ impl MemoryMapper for TestMemory {
     fn execute_next_event(&mut self) -> bool {
         unimplemented!()
     }
+
+    fn joypad_pressed(&self) -> bool {
+        false
+    }
+
+    fn current_cycle(&self) -> u64 {
+        0
+    }
 }
 
 impl Debug for TestMemory {
@@ -102,7 +110,7 @@ fn initial_cpu() -> CPU<TestMemory> {
             Default::default(),
             Default::default(),
         ),
-        apu: APU::new(),
+        apu: APU::new(DEFAULT_SAMPLE_RATE),
         timers: Default::default(),
         interrupts: Default::default(),
         cgb_data: Default::default(),
@@ -0,0 +1,68 @@
+//! A small ring buffer of the most recently executed instructions, used for crash reports.
+
+/// The amount of instructions kept in the trace.
+pub const TRACE_LENGTH: usize = 32;
+
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct TraceEntry {
+    pub pc: u16,
+    pub opcode: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct InstructionTrace {
+    entries: [TraceEntry; TRACE_LENGTH],
+    next: usize,
+    len: usize,
+}
+
+impl Default for InstructionTrace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InstructionTrace {
+    pub fn new() -> Self {
+        InstructionTrace {
+            entries: [TraceEntry::default(); TRACE_LENGTH],
+            next: 0,
+            len: 0,
+        }
+    }
+
+    #[inline(always)]
+    pub fn record(&mut self, pc: u16, opcode: u8) {
+        self.entries[self.next] = TraceEntry { pc, opcode };
+        self.next = (self.next + 1) % TRACE_LENGTH;
+        self.len = (self.len + 1).min(TRACE_LENGTH);
+    }
+
+    /// Returns the recorded instructions, oldest first.
+    pub fn to_vec(&self) -> Vec<TraceEntry> {
+        let start = (self.next + TRACE_LENGTH - self.len) % TRACE_LENGTH;
+
+        (0..self.len).map(|i| self.entries[(start + i) % TRACE_LENGTH]).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hardware::cpu::trace::{InstructionTrace, TRACE_LENGTH};
+
+    #[test]
+    fn test_trace_wraps_around() {
+        let mut trace = InstructionTrace::new();
+        trace.record(0x100, 0x00);
+        assert_eq!(trace.to_vec().len(), 1);
+
+        for i in 0..(TRACE_LENGTH as u16 + 5) {
+            trace.record(i, i as u8);
+        }
+        let entries = trace.to_vec();
+
+        assert_eq!(entries.len(), TRACE_LENGTH);
+        assert_eq!(entries[0].pc, 5);
+        assert_eq!(entries.last().unwrap().pc, TRACE_LENGTH as u16 + 4);
+    }
+}
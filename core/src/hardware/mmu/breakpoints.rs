@@ -0,0 +1,142 @@
+//! Memory breakpoints for debugging, checked on every memory access made through `Memory`.
+//!
+//! The emulator doesn't stop by itself once a breakpoint is hit, instead the hit should be
+//! polled with `take_breakpoint_hit()` (e.g, after every `step_instruction()`).
+
+use std::collections::HashSet;
+
+/// Describes the first breakpoint which was hit since the last `take_hit()`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BreakpointHit {
+    /// The `address` was read from or written to.
+    Address { address: u16, write: bool },
+    /// The watched value was written to, or read from, the `address`.
+    ///
+    /// For reads both values are the same.
+    Value {
+        address: u16,
+        old_value: u8,
+        new_value: u8,
+        write: bool,
+    },
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Breakpoints {
+    addresses: HashSet<u16>,
+    value_watches: Vec<(u16, u8)>,
+    hit: Option<BreakpointHit>,
+}
+
+impl Breakpoints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Break on any read or write to the `address`.
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.addresses.insert(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) {
+        self.addresses.remove(&address);
+    }
+
+    /// Break whenever `value` is written to, or read from, the `address`.
+    pub fn add_value_watch(&mut self, address: u16, value: u8) {
+        if !self.value_watches.contains(&(address, value)) {
+            self.value_watches.push((address, value));
+        }
+    }
+
+    pub fn remove_value_watch(&mut self, address: u16, value: u8) {
+        self.value_watches.retain(|&watch| watch != (address, value));
+    }
+
+    /// Remove all breakpoints and watches, as well as any pending hit.
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Returns `true` if there are no breakpoints at all, allowing the memory accesses to skip
+    /// any further checks.
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty() && self.value_watches.is_empty()
+    }
+
+    /// Whether there is a value watch on the `address`, in which case the old value is needed
+    /// when writing.
+    pub fn is_value_watched(&self, address: u16) -> bool {
+        self.value_watches.iter().any(|&(watched, _)| watched == address)
+    }
+
+    pub fn check_read(&mut self, address: u16, value: u8) {
+        self.check_access(address, value, value, false);
+    }
+
+    pub fn check_write(&mut self, address: u16, old_value: u8, new_value: u8) {
+        self.check_access(address, old_value, new_value, true);
+    }
+
+    /// Whether a breakpoint was hit which hasn't been taken yet.
+    pub fn has_hit(&self) -> bool {
+        self.hit.is_some()
+    }
+
+    /// Returns the first breakpoint hit since the last call, if any.
+    pub fn take_hit(&mut self) -> Option<BreakpointHit> {
+        self.hit.take()
+    }
+
+    fn check_access(&mut self, address: u16, old_value: u8, new_value: u8, write: bool) {
+        // Only keep the first hit, so that the earliest cause is reported.
+        if self.hit.is_some() {
+            return;
+        }
+
+        if self.value_watches.contains(&(address, new_value)) {
+            self.hit = Some(BreakpointHit::Value {
+                address,
+                old_value,
+                new_value,
+                write,
+            });
+        } else if self.addresses.contains(&address) {
+            self.hit = Some(BreakpointHit::Address { address, write });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hardware::mmu::breakpoints::{BreakpointHit, Breakpoints};
+
+    #[test]
+    fn test_first_hit_is_kept() {
+        let mut breakpoints = Breakpoints::new();
+        assert!(breakpoints.is_empty());
+        breakpoints.add_breakpoint(0xFF80);
+        breakpoints.add_value_watch(0xC000, 0x05);
+        assert!(!breakpoints.is_empty());
+
+        breakpoints.check_read(0xC000, 0x04);
+        assert_eq!(breakpoints.take_hit(), None);
+        breakpoints.check_read(0xC000, 0x05);
+        breakpoints.check_write(0xFF80, 0x00, 0x01);
+
+        let expected = BreakpointHit::Value {
+            address: 0xC000,
+            old_value: 0x05,
+            new_value: 0x05,
+            write: false,
+        };
+        assert_eq!(breakpoints.take_hit(), Some(expected));
+        breakpoints.check_write(0xFF80, 0x00, 0x01);
+        assert_eq!(breakpoints.take_hit(), Some(BreakpointHit::Address { address: 0xFF80, write: true }));
+
+        breakpoints.remove_value_watch(0xC000, 0x05);
+        breakpoints.remove_breakpoint(0xFF80);
+        assert!(breakpoints.is_empty());
+    }
+}
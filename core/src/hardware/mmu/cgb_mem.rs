@@ -8,6 +8,7 @@
 use crate::hardware::mmu::cgb_mem::HdmaMode::{GDMA, HDMA};
 use crate::hardware::mmu::INVALID_READ;
 use crate::scheduler::{EventType, Scheduler};
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
 
 #[derive(Default, Debug, Copy, Clone)]
 pub struct CgbSpeedData {
@@ -45,6 +46,59 @@ impl CgbSpeedData {
     }
 }
 
+/// The undocumented (but readable/writable) CGB registers `FF72..=FF75`, which have no known
+/// function. These all read `FFh` on a DMG.
+#[derive(Debug, Copy, Clone)]
+pub struct CgbUndocumentedRegisters {
+    ff72: u8,
+    ff73: u8,
+    /// Only available in CGB mode, reads `FFh` otherwise.
+    ff74: u8,
+    /// Only bits 4-6 are used, the rest always read 1.
+    ff75: u8,
+}
+
+impl Default for CgbUndocumentedRegisters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CgbUndocumentedRegisters {
+    pub fn new() -> Self {
+        CgbUndocumentedRegisters {
+            ff72: 0x00,
+            ff73: 0x00,
+            ff74: 0x00,
+            ff75: 0x8F,
+        }
+    }
+
+    /// Read one of the registers, `cgb_mode` indicates whether a CGB is running a CGB cartridge
+    /// (as opposed to a DMG cartridge in compatibility mode).
+    pub fn read_register(&self, address: u16, cgb: bool, cgb_mode: bool) -> u8 {
+        match address {
+            _ if !cgb => INVALID_READ,
+            0xFF72 => self.ff72,
+            0xFF73 => self.ff73,
+            0xFF74 if cgb_mode => self.ff74,
+            0xFF75 => self.ff75,
+            _ => INVALID_READ,
+        }
+    }
+
+    pub fn write_register(&mut self, address: u16, value: u8, cgb: bool, cgb_mode: bool) {
+        match address {
+            _ if !cgb => {}
+            0xFF72 => self.ff72 = value,
+            0xFF73 => self.ff73 = value,
+            0xFF74 if cgb_mode => self.ff74 = value,
+            0xFF75 => self.ff75 = 0x8F | (value & 0x70),
+            _ => {}
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialOrd, PartialEq)]
 pub enum HdmaMode {
     GDMA,
@@ -161,3 +215,82 @@ impl HdmaRegister {
         }
     }
 }
+
+impl SaveState for CgbSpeedData {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.double_speed);
+        state.write_u8(self.prepare_speed_switch);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.double_speed = state.read_bool()?;
+        self.prepare_speed_switch = state.read_u8()?;
+        Ok(())
+    }
+}
+
+impl SaveState for CgbUndocumentedRegisters {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&[self.ff72, self.ff73, self.ff74, self.ff75]);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        let mut registers = [0; 4];
+        state.read_into(&mut registers)?;
+        let [ff72, ff73, ff74, ff75] = registers;
+        *self = CgbUndocumentedRegisters { ff72, ff73, ff74, ff75 };
+        Ok(())
+    }
+}
+
+impl SaveState for HdmaRegister {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.current_mode == HDMA);
+        state.write_u16(self.transfer_size);
+        state.write_u16(self.source_address);
+        state.write_u16(self.destination_address);
+        state.write_u8(self.hdma_length);
+        state.write_bool(self.transfer_ongoing);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.current_mode = if state.read_bool()? { HDMA } else { GDMA };
+        self.transfer_size = state.read_u16()?;
+        self.source_address = state.read_u16()?;
+        self.destination_address = state.read_u16()?;
+        self.hdma_length = state.read_u8()?;
+        self.transfer_ongoing = state.read_bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hardware::mmu::cgb_mem::CgbUndocumentedRegisters;
+
+    #[test]
+    fn test_undocumented_registers() {
+        let mut registers = CgbUndocumentedRegisters::new();
+        assert_eq!(registers.read_register(0xFF75, true, true), 0x8F);
+        registers.write_register(0xFF75, 0x00, true, true);
+        assert_eq!(registers.read_register(0xFF75, true, true), 0x8F);
+        registers.write_register(0xFF75, 0xFF, true, true);
+        assert_eq!(registers.read_register(0xFF75, true, true), 0xFF);
+        registers.write_register(0xFF75, 0x50, true, true);
+        assert_eq!(registers.read_register(0xFF75, true, true), 0xDF);
+
+        registers.write_register(0xFF72, 0x12, true, false);
+        assert_eq!(registers.read_register(0xFF72, true, false), 0x12);
+        // FF74 is only available in CGB mode.
+        registers.write_register(0xFF74, 0x34, true, false);
+        assert_eq!(registers.read_register(0xFF74, true, false), 0xFF);
+        assert_eq!(registers.read_register(0xFF74, true, true), 0x00);
+        registers.write_register(0xFF74, 0x34, true, true);
+        assert_eq!(registers.read_register(0xFF74, true, true), 0x34);
+
+        // None of them exist on a DMG.
+        assert_eq!(registers.read_register(0xFF72, false, false), 0xFF);
+        registers.write_register(0xFF73, 0x56, false, false);
+        assert_eq!(registers.read_register(0xFF73, true, true), 0x00);
+    }
+}
@@ -0,0 +1,261 @@
+//! Game Genie cheat codes, which substitute the bytes the game reads from its ROM, and GameShark
+//! codes, which poke a value into RAM every frame.
+//!
+//! Just like the real device the Game Genie codes don't know about ROM banks. Codes for the
+//! switchable bank (`0x4000-0x7FFF`) should therefore have a compare value, so that they only fire
+//! while the bank holding the original byte is mapped.
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::hardware::mmu::{EXTERNAL_RAM_START, HRAM_END, HRAM_START, WRAM_BANK_NN_END};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CheatParseError {
+    /// The code didn't consist of either 6 or 9 hexadecimal digits for a Game Genie code,
+    /// e.g. `010-00A` or `010-00A-C49`, or 8 digits for a GameShark code, e.g. `01FF16D0`.
+    InvalidLength,
+    InvalidDigit(char),
+    /// The code would patch an address outside of the ROM, which isn't possible.
+    NotRomAddress(u16),
+    /// The GameShark code would poke an address outside of the external RAM, WRAM, or HRAM.
+    NotRamAddress(u16),
+    /// The GameShark code type isn't supported, only `01` (a RAM poke) is.
+    UnsupportedType(u8),
+}
+
+impl fmt::Display for CheatParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheatParseError::InvalidLength => write!(f, "A Game Genie code should have either 6 or 9 digits"),
+            CheatParseError::InvalidDigit(digit) => write!(f, "The code contains an invalid digit: {}", digit),
+            CheatParseError::NotRomAddress(address) => {
+                write!(f, "The code patches a non-ROM address: 0x{:04X}", address)
+            }
+            CheatParseError::NotRamAddress(address) => {
+                write!(f, "The code pokes a non-RAM address: 0x{:04X}", address)
+            }
+            CheatParseError::UnsupportedType(code_type) => {
+                write!(f, "The GameShark code type is not supported: {:02X}", code_type)
+            }
+        }
+    }
+}
+
+impl Error for CheatParseError {}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CheatCode {
+    GameGenie(GameGenieCode),
+    GameShark(GameSharkCode),
+}
+
+impl FromStr for CheatCode {
+    type Err = CheatParseError;
+
+    /// Parse either kind of code, which is told apart by the amount of digits.
+    fn from_str(code: &str) -> Result<Self, Self::Err> {
+        let code = code.trim();
+
+        if code.len() == 8 && !code.contains('-') {
+            code.parse().map(CheatCode::GameShark)
+        } else {
+            code.parse().map(CheatCode::GameGenie)
+        }
+    }
+}
+
+/// Parse all hexadecimal digits in the `code`, ignoring any dashes.
+fn parse_digits(code: &str) -> Result<Vec<u16>, CheatParseError> {
+    code.chars()
+        .filter(|&c| c != '-')
+        .map(|c| c.to_digit(16).map(|d| d as u16).ok_or(CheatParseError::InvalidDigit(c)))
+        .collect()
+}
+
+/// A single parsed Game Genie code in the `ABC-DEF-GHI` format, where the last group is optional.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GameGenieCode {
+    pub address: u16,
+    pub value: u8,
+    /// The byte which has to be in the ROM for the code to fire.
+    pub compare: Option<u8>,
+}
+
+impl FromStr for GameGenieCode {
+    type Err = CheatParseError;
+
+    fn from_str(code: &str) -> Result<Self, Self::Err> {
+        let digits = parse_digits(code)?;
+
+        if digits.len() != 6 && digits.len() != 9 {
+            return Err(CheatParseError::InvalidLength);
+        }
+
+        let value = ((digits[0] << 4) | digits[1]) as u8;
+        // The highest nibble of the address is stored inverted, and last.
+        let address = ((digits[5] ^ 0xF) << 12) | (digits[2] << 8) | (digits[3] << 4) | digits[4];
+        // The 8th digit is unused.
+        let compare = digits
+            .get(6..9)
+            .map(|digits| (((digits[0] << 4) | digits[2]) as u8).rotate_right(2) ^ 0xBA);
+
+        if address > 0x7FFF {
+            return Err(CheatParseError::NotRomAddress(address));
+        }
+
+        Ok(GameGenieCode { address, value, compare })
+    }
+}
+
+/// A single parsed GameShark code in the `TTVVLLHH` format, with the type, value, and the little
+/// endian address.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GameSharkCode {
+    pub address: u16,
+    pub value: u8,
+}
+
+impl FromStr for GameSharkCode {
+    type Err = CheatParseError;
+
+    fn from_str(code: &str) -> Result<Self, Self::Err> {
+        let digits = parse_digits(code)?;
+
+        if digits.len() != 8 || code.contains('-') {
+            return Err(CheatParseError::InvalidLength);
+        }
+
+        let byte = |index: usize| ((digits[index] << 4) | digits[index + 1]) as u8;
+
+        if byte(0) != 0x01 {
+            return Err(CheatParseError::UnsupportedType(byte(0)));
+        }
+
+        let address = u16::from_le_bytes([byte(4), byte(6)]);
+
+        match address {
+            EXTERNAL_RAM_START..=WRAM_BANK_NN_END | HRAM_START..=HRAM_END => Ok(GameSharkCode {
+                address,
+                value: byte(2),
+            }),
+            _ => Err(CheatParseError::NotRamAddress(address)),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct CheatEngine {
+    genie_codes: Vec<GameGenieCode>,
+    shark_codes: Vec<GameSharkCode>,
+}
+
+impl CheatEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_code(&mut self, code: CheatCode) {
+        match code {
+            CheatCode::GameGenie(code) if !self.genie_codes.contains(&code) => self.genie_codes.push(code),
+            CheatCode::GameShark(code) if !self.shark_codes.contains(&code) => self.shark_codes.push(code),
+            _ => {}
+        }
+    }
+
+    pub fn remove_code(&mut self, code: CheatCode) {
+        match code {
+            CheatCode::GameGenie(code) => self.genie_codes.retain(|&existing| existing != code),
+            CheatCode::GameShark(code) => self.shark_codes.retain(|&existing| existing != code),
+        }
+    }
+
+    /// Returns `true` if there are no codes at all.
+    pub fn is_empty(&self) -> bool {
+        self.genie_codes.is_empty() && self.shark_codes.is_empty()
+    }
+
+    /// Returns `true` if there are any Game Genie codes, otherwise ROM reads can skip any
+    /// further checks.
+    #[inline(always)]
+    pub fn patches_rom(&self) -> bool {
+        !self.genie_codes.is_empty()
+    }
+
+    /// The GameShark codes, which should be written to memory once every V-blank.
+    pub fn ram_pokes(&self) -> &[GameSharkCode] {
+        &self.shark_codes
+    }
+
+    /// Returns the byte the game should see when reading the `original` byte from the ROM `address`.
+    ///
+    /// As the `original` is whatever is currently mapped, codes with a compare value only fire
+    /// for the bank they were meant for.
+    pub fn apply(&self, address: u16, original: u8) -> u8 {
+        self.genie_codes
+            .iter()
+            .find(|code| code.address == address && (code.compare.is_none() || code.compare == Some(original)))
+            .map_or(original, |code| code.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hardware::mmu::cheats::{CheatCode, CheatEngine, CheatParseError, GameGenieCode, GameSharkCode};
+
+    #[test]
+    fn test_parse_code() {
+        let code: GameGenieCode = "010-00A-C49".parse().unwrap();
+        let expected = GameGenieCode {
+            address: 0x5000,
+            value: 0x01,
+            compare: Some(0xC8),
+        };
+        assert_eq!(code, expected);
+
+        let code: GameGenieCode = "3e1-21f".parse().unwrap();
+        assert_eq!(code.address, 0x0121);
+        assert_eq!((code.value, code.compare), (0x3E, None));
+
+        assert_eq!("010-00A-C4".parse::<GameGenieCode>(), Err(CheatParseError::InvalidLength));
+        assert_eq!("010-00G".parse::<GameGenieCode>(), Err(CheatParseError::InvalidDigit('G')));
+        assert_eq!("010-007".parse::<GameGenieCode>(), Err(CheatParseError::NotRomAddress(0x8000)));
+    }
+
+    #[test]
+    fn test_compare_value() {
+        let mut cheats = CheatEngine::new();
+        assert!(cheats.is_empty());
+        let code = "010-00A-C49".parse().unwrap();
+        cheats.add_code(code);
+        assert!(cheats.patches_rom());
+
+        assert_eq!(cheats.apply(0x5000, 0xC8), 0x01);
+        assert_eq!(cheats.apply(0x5000, 0xC9), 0xC9);
+        assert_eq!(cheats.apply(0x5001, 0xC8), 0xC8);
+
+        cheats.remove_code(code);
+        assert!(cheats.is_empty());
+    }
+
+    #[test]
+    fn test_parse_gameshark_code() {
+        let expected = GameSharkCode {
+            address: 0xD016,
+            value: 0xFF,
+        };
+        assert_eq!("01FF16D0".parse(), Ok(CheatCode::GameShark(expected)));
+        assert!(matches!("010-00A".parse::<CheatCode>(), Ok(CheatCode::GameGenie(_))));
+
+        assert_eq!("91FF16D0".parse::<GameSharkCode>(), Err(CheatParseError::UnsupportedType(0x91)));
+        assert_eq!("01FF16D".parse::<GameSharkCode>(), Err(CheatParseError::InvalidLength));
+        assert_eq!("01FF4000".parse::<GameSharkCode>(), Err(CheatParseError::NotRamAddress(0x0040)));
+        assert_eq!("01FF40FF".parse::<GameSharkCode>(), Err(CheatParseError::NotRamAddress(0xFF40)));
+
+        let mut cheats = CheatEngine::new();
+        cheats.add_code(CheatCode::GameShark(expected));
+        assert!(!cheats.patches_rom());
+        assert_eq!(cheats.ram_pokes(), &[expected]);
+    }
+}
@@ -13,7 +13,16 @@ impl Memory {
         self.scheduler.push_relative(DMARequested, 4);
     }
 
+    /// Gather the `0xA0` bytes an OAM DMA transfer from `start_address` would copy.
+    ///
+    /// The DMA can't read from OAM or the IO registers. A source of `0xE0` or above instead
+    /// reads from work ram as if the source were `0x2000` lower (e.g, `0xFE00` reads `0xDE00`).
     pub fn gather_shadow_oam(&mut self, start_address: usize) -> Vec<u8> {
+        let start_address = if start_address >= 0xE000 {
+            start_address - 0x2000
+        } else {
+            start_address
+        };
         (0..0xA0).map(|i| self.read_byte((start_address + i) as u16)).collect()
     }
 
@@ -40,19 +49,26 @@ impl Memory {
 
     /// Checks, assuming the current PPU mode is `HBLANK`, whether an `HDMA` transfer should
     /// occur at this point in time. If so, it also executes it.
-    pub fn hdma_check_and_transfer(&mut self) {
+    ///
+    /// Returns true if a vblank interrupt happened during the transfer.
+    pub fn hdma_check_and_transfer(&mut self) -> bool {
+        let mut vblank_occurred = false;
+
         if self.hdma.transfer_ongoing && self.hdma.current_mode == HDMA {
             log::info!("Performing HDMA transfer");
             if self.hdma.transfer_ongoing {
-                self.do_m_cycle();
-                // Pass 36 (single speed)/68 (double speed) cycles where the CPU does nothing.
+                vblank_occurred |= self.do_m_cycle();
+                // The CPU is stalled for the M-cycle above, plus 8 microseconds to copy the block.
+                // That's 8 M-cycles in single speed, and 16 (fast) M-cycles in double speed.
                 for _ in 0..(8 << self.get_speed_shift()) {
                     //TODO: Skip ahead, since CPU is halted during transfer.
-                    self.do_m_cycle();
+                    vblank_occurred |= self.do_m_cycle();
                 }
             }
             self.hdma_transfer();
         }
+
+        vblank_occurred
     }
 
     /// Required here since the HDMA can write to arbitrary PPU addresses.
@@ -69,3 +85,117 @@ impl Memory {
         self.hdma.advance_hdma();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::gb_emu::tests::TestRom;
+    use crate::gb_emu::GameBoyModel::{CGB, DMG};
+    use crate::hardware::mmu::*;
+    use crate::EmulatorOptionsBuilder;
+
+    #[test]
+    fn test_gdma_stall_limit() {
+        let options = EmulatorOptionsBuilder::new().with_mode(CGB).with_max_gdma_stall_cycles(256);
+        let mut memory = TestRom::new().with_options(options).memory();
+        memory.write_byte(CGB_HDMA_5, 0x7F);
+
+        let start_time = memory.scheduler.current_time;
+        memory.do_m_cycle();
+
+        // The CPU resumes at the first M-cycle past the limit, even though the transfer isn't done.
+        assert_eq!(memory.scheduler.current_time, start_time + 4 + 256 + 4);
+        assert!(!memory.gdma_stall);
+    }
+
+    #[test]
+    fn test_gdma_cycle_cost() {
+        let mut memory = TestRom::new().with_mode(CGB).memory();
+        let mut reference = TestRom::new().with_mode(CGB).memory();
+
+        for i in 0..0x800 {
+            memory.write_byte(0xC000 + i, i as u8);
+        }
+        memory.write_byte(CGB_HDMA_1, 0xC0);
+        memory.write_byte(CGB_HDMA_2, 0x00);
+        memory.write_byte(CGB_HDMA_3, 0x00);
+        memory.write_byte(CGB_HDMA_4, 0x00);
+        // 128 blocks of 16 bytes, bit 7 reset for GDMA.
+        memory.write_byte(CGB_HDMA_5, 0x7F);
+
+        let start_time = memory.scheduler.current_time;
+        memory.do_m_cycle();
+
+        // The transfer starts 4 cycles after the write, and takes 32 cycles per block.
+        let expected_time = start_time + 4 + 128 * GDMA_BLOCK_CYCLES;
+        assert_eq!(memory.scheduler.current_time, expected_time);
+        assert!(!memory.hdma.transfer_ongoing);
+        assert_eq!(memory.read_byte(CGB_HDMA_5), INVALID_READ);
+        for i in 0..0x800 {
+            assert_eq!(memory.read_byte(0x8000 + i), i as u8);
+        }
+
+        // All events which should have occurred during the stall should have been executed.
+        while reference.scheduler.current_time < expected_time {
+            reference.do_m_cycle();
+        }
+        assert_eq!(memory.ppu.current_y, reference.ppu.current_y);
+        assert_eq!(memory.ppu.get_current_mode(), reference.ppu.get_current_mode());
+        assert_eq!(memory.scheduler.next_event_timestamp(), reference.scheduler.next_event_timestamp());
+    }
+
+    /// Run an OAM DMA from the `source` page, returns the resulting OAM.
+    #[test]
+    fn test_oam_dma_high_source() {
+        let work_ram: Vec<u8> = (0..0xA0).collect();
+        let upper_work_ram: Vec<u8> = (0..0xA0).map(|i| 0xFF - i).collect();
+        // Rather than reading OAM/IO a 0xFE source should wrap back to work ram.
+        let cases = [(0xC0, &work_ram), (0xE0, &work_ram), (0xFE, &upper_work_ram)];
+
+        for &(source, expected) in cases.iter() {
+            let mut memory = TestRom::new().with_mode(DMG).memory();
+            for i in 0..0xA0 {
+                memory.write_byte(0xC000 + i, i as u8);
+                memory.write_byte(0xDE00 + i, 0xFF - i as u8);
+            }
+
+            memory.write_byte(DMA_TRANSFER, source);
+            // The transfer starts 4 cycles after the write.
+            memory.do_m_cycle();
+            memory.do_m_cycle();
+            assert_eq!(memory.ppu.oam_bytes()[..], expected[..], "Source: {:#04X}", source);
+        }
+    }
+
+    #[test]
+    fn test_hdma_cycle_cost() {
+        // Every block stalls for 4 cycles of overhead + 8 microseconds (32/64 cycles depending on speed).
+        for &(double_speed, block_cycles) in [(false, 4 + 32), (true, 4 + 64)].iter() {
+            let mut memory = TestRom::new().with_mode(CGB).memory();
+            if double_speed {
+                memory.cgb_data.toggle_speed();
+            }
+
+            for i in 0..64 {
+                memory.write_byte(0xC000 + i, i as u8);
+            }
+            memory.write_byte(CGB_HDMA_1, 0xC0);
+            memory.write_byte(CGB_HDMA_2, 0x00);
+            memory.write_byte(CGB_HDMA_3, 0x00);
+            memory.write_byte(CGB_HDMA_4, 0x00);
+            // 4 blocks of 16 bytes, bit 7 set for HDMA.
+            memory.write_byte(CGB_HDMA_5, 0x83);
+
+            let mut stalled_cycles = 0;
+            while memory.hdma.transfer_ongoing {
+                let start_time = memory.scheduler.current_time;
+                memory.do_m_cycle();
+                stalled_cycles += memory.scheduler.current_time - start_time - 4;
+            }
+
+            assert_eq!(stalled_cycles, 4 * block_cycles, "Double speed: {}", double_speed);
+            for i in 0..64 {
+                assert_eq!(memory.read_byte(0x8000 + i), i as u8);
+            }
+        }
+    }
+}
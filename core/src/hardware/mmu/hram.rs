@@ -16,6 +16,28 @@ impl Hram {
         }
     }
 
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn dump(&self) -> Vec<u8> {
+        self.memory.to_vec()
+    }
+
+    /// Restore the state previously returned by [dump](#method.dump).
+    ///
+    /// # Panics
+    ///
+    /// If `data` is not exactly `HRAM_SIZE` bytes long.
+    pub fn restore(&mut self, data: &[u8]) {
+        assert_eq!(data.len(), HRAM_SIZE, "Invalid HRAM dump size");
+        self.memory.copy_from_slice(data);
+    }
+
+    pub fn copy_memory_from(&mut self, other: &Hram) {
+        self.memory = other.memory;
+    }
+
     pub fn read_byte(&self, address: u16) -> u8 {
         self.memory[(address - HRAM_OFFSET) as usize]
     }
@@ -24,3 +46,21 @@ impl Hram {
         self.memory[(address - HRAM_OFFSET) as usize] = value;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::hardware::mmu::hram::Hram;
+
+    #[test]
+    fn test_dump_restore_round_trip() {
+        let mut hram = Hram::new();
+        hram.set_byte(0xFF80, 0x12);
+        hram.set_byte(0xFFFE, 0x34);
+
+        let mut restored = Hram::new();
+        restored.restore(&hram.dump());
+        assert_eq!(restored.read_byte(0xFF80), 0x12);
+        assert_eq!(restored.read_byte(0xFFFE), 0x34);
+        assert_eq!(restored.memory(), hram.memory());
+    }
+}
@@ -3,25 +3,34 @@ use std::fmt::{Debug, Formatter};
 
 use log::*;
 
-use hram::Hram;
+use hram::{Hram, HRAM_SIZE};
 
 use crate::gb_emu::GameBoyModel;
 use crate::hardware::apu::{APU, APU_MEM_END, APU_MEM_START, WAVE_SAMPLE_END, WAVE_SAMPLE_START};
+use crate::hardware::cartridge::bank_switches::BankSwitch;
 use crate::hardware::cartridge::Cartridge;
-use crate::hardware::mmu::cgb_mem::{CgbSpeedData, HdmaRegister};
-use crate::hardware::mmu::wram::Wram;
+use crate::hardware::mmu::breakpoints::Breakpoints;
+use crate::hardware::mmu::cheats::CheatEngine;
+use crate::hardware::mmu::cgb_mem::{CgbSpeedData, CgbUndocumentedRegisters, HdmaRegister};
+use crate::hardware::mmu::wram::{Wram, WRAM_SIZE};
 use crate::hardware::ppu::memory_binds::DMA_TRANSFER;
+use crate::hardware::ppu::palette::RGB;
 use crate::hardware::ppu::timing::{OAM_SEARCH_DURATION, SCANLINE_DURATION};
 use crate::hardware::ppu::{Mode, PPU};
 use crate::io::bootrom::BootRom;
+use crate::io::infrared::InfraredPort;
 use crate::io::interrupts::{InterruptFlags, Interrupts};
 use crate::io::io_registers::IORegisters;
 use crate::io::joypad::JoyPad;
+use crate::io::serial::{SerialLink, SERIAL_FAST_TRANSFER_CYCLES, SERIAL_TRANSFER_CYCLES};
 use crate::io::timer::{TimerRegisters, TIMER_CONTROL, TIMER_COUNTER};
 use crate::scheduler::{EventType, Scheduler};
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
 use crate::EmulatorOptions;
 
+pub mod breakpoints;
 pub mod cgb_mem;
+pub mod cheats;
 mod dma;
 mod hram;
 mod wram;
@@ -74,10 +83,14 @@ pub const PPU_IO_START: u16 = 0xF40;
 pub const PPU_IO_END: u16 = 0xFF4F;
 pub const PPU_CGB_IO_START: u16 = 0xFF68;
 pub const PPU_CGB_IO_END: u16 = 0xFF6C;
-// TODO: Implement
-/// Not documented anywhere I could find, but if one writes 0x04 to this register it'll manually
-/// put the CGB into DMG mode (e.g, sprite priority changes)
+/// KEY0, only writable (and readable) while the CGB boot rom is running, reads as FFh afterwards.
+///
+/// The boot rom writes the CGB flag of the cartridge header here, or 0x04 for DMG cartridges,
+/// which puts the CGB into DMG compatibility mode (e.g, sprite priority changes) once the boot rom
+/// is disabled.
 pub const CGB_SWITCH_MODE: u16 = 0xFF4C;
+/// The bit of `CGB_SWITCH_MODE` which selects DMG compatibility mode.
+const DMG_COMPATIBILITY_MODE: u8 = 0x04;
 pub const CGB_PREPARE_SWITCH: u16 = 0xFF4D;
 /// Specifies the higher byte of the source address. Always returns FFh when read.
 pub const CGB_HDMA_1: u16 = 0xFF51;
@@ -95,10 +108,13 @@ pub const CGB_HDMA_4: u16 = 0xFF54;
 /// Bit 7 – Transfer mode (0=GDMA, 1=HDMA)
 /// Bits 6-0 – Blocks (Size = (Blocks+1)×16 bytes)
 pub const CGB_HDMA_5: u16 = 0xFF55;
-/// Infrared Communications Port //TODO: Implement?
+/// Infrared Communications Port, see `InfraredPort`.
 pub const CGB_RP: u16 = 0xFF56;
 /// Work ram bank switching.
 pub const CGB_WRAM_BANK: u16 = 0xFF70;
+/// Undocumented CGB registers, see `CgbUndocumentedRegisters`.
+pub const CGB_UNDOCUMENTED_START: u16 = 0xFF72;
+pub const CGB_UNDOCUMENTED_END: u16 = 0xFF75;
 
 /// The flag used to signal that an interrupt is pending.
 pub const INTERRUPTS_FLAG: u16 = 0xFF0F;
@@ -109,6 +125,12 @@ pub const HRAM_END: u16 = 0xFFFE;
 pub const INTERRUPTS_ENABLE: u16 = 0xFFFF;
 /// The value to return for an invalid read
 pub const INVALID_READ: u8 = 0xFF;
+/// The amount of cycles (in single speed mode) it takes to transfer one 16 byte block during GDMA.
+pub const GDMA_BLOCK_CYCLES: u64 = 32;
+/// The longest a GDMA can stall the CPU, a full 128 block transfer in double speed mode.
+///
+/// The default for `EmulatorOptions::max_gdma_stall_cycles`.
+pub const MAX_GDMA_STALL_CYCLES: u64 = 0x80 * (GDMA_BLOCK_CYCLES << 1);
 
 /// Simple memory interface for reading and writing bytes, as well as determining the
 /// state of the BootRom.
@@ -131,7 +153,14 @@ pub trait MemoryMapper: Debug {
     fn do_m_cycle(&mut self) -> bool;
     /// Skip ahead to the next event, whenever that may be.
     /// Useful for halt skipping.
+    ///
+    /// Returns `true` if V-blank occurred
     fn execute_next_event(&mut self) -> bool;
+    /// Returns `true` if any button on the currently selected joypad line(s) is pressed,
+    /// which is what wakes the CPU from STOP mode.
+    fn joypad_pressed(&self) -> bool;
+    /// The amount of cycles which have passed since the system started.
+    fn current_cycle(&self) -> u64;
 }
 
 pub struct Memory {
@@ -141,6 +170,9 @@ pub struct Memory {
     pub emulated_model: GameBoyModel,
     pub cgb_data: CgbSpeedData,
     pub hdma: HdmaRegister,
+    pub cgb_undocumented: CgbUndocumentedRegisters,
+    pub breakpoints: Breakpoints,
+    pub cheats: CheatEngine,
 
     pub ppu: PPU,
     pub apu: APU,
@@ -151,36 +183,184 @@ pub struct Memory {
     pub timers: TimerRegisters,
     pub interrupts: Interrupts,
     pub io_registers: IORegisters,
+    /// Set while a GDMA transfer is ongoing, during which the CPU can't execute.
+    gdma_stall: bool,
+    /// Whether DMG ROMs should still be rendered with the CGB renderer.
+    force_cgb_rendering: bool,
+    /// See `CGB_SWITCH_MODE`
+    cgb_switch_mode: u8,
+    /// The device connected to the serial port, if any.
+    pub serial_link: Option<Box<dyn SerialLink + Send>>,
+    pub infrared: InfraredPort,
+    /// Set whenever the game reads the joypad after selecting a line, cleared by `take_input_polled()`
+    input_polled: bool,
+    /// See `EmulatorOptions::disable_shortcuts`
+    disable_shortcuts: bool,
+    /// See `EmulatorOptions::max_gdma_stall_cycles`
+    max_gdma_stall_cycles: u64,
 }
 
 impl Memory {
     pub fn new(rom_data: &[u8], emu_opts: EmulatorOptions) -> Self {
-        let cartridge = Cartridge::new(rom_data, emu_opts.saved_ram);
-        Memory {
-            boot_rom: BootRom::new(emu_opts.boot_rom.clone()),
-            ppu: PPU::new(
+        let mut cartridge = Cartridge::with_mbc_override(rom_data, emu_opts.saved_ram, emu_opts.mbc_override);
+        cartridge.set_log_bank_switches(emu_opts.log_bank_switches);
+        let force_cgb_rendering = emu_opts.force_cgb_rendering && emu_opts.emulator_mode.is_cgb();
+        let cgb_rendering =
+            emu_opts.emulator_mode.is_cgb() && (cartridge.cartridge_header().cgb_flag || emu_opts.boot_rom.is_some());
+        let display_colours = (
+            emu_opts.bg_display_colour,
+            emu_opts.sp0_display_colour,
+            emu_opts.sp1_display_colour,
+        );
+        let shared_tables = emu_opts.palette_tables.as_ref().filter(|tables| {
+            let matches = tables.is_created_from(display_colours.0, display_colours.1, display_colours.2);
+            if !matches {
+                log::warn!("The shared palette tables don't match the display colours, ignoring them");
+            }
+            matches
+        });
+        let mut ppu = if let Some(palette_tables) = shared_tables {
+            PPU::with_palette_tables(palette_tables, cgb_rendering, emu_opts.emulator_mode)
+        } else {
+            PPU::new(
                 emu_opts.bg_display_colour,
                 emu_opts.sp0_display_colour,
                 emu_opts.sp1_display_colour,
-                emu_opts.emulator_mode.is_cgb() && (cartridge.cartridge_header().cgb_flag || emu_opts.boot_rom.is_some()),
+                cgb_rendering,
                 emu_opts.emulator_mode,
-            ),
+            )
+        };
+        // We only set this after construction so that the CGB palettes are still initialised with
+        // the DMG display colours, otherwise a DMG ROM would never set any visible colours.
+        if force_cgb_rendering {
+            ppu.set_cgb_rendering(true);
+        }
+        ppu.set_strict_register_writes(emu_opts.strict_register_writes);
+        if !emu_opts.blank_initial_frame {
+            ppu.clear_frame_buffer(RGB::default());
+        }
+        ppu.set_supersampling(emu_opts.supersample_2x);
+        let mut apu = APU::new(emu_opts.sample_rate);
+        apu.set_mono(emu_opts.mono_audio);
+        apu.set_highpass_filter(emu_opts.highpass_filter);
+        apu.set_stereo_width(emu_opts.stereo_width);
+        apu.set_gain(emu_opts.audio_master_gain, emu_opts.audio_volume_step);
+        apu.prebuffer_silence(emu_opts.audio_prebuffer_ms);
+        // Matches what the boot rom would've written, in case it doesn't do so itself.
+        let cgb_switch_mode = if emu_opts.emulator_mode.is_cgb() && cartridge.cartridge_header().cgb_flag {
+            rom_data[0x143]
+        } else {
+            DMG_COMPATIBILITY_MODE
+        };
+
+        Memory {
+            boot_rom: BootRom::new(emu_opts.boot_rom.clone()),
+            ppu,
             cartridge,
             scheduler: Scheduler::new(),
             emulated_model: emu_opts.emulator_mode,
             cgb_data: CgbSpeedData::new(),
             hdma: HdmaRegister::new(),
-            apu: APU::new(),
+            cgb_undocumented: CgbUndocumentedRegisters::new(),
+            breakpoints: Breakpoints::new(),
+            cheats: CheatEngine::new(),
+            apu,
             hram: Hram::new(),
             wram: Wram::new(),
             joypad_register: JoyPad::new(),
             timers: Default::default(),
             interrupts: Default::default(),
             io_registers: IORegisters::new(),
+            gdma_stall: false,
+            force_cgb_rendering,
+            cgb_switch_mode,
+            serial_link: None,
+            infrared: InfraredPort::new(),
+            input_polled: false,
+            disable_shortcuts: emu_opts.disable_shortcuts,
+            max_gdma_stall_cycles: emu_opts.max_gdma_stall_cycles,
+        }
+    }
+
+    /// Create a new `Memory` for a restart of the system, carrying over the state which survives it.
+    ///
+    /// * A reset keeps the work ram, high ram and all external ram.
+    /// * A power cycle only keeps battery backed external ram, everything else is re-initialised.
+    ///
+    /// In both cases the RTC (being battery backed) and any connected devices are kept, while
+    /// all other hardware state (including MBC banking) is reset.
+    pub fn restart(&mut self, mut emu_opts: EmulatorOptions, power_cycle: bool) -> Memory {
+        emu_opts.saved_ram = if power_cycle {
+            self.cartridge.battery_ram().map(<[u8]>::to_vec)
+        } else {
+            Some(self.cartridge.ram().to_vec())
+        };
+        let mut memory = Memory::new(self.cartridge.rom(), emu_opts);
+
+        memory.cartridge.copy_rtc_from(&self.cartridge);
+        memory.serial_link = self.serial_link.take();
+        memory.infrared.device = self.infrared.device.take();
+        // Not part of the options, but shouldn't be forgotten by a reset either.
+        for channel in 0..4 {
+            memory.apu.set_channel_masked(channel, self.apu.channel_masked(channel));
         }
+
+        if !power_cycle {
+            memory.wram.copy_memory_from(&self.wram);
+            memory.hram.copy_memory_from(&self.hram);
+        }
+
+        memory
+    }
+
+    pub fn set_log_bank_switches(&mut self, enabled: bool) {
+        self.cartridge.set_log_bank_switches(enabled);
+    }
+
+    pub fn take_bank_switches(&mut self) -> Vec<BankSwitch> {
+        self.cartridge.take_bank_switches()
+    }
+
+    /// Returns whether the external ram of the cartridge was written to since the last call.
+    pub fn take_external_ram_dirty(&mut self) -> bool {
+        self.cartridge.take_ram_dirty()
+    }
+
+    /// Returns whether the game polled the joypad since the last call.
+    pub fn take_input_polled(&mut self) -> bool {
+        std::mem::replace(&mut self.input_polled, false)
     }
 
     pub fn read_byte(&mut self, address: u16) -> u8 {
+        let mut value = self.read_memory(address);
+
+        // Only once the game runs, so that the boot ROM's header checks aren't affected.
+        if self.cheats.patches_rom() && address <= ROM_BANK_NN_END && self.boot_rom.is_finished {
+            value = self.cheats.apply(address, value);
+        }
+
+        if !self.breakpoints.is_empty() {
+            self.breakpoints.check_read(address, value);
+        }
+
+        value
+    }
+
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        if self.breakpoints.is_empty() {
+            self.write_memory(address, value);
+        } else {
+            let old_value = if self.breakpoints.is_value_watched(address) {
+                self.peek_memory(address)
+            } else {
+                value
+            };
+            self.write_memory(address, value);
+            self.breakpoints.check_write(address, old_value, value);
+        }
+    }
+
+    fn read_memory(&mut self, address: u16) -> u8 {
         match address {
             0x0000..=0x00FF if !self.boot_rom.is_finished => self.boot_rom.read_byte(address),
             0x0200..=0x08FF if !self.boot_rom.is_finished && self.emulated_model.is_cgb() => {
@@ -202,7 +382,18 @@ impl Memory {
         }
     }
 
-    pub fn write_byte(&mut self, address: u16, value: u8) {
+    /// Read the `address` without the side effects a read by the game could have, such as the
+    /// joypad counting as polled.
+    ///
+    /// The APU does still catch up to the current cycle, but it'll do so for the write anyway.
+    fn peek_memory(&mut self, address: u16) -> u8 {
+        match address {
+            JOYPAD_REGISTER => self.joypad_register.get_register(),
+            _ => self.read_memory(address),
+        }
+    }
+
+    fn write_memory(&mut self, address: u16, value: u8) {
         match address {
             ROM_BANK_00_START..=ROM_BANK_NN_END => self.cartridge.write_byte(address, value),
             VRAM_START..=VRAM_END => self.ppu.write_vram(address, value, &mut self.scheduler, &mut self.interrupts),
@@ -222,7 +413,10 @@ impl Memory {
     /// Specific method for all calls to the IO registers.
     fn read_io_byte(&mut self, address: u16) -> u8 {
         match address {
-            JOYPAD_REGISTER => self.joypad_register.get_register(),
+            JOYPAD_REGISTER => {
+                self.input_polled |= self.joypad_register.line_selected();
+                self.joypad_register.get_register()
+            }
             SIO_DATA => self.io_registers.read_byte(address),
             SIO_CONT => self.io_registers.read_byte(address),
             DIVIDER_REGISTER => self.timers.divider_register(&self.scheduler),
@@ -238,6 +432,13 @@ impl Memory {
                     INVALID_READ
                 }
             }
+            CGB_SWITCH_MODE => {
+                if self.emulated_model.is_cgb() && !self.boot_rom.is_finished {
+                    self.cgb_switch_mode
+                } else {
+                    INVALID_READ
+                }
+            }
             0xFF4E => self.io_registers.read_byte(address),
             PPU_IO_START..=PPU_IO_END => self.ppu.read_vram(address),
             CGB_HDMA_1 | CGB_HDMA_2 | CGB_HDMA_3 | CGB_HDMA_4 => INVALID_READ,
@@ -248,9 +449,20 @@ impl Memory {
                     self.hdma.hdma5()
                 }
             }
-            CGB_RP => self.io_registers.read_byte(address),
+            CGB_RP => self.infrared.read_register(self.emulated_model.is_cgb()),
             PPU_CGB_IO_START..=PPU_CGB_IO_END => self.ppu.read_vram(address),
-            CGB_WRAM_BANK => self.wram.read_bank_select(),
+            CGB_WRAM_BANK => {
+                if self.emulated_model.is_cgb() {
+                    self.wram.read_bank_select()
+                } else {
+                    INVALID_READ
+                }
+            }
+            CGB_UNDOCUMENTED_START..=CGB_UNDOCUMENTED_END => self.cgb_undocumented.read_register(
+                address,
+                self.emulated_model.is_cgb(),
+                self.cartridge.cartridge_header().cgb_flag,
+            ),
             _ => self.io_registers.read_byte(address),
         }
     }
@@ -263,7 +475,10 @@ impl Memory {
         match address {
             JOYPAD_REGISTER => self.joypad_register.set_register(value),
             SIO_DATA => self.io_registers.write_byte(address, value),
-            SIO_CONT => self.io_registers.write_byte(address, value),
+            SIO_CONT => {
+                self.io_registers.write_byte(address, value);
+                self.start_serial_transfer(value);
+            }
             DIVIDER_REGISTER => self.timers.set_divider(&mut self.scheduler),
             TIMER_COUNTER..=TIMER_CONTROL => self.timers.write_register(address, value, &mut self.scheduler),
             INTERRUPTS_FLAG => self.interrupts.overwrite_if(value),
@@ -280,6 +495,10 @@ impl Memory {
             }
             DMA_TRANSFER => self.dma_transfer(value),
             CGB_PREPARE_SWITCH => self.cgb_data.write_prepare_switch(value),
+            CGB_SWITCH_MODE if self.emulated_model.is_cgb() && !self.boot_rom.is_finished => {
+                self.cgb_switch_mode = value
+            }
+            CGB_SWITCH_MODE => {}
             0xFF4E => self.io_registers.write_byte(address, value),
             PPU_IO_START..=PPU_IO_END => self.ppu.write_vram(address, value, &mut self.scheduler, &mut self.interrupts),
             CGB_HDMA_1 => self.hdma.write_hdma1(value),
@@ -290,21 +509,31 @@ impl Memory {
                 self.hdma.write_hdma5(value, &mut self.scheduler);
                 // If a HDMA is started during HBlank one copy occurs right away.
                 if self.ppu.get_current_mode() == Mode::Hblank {
-                    self.hdma_check_and_transfer()
+                    self.hdma_check_and_transfer();
                 }
             }
             BOOT_ROM_REGISTER => {
                 // Ignore writes to the bootrom register after the bootrom has been ran.
                 if !self.boot_rom.is_finished {
                     self.boot_rom.is_finished = true;
-                    // We may have ran the CGB bootrom, which requires CGB rendering.
-                    self.ppu.set_cgb_rendering(self.cartridge.cartridge_header().cgb_flag);
+                    // We may have ran the CGB bootrom, which selected the mode to continue in.
+                    let dmg_compatibility = (self.cgb_switch_mode & DMG_COMPATIBILITY_MODE) != 0;
+                    self.ppu
+                        .set_cgb_rendering(!dmg_compatibility || self.force_cgb_rendering);
                     info!("Finished executing BootRom!");
                 }
             }
-            CGB_RP => self.io_registers.write_byte(address, value),
+            CGB_RP => self.infrared.write_register(value, self.emulated_model.is_cgb()),
             PPU_CGB_IO_START..=PPU_CGB_IO_END => self.ppu.write_vram(address, value, &mut self.scheduler, &mut self.interrupts),
-            CGB_WRAM_BANK => self.wram.write_bank_select(value),
+            // WRAM (and thus echo RAM) is only banked on the CGB.
+            CGB_WRAM_BANK if self.emulated_model.is_cgb() => self.wram.write_bank_select(value),
+            CGB_WRAM_BANK => {}
+            CGB_UNDOCUMENTED_START..=CGB_UNDOCUMENTED_END => self.cgb_undocumented.write_register(
+                address,
+                value,
+                self.emulated_model.is_cgb(),
+                self.cartridge.cartridge_header().cgb_flag,
+            ),
             _ => self.io_registers.write_byte(address, value),
         }
     }
@@ -323,6 +552,31 @@ impl Memory {
         self.apu.synchronise(&mut self.scheduler, speed_multiplier);
     }
 
+    /// Write all GameShark codes directly to the RAM bank which is currently mapped.
+    ///
+    /// The codes can only target RAM, so unlike `write_byte()` this can't have any side effects
+    /// (such as scheduling events) while we're in the middle of executing the scheduled events.
+    fn apply_ram_pokes(&mut self) {
+        for poke in self.cheats.ram_pokes() {
+            match poke.address {
+                EXTERNAL_RAM_START..=EXTERNAL_RAM_END => self.cartridge.write_external_ram(poke.address, poke.value),
+                WRAM_BANK_00_START..=WRAM_BANK_00_END => self.wram.write_bank_0(poke.address, poke.value),
+                WRAM_BANK_NN_START..=WRAM_BANK_NN_END => self.wram.write_bank_n(poke.address, poke.value),
+                HRAM_START..=HRAM_END => self.hram.set_byte(poke.address, poke.value),
+                _ => unreachable!("GameShark code pokes a non-RAM address: 0x{:04X}", poke.address),
+            }
+        }
+    }
+
+    /// Synchronise the lazily updated components every M-cycle, if shortcuts are disabled.
+    #[inline(always)]
+    fn synchronise_unbatched(&mut self) {
+        if self.disable_shortcuts {
+            let speed_multiplier = self.get_speed_shift();
+            self.apu.synchronise(&mut self.scheduler, speed_multiplier);
+        }
+    }
+
     /// Executes any scheduled events if they should happen at this point in time.
     /// Returns true if a vblank interrupt happened.
     #[inline(always)]
@@ -342,6 +596,10 @@ impl Memory {
                     vblank_occurred = true;
                     // Used for APU syncing.
                     self.synchronise_state_for_vblank();
+
+                    if !self.cheats.ram_pokes().is_empty() {
+                        self.apply_ram_pokes();
+                    }
                 }
                 EventType::OamSearch => {
                     self.ppu.oam_search(&mut self.interrupts);
@@ -365,7 +623,9 @@ impl Memory {
                     self.scheduler.push_event(next_event, event.timestamp + (self.ppu.get_hblank_duration() << self.get_speed_shift()));
 
                     // HDMA transfers 16 bytes every HBLANK
-                    self.hdma_check_and_transfer();
+                    if self.hdma_check_and_transfer() {
+                        vblank_occurred = true;
+                    }
                 }
                 EventType::VblankWait => {
                     self.ppu.vblank_wait(&mut self.interrupts);
@@ -396,16 +656,11 @@ impl Memory {
                 }
                 EventType::GDMARequested => {
                     log::info!("Performing GDMA transfer at cycle: {}", self.scheduler.current_time);
-                    let mut clocks_to_wait =
-                        (self.hdma.transfer_size / 16) as u64 * if self.cgb_data.double_speed { 64 } else { 32 };
+                    let clocks_to_wait = (self.hdma.transfer_size / 16) as u64 * (GDMA_BLOCK_CYCLES << self.get_speed_shift());
                     self.scheduler.push_relative(EventType::GDMATransferComplete, clocks_to_wait);
+                    // The copy itself is instant, the CPU will be stalled until the above event fires.
                     self.gdma_transfer();
-                    while clocks_to_wait > 0 {
-                        if self.do_m_cycle() {
-                            vblank_occurred = true;
-                        }
-                        clocks_to_wait -= 4;
-                    }
+                    self.gdma_stall = true;
                 }
                 EventType::GDMATransferComplete => {
                     // If a new transfer is started without updating these registers they should
@@ -418,15 +673,101 @@ impl Memory {
                     self.hdma.destination_address += self.hdma.transfer_size;
 
                     self.hdma.complete_transfer();
+                    self.gdma_stall = false;
                 }
                 EventType::Y153TickToZero => {
                     self.ppu.late_y_153_to_0(&mut self.interrupts);
                 }
+                EventType::SerialTransferComplete => self.complete_serial_transfer(),
             };
         }
         vblank_occurred
     }
 
+    /// Schedule the completion of a transfer started with the internal clock, after 8 clock periods.
+    ///
+    /// With the external clock selected nothing happens until the other side drives the clock,
+    /// see `external_serial_transfer()`.
+    fn start_serial_transfer(&mut self, serial_control: u8) {
+        if (serial_control & 0x81) != 0x81 {
+            return;
+        }
+        let fast_clock = self.emulated_model.is_cgb() && (serial_control & 0x2) != 0;
+        let transfer_cycles = if fast_clock {
+            SERIAL_FAST_TRANSFER_CYCLES
+        } else {
+            SERIAL_TRANSFER_CYCLES
+        };
+
+        self.scheduler.remove_event_type(EventType::SerialTransferComplete);
+        self.scheduler.push_relative(EventType::SerialTransferComplete, transfer_cycles);
+    }
+
+    fn complete_serial_transfer(&mut self) {
+        let sent = self.io_registers.read_byte(SIO_DATA);
+        // Without anything connected the line is pulled high, so we just receive 0xFF.
+        let received = match &mut self.serial_link {
+            Some(link) => link.transfer(sent),
+            None => 0xFF,
+        };
+        let serial_control = self.io_registers.read_byte(SIO_CONT);
+
+        self.io_registers.write_byte(SIO_DATA, received);
+        self.io_registers.write_byte(SIO_CONT, serial_control & 0x7F);
+        self.interrupts.insert_interrupt(InterruptFlags::SERIAL);
+    }
+
+    /// Shift in the `received` byte clocked by the other side of the link, returning the byte
+    /// shifted out in exchange.
+    ///
+    /// Only a game awaiting a transfer with the external clock takes part, otherwise nothing
+    /// is shifted and the other side receives `0xFF`.
+    pub fn external_serial_transfer(&mut self, received: u8) -> u8 {
+        let serial_control = self.io_registers.read_byte(SIO_CONT);
+        if (serial_control & 0x81) != 0x80 {
+            return 0xFF;
+        }
+        let sent = self.io_registers.read_byte(SIO_DATA);
+
+        self.io_registers.write_byte(SIO_DATA, received);
+        self.io_registers.write_byte(SIO_CONT, serial_control & 0x7F);
+        self.interrupts.insert_interrupt(InterruptFlags::SERIAL);
+        sent
+    }
+
+    /// Keeps ticking the system while a GDMA transfer is stalling the CPU.
+    ///
+    /// This is done outside of `execute_scheduled_events` so that we never re-enter the event loop.
+    /// Returns true if a vblank interrupt happened in the meantime.
+    #[inline]
+    fn wait_for_gdma(&mut self) -> bool {
+        let start_time = self.scheduler.current_time;
+        let mut vblank_occurred = false;
+
+        while self.gdma_stall {
+            self.scheduler.add_cycles(4);
+            if self.execute_scheduled_events() {
+                vblank_occurred = true;
+            }
+            self.synchronise_unbatched();
+
+            // Should the completion event ever go missing we'd otherwise be stuck here forever.
+            if self.scheduler.current_time - start_time > self.max_gdma_stall_cycles {
+                log::error!(
+                    "GDMA stalled for more than {} cycles, resuming the CPU",
+                    self.max_gdma_stall_cycles
+                );
+                // Only a bug if the limit leaves room for a full transfer.
+                debug_assert!(
+                    self.max_gdma_stall_cycles < MAX_GDMA_STALL_CYCLES,
+                    "GDMA stall exceeded the maximum transfer length"
+                );
+                self.gdma_stall = false;
+            }
+        }
+        vblank_occurred
+    }
+
     /// Add a new interrupt to the IF flag.
     #[inline]
     pub fn add_new_interrupts(&mut self, interrupt: Option<InterruptFlags>) {
@@ -483,12 +824,71 @@ impl MemoryMapper for Memory {
 
     fn do_m_cycle(&mut self) -> bool {
         self.scheduler.add_cycles(4);
-        self.execute_scheduled_events()
+        let vblank_occurred = self.execute_scheduled_events();
+        self.synchronise_unbatched();
+        self.wait_for_gdma() || vblank_occurred
     }
 
     fn execute_next_event(&mut self) -> bool {
+        if self.disable_shortcuts {
+            return self.do_m_cycle();
+        }
         self.scheduler.skip_to_next_event();
-        self.execute_scheduled_events()
+        let vblank_occurred = self.execute_scheduled_events();
+        self.wait_for_gdma() || vblank_occurred
+    }
+
+    fn joypad_pressed(&self) -> bool {
+        (self.joypad_register.get_register() & 0x0F) != 0x0F
+    }
+
+    fn current_cycle(&self) -> u64 {
+        self.scheduler.current_time
+    }
+}
+
+impl SaveState for Memory {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.boot_rom.save_state(state);
+        self.cartridge.save_state(state);
+        self.scheduler.save_state(state);
+        self.cgb_data.save_state(state);
+        self.hdma.save_state(state);
+        self.cgb_undocumented.save_state(state);
+        self.ppu.save_state(state);
+        self.apu.save_state(state);
+        state.write_bytes(&self.hram.dump());
+        state.write_bytes(&self.wram.dump());
+        self.joypad_register.save_state(state);
+        self.timers.save_state(state);
+        self.interrupts.save_state(state);
+        self.io_registers.save_state(state);
+        self.infrared.save_state(state);
+        state.write_bool(self.gdma_stall);
+        state.write_u8(self.cgb_switch_mode);
+        state.write_bool(self.input_polled);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.boot_rom.load_state(state)?;
+        self.cartridge.load_state(state)?;
+        self.scheduler.load_state(state)?;
+        self.cgb_data.load_state(state)?;
+        self.hdma.load_state(state)?;
+        self.cgb_undocumented.load_state(state)?;
+        self.ppu.load_state(state)?;
+        self.apu.load_state(state)?;
+        self.hram.restore(state.read_bytes(HRAM_SIZE)?);
+        self.wram.restore(state.read_bytes(WRAM_SIZE + 1)?);
+        self.joypad_register.load_state(state)?;
+        self.timers.load_state(state)?;
+        self.interrupts.load_state(state)?;
+        self.io_registers.load_state(state)?;
+        self.infrared.load_state(state)?;
+        self.gdma_stall = state.read_bool()?;
+        self.cgb_switch_mode = state.read_u8()?;
+        self.input_polled = state.read_bool()?;
+        Ok(())
     }
 }
 
@@ -43,6 +43,34 @@ impl Wram {
         }
     }
 
+    /// Copy the contents of `other`, leaving the bank selection untouched.
+    pub fn copy_memory_from(&mut self, other: &Wram) {
+        self.memory = other.memory;
+    }
+
+    /// The raw contents of all 8 banks, regardless of the emulated model.
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// Returns the contents of all 8 banks followed by the bank select register (`SVBK`).
+    pub fn dump(&self) -> Vec<u8> {
+        let mut result = self.memory.to_vec();
+        result.push(self.bank_select);
+        result
+    }
+
+    /// Restore the state previously returned by [dump](#method.dump).
+    ///
+    /// # Panics
+    ///
+    /// If `data` is not exactly `WRAM_SIZE + 1` bytes long.
+    pub fn restore(&mut self, data: &[u8]) {
+        assert_eq!(data.len(), WRAM_SIZE + 1, "Invalid WRAM dump size");
+        self.memory.copy_from_slice(&data[..WRAM_SIZE]);
+        self.write_bank_select(data[WRAM_SIZE]);
+    }
+
     pub fn read_bank_select(&self) -> u8 {
         0xF8 | self.bank_select
     }
@@ -74,3 +102,56 @@ impl Wram {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::hardware::mmu::wram::{Wram, WRAM_SIZE};
+
+    #[test]
+    fn test_dump_restore_round_trip() {
+        let mut wram = Wram::new();
+        wram.write_bank_0(0xC000, 0x42);
+        for bank in 1..8 {
+            wram.write_bank_select(bank);
+            wram.write_bank_n(0xD000, bank * 0x10);
+            wram.write_bank_n(0xDFFF, bank);
+        }
+        wram.write_bank_select(5);
+
+        let dump = wram.dump();
+        assert_eq!(dump.len(), WRAM_SIZE + 1);
+
+        let mut restored = Wram::new();
+        restored.restore(&dump);
+        assert_eq!(restored.read_bank_select(), 0xFD);
+        assert_eq!(restored.read_bank_0(0xC000), 0x42);
+        assert_eq!(restored.read_bank_n(0xD000), 0x50);
+        for bank in 1..8 {
+            restored.write_bank_select(bank);
+            assert_eq!(restored.read_bank_n(0xD000), bank * 0x10);
+            assert_eq!(restored.read_bank_n(0xDFFF), bank);
+        }
+        assert_eq!(restored.memory(), wram.memory());
+    }
+
+    #[test]
+    fn test_echo_ram_follows_bank_select() {
+        let mut wram = Wram::new();
+        wram.write_bank_0(0xC000, 0x42);
+        wram.write_bank_select(3);
+        wram.write_echo_ram(0xFDFF, 0x33);
+
+        assert_eq!(wram.read_echo_ram(0xE000), 0x42);
+        assert_eq!(wram.read_bank_n(0xDDFF), 0x33);
+        wram.write_bank_select(4);
+        wram.write_bank_n(0xDDFF, 0x44);
+        assert_eq!(wram.read_echo_ram(0xFDFF), 0x44);
+        wram.write_bank_select(3);
+        assert_eq!(wram.read_echo_ram(0xFDFF), 0x33);
+        // Bank 0 selects bank 1.
+        wram.write_bank_select(1);
+        wram.write_bank_n(0xD000, 0x11);
+        wram.write_bank_select(0);
+        assert_eq!(wram.read_echo_ram(0xF000), 0x11);
+    }
+}
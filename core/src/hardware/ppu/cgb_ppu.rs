@@ -19,6 +19,7 @@ impl PPU {
             self.draw_cgb_bg_scanline()
         }
 
+        self.store_supersampling_background();
         if self.lcd_control.contains(LcdControl::SPRITE_DISPLAY_ENABLE) {
             self.draw_cgb_sprite_scanline();
         }
@@ -116,6 +117,14 @@ impl PPU {
             .take(10)
             .collect_vec(); // Max 10 sprites per scanline
 
+        // In coordinate priority mode a lower x-pos overwrites a higher one, like on the DMG.
+        // The sort is stable, so sprites with the same x-pos still fall back on their OAM order.
+        let sprites_to_draw = if self.cgb_object_priority {
+            sprites_to_draw.into_iter().sorted_by_key(|x| x.x_pos).collect_vec()
+        } else {
+            sprites_to_draw
+        };
+
         // Need to reverse here since we can't take rev() after take() :(
         // We reverse since the CGB sorts based on sprite position in OAM.
         for sprite in sprites_to_draw.into_iter().rev() {
@@ -158,14 +167,18 @@ impl PPU {
                     screen_x_pos + (7 - j)
                 };
 
-                if !always_display_sprite {
-                    if (pixel < 0)
-                        || (pixel > 159)
-                        || ((is_background_sprite || self.scanline_buffer_unpalette[pixel as usize].1)
-                            && self.scanline_buffer_unpalette[pixel as usize].0 != 0)
-                    {
-                        continue;
-                    }
+                // Off screen pixels should never be drawn, regardless of priority.
+                if (pixel < 0) || (pixel > 159) {
+                    continue;
+                }
+
+                // In CGB mode a reset BG_WINDOW_PRIORITY doesn't disable the background, it
+                // instead ensures sprites are always drawn on top.
+                if !always_display_sprite
+                    && ((is_background_sprite || self.scanline_buffer_unpalette[pixel as usize].1)
+                        && self.scanline_buffer_unpalette[pixel as usize].0 != 0)
+                {
+                    continue;
                 }
 
                 let colour = pixels[j as usize];
@@ -1,5 +1,6 @@
 use crate::hardware::ppu::palette::RGB;
 use crate::hardware::ppu::tiledata::BACKGROUND_TILE_SIZE;
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
 use bitflags::*;
 
 #[derive(Debug)]
@@ -42,6 +43,24 @@ impl CgbTileAttribute {
     pub fn set_bg_palette_numb(&mut self, value: u8) {
         self.bits = (self.bits & 0xF8) | (value & 0x7);
     }
+
+    /// Returns the VRAM bank (`0` or `1`) the tile data is fetched from.
+    pub fn vram_bank(&self) -> usize {
+        self.contains(CgbTileAttribute::TILE_VRAM_BANK_NUMBER) as usize
+    }
+
+    pub fn x_flipped(&self) -> bool {
+        self.contains(CgbTileAttribute::X_FLIP)
+    }
+
+    pub fn y_flipped(&self) -> bool {
+        self.contains(CgbTileAttribute::Y_FLIP)
+    }
+
+    /// Whether the tile is drawn above sprites (provided `LCDC.0` is set).
+    pub fn bg_priority(&self) -> bool {
+        self.contains(CgbTileAttribute::BG_TO_OAM_PRIORITY)
+    }
 }
 
 #[derive(Debug, Default, Copy, Clone)]
@@ -119,7 +138,7 @@ impl CgbRGBColour {
     }
 
     pub fn get_high_byte(&self) -> u8 {
-        (self.b5 << 2) | self.g5 & 0x3
+        (self.b5 << 2) | (self.g5 >> 3)
     }
 
     pub fn get_low_byte(&self) -> u8 {
@@ -127,6 +146,40 @@ impl CgbRGBColour {
     }
 }
 
+impl SaveState for CgbPaletteIndex {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.get_value());
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.set_value(state.read_u8()?);
+        Ok(())
+    }
+}
+
+impl SaveState for CgbPalette {
+    fn save_state(&self, state: &mut StateWriter) {
+        for colour in self.colours.iter() {
+            // The RGB value is saved separately, as the DMG display colours are loaded into it
+            // without setting the 15 bit colour.
+            colour.rgb.save_state(state);
+            state.write_u8(colour.get_low_byte());
+            state.write_u8(colour.get_high_byte());
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        for colour in self.colours.iter_mut() {
+            let mut rgb = RGB::default();
+            rgb.load_state(state)?;
+            colour.set_low_byte(state.read_u8()?);
+            colour.set_high_byte(state.read_u8()?);
+            colour.rgb = rgb;
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::hardware::ppu::cgb_vram::{CgbRGBColour, CgbTileAttribute};
@@ -144,6 +197,17 @@ mod tests {
         assert_eq!(attr.bg_palette_numb(), 7);
     }
 
+    #[test]
+    fn test_decoded_attributes() {
+        let attr = CgbTileAttribute::from_bits_truncate(0b1010_1101);
+
+        assert_eq!(attr.bg_palette_numb(), 5);
+        assert_eq!(attr.vram_bank(), 1);
+        assert!(attr.x_flipped());
+        assert!(!attr.y_flipped());
+        assert!(attr.bg_priority());
+    }
+
     #[test]
     fn test_cgb_rgb() {
         let mut rgb = CgbRGBColour::default();
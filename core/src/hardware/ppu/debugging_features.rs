@@ -1,12 +1,51 @@
 use bitflags::_core::iter::FromIterator;
 
 use crate::gb_emu::GameBoyModel;
-use crate::hardware::ppu::cgb_vram::CgbPalette;
+use crate::hardware::ppu::cgb_vram::{CgbPalette, CgbTileAttribute};
+use crate::hardware::ppu::memory_binds::*;
 use crate::hardware::ppu::palette::RGB;
+use crate::hardware::ppu::register_flags::{AttributeFlags, LcdControl};
 use crate::hardware::ppu::tiledata::Tile;
-use crate::hardware::ppu::PPU;
+use crate::hardware::ppu::{FRAMEBUFFER_SIZE, PPU, RESOLUTION_HEIGHT, RESOLUTION_WIDTH};
+
+/// The outline colour for sprites drawn above the background.
+pub const SPRITE_BOX_COLOUR: RGB = RGB(255, 0, 0);
+/// The outline colour for sprites with `OBJ_TO_BG_PRIORITY` set, drawn behind the background.
+pub const SPRITE_BOX_BEHIND_BG_COLOUR: RGB = RGB(0, 128, 255);
+
+/// The width and height in pixels of a rendered tile map (32x32 tiles).
+pub const TILEMAP_RESOLUTION: usize = 256;
+
+/// Selects one of the two background tile maps in VRAM.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TileMapSelect {
+    /// The tile map at `0x9800..=0x9BFF`
+    Map9800,
+    /// The tile map at `0x9C00..=0x9FFF`
+    Map9C00,
+}
 
 impl PPU {
+    /// Returns all PPU registers as the CPU would read them right now, e.g. to compare against
+    /// the expected values at a specific cycle in a test.
+    pub fn register_snapshot(&self) -> PpuRegisters {
+        PpuRegisters {
+            lcdc: self.read_vram(LCD_CONTROL_REGISTER),
+            stat: self.read_vram(LCD_STATUS_REGISTER),
+            scy: self.read_vram(SCY_REGISTER),
+            scx: self.read_vram(SCX_REGISTER),
+            ly: self.read_vram(LY_REGISTER),
+            lyc: self.read_vram(LYC_REGISTER),
+            wy: self.read_vram(WY_REGISTER),
+            wx: self.read_vram(WX_REGISTER),
+            bgp: self.read_vram(BG_PALETTE),
+            obp0: self.read_vram(OB_PALETTE_0),
+            obp1: self.read_vram(OB_PALETTE_1),
+            bcps: self.read_vram(CGB_BACKGROUND_COLOR_INDEX),
+            ocps: self.read_vram(CGB_SPRITE_COLOR_INDEX),
+        }
+    }
+
     /// Returns an array of the full 768 tiles rendered next to each other in a
     /// 128 * 384 RGB pixel array. (16 tiles per line)
     pub fn tiles_cgb(&self) -> [RGB; 49152] {
@@ -56,6 +95,285 @@ impl PPU {
 
         result
     }
+
+    /// Returns the selected tile map rendered as a 256x256 RGB pixel array, using the current
+    /// tile data addressing mode (`BG_WINDOW_TILE_SELECT`) and background palette.
+    ///
+    /// Only the tiles in VRAM bank 0 are used, CGB attributes (bank, flips and palette) are ignored.
+    pub fn render_tilemap(&self, which: TileMapSelect) -> Vec<RGB> {
+        let mut result = vec![RGB::default(); TILEMAP_RESOLUTION * TILEMAP_RESOLUTION];
+        let tile_map = match which {
+            TileMapSelect::Map9800 => &self.tile_map_9800,
+            TileMapSelect::Map9C00 => &self.tile_map_9c00,
+        };
+
+        for (map_index, &tile_index) in tile_map.data.iter().enumerate() {
+            // Same addressing as during rendering, 8800-97FF mode uses signed tile indices.
+            let tile_address = if self.lcd_control.contains(LcdControl::BG_WINDOW_TILE_SELECT) {
+                tile_index as usize
+            } else {
+                (256_usize).wrapping_add((tile_index as i8) as usize)
+            };
+            let rendered_tile = self.render_tile(&self.tiles[tile_address]);
+            let tile_x = (map_index % 32) * 8;
+            let tile_y = (map_index / 32) * 8;
+
+            for (index, colour) in rendered_tile.iter().enumerate() {
+                result[(tile_y + index / 8) * TILEMAP_RESOLUTION + tile_x + (index % 8)] = *colour;
+            }
+        }
+
+        result
+    }
+
+    /// Returns the 32x32 CGB attribute grid (stored in VRAM bank 1) of the selected tile map,
+    /// in row-major order.
+    ///
+    /// In DMG mode these attributes exist, but are ignored during rendering.
+    pub fn cgb_tilemap_attributes(&self, which: TileMapSelect) -> Vec<CgbTileAttribute> {
+        let tile_map = match which {
+            TileMapSelect::Map9800 => &self.cgb_9800_tile_map,
+            TileMapSelect::Map9C00 => &self.cgb_9c00_tile_map,
+        };
+
+        tile_map.attributes.to_vec()
+    }
+
+    /// Returns a copy of the last completed frame with a 1 pixel outline around every sprite in OAM,
+    /// using the current sprite size (8x8 or 8x16).
+    ///
+    /// Sprites which are behind the background are outlined in
+    /// [SPRITE_BOX_BEHIND_BG_COLOUR](constant.SPRITE_BOX_BEHIND_BG_COLOUR.html), all others in
+    /// [SPRITE_BOX_COLOUR](constant.SPRITE_BOX_COLOUR.html). Partially off-screen sprites are
+    /// clipped, while fully hidden sprites aren't drawn at all.
+    pub fn render_with_sprite_boxes(&self) -> Vec<RGB> {
+        let mut result = self.frame_buffer().to_vec();
+        let height = if self.lcd_control.contains(LcdControl::SPRITE_SIZE) { 16 } else { 8 };
+
+        for sprite in self.oam.iter() {
+            // OAM positions are offset by (8, 16) from the top left of the screen.
+            let left = sprite.x_pos as i32 - 8;
+            let top = sprite.y_pos as i32 - 16;
+            let (right, bottom) = (left + 7, top + height - 1);
+
+            if right < 0 || bottom < 0 || left >= RESOLUTION_WIDTH as i32 || top >= RESOLUTION_HEIGHT as i32 {
+                continue;
+            }
+
+            let colour = if sprite.attribute_flags.contains(AttributeFlags::OBJ_TO_BG_PRIORITY) {
+                SPRITE_BOX_BEHIND_BG_COLOUR
+            } else {
+                SPRITE_BOX_COLOUR
+            };
+            let mut plot = |x: i32, y: i32| {
+                if x >= 0 && y >= 0 && x < RESOLUTION_WIDTH as i32 && y < RESOLUTION_HEIGHT as i32 {
+                    result[y as usize * RESOLUTION_WIDTH + x as usize] = colour;
+                }
+            };
+
+            for x in left..=right {
+                plot(x, top);
+                plot(x, bottom);
+            }
+            for y in top..=bottom {
+                plot(left, y);
+                plot(right, y);
+            }
+        }
+
+        result
+    }
+
+    /// The internal line counter of the window, which determines which line of the window
+    /// tile map is rendered next. Only incremented on lines where the window was actually drawn.
+    pub fn window_line_counter(&self) -> u8 {
+        self.window_counter
+    }
+
+    /// Whether `WY` matched `LY` at some point during the current frame, allowing the window
+    /// to be drawn for the remainder of the frame.
+    pub fn window_triggered(&self) -> bool {
+        self.window_triggered
+    }
+
+    /// Whether the window is currently being drawn, i.e. it has been triggered this frame and
+    /// `WINDOW_DISPLAY` is set.
+    ///
+    /// Doesn't take `WX` into account, a window placed off-screen is still considered active.
+    pub fn window_active(&self) -> bool {
+        self.window_triggered && self.lcd_control.contains(LcdControl::WINDOW_DISPLAY)
+    }
+
+    /// The raw `SCX` register.
+    pub fn scroll_x(&self) -> u8 {
+        self.scroll_x
+    }
+
+    /// The raw `SCY` register.
+    pub fn scroll_y(&self) -> u8 {
+        self.scroll_y
+    }
+
+    /// The raw `WX` register, note that the window is drawn at `WX - 7`.
+    pub fn window_x(&self) -> u8 {
+        self.window_x
+    }
+
+    /// The raw `WY` register.
+    pub fn window_y(&self) -> u8 {
+        self.window_y
+    }
+
+    /// Returns the raw colour indices (`0..=3`) of the most recently rendered scanline,
+    /// before any palette was applied.
+    ///
+    /// Useful for figuring out whether a rendering bug lies in the tile fetching or the
+    /// palette mapping. Note that this only reflects the last *completed* scanline, not the
+    /// full frame.
+    pub fn scanline_indices(&self) -> [u8; RESOLUTION_WIDTH] {
+        let mut result = [0; RESOLUTION_WIDTH];
+
+        for (index, (colour, _)) in result.iter_mut().zip(self.scanline_buffer_unpalette.iter()) {
+            *index = *colour;
+        }
+
+        result
+    }
+}
+
+/// All the (internal) state relevant for debugging window rendering.
+#[derive(Debug, Default, Copy, Clone, PartialOrd, PartialEq)]
+pub struct WindowDebugInfo {
+    pub window_x: u8,
+    pub window_y: u8,
+    pub line_counter: u8,
+    pub triggered: bool,
+    /// The `WINDOW_DISPLAY` bit in `LCDC`
+    pub enabled: bool,
+    /// See [window_active](../struct.PPU.html#method.window_active).
+    pub active: bool,
+}
+
+impl WindowDebugInfo {
+    pub fn new(ppu: &PPU) -> Self {
+        WindowDebugInfo {
+            window_x: ppu.window_x(),
+            window_y: ppu.window_y(),
+            line_counter: ppu.window_line_counter(),
+            triggered: ppu.window_triggered(),
+            enabled: ppu.lcd_control.contains(LcdControl::WINDOW_DISPLAY),
+            active: ppu.window_active(),
+        }
+    }
+}
+
+/// The values of the PPU registers, see [register_snapshot](../struct.PPU.html#method.register_snapshot).
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct PpuRegisters {
+    pub lcdc: u8,
+    pub stat: u8,
+    pub scy: u8,
+    pub scx: u8,
+    pub ly: u8,
+    pub lyc: u8,
+    pub wy: u8,
+    pub wx: u8,
+    pub bgp: u8,
+    pub obp0: u8,
+    pub obp1: u8,
+    /// The CGB background palette index.
+    pub bcps: u8,
+    /// The CGB object palette index.
+    pub ocps: u8,
+}
+
+/// The background tile map currently selected by `LCDC`, along with the scroll and window
+/// positions. Enough to draw a minimap with the visible area outlined.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackgroundSnapshot {
+    /// The rendered background tile map, `width` * `height` pixels in row-major order.
+    pub tiles: Vec<RGB>,
+    pub width: usize,
+    pub height: usize,
+    pub scroll_x: u8,
+    pub scroll_y: u8,
+    /// The raw `WX` register, the window is drawn at `WX - 7`.
+    pub window_x: u8,
+    pub window_y: u8,
+}
+
+impl BackgroundSnapshot {
+    pub fn new(ppu: &PPU) -> Self {
+        let which = if ppu.lcd_control.contains(LcdControl::BG_TILE_MAP_SELECT) {
+            TileMapSelect::Map9C00
+        } else {
+            TileMapSelect::Map9800
+        };
+
+        BackgroundSnapshot {
+            tiles: ppu.render_tilemap(which),
+            width: TILEMAP_RESOLUTION,
+            height: TILEMAP_RESOLUTION,
+            scroll_x: ppu.scroll_x(),
+            scroll_y: ppu.scroll_y(),
+            window_x: ppu.window_x(),
+            window_y: ppu.window_y(),
+        }
+    }
+}
+
+/// Accumulates how often every pixel changed between consecutive frames, handy for spotting
+/// unintended flicker or telling static and dynamic regions apart.
+#[derive(Debug, Clone)]
+pub struct FrameHeatmap {
+    previous_frame: Option<Box<[RGB; FRAMEBUFFER_SIZE]>>,
+    change_counts: Vec<u32>,
+    frames_compared: u32,
+}
+
+impl FrameHeatmap {
+    pub fn new() -> Self {
+        FrameHeatmap {
+            previous_frame: None,
+            change_counts: vec![0; FRAMEBUFFER_SIZE],
+            frames_compared: 0,
+        }
+    }
+
+    /// Compare the `frame` to the previously added one, and count every pixel which changed.
+    pub fn add_frame(&mut self, frame: &[RGB; FRAMEBUFFER_SIZE]) {
+        match &mut self.previous_frame {
+            Some(previous) => {
+                for ((count, old), new) in self.change_counts.iter_mut().zip(previous.iter()).zip(frame.iter()) {
+                    *count += (old != new) as u32;
+                }
+                previous.copy_from_slice(frame);
+                self.frames_compared += 1;
+            }
+            None => self.previous_frame = Some(Box::new(*frame)),
+        }
+    }
+
+    /// Returns, for every pixel, how often it changed between `0` (never) and `255` (every frame).
+    pub fn heatmap(&self) -> Vec<u8> {
+        let frames = self.frames_compared.max(1) as u64;
+
+        self.change_counts
+            .iter()
+            .map(|&count| (count as u64 * 255 / frames) as u8)
+            .collect()
+    }
+
+    /// Clear all accumulated changes, the next frame will only serve as the new reference.
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+impl Default for FrameHeatmap {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[derive(Debug, Clone, PartialOrd, PartialEq)]
@@ -111,3 +429,27 @@ impl FromIterator<RGB> for [RGB; 4] {
         result
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::hardware::ppu::debugging_features::FrameHeatmap;
+    use crate::hardware::ppu::palette::RGB;
+    use crate::hardware::ppu::FRAMEBUFFER_SIZE;
+
+    #[test]
+    fn test_frame_heatmap() {
+        // Change the first pixel in every other frame.
+        let mut heatmap = FrameHeatmap::new();
+        let mut frame = [RGB::default(); FRAMEBUFFER_SIZE];
+        for i in 0..5 {
+            frame[0] = RGB((i / 2) as u8, 0, 0);
+            heatmap.add_frame(&frame);
+        }
+        assert_eq!(heatmap.heatmap()[0], 127);
+        assert_eq!(heatmap.heatmap()[1], 0);
+
+        heatmap.reset();
+        heatmap.add_frame(&frame);
+        assert_eq!(heatmap.heatmap()[0], 0);
+    }
+}
@@ -87,6 +87,21 @@ pub const CGB_OBJECT_PALETTE_DATA: u16 = 0xFF6B;
 /// This flag is set by the CGB bios after checking the game's CGB compatibility.
 pub const CGB_OBJECT_PRIORITY_MODE: u16 = 0xFF6C;
 
+/// Returns the bits of the provided PPU register which can be written to by the CPU.
+///
+/// Any bit not in the mask is either read-only (like the mode bits of `STAT`, or the entirety
+/// of `LY`) or unused. Addresses which aren't PPU registers are fully writable.
+pub fn register_write_mask(address: u16) -> u8 {
+    match address {
+        LCD_STATUS_REGISTER => 0x78,
+        LY_REGISTER => 0x00,
+        CGB_VRAM_BANK_REGISTER => 0x01,
+        CGB_BACKGROUND_COLOR_INDEX | CGB_SPRITE_COLOR_INDEX => 0xBF,
+        CGB_OBJECT_PRIORITY_MODE => 0x01,
+        _ => 0xFF,
+    }
+}
+
 impl PPU {
     pub fn synchronise(&mut self, scheduler: &mut Scheduler) {
         unimplemented!()
@@ -124,7 +139,16 @@ impl PPU {
     }
 
     #[inline]
-    pub fn write_vram(&mut self, address: u16, value: u8, scheduler: &mut Scheduler, interrupts: &mut Interrupts) {
+    pub fn write_vram(&mut self, address: u16, mut value: u8, scheduler: &mut Scheduler, interrupts: &mut Interrupts) {
+        if self.strict_register_writes {
+            match register_write_mask(address) {
+                0x00 => return,
+                0xFF => {}
+                // Keep the current state of the read-only bits, so the register itself can't touch them.
+                write_mask => value = (self.read_vram(address) & !write_mask) | (value & write_mask),
+            }
+        }
+
         //TODO: Reimplement, however it seems to break CGB bootrom (and tests don't seem to mind)?:
         //TILE_BLOCK_0_START..=TILE_BLOCK_2_END if self.can_access_vram()
         //TILEMAP_9800_START..=TILEMAP_9C00_END if self.can_access_vram()
@@ -277,9 +301,9 @@ impl PPU {
         let addr = self.cgb_bg_palette_ind.selected_address;
 
         if addr % 2 == 0 {
-            self.cgb_bg_palette[addr / 8].colours[(addr % 8) / 2].get_high_byte()
-        } else {
             self.cgb_bg_palette[addr / 8].colours[(addr % 8) / 2].get_low_byte()
+        } else {
+            self.cgb_bg_palette[addr / 8].colours[(addr % 8) / 2].get_high_byte()
         }
     }
 
@@ -301,9 +325,9 @@ impl PPU {
         let addr = self.cgb_sprite_palette_ind.selected_address;
 
         if addr % 2 == 0 {
-            self.cgb_sprite_palette[addr / 8].colours[(addr % 8) / 2].get_high_byte()
-        } else {
             self.cgb_sprite_palette[addr / 8].colours[(addr % 8) / 2].get_low_byte()
+        } else {
+            self.cgb_sprite_palette[addr / 8].colours[(addr % 8) / 2].get_high_byte()
         }
     }
 
@@ -363,6 +387,8 @@ impl PPU {
         if self.emulated_model.is_dmg() {
             // Enable all interrupt flags.
             self.lcd_status = LcdStatus::from_bits_truncate(0xF8 | read_only_bits);
+            // As the STAT line then stays high until the next mode change, repeated writes within
+            // the same mode won't see a rising edge and thus only trigger the interrupt once.
             self.request_stat_interrupt(interrupts);
             // Mask bit 3..=6 in case a game tries to write to the three lower bits as well.
             self.lcd_status = LcdStatus::from_bits_truncate(0x80 | (value & 0x78) | read_only_bits);
@@ -424,9 +450,9 @@ impl PPU {
     ) {
         // We don't want to overwrite CGB registers if we're actually running a CGB game.
         if !self.cgb_rendering {
-            let (cgb_bg_palette, cgb_sprite_palette) = initialise_cgb_palette(bg_palette, sp0_palette, sp1_palette);
-            self.cgb_bg_palette = cgb_bg_palette;
-            self.cgb_sprite_palette = cgb_sprite_palette;
+            let palette_tables = PaletteTables::new(bg_palette, sp0_palette, sp1_palette);
+            self.cgb_bg_palette = palette_tables.bg_palette;
+            self.cgb_sprite_palette = palette_tables.sprite_palette;
             self.set_bg_palette(self.bg_window_palette.into());
             self.set_oam_palette_0(self.oam_palette_0.into());
             self.set_oam_palette_1(self.oam_palette_1.into());
@@ -436,6 +462,17 @@ impl PPU {
     pub fn set_cgb_rendering(&mut self, cgb_rendering: bool) {
         self.cgb_rendering = cgb_rendering;
     }
+
+    /// Whether the CGB renderer is used, `false` for DMG (compatibility) rendering.
+    pub fn cgb_rendering(&self) -> bool {
+        self.cgb_rendering
+    }
+
+    /// Whether to ignore all writes to read-only register bits, instead of leaving it up
+    /// to the individual registers.
+    pub fn set_strict_register_writes(&mut self, strict_register_writes: bool) {
+        self.strict_register_writes = strict_register_writes;
+    }
 }
 
 /// Get the internal PPU address for a tile from a normal u16 address.
@@ -2,18 +2,25 @@ use itertools::Itertools;
 use num_integer::Integer;
 
 use crate::gb_emu::GameBoyModel;
-use crate::hardware::ppu::cgb_vram::{CgbPalette, CgbPaletteIndex, CgbTileMap};
-use crate::hardware::ppu::palette::{DisplayColour, Palette, RGB};
+use crate::hardware::ppu::cgb_vram::{CgbPalette, CgbPaletteIndex, CgbTileAttribute, CgbTileMap};
+use crate::hardware::ppu::palette::{DisplayColour, Palette, PaletteTables, RGB};
 use crate::hardware::ppu::register_flags::*;
+use crate::hardware::ppu::supersampling::Supersampler;
 use crate::hardware::ppu::tiledata::*;
 use crate::hardware::ppu::Mode::{Hblank, LcdTransfer, OamSearch, Vblank};
 use crate::io::interrupts::{InterruptFlags, Interrupts};
 use crate::scheduler::{EventType, Scheduler};
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
 
 pub const RESOLUTION_WIDTH: usize = 160;
 pub const RESOLUTION_HEIGHT: usize = 144;
 pub const RGB_CHANNELS: usize = 3;
 pub const FRAMEBUFFER_SIZE: usize = RESOLUTION_HEIGHT * RESOLUTION_WIDTH;
+/// The value of `BGP` after the bootrom has finished.
+pub const INITIAL_BG_PALETTE: u8 = 0xFC;
+/// The value of `OBP0`/`OBP1` after the bootrom has finished. These are left uninitialised by the
+/// bootrom, but read as `0xFF` on most units.
+pub const INITIAL_OB_PALETTE: u8 = 0xFF;
 
 pub mod cgb_ppu;
 pub mod cgb_vram;
@@ -22,9 +29,13 @@ pub mod dma;
 pub mod memory_binds;
 pub mod palette;
 pub mod register_flags;
+pub mod supersampling;
 pub mod tiledata;
 pub mod timing;
 
+#[cfg(test)]
+mod tests;
+
 #[derive(Debug, PartialOrd, PartialEq, Copy, Clone)]
 pub enum Mode {
     Hblank = 0x0,
@@ -34,10 +45,15 @@ pub enum Mode {
 }
 
 pub struct PPU {
+    /// The back buffer, which scanlines are pushed to as they're rendered.
     frame_buffer: [RGB; FRAMEBUFFER_SIZE],
+    /// The last completed frame, copied over from `frame_buffer` on V-blank.
+    presented_frame_buffer: Box<[RGB; FRAMEBUFFER_SIZE]>,
     scanline_buffer: [RGB; RESOLUTION_WIDTH],
     // Bool is used for BG-to-OAM priority
     scanline_buffer_unpalette: [(u8, bool); RESOLUTION_WIDTH],
+    /// Only present if the experimental 2x rendering is enabled.
+    supersampler: Option<Box<Supersampler>>,
     // 768 tiles for CGB mode, 384 for DMG mode.
     tiles: [Tile; 768],
     tile_bank_currently_used: u8,
@@ -75,6 +91,8 @@ pub struct PPU {
     stat_irq_triggered: bool,
     /// Whether to use the CGB scanline renderer
     cgb_rendering: bool,
+    /// Whether to ignore all writes to read-only register bits, see `register_write_mask()`
+    strict_register_writes: bool,
     emulated_model: GameBoyModel,
     /// Advanced timing and synchronisation.
     latest_lcd_transfer_start: u64,
@@ -95,15 +113,25 @@ impl PPU {
         cgb_rendering: bool,
         gb_model: GameBoyModel,
     ) -> Self {
+        let palette_tables = PaletteTables::new(bg_display_colour, sp0_display, sp1_display);
+        Self::with_palette_tables(&palette_tables, cgb_rendering, gb_model)
+    }
+
+    /// Instantiates a PPU with already initialised `PaletteTables`, see [`PPU::new`].
+    pub fn with_palette_tables(palette_tables: &PaletteTables, cgb_rendering: bool, gb_model: GameBoyModel) -> Self {
         let (cgb_bg_palette, cgb_sprite_palette) = if !cgb_rendering {
-            initialise_cgb_palette(bg_display_colour, sp0_display, sp1_display)
+            (palette_tables.bg_palette, palette_tables.sprite_palette)
         } else {
             ([CgbPalette::default(); 8], [CgbPalette::default(); 8])
         };
+        // Until the first frame is drawn the LCD should look blank, rather than black.
+        let blank_colour = palette_tables.bg_palette[0].colours[0].rgb;
         PPU {
-            frame_buffer: [RGB::default(); FRAMEBUFFER_SIZE],
+            frame_buffer: [blank_colour; FRAMEBUFFER_SIZE],
+            presented_frame_buffer: Box::new([blank_colour; FRAMEBUFFER_SIZE]),
             scanline_buffer: [RGB::default(); RESOLUTION_WIDTH],
             scanline_buffer_unpalette: [(0, false); RESOLUTION_WIDTH],
+            supersampler: None,
             tiles: [Tile::default(); 768],
             tile_bank_currently_used: 0,
             tile_map_9800: TileMap::new(),
@@ -113,9 +141,9 @@ impl PPU {
             oam: [SpriteAttribute::default(); 40],
             lcd_control: LcdControl::from_bits_truncate(0b1001_0011),
             lcd_status: LcdStatus::from_bits_truncate(0b1000_0001),
-            bg_window_palette: Palette::new(0b1110_0100, DisplayColour::from(cgb_bg_palette[0].rgb())),
-            oam_palette_0: Palette::new(0b1110_0100, DisplayColour::from(cgb_sprite_palette[0].rgb())),
-            oam_palette_1: Palette::new(0b1110_0100, DisplayColour::from(cgb_sprite_palette[1].rgb())),
+            bg_window_palette: Palette::new(INITIAL_BG_PALETTE, DisplayColour::from(cgb_bg_palette[0].rgb())),
+            oam_palette_0: Palette::new(INITIAL_OB_PALETTE, DisplayColour::from(cgb_sprite_palette[0].rgb())),
+            oam_palette_1: Palette::new(INITIAL_OB_PALETTE, DisplayColour::from(cgb_sprite_palette[1].rgb())),
             cgb_bg_palette_ind: CgbPaletteIndex::default(),
             cgb_sprite_palette_ind: CgbPaletteIndex::default(),
             cgb_bg_palette,
@@ -129,9 +157,11 @@ impl PPU {
             window_counter: 0,
             window_triggered: false,
             oam_transfer_ongoing: false,
-            cgb_object_priority: true,
+            // Games running in CGB mode get OAM priority, as the CGB bootrom would set up.
+            cgb_object_priority: !cgb_rendering,
             stat_irq_triggered: false,
             cgb_rendering,
+            strict_register_writes: false,
             emulated_model: gb_model,
             latest_lcd_transfer_start: 0,
             current_lcd_transfer_duration: 0,
@@ -174,6 +204,11 @@ impl PPU {
 
     pub fn vblank(&mut self, interrupts: &mut Interrupts) {
         self.lcd_status.set_mode_flag(Vblank);
+        // Only expose complete frames, so a frame can't be read while it's half drawn.
+        self.presented_frame_buffer.copy_from_slice(&self.frame_buffer);
+        if let Some(supersampler) = &mut self.supersampler {
+            supersampler.present();
+        }
 
         // Check for line 144 lyc.
         self.increment_current_y(interrupts);
@@ -204,6 +239,19 @@ impl PPU {
         let current_address: usize = self.current_y as usize * RESOLUTION_WIDTH;
         // Copy the value of the current scanline to the framebuffer.
         self.frame_buffer[current_address..current_address + RESOLUTION_WIDTH].copy_from_slice(&self.scanline_buffer);
+
+        if let Some(supersampler) = &mut self.supersampler {
+            supersampler.push_scanline(self.current_y, &self.scanline_buffer);
+        }
+    }
+
+    /// Remember the background and window of the current scanline for the 2x rendering, should be
+    /// called right before the sprites are drawn.
+    #[inline]
+    fn store_supersampling_background(&mut self) {
+        if let Some(supersampler) = &mut self.supersampler {
+            supersampler.set_background(&self.scanline_buffer);
+        }
     }
 
     #[inline(always)]
@@ -234,12 +282,18 @@ impl PPU {
                 self.draw_bg_scanline()
             }
         } else {
+            // On the DMG a reset BG_WINDOW_PRIORITY disables both the background and window,
+            // which is then considered colour 0 for sprite priority purposes as well.
             let bg_colour = self.bg_window_palette.colours[0];
             for pixel in self.scanline_buffer.iter_mut() {
                 *pixel = bg_colour;
             }
+            for pixel in self.scanline_buffer_unpalette.iter_mut() {
+                *pixel = (0, false);
+            }
         }
 
+        self.store_supersampling_background();
         if self.lcd_control.contains(LcdControl::SPRITE_DISPLAY_ENABLE) {
             self.draw_sprite_scanline();
         }
@@ -491,32 +545,162 @@ impl PPU {
         self.request_stat_interrupt(interrupts);
     }
 
+    /// Returns the last completed frame, the frame currently being drawn is never exposed.
     pub fn frame_buffer(&self) -> &[RGB; FRAMEBUFFER_SIZE] {
-        &self.frame_buffer
+        &self.presented_frame_buffer
     }
-}
 
-/// Initialises BG0, OBJ0, OBJ1 in the CGB palettes to `dmg_display_colour` while leaving
-/// the remaining palettes default. See PPU `new()` for an explanation as to why.
-fn initialise_cgb_palette(
-    bg_display: DisplayColour,
-    sp0_display: DisplayColour,
-    sp1_display: DisplayColour,
-) -> ([CgbPalette; 8], [CgbPalette; 8]) {
-    fn set_cgb_palette(p: &mut CgbPalette, dmg_display_colour: DisplayColour) {
-        for (i, colour) in p.colours.iter_mut().enumerate() {
-            colour.rgb = dmg_display_colour.get_colour(i);
+    /// Fill both the frame being drawn and the last completed frame with `colour`.
+    pub fn clear_frame_buffer(&mut self, colour: RGB) {
+        self.frame_buffer = [colour; FRAMEBUFFER_SIZE];
+        *self.presented_frame_buffer = [colour; FRAMEBUFFER_SIZE];
+        if let Some(supersampler) = &mut self.supersampler {
+            supersampler.clear(colour);
+        }
+    }
+
+    /// **Experimental**, enable or disable rendering at twice the native resolution, see the
+    /// [supersampling](supersampling/index.html) module. Disabled by default.
+    ///
+    /// The 2x frame starts out as the current frame buffer's first colour until a frame is completed.
+    pub fn set_supersampling(&mut self, enabled: bool) {
+        self.supersampler = if enabled {
+            Some(Box::new(Supersampler::new(self.presented_frame_buffer[0])))
+        } else {
+            None
+        };
+    }
+
+    /// Returns the last completed 2x frame, if [set_supersampling](#method.set_supersampling) is enabled.
+    pub fn supersampled_frame_buffer(&self) -> Option<&[RGB]> {
+        self.supersampler.as_ref().map(|supersampler| supersampler.frame_buffer())
+    }
+
+    /// Returns the full contents of OAM, laid out as it would be in memory (`0xFE00..=0xFE9F`).
+    ///
+    /// Unlike normal memory reads this ignores any OAM access restrictions, as it's meant for
+    /// save states and debugging.
+    pub fn oam_bytes(&self) -> [u8; 160] {
+        let mut result = [0; 160];
+
+        for (sprite, bytes) in self.oam.iter().zip(result.chunks_exact_mut(4)) {
+            for (byte_num, byte) in bytes.iter_mut().enumerate() {
+                *byte = sprite.get_byte(byte_num as u8);
+            }
         }
+
+        result
     }
-    let mut bg_palette = [CgbPalette::default(); 8];
-    let mut sprite_palette = [CgbPalette::default(); 8];
-    set_cgb_palette(&mut bg_palette[0], bg_display);
-    set_cgb_palette(&mut sprite_palette[0], sp0_display);
-    set_cgb_palette(&mut sprite_palette[1], sp1_display);
 
-    (bg_palette, sprite_palette)
+    /// Overwrites the full contents of OAM, the inverse of [`PPU::oam_bytes`].
+    ///
+    /// Ignores any OAM access restrictions as well.
+    pub fn set_oam_bytes(&mut self, oam_bytes: &[u8; 160]) {
+        for (sprite, bytes) in self.oam.iter_mut().zip(oam_bytes.chunks_exact(4)) {
+            for (byte_num, byte) in bytes.iter().enumerate() {
+                sprite.set_byte(byte_num as u8, *byte);
+            }
+        }
+    }
 }
 
 fn is_sprite_on_scanline(scanline_y: i16, y_pos: i16, y_size: i16) -> bool {
     (scanline_y >= y_pos) && (scanline_y < (y_pos + y_size))
 }
+
+impl SaveState for PPU {
+    fn save_state(&self, state: &mut StateWriter) {
+        let buffers = self.frame_buffer.iter().chain(self.presented_frame_buffer.iter());
+        buffers.chain(self.scanline_buffer.iter()).for_each(|colour| colour.save_state(state));
+        for &(colour, priority) in self.scanline_buffer_unpalette.iter() {
+            state.write_u8(colour);
+            state.write_bool(priority);
+        }
+        for tile in self.tiles.iter() {
+            state.write_bytes(&tile.data);
+            state.write_bytes(&tile.unpaletted_pixels);
+        }
+        state.write_u8(self.tile_bank_currently_used);
+        state.write_bytes(&self.tile_map_9800.data);
+        state.write_bytes(&self.tile_map_9c00.data);
+        for tile_map in [&self.cgb_9800_tile_map, &self.cgb_9c00_tile_map].iter() {
+            tile_map.attributes.iter().for_each(|attribute| state.write_u8(attribute.bits()));
+        }
+        for sprite in self.oam.iter() {
+            (0..4).for_each(|byte| state.write_u8(sprite.get_byte(byte)));
+        }
+
+        state.write_u8(self.lcd_control.bits());
+        state.write_u8(self.lcd_status.bits());
+        self.bg_window_palette.save_state(state);
+        self.oam_palette_0.save_state(state);
+        self.oam_palette_1.save_state(state);
+        self.cgb_bg_palette_ind.save_state(state);
+        self.cgb_sprite_palette_ind.save_state(state);
+        let cgb_palettes = self.cgb_bg_palette.iter().chain(self.cgb_sprite_palette.iter());
+        cgb_palettes.for_each(|palette| palette.save_state(state));
+
+        state.write_bytes(&[self.current_y, self.lyc_compare, self.scroll_x, self.scroll_y]);
+        state.write_bytes(&[self.window_x, self.window_y, self.window_counter]);
+        state.write_bool(self.window_triggered);
+        state.write_bool(self.oam_transfer_ongoing);
+        state.write_bool(self.cgb_object_priority);
+        state.write_bool(self.stat_irq_triggered);
+        state.write_bool(self.cgb_rendering);
+        state.write_u64(self.latest_lcd_transfer_start);
+        state.write_u64(self.current_lcd_transfer_duration);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        let buffers = self.frame_buffer.iter_mut().chain(self.presented_frame_buffer.iter_mut());
+        buffers
+            .chain(self.scanline_buffer.iter_mut())
+            .try_for_each(|colour| colour.load_state(state))?;
+        for pixel in self.scanline_buffer_unpalette.iter_mut() {
+            *pixel = (state.read_u8()?, state.read_bool()?);
+        }
+        for tile in self.tiles.iter_mut() {
+            state.read_into(&mut tile.data)?;
+            state.read_into(&mut tile.unpaletted_pixels)?;
+        }
+        self.tile_bank_currently_used = state.read_u8()?;
+        state.read_into(&mut self.tile_map_9800.data)?;
+        state.read_into(&mut self.tile_map_9c00.data)?;
+        for tile_map in [&mut self.cgb_9800_tile_map, &mut self.cgb_9c00_tile_map].iter_mut() {
+            for attribute in tile_map.attributes.iter_mut() {
+                *attribute = CgbTileAttribute::from_bits_truncate(state.read_u8()?);
+            }
+        }
+        for sprite in self.oam.iter_mut() {
+            for byte in 0..4 {
+                sprite.set_byte(byte, state.read_u8()?);
+            }
+        }
+
+        self.lcd_control = LcdControl::from_bits_truncate(state.read_u8()?);
+        self.lcd_status = LcdStatus::from_bits_truncate(state.read_u8()?);
+        self.bg_window_palette.load_state(state)?;
+        self.oam_palette_0.load_state(state)?;
+        self.oam_palette_1.load_state(state)?;
+        self.cgb_bg_palette_ind.load_state(state)?;
+        self.cgb_sprite_palette_ind.load_state(state)?;
+        let mut cgb_palettes = self.cgb_bg_palette.iter_mut().chain(self.cgb_sprite_palette.iter_mut());
+        cgb_palettes.try_for_each(|palette| palette.load_state(state))?;
+
+        self.current_y = state.read_u8()?;
+        self.lyc_compare = state.read_u8()?;
+        self.scroll_x = state.read_u8()?;
+        self.scroll_y = state.read_u8()?;
+        self.window_x = state.read_u8()?;
+        self.window_y = state.read_u8()?;
+        self.window_counter = state.read_u8()?;
+        self.window_triggered = state.read_bool()?;
+        self.oam_transfer_ongoing = state.read_bool()?;
+        self.cgb_object_priority = state.read_bool()?;
+        self.stat_irq_triggered = state.read_bool()?;
+        self.cgb_rendering = state.read_bool()?;
+        self.latest_lcd_transfer_start = state.read_u64()?;
+        self.current_lcd_transfer_duration = state.read_u64()?;
+        Ok(())
+    }
+}
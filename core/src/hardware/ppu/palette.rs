@@ -1,7 +1,26 @@
+use crate::hardware::ppu::cgb_vram::CgbPalette;
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
+
 #[derive(Copy, Clone, Debug, Default, PartialOrd, PartialEq)]
 pub struct RGB(pub u8, pub u8, pub u8);
 
-#[derive(Debug, Default, Copy, Clone)]
+// Ordered from white to black.
+const GRAYSCALE_COLOURS: [RGB; 4] = [RGB(255, 255, 255), RGB(170, 170, 170), RGB(85, 85, 85), RGB(0, 0, 0)];
+const DEUTERANOPIA_COLOURS: [RGB; 4] = [
+    RGB(248, 240, 210),
+    RGB(230, 170, 60),
+    RGB(70, 100, 170),
+    RGB(20, 20, 50),
+];
+const PROTANOPIA_COLOURS: [RGB; 4] = [RGB(250, 250, 220), RGB(200, 190, 80), RGB(40, 90, 170), RGB(10, 15, 40)];
+const TRITANOPIA_COLOURS: [RGB; 4] = [
+    RGB(255, 240, 240),
+    RGB(235, 130, 130),
+    RGB(20, 100, 110),
+    RGB(15, 20, 30),
+];
+
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
 pub struct DisplayColour {
     pub white: RGB,
     pub light_grey: RGB,
@@ -10,6 +29,9 @@ pub struct DisplayColour {
 }
 
 impl DisplayColour {
+    /// Map a DMG colour index (`0..=3`, white to black) to its `RGB` value.
+    ///
+    /// Any index above 3 is treated as black.
     pub fn get_colour(&self, val: usize) -> RGB {
         match val {
             0 => self.white,
@@ -18,6 +40,92 @@ impl DisplayColour {
             _ => self.black,
         }
     }
+
+    /// Returns all four colours ordered by their DMG colour index, from white to black.
+    pub fn to_array(&self) -> [RGB; 4] {
+        [self.white, self.light_grey, self.dark_grey, self.black]
+    }
+
+    pub fn from_preset(preset: DisplayColourPreset) -> Self {
+        match preset {
+            DisplayColourPreset::Grayscale => GRAYSCALE_COLOURS,
+            DisplayColourPreset::Deuteranopia => DEUTERANOPIA_COLOURS,
+            DisplayColourPreset::Protanopia => PROTANOPIA_COLOURS,
+            DisplayColourPreset::Tritanopia => TRITANOPIA_COLOURS,
+        }
+        .into()
+    }
+}
+
+/// Built-in `DisplayColour`s, see [DisplayColour::from_preset].
+///
+/// The colourblind friendly ramps avoid the colour pairs the respective deficiency confuses,
+/// and every ramp has a clear luminance difference between shades so they remain
+/// distinguishable even without any colour perception.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DisplayColourPreset {
+    Grayscale,
+    /// Blue/orange ramp, for red-green (green weak) colour blindness.
+    Deuteranopia,
+    /// Blue/yellow ramp, for red-green (red weak) colour blindness.
+    Protanopia,
+    /// Red/teal ramp, for blue-yellow colour blindness.
+    Tritanopia,
+}
+
+impl DisplayColourPreset {
+    pub const ALL: [DisplayColourPreset; 4] = [
+        DisplayColourPreset::Grayscale,
+        DisplayColourPreset::Deuteranopia,
+        DisplayColourPreset::Protanopia,
+        DisplayColourPreset::Tritanopia,
+    ];
+}
+
+/// The CGB palettes as initialised from the DMG `DisplayColour`s.
+///
+/// These only depend on the chosen display colours, so a frontend running several emulators
+/// can create these once and share them (via an `Arc`) through the `EmulatorOptions`.
+#[derive(Debug, Copy, Clone)]
+pub struct PaletteTables {
+    pub bg_palette: [CgbPalette; 8],
+    pub sprite_palette: [CgbPalette; 8],
+    /// The BG, OBJ0 and OBJ1 display colours these tables were created from.
+    display_colours: [DisplayColour; 3],
+}
+
+impl PaletteTables {
+    /// Initialises BG0, OBJ0, OBJ1 in the CGB palettes to the provided display colours while
+    /// leaving the remaining palettes default. See PPU `new()` for an explanation as to why.
+    pub fn new(bg_display: DisplayColour, sp0_display: DisplayColour, sp1_display: DisplayColour) -> Self {
+        fn set_cgb_palette(p: &mut CgbPalette, dmg_display_colour: DisplayColour) {
+            for (i, colour) in p.colours.iter_mut().enumerate() {
+                colour.rgb = dmg_display_colour.get_colour(i);
+            }
+        }
+        let mut bg_palette = [CgbPalette::default(); 8];
+        let mut sprite_palette = [CgbPalette::default(); 8];
+        set_cgb_palette(&mut bg_palette[0], bg_display);
+        set_cgb_palette(&mut sprite_palette[0], sp0_display);
+        set_cgb_palette(&mut sprite_palette[1], sp1_display);
+
+        PaletteTables {
+            bg_palette,
+            sprite_palette,
+            display_colours: [bg_display, sp0_display, sp1_display],
+        }
+    }
+
+    /// Returns `true` if these tables were created from the provided display colours, and can
+    /// therefore be used in their place.
+    pub fn is_created_from(
+        &self,
+        bg_display: DisplayColour,
+        sp0_display: DisplayColour,
+        sp1_display: DisplayColour,
+    ) -> bool {
+        self.display_colours == [bg_display, sp0_display, sp1_display]
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -74,6 +182,30 @@ impl Into<u8> for Palette {
     }
 }
 
+impl SaveState for Palette {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.palette_byte);
+        self.colours.iter().for_each(|colour| colour.save_state(state));
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.palette_byte = state.read_u8()?;
+        self.colours.iter_mut().try_for_each(|colour| colour.load_state(state))
+    }
+}
+
+impl SaveState for RGB {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&[self.0, self.1, self.2]);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        let rgb = state.read_bytes(3)?;
+        *self = RGB(rgb[0], rgb[1], rgb[2]);
+        Ok(())
+    }
+}
+
 impl From<(u8, u8, u8)> for RGB {
     fn from(rgb_tuple: (u8, u8, u8)) -> Self {
         RGB(rgb_tuple.0, rgb_tuple.1, rgb_tuple.2)
@@ -85,3 +217,39 @@ impl Into<(u8, u8, u8)> for RGB {
         (self.0, self.1, self.2)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::hardware::ppu::palette::{DisplayColour, DisplayColourPreset, RGB};
+
+    fn luminance(colour: RGB) -> f32 {
+        0.2126 * colour.0 as f32 + 0.7152 * colour.1 as f32 + 0.0722 * colour.2 as f32
+    }
+
+    #[test]
+    fn test_presets_luminance_separation() {
+        for &preset in DisplayColourPreset::ALL.iter() {
+            let colours = DisplayColour::from_preset(preset);
+
+            for pair in colours.to_array().windows(2) {
+                assert!(
+                    luminance(pair[0]) - luminance(pair[1]) > 40.0,
+                    "{:?} has indistinguishable shades",
+                    preset
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_index_mapping() {
+        let colours = DisplayColour::from_preset(DisplayColourPreset::Deuteranopia);
+        let array = colours.to_array();
+
+        for (index, &colour) in array.iter().enumerate() {
+            assert_eq!(colours.get_colour(index), colour);
+        }
+        assert_eq!(colours.get_colour(7), colours.black);
+        assert_eq!(DisplayColour::from(array).to_array(), array);
+    }
+}
@@ -0,0 +1,135 @@
+//! **Experimental** rendering at twice the native resolution, an enhancement which is in no way
+//! accurate to real hardware.
+//!
+//! Every scanline is sampled a second time at a half-pixel offset, both horizontally and (with the
+//! next scanline) vertically. The background and window are interpolated at those offsets, which
+//! smooths out the one pixel steps of sub-tile scrolling, while sprites keep their hard edges.
+
+use crate::hardware::ppu::palette::RGB;
+use crate::hardware::ppu::{FRAMEBUFFER_SIZE, RESOLUTION_HEIGHT, RESOLUTION_WIDTH};
+
+pub const SUPERSAMPLED_WIDTH: usize = RESOLUTION_WIDTH * 2;
+pub const SUPERSAMPLED_HEIGHT: usize = RESOLUTION_HEIGHT * 2;
+pub const SUPERSAMPLED_FRAMEBUFFER_SIZE: usize = FRAMEBUFFER_SIZE * 4;
+
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+struct Sample {
+    colour: RGB,
+    /// Whether the colour came from the background/window, rather than a sprite.
+    background: bool,
+}
+
+impl Sample {
+    /// The sample half way between `self` and the `later` one, sprites aren't interpolated.
+    fn half_way(self, later: Sample) -> Sample {
+        if self.background && later.background {
+            Sample {
+                colour: blend(self.colour, later.colour),
+                background: true,
+            }
+        } else {
+            self
+        }
+    }
+}
+
+type SampleRow = [Sample; SUPERSAMPLED_WIDTH];
+
+#[derive(Debug, Clone)]
+pub struct Supersampler {
+    /// Rows are rendered to the back buffer as their scanlines complete.
+    back_buffer: Vec<RGB>,
+    presented_frame_buffer: Vec<RGB>,
+    /// The current scanline as it was before any sprites were drawn on top of it.
+    background: [RGB; RESOLUTION_WIDTH],
+    previous_row: SampleRow,
+}
+
+impl Supersampler {
+    pub fn new(colour: RGB) -> Self {
+        Supersampler {
+            back_buffer: vec![colour; SUPERSAMPLED_FRAMEBUFFER_SIZE],
+            presented_frame_buffer: vec![colour; SUPERSAMPLED_FRAMEBUFFER_SIZE],
+            background: [RGB::default(); RESOLUTION_WIDTH],
+            previous_row: [Sample::default(); SUPERSAMPLED_WIDTH],
+        }
+    }
+
+    /// Should be called with the scanline after the background and window were drawn, but
+    /// before any sprites.
+    ///
+    /// Should the scanline change afterwards for any other reason (e.g, a mid-scanline palette
+    /// write) those pixels are treated like sprites, and thus aren't interpolated.
+    pub fn set_background(&mut self, scanline: &[RGB; RESOLUTION_WIDTH]) {
+        self.background = *scanline;
+    }
+
+    /// Render the completed `scanline` to the rows `2 * current_y` and `2 * current_y - 1`.
+    pub fn push_scanline(&mut self, current_y: u8, scanline: &[RGB; RESOLUTION_WIDTH]) {
+        let current_y = current_y as usize;
+        if current_y >= RESOLUTION_HEIGHT {
+            return;
+        }
+        let row = self.sample_row(scanline);
+
+        if current_y > 0 {
+            let start = (current_y * 2 - 1) * SUPERSAMPLED_WIDTH;
+            let half_way_row = self.previous_row.iter().zip(row.iter());
+            for (pixel, (&previous, &current)) in self.back_buffer[start..].iter_mut().zip(half_way_row) {
+                *pixel = previous.half_way(current).colour;
+            }
+        }
+
+        let start = current_y * 2 * SUPERSAMPLED_WIDTH;
+        for (pixel, sample) in self.back_buffer[start..start + SUPERSAMPLED_WIDTH].iter_mut().zip(row.iter()) {
+            *pixel = sample.colour;
+        }
+        // There's no scanline below the last one to interpolate with.
+        if current_y == RESOLUTION_HEIGHT - 1 {
+            self.back_buffer.copy_within(start..start + SUPERSAMPLED_WIDTH, start + SUPERSAMPLED_WIDTH);
+        }
+
+        self.previous_row = row;
+    }
+
+    fn sample_row(&self, scanline: &[RGB; RESOLUTION_WIDTH]) -> SampleRow {
+        let sample = |x: usize| Sample {
+            colour: scanline[x],
+            background: scanline[x] == self.background[x],
+        };
+        let mut result = [Sample::default(); SUPERSAMPLED_WIDTH];
+
+        for x in 0..RESOLUTION_WIDTH {
+            let current = sample(x);
+            result[x * 2] = current;
+            // Clamp at the right edge, like the bottom row.
+            result[x * 2 + 1] = current.half_way(sample((x + 1).min(RESOLUTION_WIDTH - 1)));
+        }
+
+        result
+    }
+
+    /// Expose the completed frame, should be called on V-blank.
+    pub fn present(&mut self) {
+        self.presented_frame_buffer.copy_from_slice(&self.back_buffer);
+    }
+
+    pub fn clear(&mut self, colour: RGB) {
+        self.back_buffer.iter_mut().for_each(|pixel| *pixel = colour);
+        self.presented_frame_buffer.iter_mut().for_each(|pixel| *pixel = colour);
+    }
+
+    /// The last completed frame, of `SUPERSAMPLED_WIDTH`x`SUPERSAMPLED_HEIGHT`.
+    pub fn frame_buffer(&self) -> &[RGB] {
+        &self.presented_frame_buffer
+    }
+}
+
+fn blend(first: RGB, second: RGB) -> RGB {
+    let average = |a: u8, b: u8| ((a as u16 + b as u16) / 2) as u8;
+    RGB(
+        average(first.0, second.0),
+        average(first.1, second.1),
+        average(first.2, second.2),
+    )
+}
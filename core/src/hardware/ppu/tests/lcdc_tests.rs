@@ -0,0 +1,145 @@
+use crate::gb_emu::GameBoyModel;
+use crate::gb_emu::GameBoyModel::{CGB, DMG};
+use crate::hardware::ppu::debugging_features::{BackgroundSnapshot, TileMapSelect};
+use crate::hardware::ppu::register_flags::LcdControl;
+use crate::hardware::ppu::tests::{initial_ppu, render_line, set_sprite, set_tile_lines};
+use crate::hardware::ppu::PPU;
+use crate::io::interrupts::Interrupts;
+
+const OBJ_TO_BG_PRIORITY: u8 = 0x80;
+
+/// The background consists entirely of colour 3 (tile 0), with an 8x8 sprite of colour 1 (tile 1)
+/// in the top left corner.
+fn priority_ppu(model: GameBoyModel, sprite_attributes: u8) -> PPU {
+    let mut ppu = initial_ppu(model);
+    ppu.lcd_control.insert(LcdControl::BG_WINDOW_TILE_SELECT);
+    set_tile_lines(&mut ppu, 0, [3; 8]);
+    set_tile_lines(&mut ppu, 1, [1; 8]);
+    set_sprite(&mut ppu, 0, 8, 16, 1, sprite_attributes);
+    ppu
+}
+
+#[test]
+fn test_dmg_bg_disabled() {
+    let mut ppu = priority_ppu(DMG, 0);
+    ppu.lcd_control.remove(LcdControl::BG_WINDOW_PRIORITY);
+
+    let line = render_line(&mut ppu, 0);
+    assert!(line[0..8].iter().all(|&c| c == 1));
+    // The background should be blank.
+    assert!(line[8..].iter().all(|&c| c == 0));
+    assert!(ppu.scanline_buffer.iter().skip(8).all(|&c| c == ppu.bg_window_palette.colour(0)));
+}
+
+#[test]
+fn test_dmg_bg_disabled_window_disabled() {
+    let mut ppu = priority_ppu(DMG, 0);
+    ppu.lcd_control.remove(LcdControl::BG_WINDOW_PRIORITY);
+    ppu.lcd_control.insert(LcdControl::WINDOW_DISPLAY);
+    ppu.window_x = 7;
+    ppu.window_y = 0;
+
+    let line = render_line(&mut ppu, 0);
+    assert!(line[8..].iter().all(|&c| c == 0));
+}
+
+#[test]
+fn test_window_active() {
+    let mut ppu = initial_ppu(DMG);
+    ppu.window_x = 7;
+    ppu.window_y = 2;
+
+    render_line(&mut ppu, 1);
+    assert!(!ppu.window_active());
+    // Triggered, but not displayed.
+    render_line(&mut ppu, 2);
+    assert!(ppu.window_triggered() && !ppu.window_active());
+
+    ppu.lcd_control.insert(LcdControl::WINDOW_DISPLAY);
+    render_line(&mut ppu, 3);
+    assert!(ppu.window_active());
+    assert_eq!(ppu.window_line_counter(), 1);
+}
+
+#[test]
+fn test_only_complete_frames_presented() {
+    let mut ppu = initial_ppu(DMG);
+    let mut interrupts = Interrupts::default();
+    let blank = *ppu.frame_buffer();
+    set_tile_lines(&mut ppu, 0, [3; 8]);
+    ppu.lcd_control.insert(LcdControl::BG_WINDOW_TILE_SELECT);
+
+    render_line(&mut ppu, 0);
+    ppu.hblank(&mut interrupts);
+    assert_eq!(ppu.frame_buffer(), &blank);
+
+    ppu.vblank(&mut interrupts);
+    assert_eq!(ppu.frame_buffer()[0], ppu.bg_window_palette.colour(3));
+}
+
+#[test]
+fn test_background_snapshot() {
+    let mut ppu = initial_ppu(DMG);
+    ppu.lcd_control.insert(LcdControl::BG_TILE_MAP_SELECT);
+    ppu.scroll_x = 12;
+    ppu.window_y = 40;
+    set_tile_lines(&mut ppu, 1, [3; 8]);
+    set_tile_lines(&mut ppu, 257, [1; 8]);
+    // Tile (1, 0) points to tile 1, which in 8800-97FF addressing is tile 257.
+    ppu.tile_map_9c00.data[1] = 1;
+
+    let snapshot = BackgroundSnapshot::new(&ppu);
+    assert_eq!(snapshot.tiles.len(), snapshot.width * snapshot.height);
+    assert_eq!((snapshot.scroll_x, snapshot.scroll_y, snapshot.window_y), (12, 0, 40));
+    assert_eq!(snapshot.tiles[7], ppu.bg_window_palette.colour(0));
+    assert_eq!(snapshot.tiles[8], ppu.bg_window_palette.colour(1));
+    assert_eq!(snapshot.tiles[7 * 256 + 15], ppu.bg_window_palette.colour(1));
+
+    ppu.lcd_control.insert(LcdControl::BG_WINDOW_TILE_SELECT);
+    assert_eq!(ppu.render_tilemap(TileMapSelect::Map9C00)[8], ppu.bg_window_palette.colour(3));
+    assert_eq!(ppu.render_tilemap(TileMapSelect::Map9800)[8], ppu.bg_window_palette.colour(0));
+}
+
+#[test]
+fn test_dmg_bg_disabled_background_sprite() {
+    let mut ppu = priority_ppu(DMG, OBJ_TO_BG_PRIORITY);
+    // Ensure a previous line with a visible background doesn't affect the next.
+    render_line(&mut ppu, 0);
+    ppu.lcd_control.remove(LcdControl::BG_WINDOW_PRIORITY);
+
+    // A disabled background is colour 0, so the sprite should be visible.
+    let line = render_line(&mut ppu, 1);
+    assert!(line[0..8].iter().all(|&c| c == 1));
+}
+
+#[test]
+fn test_cgb_bg_priority_off() {
+    let mut ppu = priority_ppu(CGB, OBJ_TO_BG_PRIORITY);
+    ppu.lcd_control.remove(LcdControl::BG_WINDOW_PRIORITY);
+
+    let line = render_line(&mut ppu, 0);
+    // The background should still be rendered on the CGB.
+    assert!(line[8..].iter().all(|&c| c == 3));
+    // And the sprite should always be on top, despite its attribute.
+    assert!(line[0..8].iter().all(|&c| c == 1));
+}
+
+#[test]
+fn test_cgb_bg_priority_on() {
+    let mut ppu = priority_ppu(CGB, OBJ_TO_BG_PRIORITY);
+
+    let line = render_line(&mut ppu, 0);
+    assert!(line.iter().all(|&c| c == 3));
+}
+
+#[test]
+fn test_cgb_bg_priority_off_partial_sprite() {
+    let mut ppu = priority_ppu(CGB, 0);
+    ppu.lcd_control.remove(LcdControl::BG_WINDOW_PRIORITY);
+    // Only the right half of the sprite is on screen.
+    set_sprite(&mut ppu, 0, 4, 16, 1, 0);
+
+    let line = render_line(&mut ppu, 0);
+    assert!(line[0..4].iter().all(|&c| c == 1));
+    assert!(line[4..].iter().all(|&c| c == 3));
+}
@@ -0,0 +1,49 @@
+use crate::gb_emu::GameBoyModel;
+use crate::hardware::ppu::palette::DisplayColour;
+use crate::hardware::ppu::register_flags::LcdControl;
+use crate::hardware::ppu::tiledata::SpriteAttribute;
+use crate::hardware::ppu::{RESOLUTION_WIDTH, PPU};
+
+mod lcdc_tests;
+mod register_tests;
+mod sprite_tests;
+
+// Common functionality for the tests.
+
+fn initial_ppu(model: GameBoyModel) -> PPU {
+    let mut ppu = PPU::new(
+        DisplayColour::default(),
+        DisplayColour::default(),
+        DisplayColour::default(),
+        model.is_cgb(),
+        model,
+    );
+    ppu.lcd_control = LcdControl::LCD_DISPLAY | LcdControl::BG_WINDOW_PRIORITY | LcdControl::SPRITE_DISPLAY_ENABLE;
+    ppu
+}
+
+/// Fill every line of the tile at `tile_index` with the colour provided for that line.
+fn set_tile_lines(ppu: &mut PPU, tile_index: usize, line_colours: [u8; 8]) {
+    for (line, colour) in line_colours.iter().enumerate() {
+        let low_byte = if colour & 0x1 != 0 { 0xFF } else { 0x00 };
+        let high_byte = if colour & 0x2 != 0 { 0xFF } else { 0x00 };
+        ppu.tiles[tile_index].update_pixel_data(line * 2, low_byte);
+        ppu.tiles[tile_index].update_pixel_data(line * 2 + 1, high_byte);
+    }
+}
+
+fn set_sprite(ppu: &mut PPU, oam_index: usize, x_pos: u8, y_pos: u8, tile_number: u8, attributes: u8) {
+    let mut sprite = SpriteAttribute::default();
+    sprite.set_byte(0, y_pos);
+    sprite.set_byte(1, x_pos);
+    sprite.set_byte(2, tile_number);
+    sprite.set_byte(3, attributes);
+    ppu.oam[oam_index] = sprite;
+}
+
+/// Render the provided scanline and return the unpaletted colours.
+fn render_line(ppu: &mut PPU, line: u8) -> [u8; RESOLUTION_WIDTH] {
+    ppu.current_y = line;
+    ppu.draw_scanline();
+    ppu.scanline_indices()
+}
@@ -0,0 +1,199 @@
+use crate::gb_emu::GameBoyModel::{CGB, DMG};
+use crate::hardware::ppu::debugging_features::{PpuRegisters, TileMapSelect};
+use crate::hardware::ppu::memory_binds::*;
+use crate::hardware::ppu::tests::initial_ppu;
+use crate::hardware::ppu::Mode;
+use crate::io::interrupts::{InterruptFlags, Interrupts};
+use crate::scheduler::Scheduler;
+
+/// Every PPU register with the bits which should be writable.
+const WRITABLE_MASKS: [(u16, u8); 17] = [
+    (LCD_CONTROL_REGISTER, 0xFF),
+    (LCD_STATUS_REGISTER, 0x78),
+    (SCY_REGISTER, 0xFF),
+    (SCX_REGISTER, 0xFF),
+    (LY_REGISTER, 0x00),
+    (LYC_REGISTER, 0xFF),
+    (BG_PALETTE, 0xFF),
+    (OB_PALETTE_0, 0xFF),
+    (OB_PALETTE_1, 0xFF),
+    (WY_REGISTER, 0xFF),
+    (WX_REGISTER, 0xFF),
+    (CGB_VRAM_BANK_REGISTER, 0x01),
+    (CGB_BACKGROUND_COLOR_INDEX, 0xBF),
+    (CGB_BACKGROUND_PALETTE_DATA, 0xFF),
+    (CGB_SPRITE_COLOR_INDEX, 0xBF),
+    (CGB_OBJECT_PALETTE_DATA, 0xFF),
+    (CGB_OBJECT_PRIORITY_MODE, 0x01),
+];
+
+#[test]
+fn test_register_write_masks() {
+    for &(address, expected_mask) in WRITABLE_MASKS.iter() {
+        assert_eq!(register_write_mask(address), expected_mask, "Register {:#06X}", address);
+    }
+}
+
+#[test]
+fn test_strict_writable_bits() {
+    for &model in [DMG, CGB].iter() {
+        for &(address, expected_mask) in WRITABLE_MASKS.iter() {
+            let mut ppu = initial_ppu(model);
+            let mut scheduler = Scheduler::new();
+            let mut interrupts = Interrupts::default();
+            ppu.set_strict_register_writes(true);
+
+            ppu.write_vram(address, 0x00, &mut scheduler, &mut interrupts);
+            let cleared = ppu.read_vram(address);
+            ppu.write_vram(address, 0xFF, &mut scheduler, &mut interrupts);
+            let set = ppu.read_vram(address);
+
+            // Only the writable bits should have been able to change.
+            assert_eq!(cleared ^ set, expected_mask, "Register {:#06X} in {:?}", address, model);
+        }
+    }
+}
+
+#[test]
+fn test_cgb_palette_data_round_trip() {
+    let palettes = [
+        (CGB_BACKGROUND_COLOR_INDEX, CGB_BACKGROUND_PALETTE_DATA),
+        (CGB_SPRITE_COLOR_INDEX, CGB_OBJECT_PALETTE_DATA),
+    ];
+
+    for &(index_register, data_register) in palettes.iter() {
+        let mut ppu = initial_ppu(CGB);
+        let mut scheduler = Scheduler::new();
+        let mut interrupts = Interrupts::default();
+        // Auto-increment, starting with the low byte of the second colour.
+        ppu.write_vram(index_register, 0x82, &mut scheduler, &mut interrupts);
+        // The upper two bits of green are in the high byte.
+        ppu.write_vram(data_register, 0x1F, &mut scheduler, &mut interrupts);
+        ppu.write_vram(data_register, 0x7D, &mut scheduler, &mut interrupts);
+
+        ppu.write_vram(index_register, 0x02, &mut scheduler, &mut interrupts);
+        assert_eq!(ppu.read_vram(data_register), 0x1F, "Register {:#06X}", data_register);
+        ppu.write_vram(index_register, 0x03, &mut scheduler, &mut interrupts);
+        assert_eq!(ppu.read_vram(data_register), 0x7D, "Register {:#06X}", data_register);
+    }
+}
+
+#[test]
+fn test_strict_read_only_bits_preserved() {
+    let mut ppu = initial_ppu(DMG);
+    let mut scheduler = Scheduler::new();
+    let mut interrupts = Interrupts::default();
+    ppu.set_strict_register_writes(true);
+    ppu.current_y = 42;
+    ppu.lcd_status.set_mode_flag(Mode::Vblank);
+
+    ppu.write_vram(LY_REGISTER, 0, &mut scheduler, &mut interrupts);
+    ppu.write_vram(LCD_STATUS_REGISTER, 0x00, &mut scheduler, &mut interrupts);
+
+    assert_eq!(ppu.read_vram(LY_REGISTER), 42);
+    assert_eq!(ppu.get_current_mode(), Mode::Vblank);
+}
+
+#[test]
+fn test_dmg_stat_write_quirk_fires_once() {
+    let mut ppu = initial_ppu(DMG);
+    let mut scheduler = Scheduler::new();
+    let mut interrupts = Interrupts::default();
+    ppu.current_y = 1;
+    ppu.lyc_compare = 5;
+    ppu.lcd_status.set_mode_flag(Mode::Hblank);
+
+    ppu.write_vram(LCD_STATUS_REGISTER, 0x00, &mut scheduler, &mut interrupts);
+    assert!(interrupts.interrupt_flag.contains(InterruptFlags::LCD));
+    interrupts.remove_interrupt(InterruptFlags::LCD);
+
+    // Writing again in the same mode should not trigger the spurious interrupt again.
+    ppu.write_vram(LCD_STATUS_REGISTER, 0x00, &mut scheduler, &mut interrupts);
+    assert!(!interrupts.interrupt_flag.contains(InterruptFlags::LCD));
+
+    // Once the mode changes the quirk applies to the next write again.
+    ppu.lcd_status.set_mode_flag(Mode::OamSearch);
+    ppu.request_stat_interrupt(&mut interrupts);
+    ppu.write_vram(LCD_STATUS_REGISTER, 0x00, &mut scheduler, &mut interrupts);
+    assert!(interrupts.interrupt_flag.contains(InterruptFlags::LCD));
+
+    // CGB doesn't have the quirk at all.
+    let mut ppu = initial_ppu(CGB);
+    let mut interrupts = Interrupts::default();
+    ppu.lcd_status.set_mode_flag(Mode::Hblank);
+    ppu.write_vram(LCD_STATUS_REGISTER, 0x00, &mut scheduler, &mut interrupts);
+    assert!(!interrupts.interrupt_flag.contains(InterruptFlags::LCD));
+}
+
+#[test]
+fn test_cgb_tilemap_attributes() {
+    let mut ppu = initial_ppu(CGB);
+    let mut scheduler = Scheduler::new();
+    let mut interrupts = Interrupts::default();
+
+    ppu.write_vram(CGB_VRAM_BANK_REGISTER, 0x01, &mut scheduler, &mut interrupts);
+    ppu.write_vram(0x9C21, 0x6B, &mut scheduler, &mut interrupts);
+
+    let attributes = ppu.cgb_tilemap_attributes(TileMapSelect::Map9C00);
+    assert_eq!(attributes.len(), 32 * 32);
+    assert_eq!(attributes[32 + 1].bits(), 0x6B);
+    assert_eq!(attributes[32 + 1].bg_palette_numb(), 3);
+    assert_eq!(attributes[32 + 1].vram_bank(), 1);
+    assert!(attributes[32 + 1].x_flipped() && attributes[32 + 1].y_flipped());
+    assert!(ppu.cgb_tilemap_attributes(TileMapSelect::Map9800).iter().all(|a| a.bits() == 0));
+}
+
+#[test]
+fn test_initial_palette_registers() {
+    for &model in [DMG, CGB].iter() {
+        let ppu = initial_ppu(model);
+
+        assert_eq!(ppu.read_vram(BG_PALETTE), 0xFC, "{:?}", model);
+        assert_eq!(ppu.read_vram(OB_PALETTE_0), 0xFF, "{:?}", model);
+        assert_eq!(ppu.read_vram(OB_PALETTE_1), 0xFF, "{:?}", model);
+    }
+}
+
+#[test]
+fn test_register_snapshot() {
+    let mut ppu = initial_ppu(CGB);
+    let mut scheduler = Scheduler::new();
+    let mut interrupts = Interrupts::default();
+    let writes = [
+        (LCD_CONTROL_REGISTER, 0x93),
+        // Only the LYC interrupt, the mode and coincidence bits are read-only.
+        (LCD_STATUS_REGISTER, 0x47),
+        (SCY_REGISTER, 0x12),
+        (SCX_REGISTER, 0x34),
+        (LYC_REGISTER, 0x56),
+        (WY_REGISTER, 0x20),
+        (WX_REGISTER, 0x07),
+        (BG_PALETTE, 0xE4),
+        (OB_PALETTE_0, 0xD2),
+        (OB_PALETTE_1, 0x1B),
+        (CGB_BACKGROUND_COLOR_INDEX, 0x83),
+        (CGB_SPRITE_COLOR_INDEX, 0x05),
+    ];
+    for &(address, value) in writes.iter() {
+        ppu.write_vram(address, value, &mut scheduler, &mut interrupts);
+    }
+    let snapshot = ppu.register_snapshot();
+
+    let expected = PpuRegisters {
+        lcdc: 0x93,
+        // Still in the initial V-blank mode, with LY (0) != LYC.
+        stat: 0xC1,
+        scy: 0x12,
+        scx: 0x34,
+        ly: 0x00,
+        lyc: 0x56,
+        wy: 0x20,
+        wx: 0x07,
+        bgp: 0xE4,
+        obp0: 0xD2,
+        obp1: 0x1B,
+        bcps: 0x83,
+        ocps: 0x05,
+    };
+    assert_eq!(snapshot, expected);
+}
@@ -0,0 +1,153 @@
+use crate::gb_emu::GameBoyModel;
+use crate::gb_emu::GameBoyModel::{CGB, DMG};
+use crate::hardware::ppu::debugging_features::{SPRITE_BOX_BEHIND_BG_COLOUR, SPRITE_BOX_COLOUR};
+use crate::hardware::ppu::memory_binds::CGB_OBJECT_PRIORITY_MODE;
+use crate::hardware::ppu::register_flags::LcdControl;
+use crate::hardware::ppu::tests::{initial_ppu, render_line, set_sprite, set_tile_lines};
+use crate::hardware::ppu::{Mode, PPU, RESOLUTION_WIDTH};
+use crate::io::interrupts::Interrupts;
+use crate::scheduler::Scheduler;
+
+const Y_FLIP: u8 = 0x40;
+
+/// Sets up tile 2 and 3 such that every relevant line of a tall sprite using them is
+/// distinguishable.
+///
+/// Tile 2 (top half): line 0 is colour 3, the remainder colour 1.
+/// Tile 3 (bottom half): line 7 is colour 1, the remainder colour 2.
+fn tall_sprite_ppu(model: GameBoyModel, tile_number: u8, attributes: u8) -> PPU {
+    let mut ppu = initial_ppu(model);
+    ppu.lcd_control.insert(LcdControl::SPRITE_SIZE);
+    set_tile_lines(&mut ppu, 2, [3, 1, 1, 1, 1, 1, 1, 1]);
+    set_tile_lines(&mut ppu, 3, [2, 2, 2, 2, 2, 2, 2, 1]);
+    // Top left of the screen.
+    set_sprite(&mut ppu, 0, 8, 16, tile_number, attributes);
+    ppu
+}
+
+/// Returns the colour of the sprite for the lines 0, 7, 8, and 15.
+fn sprite_line_colours(ppu: &mut PPU) -> [u8; 4] {
+    let mut result = [0; 4];
+    for (colour, &line) in result.iter_mut().zip([0u8, 7, 8, 15].iter()) {
+        let rendered = render_line(ppu, line);
+        assert!(rendered[0..8].iter().all(|&c| c == rendered[0]));
+        // Pixels right after the sprite should just be background.
+        assert_eq!(rendered[8], 0);
+        *colour = rendered[0];
+    }
+    result
+}
+
+#[test]
+fn test_tall_sprite_odd_tile() {
+    for &model in [DMG, CGB].iter() {
+        let mut ppu = tall_sprite_ppu(model, 3, 0);
+
+        assert_eq!(sprite_line_colours(&mut ppu), [3, 1, 2, 1], "{:?}", model);
+    }
+}
+
+#[test]
+fn test_tall_sprite_even_tile() {
+    for &model in [DMG, CGB].iter() {
+        let mut ppu = tall_sprite_ppu(model, 2, 0);
+
+        assert_eq!(sprite_line_colours(&mut ppu), [3, 1, 2, 1], "{:?}", model);
+    }
+}
+
+#[test]
+fn test_tall_sprite_y_flip() {
+    for &model in [DMG, CGB].iter() {
+        // With y-flip the bottom tile should be displayed first, upside down.
+        let mut ppu = tall_sprite_ppu(model, 3, Y_FLIP);
+
+        assert_eq!(sprite_line_colours(&mut ppu), [1, 2, 1, 3], "{:?}", model);
+    }
+}
+
+#[test]
+fn test_small_sprite_odd_tile() {
+    for &model in [DMG, CGB].iter() {
+        let mut ppu = tall_sprite_ppu(model, 3, 0);
+        ppu.lcd_control.remove(LcdControl::SPRITE_SIZE);
+
+        // 8x8 sprites shouldn't have their index masked.
+        assert_eq!(render_line(&mut ppu, 0)[0], 2, "{:?}", model);
+        assert_eq!(render_line(&mut ppu, 7)[0], 1, "{:?}", model);
+        // No longer part of the sprite.
+        assert_eq!(render_line(&mut ppu, 8)[0], 0, "{:?}", model);
+    }
+}
+
+#[test]
+fn test_oam_bytes_round_trip() {
+    let mut ppu = initial_ppu(CGB);
+    // OAM would normally be inaccessible in this mode.
+    ppu.lcd_status.set_mode_flag(Mode::OamSearch);
+
+    let mut oam_bytes = [0; 160];
+    for (i, byte) in oam_bytes.iter_mut().enumerate() {
+        *byte = (i as u8).wrapping_mul(37);
+    }
+    ppu.set_oam_bytes(&oam_bytes);
+
+    assert_eq!(ppu.oam[1].x_pos, oam_bytes[5]);
+    assert_eq!(ppu.oam_bytes()[..], oam_bytes[..]);
+}
+
+#[test]
+fn test_sprite_boxes() {
+    let mut ppu = tall_sprite_ppu(DMG, 2, 0);
+    // Move the first sprite to (10, 20), and add a second one behind the background which is
+    // partially off-screen on the left.
+    set_sprite(&mut ppu, 0, 18, 36, 2, 0);
+    set_sprite(&mut ppu, 1, 4, 16, 2, 0x80);
+    let background = ppu.frame_buffer()[0];
+
+    let result = ppu.render_with_sprite_boxes();
+    let pixel = |x: usize, y: usize| result[y * RESOLUTION_WIDTH + x];
+
+    assert_eq!(pixel(10, 20), SPRITE_BOX_COLOUR);
+    assert_eq!(pixel(17, 20), SPRITE_BOX_COLOUR);
+    assert_eq!(pixel(10, 35), SPRITE_BOX_COLOUR);
+    assert_eq!(pixel(17, 35), SPRITE_BOX_COLOUR);
+    assert_eq!(pixel(13, 27), background);
+    assert_eq!(pixel(18, 20), background);
+    assert_eq!(pixel(10, 36), background);
+
+    // Only the right edge, and the visible parts of the top and bottom edges should be drawn.
+    assert_eq!(pixel(0, 0), SPRITE_BOX_BEHIND_BG_COLOUR);
+    assert_eq!(pixel(3, 8), SPRITE_BOX_BEHIND_BG_COLOUR);
+    assert_eq!(pixel(0, 8), background);
+    assert_eq!(pixel(0, 15), SPRITE_BOX_BEHIND_BG_COLOUR);
+    assert_eq!(pixel(4, 0), background);
+}
+
+#[test]
+fn test_cgb_object_priority_modes() {
+    let mut ppu = initial_ppu(CGB);
+    let mut scheduler = Scheduler::new();
+    let mut interrupts = Interrupts::default();
+    set_tile_lines(&mut ppu, 1, [1; 8]);
+    set_tile_lines(&mut ppu, 2, [2; 8]);
+    // The first sprite in OAM has the higher x-pos, they overlap on the pixels 4..8.
+    set_sprite(&mut ppu, 0, 12, 16, 1, 0);
+    set_sprite(&mut ppu, 1, 8, 16, 2, 0);
+
+    // OAM priority, the default for CGB games.
+    assert_eq!(ppu.read_vram(CGB_OBJECT_PRIORITY_MODE) & 0x1, 0);
+    let oam_priority = render_line(&mut ppu, 0);
+    assert_eq!(oam_priority[3], 2);
+    assert!(oam_priority[4..12].iter().all(|&c| c == 1));
+
+    // Coordinate priority, the lower x-pos should now be on top.
+    ppu.write_vram(CGB_OBJECT_PRIORITY_MODE, 0x1, &mut scheduler, &mut interrupts);
+    let coordinate_priority = render_line(&mut ppu, 0);
+    assert!(coordinate_priority[0..8].iter().all(|&c| c == 2));
+    assert!(coordinate_priority[8..12].iter().all(|&c| c == 1));
+
+    // Sprites with the same x-pos still fall back on their OAM order.
+    set_sprite(&mut ppu, 1, 12, 16, 2, 0);
+    assert_eq!(render_line(&mut ppu, 0)[4], 1);
+}
@@ -1,10 +1,11 @@
 use itertools::Itertools;
 
+use crate::gb_emu::CYCLES_PER_FRAME;
 use crate::hardware::ppu::register_flags::LcdControl;
 use crate::hardware::ppu::tiledata::SpriteAttribute;
 use crate::hardware::ppu::Mode::LcdTransfer;
-use crate::hardware::ppu::{is_sprite_on_scanline, PPU};
-use crate::scheduler::Scheduler;
+use crate::hardware::ppu::{is_sprite_on_scanline, Mode, PPU};
+use crate::scheduler::{EventType, Scheduler};
 
 pub const SCANLINE_DURATION: u64 = 456;
 pub const BASE_LCD_TRANSFER_DURATION: u64 = 172;
@@ -24,6 +25,43 @@ impl PPU {
         376 - self.current_lcd_transfer_duration
     }
 
+    /// The amount of cycles since the start of the current frame, where a frame starts at
+    /// V-blank (the start of line 144), derived from the next pending PPU event.
+    ///
+    /// Always `0` while the LCD is off. Note that the first frame after turning on the LCD
+    /// starts at line 0 instead, and will thus be shorter.
+    pub fn cycles_into_frame(&self, scheduler: &Scheduler, speed_shift: u64) -> u64 {
+        let ppu_events = [
+            EventType::OamSearch,
+            EventType::LcdTransfer,
+            EventType::Hblank,
+            EventType::Vblank,
+            EventType::VblankWait,
+        ];
+        let event = match scheduler.next_event_of(&ppu_events) {
+            Some(event) => event,
+            None => return 0,
+        };
+        // Lines 144-153 come first, followed by 0-143.
+        let line_start = |line: u64| ((line + 10) % 154) * SCANLINE_DURATION;
+        let current_line = self.current_y as u64;
+
+        let event_position = match event.event_type {
+            EventType::Vblank => CYCLES_PER_FRAME,
+            // LY already reads 0 for most of line 153.
+            EventType::OamSearch if self.get_current_mode() == Mode::Vblank => line_start(0),
+            EventType::OamSearch | EventType::VblankWait => line_start(current_line + 1),
+            EventType::LcdTransfer => line_start(current_line) + OAM_SEARCH_DURATION,
+            EventType::Hblank => {
+                line_start(current_line) + OAM_SEARCH_DURATION + self.current_lcd_transfer_duration
+            }
+            _ => unreachable!(),
+        };
+        let remaining = event.timestamp.saturating_sub(scheduler.current_time) >> speed_shift;
+
+        event_position.saturating_sub(remaining)
+    }
+
     /// Roughly calculates the expected duration of LCD transfer (mode 3)
     /// This is not entirely accurate yet, as I'm not sure about the sprite timings.
     #[inline]
@@ -1,3 +1,5 @@
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
+
 /// 256 bytes total for DMG
 pub const BOOTROM_SIZE_DMG: usize = 0x100;
 pub const BOOTROM_SIZE_CGB: usize = 0x900;
@@ -27,3 +29,15 @@ impl BootRom {
         self.data[address as usize]
     }
 }
+
+impl SaveState for BootRom {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.is_finished);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        // Without a bootrom to run we can only continue as if it had already finished.
+        self.is_finished = state.read_bool()? || self.data.is_empty();
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+//! The CGB infrared communications port (`RP`), to which an external device can be connected.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::hardware::mmu::INVALID_READ;
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
+
+/// A device on the other side of the infrared port.
+pub trait InfraredDevice {
+    /// Called whenever the Game Boy turns its LED on or off.
+    fn set_led(&mut self, on: bool);
+    /// Returns whether the device's LED is currently shining on the Game Boy.
+    fn receiving_light(&self) -> bool;
+}
+
+/// A device which simply reflects the Game Boy's own LED back, useful for testing.
+#[derive(Debug, Default)]
+pub struct InfraredLoopback {
+    led_on: bool,
+}
+
+impl InfraredDevice for InfraredLoopback {
+    fn set_led(&mut self, on: bool) {
+        self.led_on = on;
+    }
+
+    fn receiving_light(&self) -> bool {
+        self.led_on
+    }
+}
+
+/// One end of an infrared link between two emulators, create both ends with `pair()`.
+#[derive(Debug)]
+pub struct InfraredLink {
+    own_led: Arc<AtomicBool>,
+    peer_led: Arc<AtomicBool>,
+}
+
+impl InfraredLink {
+    pub fn pair() -> (InfraredLink, InfraredLink) {
+        let first = Arc::new(AtomicBool::new(false));
+        let second = Arc::new(AtomicBool::new(false));
+
+        (
+            InfraredLink {
+                own_led: first.clone(),
+                peer_led: second.clone(),
+            },
+            InfraredLink {
+                own_led: second,
+                peer_led: first,
+            },
+        )
+    }
+}
+
+impl InfraredDevice for InfraredLink {
+    fn set_led(&mut self, on: bool) {
+        self.own_led.store(on, Ordering::Relaxed);
+    }
+
+    fn receiving_light(&self) -> bool {
+        self.peer_led.load(Ordering::Relaxed)
+    }
+}
+
+/// The `RP` register (`0xFF56`), only available in CGB mode.
+///
+/// Bit 0   - LED (0=Off, 1=On)
+/// Bit 1   - Read data (0=Receiving IR signal, 1=Normal) (Read only)
+/// Bit 6-7 - Data read enable (0=Disable, 3=Enable)
+pub struct InfraredPort {
+    register: u8,
+    pub device: Option<Box<dyn InfraredDevice + Send>>,
+}
+
+impl InfraredPort {
+    pub fn new() -> Self {
+        InfraredPort {
+            register: 0x00,
+            device: None,
+        }
+    }
+
+    pub fn read_register(&self, cgb: bool) -> u8 {
+        if !cgb {
+            return INVALID_READ;
+        }
+        let read_enabled = (self.register & 0xC0) == 0xC0;
+        let receiving = read_enabled && self.device.as_ref().is_some_and(|device| device.receiving_light());
+
+        // Bit 2-5 are unused and always read as 1.
+        0x3C | (self.register & 0xC1) | if receiving { 0 } else { 0x2 }
+    }
+
+    pub fn write_register(&mut self, value: u8, cgb: bool) {
+        if !cgb {
+            return;
+        }
+        self.register = value & 0xC1;
+
+        if let Some(device) = &mut self.device {
+            device.set_led((value & 0x1) == 1);
+        }
+    }
+}
+
+impl SaveState for InfraredPort {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.register);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.register = state.read_u8()? & 0xC1;
+
+        if let Some(device) = &mut self.device {
+            device.set_led((self.register & 0x1) == 1);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::infrared::{InfraredLink, InfraredLoopback, InfraredPort};
+
+    #[test]
+    fn test_read_semantics() {
+        let mut port = InfraredPort::new();
+        assert_eq!(port.read_register(true), 0x3E);
+        assert_eq!(port.read_register(false), 0xFF);
+
+        port.device = Some(Box::new(InfraredLoopback::default()));
+        port.write_register(0x01, true);
+        // Reading isn't enabled yet.
+        assert_eq!(port.read_register(true), 0x3F);
+        port.write_register(0xC1, true);
+        assert_eq!(port.read_register(true), 0xFD);
+        port.write_register(0xC0, true);
+        assert_eq!(port.read_register(true), 0xFE);
+    }
+
+    #[test]
+    fn test_linked_ports() {
+        let (first, second) = InfraredLink::pair();
+        let mut first_port = InfraredPort::new();
+        let mut second_port = InfraredPort::new();
+        first_port.device = Some(Box::new(first));
+        second_port.device = Some(Box::new(second));
+
+        first_port.write_register(0xC1, true);
+        second_port.write_register(0xC0, true);
+
+        assert_eq!(second_port.read_register(true) & 0x2, 0);
+        // The first port should not see its own LED.
+        assert_eq!(first_port.read_register(true) & 0x2, 0x2);
+    }
+}
@@ -1,11 +1,17 @@
 use bitflags::*;
 
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
+
 #[derive(Default, Debug, Clone, Copy)]
 pub struct Interrupts {
     pub interrupt_enable: InterruptFlags,
     pub interrupt_flag: InterruptFlags,
 
     pub should_check: bool,
+
+    /// Test-only override of the interrupt priority, see `force_next()`.
+    #[cfg(any(test, feature = "fuzzing"))]
+    forced: Option<InterruptFlags>,
 }
 
 impl Interrupts {
@@ -47,7 +53,26 @@ impl Interrupts {
         !(interrupt & self.interrupt_flag & self.interrupt_enable).is_empty()
     }
 
+    /// **Test-only**, only available in tests or with the `fuzzing` feature.
+    ///
+    /// Make `get_highest_priority()` prefer the provided `interrupt` over the hardware priority,
+    /// for as long as it's pending and enabled. Passing `None` restores the normal behaviour.
+    ///
+    /// Allows tests to exercise specific interrupts in, for example, the cancellation logic of
+    /// the CPU's `interrupts_routine()`.
+    #[cfg(any(test, feature = "fuzzing"))]
+    pub fn force_next(&mut self, interrupt: Option<InterruptFlags>) {
+        self.forced = interrupt;
+    }
+
     pub fn get_highest_priority(&self) -> InterruptFlags {
+        #[cfg(any(test, feature = "fuzzing"))]
+        {
+            if let Some(forced) = self.forced.filter(|&forced| self.interrupt_should_trigger(forced)) {
+                return forced;
+            }
+        }
+
         if self.interrupt_should_trigger(InterruptFlags::VBLANK) {
             InterruptFlags::VBLANK
         } else if self.interrupt_should_trigger(InterruptFlags::LCD) {
@@ -64,6 +89,20 @@ impl Interrupts {
     }
 }
 
+impl SaveState for Interrupts {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.interrupt_enable.bits());
+        state.write_u8(self.interrupt_flag.bits());
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.interrupt_enable = InterruptFlags::from_bits_truncate(state.read_u8()?);
+        self.interrupt_flag = InterruptFlags::from_bits_truncate(state.read_u8()?);
+        self.should_check = self.interrupts_pending();
+        Ok(())
+    }
+}
+
 bitflags! {
     #[derive(Default)]
     pub struct InterruptFlags: u8 {
@@ -1,4 +1,5 @@
 use crate::hardware::mmu::INVALID_READ;
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
 
 pub const IO_SIZE: usize = 0x80;
 
@@ -23,3 +24,13 @@ impl IORegisters {
         self.memory[(address & 0xFF) as usize] = value;
     }
 }
+
+impl SaveState for IORegisters {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.memory);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_into(&mut self.memory)
+    }
+}
@@ -4,6 +4,8 @@
 
 use bitflags::*;
 
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
+
 #[derive(Debug, Copy, Clone, PartialOrd, PartialEq)]
 pub enum InputKey {
     Start,
@@ -21,6 +23,8 @@ pub struct JoyPad {
     pressed_buttons: JoypadFlags,
     pressed_directions: JoypadFlags,
     selected_mode: JoypadFlags,
+    /// Whether the most recent write by the game selected at least one line.
+    line_selected: bool,
 }
 
 impl JoyPad {
@@ -28,11 +32,15 @@ impl JoyPad {
         JoyPad {
             pressed_buttons: JoypadFlags::empty(),
             pressed_directions: JoypadFlags::empty(),
-            selected_mode: JoypadFlags::from_bits_truncate(0xFF),
+            // Both modes are selected after boot, which results in a read value of 0xCF.
+            selected_mode: JoypadFlags::DIRECTION_KEYS | JoypadFlags::BUTTON_KEYS,
+            line_selected: false,
         }
     }
 
     /// Get the current JoyPad register for the most recently selected mode (Button, Direction)
+    ///
+    /// Bits 6-7 always read as 1, as do the bits of any keys which aren't pressed or selected.
     pub fn get_register(&self) -> u8 {
         !self.selected_mode.bits
     }
@@ -41,10 +49,16 @@ impl JoyPad {
     /// If a game tries to write to the lower nibble for some reason those bits will just be
     /// discarded.
     pub fn set_register(&mut self, mode: u8) {
+        self.line_selected = (mode & 0x30) != 0x30;
         self.selected_mode = JoypadFlags::from_bits_truncate(!mode);
         self.update_flags();
     }
 
+    /// Returns whether the game explicitly selected a line (Button, Direction) with its last write.
+    pub fn line_selected(&self) -> bool {
+        self.line_selected
+    }
+
     /// Register a key as pressed down.
     pub fn press_key(&mut self, input: InputKey) {
         use InputKey::*;
@@ -109,3 +123,50 @@ bitflags! {
         const UNUSED_1        = 0b1000_0000;
     }
 }
+
+impl SaveState for JoyPad {
+    fn save_state(&self, state: &mut StateWriter) {
+        // The pressed keys are the frontend's current input, and thus not part of the state.
+        state.write_u8(self.selected_mode.bits() & 0b0011_0000);
+        state.write_bool(self.line_selected);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.selected_mode = JoypadFlags::from_bits_truncate(state.read_u8()?);
+        self.line_selected = state.read_bool()?;
+        self.update_flags();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::joypad::{InputKey, JoyPad};
+
+    #[test]
+    fn test_unselected_bits() {
+        let mut joypad = JoyPad::new();
+        assert_eq!(joypad.get_register(), 0xCF);
+
+        // Neither mode selected.
+        joypad.set_register(0x30);
+        assert_eq!(joypad.get_register(), 0xFF);
+        joypad.press_key(InputKey::A);
+        joypad.press_key(InputKey::Down);
+        assert_eq!(joypad.get_register(), 0xFF);
+
+        // Only buttons selected, the lower nibble written should be discarded.
+        joypad.set_register(0x1F);
+        assert_eq!(joypad.get_register(), 0xDE);
+        // Only directions selected.
+        joypad.set_register(0x20);
+        assert_eq!(joypad.get_register(), 0xE7);
+        // Both selected, the lines are combined.
+        joypad.set_register(0x00);
+        assert_eq!(joypad.get_register(), 0xC6);
+
+        joypad.release_key(InputKey::A);
+        joypad.release_key(InputKey::Down);
+        assert_eq!(joypad.get_register(), 0xCF);
+    }
+}
@@ -1,5 +1,8 @@
 pub mod bootrom;
+pub mod infrared;
 pub mod interrupts;
 pub mod io_registers;
 pub mod joypad;
+pub mod printer;
+pub mod serial;
 pub mod timer;
@@ -0,0 +1,317 @@
+//! A virtual Game Boy Printer, connected through the serial port.
+//!
+//! The Game Boy sends packets with the following layout:
+//! `0x88 0x33 | command | compression | length (LE u16) | data | checksum (LE u16) | 0x00 0x00`
+//! to which the printer responds with `0x00`, except for the last two bytes where it responds
+//! with `0x81` (alive) and its current status.
+//!
+//! See [the pandocs](https://gbdev.io/pandocs/Gameboy_Printer.html) for more information.
+
+use crate::hardware::ppu::palette::RGB;
+use crate::io::serial::SerialLink;
+use crate::state::rle_decode_into;
+
+/// The width of a printed image in pixels, the printer always receives 20 tiles per row.
+pub const PRINTER_WIDTH: usize = 160;
+/// The amount of bytes for one row of 20 tiles.
+const TILE_ROW_SIZE: usize = 20 * 16;
+
+pub const COMMAND_INIT: u8 = 0x01;
+pub const COMMAND_PRINT: u8 = 0x02;
+pub const COMMAND_DATA: u8 = 0x04;
+pub const COMMAND_STATUS: u8 = 0x0F;
+
+pub const STATUS_CHECKSUM_ERROR: u8 = 0b0000_0001;
+pub const STATUS_PRINTING: u8 = 0b0000_0010;
+pub const STATUS_IMAGE_DATA_FULL: u8 = 0b0000_0100;
+pub const STATUS_UNPROCESSED_DATA: u8 = 0b0000_1000;
+pub const STATUS_PACKET_ERROR: u8 = 0b0001_0000;
+
+/// The amount of status requests for which we'll report to still be printing.
+/// Games usually wait for the printing flag to be set, and subsequently cleared.
+const PRINT_BUSY_POLLS: u8 = 4;
+
+/// The shades of the thermal paper, from white to black.
+const PRINTER_SHADES: [RGB; 4] = [
+    RGB(255, 255, 255),
+    RGB(170, 170, 170),
+    RGB(85, 85, 85),
+    RGB(0, 0, 0),
+];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum PacketState {
+    MagicFirst,
+    MagicSecond,
+    Command,
+    Compression,
+    LengthLow,
+    LengthHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    Alive,
+    Status,
+}
+
+#[derive(Debug)]
+pub struct PrinterDevice {
+    state: PacketState,
+    command: u8,
+    compressed: bool,
+    length: u16,
+    data: Vec<u8>,
+    checksum: u16,
+    received_checksum: u16,
+    status: u8,
+    busy_polls: u8,
+    /// The received tile data which has yet to be printed.
+    tile_data: Vec<u8>,
+    /// All lines printed since the last call to `take_image()`.
+    image: Vec<RGB>,
+}
+
+impl Default for PrinterDevice {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PrinterDevice {
+    pub fn new() -> Self {
+        PrinterDevice {
+            state: PacketState::MagicFirst,
+            command: 0,
+            compressed: false,
+            length: 0,
+            data: Vec::with_capacity(TILE_ROW_SIZE * 2),
+            checksum: 0,
+            received_checksum: 0,
+            status: 0,
+            busy_polls: 0,
+            tile_data: Vec::new(),
+            image: Vec::new(),
+        }
+    }
+
+    /// Returns all the lines printed since the last call, if any.
+    ///
+    /// The image is always [PRINTER_WIDTH](constant.PRINTER_WIDTH.html) pixels wide.
+    pub fn take_image(&mut self) -> Option<Vec<RGB>> {
+        if self.image.is_empty() {
+            None
+        } else {
+            Some(std::mem::take(&mut self.image))
+        }
+    }
+
+    fn process_packet(&mut self) {
+        if self.checksum != self.received_checksum {
+            log::warn!(
+                "Printer checksum mismatch, expected: {:#06X} got: {:#06X}",
+                self.checksum,
+                self.received_checksum
+            );
+            self.status |= STATUS_CHECKSUM_ERROR;
+            return;
+        }
+        self.status &= !(STATUS_CHECKSUM_ERROR | STATUS_PACKET_ERROR);
+
+        match self.command {
+            COMMAND_INIT => {
+                self.tile_data.clear();
+                self.status = 0;
+                self.busy_polls = 0;
+            }
+            COMMAND_DATA if self.data.is_empty() => self.status |= STATUS_IMAGE_DATA_FULL,
+            COMMAND_DATA => {
+                if self.compressed {
+                    // A truncated packet still prints whatever was decompressed before it broke off.
+                    rle_decode_into(&self.data, &mut self.tile_data);
+                } else {
+                    self.tile_data.extend_from_slice(&self.data);
+                }
+                self.status |= STATUS_UNPROCESSED_DATA;
+            }
+            COMMAND_PRINT => {
+                // Data is: sheets, margins, palette and exposure.
+                let palette = match self.data.get(2) {
+                    Some(0) | None => 0xE4,
+                    Some(&palette) => palette,
+                };
+                self.print(palette);
+                self.status &= !(STATUS_UNPROCESSED_DATA | STATUS_IMAGE_DATA_FULL);
+                self.status |= STATUS_PRINTING;
+                self.busy_polls = PRINT_BUSY_POLLS;
+            }
+            COMMAND_STATUS => {
+                if self.busy_polls > 0 {
+                    self.busy_polls -= 1;
+                    if self.busy_polls == 0 {
+                        self.status &= !STATUS_PRINTING;
+                    }
+                }
+            }
+            _ => {
+                log::warn!("Unknown printer command: {:#04X}", self.command);
+                self.status |= STATUS_PACKET_ERROR;
+            }
+        }
+    }
+
+    /// Render all buffered tile data to the image, any incomplete tile row is discarded.
+    fn print(&mut self, palette: u8) {
+        for tile_row in self.tile_data.chunks_exact(TILE_ROW_SIZE) {
+            for y in 0..8 {
+                for tile in tile_row.chunks_exact(16) {
+                    let (lower, higher) = (tile[y * 2], tile[y * 2 + 1]);
+
+                    for bit in (0..8).rev() {
+                        let colour = (((higher >> bit) & 1) << 1) | ((lower >> bit) & 1);
+                        let shade = (palette >> (colour * 2)) & 0x3;
+                        self.image.push(PRINTER_SHADES[shade as usize]);
+                    }
+                }
+            }
+        }
+
+        self.tile_data.clear();
+    }
+}
+
+impl SerialLink for PrinterDevice {
+    fn transfer(&mut self, sent: u8) -> u8 {
+        let mut response = 0x00;
+
+        self.state = match self.state {
+            PacketState::MagicFirst if sent == 0x88 => PacketState::MagicSecond,
+            PacketState::MagicFirst => PacketState::MagicFirst,
+            PacketState::MagicSecond if sent == 0x33 => PacketState::Command,
+            PacketState::MagicSecond if sent == 0x88 => PacketState::MagicSecond,
+            PacketState::MagicSecond => PacketState::MagicFirst,
+            PacketState::Command => {
+                self.command = sent;
+                self.checksum = sent as u16;
+                PacketState::Compression
+            }
+            PacketState::Compression => {
+                self.compressed = (sent & 0x1) == 1;
+                self.checksum = self.checksum.wrapping_add(sent as u16);
+                PacketState::LengthLow
+            }
+            PacketState::LengthLow => {
+                self.length = sent as u16;
+                self.checksum = self.checksum.wrapping_add(sent as u16);
+                PacketState::LengthHigh
+            }
+            PacketState::LengthHigh => {
+                self.length |= (sent as u16) << 8;
+                self.checksum = self.checksum.wrapping_add(sent as u16);
+                self.data.clear();
+                if self.length == 0 {
+                    PacketState::ChecksumLow
+                } else {
+                    PacketState::Data
+                }
+            }
+            PacketState::Data => {
+                self.data.push(sent);
+                self.checksum = self.checksum.wrapping_add(sent as u16);
+                if self.data.len() == self.length as usize {
+                    PacketState::ChecksumLow
+                } else {
+                    PacketState::Data
+                }
+            }
+            PacketState::ChecksumLow => {
+                self.received_checksum = sent as u16;
+                PacketState::ChecksumHigh
+            }
+            PacketState::ChecksumHigh => {
+                self.received_checksum |= (sent as u16) << 8;
+                self.process_packet();
+                PacketState::Alive
+            }
+            PacketState::Alive => {
+                response = 0x81;
+                PacketState::Status
+            }
+            PacketState::Status => {
+                response = self.status;
+                PacketState::MagicFirst
+            }
+        };
+
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hardware::ppu::palette::RGB;
+    use crate::io::printer::*;
+    use crate::io::serial::SerialLink;
+
+    /// Send a full packet to the printer, returns the alive and status responses.
+    fn send_packet(printer: &mut PrinterDevice, command: u8, compression: u8, data: &[u8]) -> (u8, u8) {
+        let mut packet = vec![command, compression, data.len() as u8, (data.len() >> 8) as u8];
+        packet.extend_from_slice(data);
+        let checksum = packet.iter().fold(0u16, |acc, &b| acc.wrapping_add(b as u16));
+        packet.extend_from_slice(&checksum.to_le_bytes());
+
+        for &byte in [0x88, 0x33].iter().chain(packet.iter()) {
+            assert_eq!(printer.transfer(byte), 0x00);
+        }
+
+        (printer.transfer(0x00), printer.transfer(0x00))
+    }
+
+    #[test]
+    fn test_print_image() {
+        let mut printer = PrinterDevice::new();
+
+        assert_eq!(send_packet(&mut printer, COMMAND_INIT, 0, &[]), (0x81, 0x00));
+        // One row of tiles with colour 3, one row with colour 1.
+        let mut data = vec![0xFF; 320];
+        data.extend((0..320).map(|i| if i % 2 == 0 { 0xFF } else { 0x00 }));
+        let (_, status) = send_packet(&mut printer, COMMAND_DATA, 0, &data);
+        assert_eq!(status, STATUS_UNPROCESSED_DATA);
+        let (_, status) = send_packet(&mut printer, COMMAND_DATA, 0, &[]);
+        assert_eq!(status, STATUS_UNPROCESSED_DATA | STATUS_IMAGE_DATA_FULL);
+
+        assert!(printer.take_image().is_none());
+        let (_, status) = send_packet(&mut printer, COMMAND_PRINT, 0, &[0x01, 0x00, 0xE4, 0x40]);
+        assert_eq!(status, STATUS_PRINTING);
+
+        let image = printer.take_image().unwrap();
+        assert_eq!(image.len(), PRINTER_WIDTH * 16);
+        assert_eq!(image[0], RGB(0, 0, 0));
+        assert_eq!(image[PRINTER_WIDTH * 8], RGB(170, 170, 170));
+        assert!(printer.take_image().is_none());
+
+        for _ in 0..PRINT_BUSY_POLLS - 1 {
+            assert_eq!(send_packet(&mut printer, COMMAND_STATUS, 0, &[]).1, STATUS_PRINTING);
+        }
+        assert_eq!(send_packet(&mut printer, COMMAND_STATUS, 0, &[]).1, 0);
+    }
+
+    #[test]
+    fn test_compressed_data() {
+        let mut output = Vec::new();
+        assert_eq!(rle_decode_into(&[0x81, 0xAB, 0x01, 0x12, 0x34], &mut output), Some(()));
+
+        assert_eq!(output, vec![0xAB, 0xAB, 0xAB, 0x12, 0x34]);
+    }
+
+    #[test]
+    fn test_checksum_error() {
+        let mut printer = PrinterDevice::new();
+
+        for &byte in &[0x88, 0x33, COMMAND_INIT, 0x00, 0x00, 0x00, 0xFF, 0xFF] {
+            printer.transfer(byte);
+        }
+
+        assert_eq!(printer.transfer(0x00), 0x81);
+        assert_eq!(printer.transfer(0x00), STATUS_CHECKSUM_ERROR);
+    }
+}
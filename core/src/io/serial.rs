@@ -0,0 +1,32 @@
+//! The serial port (link cable), to which external devices can be connected.
+
+use std::sync::{Arc, Mutex};
+
+/// The amount of cycles it takes to shift out one byte with the regular internal clock (8192Hz).
+///
+/// The internal clock runs twice as fast in double speed mode, so this is the same in both modes.
+pub const SERIAL_TRANSFER_CYCLES: u64 = 4096;
+/// The amount of cycles it takes to shift out one byte with the CGB fast internal clock (262144Hz).
+pub const SERIAL_FAST_TRANSFER_CYCLES: u64 = 128;
+
+/// A device on the other end of the link cable.
+///
+/// The device acts as the slave for transfers started by the Game Boy (internal clock).
+/// Should the device drive the clock instead (e.g, another Game Boy), it has to call
+/// [external_serial_transfer](gb_emu/struct.GameBoyEmulator.html#method.external_serial_transfer).
+pub trait SerialLink {
+    /// Called once the full `out` byte has been shifted out, should return the byte the device
+    /// shifted in simultaneously.
+    ///
+    /// A device which can't answer (e.g, a disconnected cable) should return `0xFF`, as the line
+    /// is pulled high without anything on the other end.
+    fn transfer(&mut self, out: u8) -> u8;
+}
+
+/// Allows a frontend to keep a handle to a device (e.g, to retrieve printed images) while it's
+/// connected to the emulator.
+impl<T: SerialLink> SerialLink for Arc<Mutex<T>> {
+    fn transfer(&mut self, out: u8) -> u8 {
+        self.lock().expect("Serial device lock was poisoned").transfer(out)
+    }
+}
@@ -1,6 +1,8 @@
+use crate::gb_emu::DMG_CLOCK_SPEED;
 use crate::io::interrupts::{InterruptFlags, Interrupts};
 use crate::io::timer::InputClock::C256;
 use crate::scheduler::{EventType, Scheduler};
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
 
 /// This timer is incremented by a clock frequency specified by the TAC register ($FF07).
 /// When the value overflows (gets bigger than FFh) then it will be reset to the value
@@ -25,6 +27,17 @@ pub struct TimerControl {
     input_select: InputClock,
 }
 
+/// A read-only snapshot of the timer registers, with `TAC` decoded.
+#[derive(Debug, Default, Copy, Clone, PartialOrd, PartialEq)]
+pub struct TimerState {
+    pub div: u8,
+    pub tima: u8,
+    pub tma: u8,
+    pub enabled: bool,
+    /// The frequency at which `TIMA` increments in single speed mode, doubles in CGB double speed.
+    pub frequency: u32,
+}
+
 #[derive(Debug, Default)]
 pub struct TimerRegisters {
     pub timer_counter: u8,
@@ -58,6 +71,16 @@ impl TimerRegisters {
         (self.get_time_passed(scheduler) >> 8) as u8
     }
 
+    pub fn timer_state(&self, scheduler: &Scheduler) -> TimerState {
+        TimerState {
+            div: self.divider_register(scheduler),
+            tima: self.timer_counter,
+            tma: self.timer_modulo,
+            enabled: self.timer_control.timer_enabled,
+            frequency: (DMG_CLOCK_SPEED / self.timer_control.get_clock_interval()) as u32,
+        }
+    }
+
     /// Is called by the `Scheduler` at the rate specified by `timer_control`.
     /// Increments the timer, if it is enabled.
     pub fn scheduled_timer_tick(&mut self, scheduler: &mut Scheduler) {
@@ -183,6 +206,27 @@ impl TimerRegisters {
     }
 }
 
+impl SaveState for TimerRegisters {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.timer_counter);
+        state.write_u8(self.timer_modulo);
+        state.write_u8(self.timer_control.to_bits());
+        state.write_bool(self.just_overflowed);
+        state.write_bool(self.timer_overflowed);
+        state.write_u64(self.last_div_reset);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.timer_counter = state.read_u8()?;
+        self.timer_modulo = state.read_u8()?;
+        self.timer_control = TimerControl::from(state.read_u8()?);
+        self.just_overflowed = state.read_bool()?;
+        self.timer_overflowed = state.read_bool()?;
+        self.last_div_reset = state.read_u64()?;
+        Ok(())
+    }
+}
+
 impl TimerControl {
     pub fn to_bits(&self) -> u8 {
         let result = if self.timer_enabled { 0x4 } else { 0 };
@@ -1,14 +1,33 @@
 pub mod gb_emu;
 mod scheduler;
-use crate::gb_emu::GameBoyModel;
-use crate::hardware::ppu::palette::DisplayColour;
+use crate::gb_emu::{DmgRevision, GameBoyModel};
+use crate::hardware::apu::{DEFAULT_MASTER_GAIN, DEFAULT_SAMPLE_RATE, DEFAULT_VOLUME_STEP};
+use crate::hardware::cartridge::mbc::MbcKind;
+use crate::hardware::cpu::registers::Registers;
+use crate::hardware::mmu::MAX_GDMA_STALL_CYCLES;
+use crate::hardware::ppu::palette::{DisplayColour, PaletteTables};
+pub use crate::gb_emu_debug::{DebugRequest, DebugResponse, StepResult};
+pub use crate::hardware::cpu::StepKind;
+pub use crate::io::infrared::{InfraredDevice, InfraredLink, InfraredLoopback};
 pub use crate::io::joypad::InputKey;
+pub use crate::io::printer::{PrinterDevice, PRINTER_WIDTH};
+pub use crate::io::serial::SerialLink;
+pub use crate::io::timer::TimerState;
+pub use crate::scheduler::{Event, EventType};
 use std::fmt::Debug;
 use std::ops::DerefMut;
+use std::sync::Arc;
 
+pub mod crash_report;
+#[cfg(feature = "debug-json")]
+pub mod debug_dump;
 mod gb_emu_debug;
 pub mod hardware;
 mod io;
+pub mod osd;
+pub mod patch;
+pub mod scaler;
+pub mod state;
 
 pub trait ExternalRamBacking: DerefMut<Target = [u8]> + Debug {
     /// Set the length of the underlying backed memory.
@@ -21,14 +40,90 @@ pub trait ExternalRamBacking: DerefMut<Target = [u8]> + Debug {
 }
 
 /// Struct for wrapping all the various options for the `Emulator`
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct EmulatorOptions {
     pub boot_rom: Option<Vec<u8>>,
     pub saved_ram: Option<Vec<u8>>,
     pub emulator_mode: GameBoyModel,
+    /// The exact hardware revision to emulate in `DMG` mode, only affects the initial registers
+    /// when no bootrom is provided.
+    pub dmg_revision: DmgRevision,
     pub bg_display_colour: DisplayColour,
     pub sp0_display_colour: DisplayColour,
     pub sp1_display_colour: DisplayColour,
+    /// Render DMG ROMs through the CGB scanline renderer, only has an effect in `CGB` mode.
+    ///
+    /// Note that the CGB renderer ignores the DMG palette registers (and DMG sprite palette
+    /// selection), so games which rely on those for effects such as fades will look off unless
+    /// a palette mod is applied.
+    pub force_cgb_rendering: bool,
+    /// Overrides the register state the CPU would have after the bootrom finished.
+    /// Only used if no `boot_rom` is provided.
+    pub initial_registers: Option<Registers>,
+    /// Pre-initialised palettes, used instead of computing the initial palette state from the
+    /// display colours above. Ignored if they weren't created from those same display colours.
+    pub palette_tables: Option<Arc<PaletteTables>>,
+    /// Ignore all writes to read-only bits of the PPU registers, instead of leaving that up to
+    /// the individual registers. See [`register_write_mask`] for the writable bits.
+    ///
+    /// [`register_write_mask`]: hardware/ppu/memory_binds/fn.register_write_mask.html
+    pub strict_register_writes: bool,
+    /// Downmix the audio to mono, for mono output devices.
+    pub mono_audio: bool,
+    /// Apply the high-pass filter to the audio output, removing the DAC's DC offset.
+    /// Can be disabled for analysis of the raw output.
+    pub highpass_filter: bool,
+    /// The stereo separation of the audio, where `0.0` is mono and `1.0` is the hard panning of
+    /// real hardware, see [set_stereo_width](hardware/apu/struct.APU.html#method.set_stereo_width).
+    pub stereo_width: f32,
+    /// The gain applied to the sum of all channels, see
+    /// [set_gain](hardware/apu/struct.APU.html#method.set_gain).
+    pub audio_master_gain: f32,
+    /// The gain for every step of the game controlled master volume, see
+    /// [set_gain](hardware/apu/struct.APU.html#method.set_gain).
+    pub audio_volume_step: f32,
+    /// Start with this many milliseconds of silence in the audio buffer, so that the audio
+    /// output has data immediately instead of underrunning while the first frame is emulated.
+    pub audio_prebuffer_ms: u32,
+    /// The rate (in Hz) at which audio samples are generated, should match the output device.
+    pub sample_rate: u32,
+    /// Record every write to the MBC registers which changes the mapped ROM or RAM banks, along
+    /// with the resulting banks. Useful when debugging games which end up reading from the wrong bank.
+    pub log_bank_switches: bool,
+    /// Run CGB-only ROMs in `CGB` mode, even if `DMG` mode was requested.
+    /// Otherwise [try_new](gb_emu/struct.GameBoyEmulator.html#method.try_new) will refuse to create
+    /// an emulator for such ROMs.
+    pub upgrade_cgb_only_roms: bool,
+    /// Disable the shortcuts the emulator takes for performance, getting as close to stepping the
+    /// system cycle by cycle as the code allows. Meant as a reference when bisecting accuracy bugs
+    /// which may have been introduced by one of the fast paths.
+    ///
+    /// * The APU is synchronised every M-cycle, instead of lazily on register accesses and V-blank.
+    /// * Halt skipping is disabled, a halted CPU steps one M-cycle at a time.
+    ///
+    /// The PPU has no per-pixel renderer, so scanlines are still rendered in one go.
+    /// Expect this to run several times slower, it's not suitable for regular play.
+    pub disable_shortcuts: bool,
+    /// Show a blank LCD (the lightest display colour) until the first frame is completed, like a
+    /// real LCD powering on. Otherwise the frame buffer starts out black.
+    pub blank_initial_frame: bool,
+    /// Use this MBC instead of the one specified by the cartridge type in the ROM header, for
+    /// mislabeled dumps and homebrew. The header itself still has to be valid.
+    ///
+    /// [try_new](gb_emu/struct.GameBoyEmulator.html#method.try_new) refuses an MBC which can't
+    /// address the entire ROM.
+    pub mbc_override: Option<MbcKind>,
+    /// The most cycles a single GDMA transfer may stall the CPU for, after which the CPU resumes
+    /// as if the transfer completed. Guards against a single
+    /// [emulate_cycle](gb_emu/struct.GameBoyEmulator.html#method.emulate_cycle) running away,
+    /// lowering it below `MAX_GDMA_STALL_CYCLES` cuts long transfers short.
+    pub max_gdma_stall_cycles: u64,
+    /// **Experimental**, additionally render every frame at twice the native resolution with
+    /// interpolated half-pixel offsets, see
+    /// [frame_buffer_2x](gb_emu/struct.GameBoyEmulator.html#method.frame_buffer_2x).
+    ///
+    /// This is a non-authentic enhancement for smoother scrolling, not an accuracy feature.
+    pub supersample_2x: bool,
 }
 
 #[derive(Debug)]
@@ -36,9 +131,28 @@ pub struct EmulatorOptionsBuilder {
     boot_rom: Option<Vec<u8>>,
     saved_ram: Option<Vec<u8>>,
     emulator_mode: GameBoyModel,
+    dmg_revision: DmgRevision,
     bg_display_colour: DisplayColour,
     sp0_display_colour: DisplayColour,
     sp1_display_colour: DisplayColour,
+    force_cgb_rendering: bool,
+    initial_registers: Option<Registers>,
+    palette_tables: Option<Arc<PaletteTables>>,
+    strict_register_writes: bool,
+    mono_audio: bool,
+    highpass_filter: bool,
+    stereo_width: f32,
+    audio_master_gain: f32,
+    audio_volume_step: f32,
+    audio_prebuffer_ms: u32,
+    sample_rate: u32,
+    log_bank_switches: bool,
+    upgrade_cgb_only_roms: bool,
+    disable_shortcuts: bool,
+    blank_initial_frame: bool,
+    mbc_override: Option<MbcKind>,
+    max_gdma_stall_cycles: u64,
+    supersample_2x: bool,
 }
 
 impl EmulatorOptionsBuilder {
@@ -47,9 +161,28 @@ impl EmulatorOptionsBuilder {
             boot_rom: None,
             saved_ram: None,
             emulator_mode: GameBoyModel::DMG,
+            dmg_revision: DmgRevision::default(),
             bg_display_colour: Default::default(),
             sp0_display_colour: Default::default(),
             sp1_display_colour: Default::default(),
+            force_cgb_rendering: false,
+            initial_registers: None,
+            palette_tables: None,
+            strict_register_writes: false,
+            mono_audio: false,
+            highpass_filter: true,
+            stereo_width: 1.0,
+            audio_master_gain: DEFAULT_MASTER_GAIN,
+            audio_volume_step: DEFAULT_VOLUME_STEP,
+            audio_prebuffer_ms: 0,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            log_bank_switches: false,
+            upgrade_cgb_only_roms: false,
+            disable_shortcuts: false,
+            blank_initial_frame: true,
+            mbc_override: None,
+            max_gdma_stall_cycles: MAX_GDMA_STALL_CYCLES,
+            supersample_2x: false,
         }
     }
 
@@ -68,6 +201,12 @@ impl EmulatorOptionsBuilder {
         self
     }
 
+    /// Select the revision whose post-bootrom register state should be used in `DMG` mode.
+    pub fn with_dmg_revision(mut self, revision: DmgRevision) -> Self {
+        self.dmg_revision = revision;
+        self
+    }
+
     pub fn with_display_colour(mut self, colours: DisplayColour) -> Self {
         self.bg_display_colour = colours;
         self.sp0_display_colour = colours;
@@ -90,14 +229,146 @@ impl EmulatorOptionsBuilder {
         self
     }
 
+    /// Whether to use the CGB renderer for DMG ROMs as well.
+    /// See [`EmulatorOptions::force_cgb_rendering`] for the caveats.
+    pub fn with_force_cgb_rendering(mut self, force_cgb_rendering: bool) -> Self {
+        self.force_cgb_rendering = force_cgb_rendering;
+        self
+    }
+
+    /// Start the CPU with the provided `registers` instead of the usual post-bootrom values.
+    ///
+    /// Mostly useful for test harnesses, this is ignored if a bootrom is provided.
+    pub fn with_initial_registers(mut self, registers: Registers) -> Self {
+        self.initial_registers = Some(registers);
+        self
+    }
+
+    /// Use the shared `palette_tables` instead of computing the palettes from the display colours.
+    ///
+    /// Useful when running many emulators with the same display colours, the tables are ignored
+    /// if they were created from different display colours.
+    pub fn with_palette_tables(mut self, palette_tables: Arc<PaletteTables>) -> Self {
+        self.palette_tables = Some(palette_tables);
+        self
+    }
+
+    /// Whether to uniformly ignore writes to the read-only bits of PPU registers.
+    pub fn with_strict_register_writes(mut self, strict_register_writes: bool) -> Self {
+        self.strict_register_writes = strict_register_writes;
+        self
+    }
+
+    /// Whether the audio should be downmixed to mono.
+    pub fn with_mono_audio(mut self, mono_audio: bool) -> Self {
+        self.mono_audio = mono_audio;
+        self
+    }
+
+    /// Whether the high-pass filter should be applied to the audio, enabled by default.
+    pub fn with_highpass_filter(mut self, highpass_filter: bool) -> Self {
+        self.highpass_filter = highpass_filter;
+        self
+    }
+
+    /// The stereo separation of the audio, `1.0` (full hardware separation) by default.
+    pub fn with_stereo_width(mut self, stereo_width: f32) -> Self {
+        self.stereo_width = stereo_width;
+        self
+    }
+
+    /// The gain stage of the audio output, see [`EmulatorOptions::audio_master_gain`] and
+    /// [`EmulatorOptions::audio_volume_step`]. Defaults to `DEFAULT_MASTER_GAIN` and `DEFAULT_VOLUME_STEP`.
+    pub fn with_audio_gain(mut self, master_gain: f32, volume_step: f32) -> Self {
+        self.audio_master_gain = master_gain;
+        self.audio_volume_step = volume_step;
+        self
+    }
+
+    /// The amount of silence (in milliseconds) to start the audio buffer with, none by default.
+    pub fn with_audio_prebuffer_ms(mut self, audio_prebuffer_ms: u32) -> Self {
+        self.audio_prebuffer_ms = audio_prebuffer_ms;
+        self
+    }
+
+    /// The rate (in Hz) at which audio samples are generated, `DEFAULT_SAMPLE_RATE` by default.
+    pub fn with_sample_rate(mut self, sample_rate: u32) -> Self {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    /// Whether every MBC bank switch should be recorded.
+    pub fn with_log_bank_switches(mut self, log_bank_switches: bool) -> Self {
+        self.log_bank_switches = log_bank_switches;
+        self
+    }
+
+    /// Whether CGB-only ROMs should automatically be run in `CGB` mode, disabled by default.
+    pub fn with_upgrade_cgb_only_roms(mut self, upgrade_cgb_only_roms: bool) -> Self {
+        self.upgrade_cgb_only_roms = upgrade_cgb_only_roms;
+        self
+    }
+
+    /// Whether to disable all performance shortcuts, disabled by default.
+    /// See [`EmulatorOptions::disable_shortcuts`] for what this entails.
+    pub fn with_disable_shortcuts(mut self, disable_shortcuts: bool) -> Self {
+        self.disable_shortcuts = disable_shortcuts;
+        self
+    }
+
+    /// Whether to show a blank LCD before the first frame, enabled by default.
+    pub fn with_blank_initial_frame(mut self, blank_initial_frame: bool) -> Self {
+        self.blank_initial_frame = blank_initial_frame;
+        self
+    }
+
+    /// Force the provided MBC regardless of the ROM header, `None` (header driven) by default.
+    pub fn with_mbc_override(mut self, mbc_override: Option<MbcKind>) -> Self {
+        self.mbc_override = mbc_override;
+        self
+    }
+
+    /// The most cycles a GDMA transfer may stall the CPU for, `MAX_GDMA_STALL_CYCLES` by default.
+    /// See [`EmulatorOptions::max_gdma_stall_cycles`].
+    pub fn with_max_gdma_stall_cycles(mut self, max_gdma_stall_cycles: u64) -> Self {
+        self.max_gdma_stall_cycles = max_gdma_stall_cycles;
+        self
+    }
+
+    /// Whether to render at twice the native resolution as well, disabled by default.
+    /// See [`EmulatorOptions::supersample_2x`].
+    pub fn with_supersample_2x(mut self, supersample_2x: bool) -> Self {
+        self.supersample_2x = supersample_2x;
+        self
+    }
+
     pub fn build(self) -> EmulatorOptions {
         EmulatorOptions {
             boot_rom: self.boot_rom,
             saved_ram: self.saved_ram,
             emulator_mode: self.emulator_mode,
+            dmg_revision: self.dmg_revision,
             bg_display_colour: self.bg_display_colour,
             sp0_display_colour: self.sp0_display_colour,
             sp1_display_colour: self.sp1_display_colour,
+            force_cgb_rendering: self.force_cgb_rendering,
+            initial_registers: self.initial_registers,
+            palette_tables: self.palette_tables,
+            strict_register_writes: self.strict_register_writes,
+            mono_audio: self.mono_audio,
+            highpass_filter: self.highpass_filter,
+            stereo_width: self.stereo_width,
+            audio_master_gain: self.audio_master_gain,
+            audio_volume_step: self.audio_volume_step,
+            audio_prebuffer_ms: self.audio_prebuffer_ms,
+            sample_rate: self.sample_rate,
+            log_bank_switches: self.log_bank_switches,
+            upgrade_cgb_only_roms: self.upgrade_cgb_only_roms,
+            disable_shortcuts: self.disable_shortcuts,
+            blank_initial_frame: self.blank_initial_frame,
+            mbc_override: self.mbc_override,
+            max_gdma_stall_cycles: self.max_gdma_stall_cycles,
+            supersample_2x: self.supersample_2x,
         }
     }
 }
@@ -108,9 +379,28 @@ impl From<EmulatorOptions> for EmulatorOptionsBuilder {
             boot_rom: from.boot_rom,
             saved_ram: from.saved_ram,
             emulator_mode: from.emulator_mode,
+            dmg_revision: from.dmg_revision,
             bg_display_colour: from.bg_display_colour,
             sp0_display_colour: from.sp0_display_colour,
             sp1_display_colour: from.sp1_display_colour,
+            force_cgb_rendering: from.force_cgb_rendering,
+            initial_registers: from.initial_registers,
+            palette_tables: from.palette_tables,
+            strict_register_writes: from.strict_register_writes,
+            mono_audio: from.mono_audio,
+            highpass_filter: from.highpass_filter,
+            stereo_width: from.stereo_width,
+            audio_master_gain: from.audio_master_gain,
+            audio_volume_step: from.audio_volume_step,
+            audio_prebuffer_ms: from.audio_prebuffer_ms,
+            sample_rate: from.sample_rate,
+            log_bank_switches: from.log_bank_switches,
+            upgrade_cgb_only_roms: from.upgrade_cgb_only_roms,
+            disable_shortcuts: from.disable_shortcuts,
+            blank_initial_frame: from.blank_initial_frame,
+            mbc_override: from.mbc_override,
+            max_gdma_stall_cycles: from.max_gdma_stall_cycles,
+            supersample_2x: from.supersample_2x,
         }
     }
 }
@@ -0,0 +1,111 @@
+//! A tiny on-screen-display renderer which can draw text directly into an `RGB` buffer.
+//!
+//! Meant for frontends which want to show simple overlay messages (save/load notifications,
+//! FPS counters, etc.) without having to pull in a full GUI library.
+
+use crate::hardware::ppu::palette::RGB;
+use crate::osd::font::{FIRST_CHAR, FONT_8X8};
+
+pub mod font;
+
+/// The width and height of a single character in pixels.
+pub const GLYPH_SIZE: usize = 8;
+
+/// Draw the provided `text` into the `buffer` with the top-left corner of the first
+/// character being at (`x`, `y`).
+///
+/// Only the pixels of the glyphs themselves are drawn, the background is left untouched.
+/// Anything falling outside the `width`x`height` buffer is clipped.
+/// A `\n` will move the next character to the start of the next line, any other character
+/// outside of the printable ASCII range will be drawn as a `?`.
+pub fn draw_text(buffer: &mut [RGB], width: usize, height: usize, x: usize, y: usize, text: &str, colour: RGB) {
+    let mut current_x = x;
+    let mut current_y = y;
+
+    for character in text.chars() {
+        if character == '\n' {
+            current_x = x;
+            current_y += GLYPH_SIZE;
+            continue;
+        }
+
+        draw_char(buffer, width, height, current_x, current_y, character, colour);
+        current_x += GLYPH_SIZE;
+    }
+}
+
+/// Draw a single character into the `buffer` at (`x`, `y`).
+///
+/// Refer to [`draw_text`] for the clipping rules.
+pub fn draw_char(buffer: &mut [RGB], width: usize, height: usize, x: usize, y: usize, character: char, colour: RGB) {
+    let glyph = &FONT_8X8[glyph_index(character)];
+
+    for (row, pixels) in glyph.iter().enumerate() {
+        let pixel_y = y + row;
+        if pixel_y >= height {
+            break;
+        }
+
+        for column in 0..GLYPH_SIZE {
+            let pixel_x = x + column;
+            if pixel_x >= width {
+                break;
+            }
+
+            if (pixels >> column) & 0x1 == 1 {
+                if let Some(pixel) = buffer.get_mut(pixel_y * width + pixel_x) {
+                    *pixel = colour;
+                }
+            }
+        }
+    }
+}
+
+/// Returns the width in pixels the provided `text` would take up when drawn, taking
+/// newlines into account.
+pub fn text_width(text: &str) -> usize {
+    text.lines().map(|line| line.chars().count()).max().unwrap_or(0) * GLYPH_SIZE
+}
+
+#[inline]
+fn glyph_index(character: char) -> usize {
+    let code = if character.is_ascii() && !character.is_ascii_control() {
+        character as u8
+    } else {
+        b'?'
+    };
+
+    (code - FIRST_CHAR) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hardware::ppu::palette::RGB;
+    use crate::osd::{draw_text, text_width};
+
+    const WHITE: RGB = RGB(255, 255, 255);
+
+    #[test]
+    fn test_draw_text() {
+        let mut buffer = [RGB::default(); 16 * 8];
+        draw_text(&mut buffer, 16, 8, 0, 0, "_|", WHITE);
+
+        // The underscore is a single line at the very bottom of the glyph.
+        assert!(buffer[7 * 16..7 * 16 + 8].iter().all(|&p| p == WHITE));
+        assert!(buffer[0..8].iter().all(|&p| p == RGB::default()));
+        // The pipe is drawn in the second glyph, columns 3 and 4.
+        assert_eq!(buffer[8 + 3], WHITE);
+        assert_eq!(buffer[8 + 4], WHITE);
+        assert_eq!(buffer[8 + 2], RGB::default());
+    }
+
+    #[test]
+    fn test_clipping() {
+        let mut buffer = [RGB::default(); 4 * 4];
+        // Should not panic despite drawing out of bounds.
+        draw_text(&mut buffer, 4, 4, 2, 2, "##\n##", WHITE);
+        draw_text(&mut buffer, 4, 4, 100, 100, "Hello", WHITE);
+
+        assert_eq!(text_width("ab\nabc"), 24);
+    }
+}
@@ -0,0 +1,410 @@
+//! Applying IPS and BPS patches (e.g, translations) to a ROM before it's loaded.
+//!
+//! See [apply_patch] for automatically detecting the format.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::hardware::cartridge::mbc::{MbcKind, ROM_BANK_SIZE};
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+const BPS_MAGIC: &[u8] = b"BPS1";
+/// The three CRC32 checksums at the end of every BPS patch.
+const BPS_FOOTER_SIZE: usize = 12;
+/// The largest ROM any of the supported MBCs can address, anything larger can't be a valid target.
+pub const MAX_ROM_SIZE: usize = MbcKind::MBC5.max_rom_banks() * ROM_BANK_SIZE;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PatchError {
+    /// The patch is neither an IPS nor a BPS patch.
+    UnknownFormat,
+    /// The patch ended unexpectedly, or refers to data outside of the ROM/patch.
+    Malformed,
+    /// The BPS patch was made for a different ROM.
+    SourceMismatch,
+    /// The result of the BPS patch doesn't match the expected output.
+    TargetMismatch,
+    /// The BPS patch itself is corrupt.
+    PatchMismatch,
+    /// The BPS patch would create a ROM larger than `MAX_ROM_SIZE`.
+    TargetTooLarge { size: usize },
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchError::UnknownFormat => write!(f, "The patch is not an IPS or BPS patch"),
+            PatchError::Malformed => write!(f, "The patch is malformed"),
+            PatchError::SourceMismatch => write!(f, "The patch was made for a different ROM"),
+            PatchError::TargetMismatch => write!(f, "The patched ROM doesn't match the expected checksum"),
+            PatchError::PatchMismatch => write!(f, "The patch doesn't match its own checksum"),
+            PatchError::TargetTooLarge { size } => write!(
+                f,
+                "The patch would create a ROM of {} bytes, while at most {} bytes are supported",
+                size, MAX_ROM_SIZE
+            ),
+        }
+    }
+}
+
+impl Error for PatchError {}
+
+/// Apply the `patch` to the `rom`, detecting whether it's an IPS or BPS patch by its header.
+pub fn apply_patch(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.starts_with(IPS_MAGIC) {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(BPS_MAGIC) {
+        apply_bps(rom, patch)
+    } else {
+        Err(PatchError::UnknownFormat)
+    }
+}
+
+/// Apply an IPS patch, the ROM is extended should the patch write past its end.
+pub fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if !patch.starts_with(IPS_MAGIC) {
+        return Err(PatchError::UnknownFormat);
+    }
+    let mut result = rom.to_vec();
+    let mut reader = PatchReader::new(patch, IPS_MAGIC.len());
+
+    loop {
+        let offset_bytes = reader.bytes(3)?;
+        if offset_bytes == IPS_EOF {
+            break;
+        }
+        let offset = u24_be(offset_bytes);
+        let size = reader.u16_be()? as usize;
+
+        // A size of 0 signals a run length encoded record.
+        let (size, run_value) = if size == 0 {
+            (reader.u16_be()? as usize, Some(reader.byte()?))
+        } else {
+            (size, None)
+        };
+
+        if result.len() < offset + size {
+            result.resize(offset + size, 0);
+        }
+
+        match run_value {
+            Some(value) => result[offset..offset + size].iter_mut().for_each(|b| *b = value),
+            None => result[offset..offset + size].copy_from_slice(reader.bytes(size)?),
+        }
+    }
+
+    // An optional extension, truncating the ROM to the provided size.
+    if let Ok(truncate_bytes) = reader.bytes(3) {
+        result.truncate(u24_be(truncate_bytes));
+    }
+
+    Ok(result)
+}
+
+/// Apply a BPS patch, verifying the checksums of the source ROM, the patch, and the result.
+pub fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if !patch.starts_with(BPS_MAGIC) {
+        return Err(PatchError::UnknownFormat);
+    }
+    if patch.len() < BPS_MAGIC.len() + BPS_FOOTER_SIZE {
+        return Err(PatchError::Malformed);
+    }
+    let actions_end = patch.len() - BPS_FOOTER_SIZE;
+    let footer = &patch[actions_end..];
+    let checksum = |offset: usize| {
+        let bytes = &footer[offset..offset + 4];
+        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    };
+
+    if crc32(&patch[..patch.len() - 4]) != checksum(8) {
+        return Err(PatchError::PatchMismatch);
+    }
+    if crc32(rom) != checksum(0) {
+        return Err(PatchError::SourceMismatch);
+    }
+
+    let mut reader = PatchReader::new(&patch[..actions_end], BPS_MAGIC.len());
+    let source_size = reader.varint()?;
+    let target_size = reader.varint()?;
+    let metadata_size = reader.varint()?;
+    reader.bytes(metadata_size)?;
+
+    if source_size != rom.len() {
+        return Err(PatchError::SourceMismatch);
+    }
+    // Don't trust the patch with the size of the allocation.
+    if target_size > MAX_ROM_SIZE {
+        return Err(PatchError::TargetTooLarge { size: target_size });
+    }
+
+    let mut result = Vec::with_capacity(target_size);
+    let mut source_offset: isize = 0;
+    let mut target_offset: isize = 0;
+
+    while !reader.is_empty() {
+        let data = reader.varint()?;
+        let length = (data >> 2) + 1;
+
+        if length > target_size - result.len() {
+            return Err(PatchError::Malformed);
+        }
+
+        match data & 0x3 {
+            // Source read
+            0 => {
+                result.extend_from_slice(source_slice(rom, result.len() as isize, length)?);
+            }
+            // Target read
+            1 => result.extend_from_slice(reader.bytes(length)?),
+            // Source copy
+            2 => {
+                source_offset = checked_offset(source_offset, reader.signed_varint()?)?;
+                result.extend_from_slice(source_slice(rom, source_offset, length)?);
+                source_offset = checked_offset(source_offset, length as isize)?;
+            }
+            // Target copy, the source may overlap with what we're writing so copy byte by byte.
+            _ => {
+                target_offset = checked_offset(target_offset, reader.signed_varint()?)?;
+                for _ in 0..length {
+                    let value = *result.get(target_offset as usize).ok_or(PatchError::Malformed)?;
+                    result.push(value);
+                    target_offset = checked_offset(target_offset, 1)?;
+                }
+            }
+        }
+    }
+
+    if result.len() != target_size || crc32(&result) != checksum(4) {
+        return Err(PatchError::TargetMismatch);
+    }
+
+    Ok(result)
+}
+
+/// Move the relative `offset` of a copy action by `delta`, an error if that overflows.
+fn checked_offset(offset: isize, delta: isize) -> Result<isize, PatchError> {
+    offset.checked_add(delta).ok_or(PatchError::Malformed)
+}
+
+/// Returns `length` bytes of the `rom` from `offset`, or an error if that's out of bounds.
+fn source_slice(rom: &[u8], offset: isize, length: usize) -> Result<&[u8], PatchError> {
+    if offset < 0 || length > rom.len() {
+        return Err(PatchError::Malformed);
+    }
+
+    rom.get(offset as usize..offset as usize + length)
+        .ok_or(PatchError::Malformed)
+}
+
+fn u24_be(bytes: &[u8]) -> usize {
+    ((bytes[0] as usize) << 16) | ((bytes[1] as usize) << 8) | bytes[2] as usize
+}
+
+struct PatchReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> PatchReader<'a> {
+    fn new(data: &'a [u8], position: usize) -> Self {
+        PatchReader { data, position }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], PatchError> {
+        let end = self.position.checked_add(length).ok_or(PatchError::Malformed)?;
+        let result = self.data.get(self.position..end).ok_or(PatchError::Malformed)?;
+        self.position += length;
+        Ok(result)
+    }
+
+    fn byte(&mut self) -> Result<u8, PatchError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16_be(&mut self) -> Result<u16, PatchError> {
+        let bytes = self.bytes(2)?;
+        Ok(((bytes[0] as u16) << 8) | bytes[1] as u16)
+    }
+
+    /// BPS variable length integers, where every byte holds 7 bits and the last byte has bit 7 set.
+    fn varint(&mut self) -> Result<usize, PatchError> {
+        let mut result: usize = 0;
+        let mut shift: usize = 1;
+
+        loop {
+            let value = self.byte()?;
+            result = ((value & 0x7F) as usize)
+                .checked_mul(shift)
+                .and_then(|value| result.checked_add(value))
+                .ok_or(PatchError::Malformed)?;
+            if (value & 0x80) != 0 {
+                return Ok(result);
+            }
+            shift = shift.checked_shl(7).filter(|&s| s != 0).ok_or(PatchError::Malformed)?;
+            result = result.checked_add(shift).ok_or(PatchError::Malformed)?;
+        }
+    }
+
+    /// A `varint` where bit 0 is the sign, used for the relative copy offsets.
+    fn signed_varint(&mut self) -> Result<isize, PatchError> {
+        let value = self.varint()?;
+        let magnitude = (value >> 1) as isize;
+
+        Ok(if (value & 0x1) != 0 { -magnitude } else { magnitude })
+    }
+}
+
+/// The standard (zlib) CRC32, as used by BPS patches.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if (crc & 1) != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::patch::*;
+
+    fn encode_varint(mut value: usize, output: &mut Vec<u8>) {
+        loop {
+            let x = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                output.push(0x80 | x);
+                break;
+            }
+            output.push(x);
+            value -= 1;
+        }
+    }
+
+    /// Build a BPS patch from `rom` to `target` using every kind of action.
+    fn create_bps(rom: &[u8], target: &[u8]) -> Vec<u8> {
+        let mut patch = BPS_MAGIC.to_vec();
+        encode_varint(rom.len(), &mut patch);
+        encode_varint(target.len(), &mut patch);
+        encode_varint(0, &mut patch);
+        // Source read of the first 4 bytes.
+        encode_varint(3 << 2, &mut patch);
+        // Target read of 2 bytes.
+        encode_varint((1 << 2) | 1, &mut patch);
+        patch.extend_from_slice(&target[4..6]);
+        // Source copy of 2 bytes from offset 6.
+        encode_varint((1 << 2) | 2, &mut patch);
+        encode_varint(6 << 1, &mut patch);
+        // Target copy of 4 bytes from offset 4, overlapping with the output.
+        encode_varint((3 << 2) | 3, &mut patch);
+        encode_varint(4 << 1, &mut patch);
+
+        patch.extend_from_slice(&crc32(rom).to_le_bytes());
+        patch.extend_from_slice(&crc32(target).to_le_bytes());
+        let patch_crc = crc32(&patch);
+        patch.extend_from_slice(&patch_crc.to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_ips_patch() {
+        let rom = [0u8; 8];
+        let mut patch = IPS_MAGIC.to_vec();
+        // Write 2 bytes at offset 2.
+        patch.extend_from_slice(&[0x00, 0x00, 0x02, 0x00, 0x02, 0xAA, 0xBB]);
+        // Run of 3 times 0xCC at offset 7, extending the ROM.
+        patch.extend_from_slice(&[0x00, 0x00, 0x07, 0x00, 0x00, 0x00, 0x03, 0xCC]);
+        patch.extend_from_slice(IPS_EOF);
+
+        let result = apply_patch(&rom, &patch).unwrap();
+        assert_eq!(result, [0, 0, 0xAA, 0xBB, 0, 0, 0, 0xCC, 0xCC, 0xCC]);
+
+        // Truncation extension.
+        patch.extend_from_slice(&[0x00, 0x00, 0x04]);
+        assert_eq!(apply_patch(&rom, &patch).unwrap(), [0, 0, 0xAA, 0xBB]);
+
+        assert_eq!(apply_patch(&rom, &patch[..10]), Err(PatchError::Malformed));
+        assert_eq!(apply_patch(&rom, b"NOT A PATCH"), Err(PatchError::UnknownFormat));
+    }
+
+    #[test]
+    fn test_bps_patch() {
+        let rom = [0, 1, 2, 3, 4, 5, 6, 7];
+        let target = [0, 1, 2, 3, 9, 9, 6, 7, 9, 9, 6, 7];
+        let patch = create_bps(&rom, &target);
+
+        assert_eq!(apply_patch(&rom, &patch).unwrap(), target);
+        assert_eq!(
+            apply_patch(&[0, 1, 2, 3, 4, 5, 6, 8], &patch),
+            Err(PatchError::SourceMismatch)
+        );
+
+        let mut corrupt = patch.clone();
+        corrupt[8] ^= 0xFF;
+        assert_eq!(apply_patch(&rom, &corrupt), Err(PatchError::PatchMismatch));
+
+        // A patch with a valid checksum for itself, but which produces the wrong output.
+        let mut wrong_target = create_bps(&rom, &[0, 1, 2, 3, 9, 8, 6, 7, 9, 8, 6, 7]);
+        let length = wrong_target.len();
+        wrong_target[length - 8..length - 4].copy_from_slice(&crc32(&target).to_le_bytes());
+        let patch_crc = crc32(&wrong_target[..length - 4]);
+        wrong_target[length - 4..].copy_from_slice(&patch_crc.to_le_bytes());
+        assert_eq!(apply_patch(&rom, &wrong_target), Err(PatchError::TargetMismatch));
+    }
+
+    #[test]
+    fn test_bps_offset_out_of_range() {
+        let rom = [0, 1, 2, 3];
+        let mut patch = BPS_MAGIC.to_vec();
+        encode_varint(rom.len(), &mut patch);
+        encode_varint(rom.len(), &mut patch);
+        encode_varint(0, &mut patch);
+        // Source copy of a single byte, from as far back as the offset can encode.
+        encode_varint(2, &mut patch);
+        encode_varint(usize::MAX, &mut patch);
+        patch.extend_from_slice(&crc32(&rom).to_le_bytes());
+        patch.extend_from_slice(&crc32(&rom).to_le_bytes());
+        let patch_crc = crc32(&patch);
+        patch.extend_from_slice(&patch_crc.to_le_bytes());
+
+        assert_eq!(apply_patch(&rom, &patch), Err(PatchError::Malformed));
+        assert_eq!(checked_offset(isize::MAX, 1), Err(PatchError::Malformed));
+    }
+
+    #[test]
+    fn test_bps_target_too_large() {
+        let rom = [0, 1, 2, 3];
+        let mut patch = BPS_MAGIC.to_vec();
+        encode_varint(rom.len(), &mut patch);
+        encode_varint(usize::MAX >> 8, &mut patch);
+        encode_varint(0, &mut patch);
+        patch.extend_from_slice(&crc32(&rom).to_le_bytes());
+        patch.extend_from_slice(&crc32(&rom).to_le_bytes());
+        let patch_crc = crc32(&patch);
+        patch.extend_from_slice(&patch_crc.to_le_bytes());
+
+        assert_eq!(
+            apply_patch(&rom, &patch),
+            Err(PatchError::TargetTooLarge { size: usize::MAX >> 8 })
+        );
+        assert_eq!(checked_offset(isize::MIN, -1), Err(PatchError::Malformed));
+    }
+}
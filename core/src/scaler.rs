@@ -0,0 +1,84 @@
+//! CPU-side scaling of the framebuffer, for frontends which can't rely on their graphics
+//! library to preserve the aspect ratio (e.g, SDL's `set_logical_size`).
+
+use crate::hardware::ppu::palette::RGB;
+use crate::hardware::ppu::{RESOLUTION_HEIGHT, RESOLUTION_WIDTH};
+
+/// Scale the provided `framebuffer` (of `RESOLUTION_WIDTH`x`RESOLUTION_HEIGHT`) to a
+/// `target_width`x`target_height` buffer while preserving the 10:9 aspect ratio.
+///
+/// The content is scaled by the largest integer factor which fits and centered, with the
+/// remaining area filled with `bar_colour`. Should the target be smaller than the native
+/// resolution the content is instead shrunk (nearest neighbour) to fit.
+pub fn scale_framebuffer_aspect(
+    framebuffer: &[RGB],
+    target_width: usize,
+    target_height: usize,
+    bar_colour: RGB,
+) -> Vec<RGB> {
+    let mut result = vec![bar_colour; target_width * target_height];
+    let scale = (target_width / RESOLUTION_WIDTH).min(target_height / RESOLUTION_HEIGHT);
+
+    let (content_width, content_height) = if scale > 0 {
+        (RESOLUTION_WIDTH * scale, RESOLUTION_HEIGHT * scale)
+    } else if target_width * RESOLUTION_HEIGHT < target_height * RESOLUTION_WIDTH {
+        (target_width, target_width * RESOLUTION_HEIGHT / RESOLUTION_WIDTH)
+    } else {
+        (target_height * RESOLUTION_WIDTH / RESOLUTION_HEIGHT, target_height)
+    };
+
+    let offset_x = (target_width - content_width) / 2;
+    let offset_y = (target_height - content_height) / 2;
+
+    for y in 0..content_height {
+        let source_row = (y * RESOLUTION_HEIGHT / content_height) * RESOLUTION_WIDTH;
+        let target_row = (y + offset_y) * target_width + offset_x;
+
+        for x in 0..content_width {
+            result[target_row + x] = framebuffer[source_row + x * RESOLUTION_WIDTH / content_width];
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hardware::ppu::palette::RGB;
+    use crate::hardware::ppu::{FRAMEBUFFER_SIZE, RESOLUTION_WIDTH};
+    use crate::scaler::scale_framebuffer_aspect;
+
+    const BAR: RGB = RGB(1, 2, 3);
+    const WHITE: RGB = RGB(255, 255, 255);
+
+    #[test]
+    fn test_integer_scale_letterbox() {
+        let mut framebuffer = [WHITE; FRAMEBUFFER_SIZE];
+        framebuffer[0] = RGB(0, 0, 0);
+        framebuffer[RESOLUTION_WIDTH - 1] = RGB(9, 9, 9);
+        // Fits a 2x scale (320x288) with 40 pixels of bars on the left/right, 6 on top/bottom.
+        let result = scale_framebuffer_aspect(&framebuffer, 400, 300, BAR);
+
+        assert_eq!(result.len(), 400 * 300);
+        assert_eq!(result[6 * 400 + 39], BAR);
+        assert_eq!(result[5 * 400 + 40], BAR);
+        assert_eq!(result[6 * 400 + 40], RGB(0, 0, 0));
+        assert_eq!(result[7 * 400 + 41], RGB(0, 0, 0));
+        assert_eq!(result[6 * 400 + 42], WHITE);
+        assert_eq!(result[6 * 400 + 359], RGB(9, 9, 9));
+        assert_eq!(result[6 * 400 + 360], BAR);
+        assert_eq!(result[294 * 400 + 200], BAR);
+    }
+
+    #[test]
+    fn test_shrink_to_fit() {
+        let framebuffer = [WHITE; FRAMEBUFFER_SIZE];
+        let result = scale_framebuffer_aspect(&framebuffer, 80, 100, BAR);
+
+        // 80x72 content, with 14 pixel bars on top and bottom.
+        assert_eq!(result[13 * 80], BAR);
+        assert_eq!(result[14 * 80], WHITE);
+        assert_eq!(result[85 * 80 + 79], WHITE);
+        assert_eq!(result[86 * 80], BAR);
+    }
+}
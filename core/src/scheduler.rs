@@ -1,23 +1,114 @@
 use binary_heap_plus::{BinaryHeap, MinComparator};
 use bitflags::_core::cmp::Ordering;
+use std::fmt;
 
-#[derive(Debug, Copy, Clone, PartialOrd, PartialEq, Eq)]
-#[repr(u8)]
-pub enum EventType {
-    None = 255,
-    Vblank = 0,
-    OamSearch = 1,
-    LcdTransfer = 2,
-    Hblank = 3,
-    VblankWait = 4,
-    TimerOverflow = 7,
-    TimerPostOverflow = 8,
-    TimerTick = 9,
-    DMARequested = 10,
-    DMATransferComplete = 11,
-    GDMARequested = 12,
-    GDMATransferComplete = 13,
-    Y153TickToZero = 14,
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
+
+/// A serialized event consists of its `u64` timestamp and `u8` event type.
+const SERIALIZED_EVENT_SIZE: usize = 9;
+
+/// Generates the enum along with the list of all its variants and the conversion from `u8`, so
+/// that both always cover every variant.
+macro_rules! event_types {
+    ($(#[$meta:meta])* $vis:vis enum $name:ident {
+        $($vname:ident = $val:expr,)*
+    }) => {
+        $(#[$meta])*
+        $vis enum $name {
+            $($vname = $val,)*
+        }
+
+        impl $name {
+            /// Every variant, in declaration order.
+            pub const ALL: [$name; [$(stringify!($vname)),*].len()] = [$($name::$vname),*];
+
+            /// The event type with the discriminant `value`, if any.
+            pub fn from_u8(value: u8) -> Option<$name> {
+                match value {
+                    $(x if x == $name::$vname as u8 => Some($name::$vname),)*
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+event_types! {
+    #[derive(Debug, Copy, Clone, PartialOrd, PartialEq, Eq)]
+    #[repr(u8)]
+    pub enum EventType {
+        None = 255,
+        Vblank = 0,
+        OamSearch = 1,
+        LcdTransfer = 2,
+        Hblank = 3,
+        VblankWait = 4,
+        TimerOverflow = 7,
+        TimerPostOverflow = 8,
+        TimerTick = 9,
+        DMARequested = 10,
+        DMATransferComplete = 11,
+        GDMARequested = 12,
+        GDMATransferComplete = 13,
+        Y153TickToZero = 14,
+        SerialTransferComplete = 15,
+    }
+}
+
+impl EventType {
+    /// Returns all event types together with their [description](EventType::description),
+    /// e.g. for a legend in a scheduler debug view.
+    pub fn all_with_descriptions() -> Vec<(EventType, &'static str)> {
+        EventType::ALL
+            .iter()
+            .map(|&event| (event, event.description()))
+            .collect()
+    }
+
+    /// A short explanation of what happens when this event fires.
+    pub fn description(self) -> &'static str {
+        match self {
+            EventType::None => "Start-up event, schedules the first PPU and timer events",
+            EventType::Vblank => "The PPU enters V-blank (mode 1) after the last visible scanline",
+            EventType::OamSearch => "The PPU starts a new scanline by searching OAM for sprites (mode 2)",
+            EventType::LcdTransfer => "The PPU draws the current scanline (mode 3)",
+            EventType::Hblank => "The PPU enters H-blank (mode 0), HDMA copies happen here",
+            EventType::VblankWait => "The PPU moves to the next scanline during V-blank",
+            EventType::TimerOverflow => "TIMA overflowed, it'll be reloaded with TMA and request an interrupt",
+            EventType::TimerPostOverflow => "The cycle after a TIMA reload, during which writes to it are ignored",
+            EventType::TimerTick => "TIMA is incremented at the frequency selected in TAC",
+            EventType::DMARequested => "An OAM DMA transfer starts, after the write to the DMA register",
+            EventType::DMATransferComplete => "The OAM DMA transfer finished, OAM is accessible again",
+            EventType::GDMARequested => "A general purpose (CGB) DMA transfer starts, stalling the CPU",
+            EventType::GDMATransferComplete => "The general purpose DMA transfer finished, the CPU resumes",
+            EventType::Y153TickToZero => "LY reads as 0 early in scanline 153",
+            EventType::SerialTransferComplete => "A serial transfer finished, requesting the serial interrupt",
+        }
+    }
+}
+
+impl fmt::Display for EventType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            EventType::None => "Start-up",
+            EventType::Vblank => "V-blank",
+            EventType::OamSearch => "OAM search",
+            EventType::LcdTransfer => "LCD transfer",
+            EventType::Hblank => "H-blank",
+            EventType::VblankWait => "V-blank scanline",
+            EventType::TimerOverflow => "Timer overflow",
+            EventType::TimerPostOverflow => "Timer post-overflow",
+            EventType::TimerTick => "Timer tick",
+            EventType::DMARequested => "OAM DMA requested",
+            EventType::DMATransferComplete => "OAM DMA complete",
+            EventType::GDMARequested => "GDMA requested",
+            EventType::GDMATransferComplete => "GDMA complete",
+            EventType::Y153TickToZero => "LY 153 to 0",
+            EventType::SerialTransferComplete => "Serial transfer complete",
+        };
+
+        write!(f, "{}", name)
+    }
 }
 
 #[derive(Debug, Copy, Clone, Eq)]
@@ -74,7 +165,7 @@ impl Scheduler {
     /// which is at or below the `current_time` for the `Scheduler`
     #[inline(always)]
     pub fn pop_closest(&mut self) -> Option<Event> {
-        if self.event_queue.peek().map_or(false, |ev| ev.timestamp <= self.current_time) {
+        if self.event_queue.peek().is_some_and(|ev| ev.timestamp <= self.current_time) {
             self.event_queue.pop()
         } else {
             None
@@ -125,4 +216,155 @@ impl Scheduler {
     pub fn next_event_timestamp(&self) -> u64 {
         self.event_queue.peek().map_or(u64::MAX, |ev| ev.timestamp)
     }
+
+    /// Returns the soonest pending event of any of the `event_types`.
+    pub fn next_event_of(&self, event_types: &[EventType]) -> Option<Event> {
+        self.event_queue
+            .iter()
+            .filter(|event| event_types.contains(&event.event_type))
+            .min_by_key(|event| event.timestamp)
+            .copied()
+    }
+
+    /// Returns all events which have yet to fire, soonest first.
+    pub fn pending_events(&self) -> Vec<Event> {
+        let mut events = self.event_queue.clone().into_vec();
+        events.sort();
+        events
+    }
+
+    /// Serialize the `current_time` and every pending event, for save states.
+    ///
+    /// Events keep their absolute timestamps, which together with the `current_time` preserves
+    /// the time remaining until each fires (including events which are already due).
+    /// The heap's internal order is kept as is, so that events with the same timestamp are
+    /// still popped in the same order after deserializing.
+    pub fn serialize(&self) -> Vec<u8> {
+        let events = self.event_queue.clone().into_vec();
+        let mut result = Vec::with_capacity(8 + events.len() * SERIALIZED_EVENT_SIZE);
+        result.extend_from_slice(&self.current_time.to_le_bytes());
+
+        for event in events {
+            result.extend_from_slice(&event.timestamp.to_le_bytes());
+            result.push(event.event_type as u8);
+        }
+
+        result
+    }
+
+    /// Restore a `Scheduler` from the output of [serialize](#method.serialize).
+    pub fn deserialize(data: &[u8]) -> Result<Self, StateError> {
+        if data.len() < 8 || !(data.len() - 8).is_multiple_of(SERIALIZED_EVENT_SIZE) {
+            return Err(StateError::Corrupt);
+        }
+        let read_u64 = |bytes: &[u8]| {
+            let mut value = [0; 8];
+            value.copy_from_slice(&bytes[..8]);
+            u64::from_le_bytes(value)
+        };
+        let events = data[8..]
+            .chunks_exact(SERIALIZED_EVENT_SIZE)
+            .map(|event| {
+                let event_type = EventType::from_u8(event[8]).ok_or(StateError::Corrupt)?;
+
+                Ok(Event {
+                    timestamp: read_u64(event),
+                    event_type,
+                })
+            })
+            .collect::<Result<Vec<_>, StateError>>()?;
+
+        Ok(Scheduler {
+            event_queue: BinaryHeap::from_vec(events),
+            current_time: read_u64(data),
+        })
+    }
+}
+
+impl SaveState for Scheduler {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_vec(&self.serialize());
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        *self = Scheduler::deserialize(state.read_vec()?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gb_emu::GameBoyEmulator;
+    use crate::hardware::ppu::Mode;
+    use crate::scheduler::{EventType, Scheduler};
+    use crate::state::StateError;
+    use crate::EmulatorOptionsBuilder;
+
+    #[test]
+    fn test_event_type_descriptions() {
+        let descriptions = EventType::all_with_descriptions();
+
+        assert_eq!(descriptions.len(), EventType::ALL.len());
+        for (i, (event, description)) in descriptions.iter().enumerate() {
+            assert_eq!(EventType::from_u8(*event as u8), Some(*event));
+            assert!(!description.is_empty());
+            assert!(!event.to_string().is_empty());
+            // Every variant should only be listed once.
+            assert!(descriptions[i + 1..].iter().all(|(other, _)| other != event));
+        }
+        assert_eq!(EventType::OamSearch.to_string(), "OAM search");
+    }
+
+    #[test]
+    fn test_pending_events() {
+        let mut scheduler = Scheduler::new();
+        scheduler.push_event(EventType::Hblank, 20);
+        scheduler.push_event(EventType::TimerTick, 10);
+
+        let events: Vec<_> = scheduler.pending_events().iter().map(|e| e.event_type).collect();
+
+        assert_eq!(events, vec![EventType::None, EventType::TimerTick, EventType::Hblank]);
+    }
+
+    #[test]
+    fn test_serialize_mid_scanline() {
+        let mut emulator = GameBoyEmulator::new(&[0; 0x8000], EmulatorOptionsBuilder::new().build());
+        emulator.run_to_vblank();
+        while emulator.cpu.mmu.ppu.get_current_mode() != Mode::LcdTransfer {
+            emulator.emulate_cycle();
+        }
+        let scheduler = &mut emulator.cpu.mmu.scheduler;
+        let mut restored = Scheduler::deserialize(&scheduler.serialize()).unwrap();
+
+        assert_eq!(restored.current_time, scheduler.current_time);
+        let expected = fire_all_events(scheduler);
+        // The PPU should still be in the middle of the scanline, waiting for H-blank.
+        assert!(expected.iter().any(|&(event, remaining)| event == EventType::Hblank && remaining > 0));
+        assert_eq!(fire_all_events(&mut restored), expected);
+    }
+
+    #[test]
+    fn test_deserialize_corrupt() {
+        let mut data = Scheduler::new().serialize();
+        assert_eq!(data.len(), 8 + 9);
+
+        assert_eq!(Scheduler::deserialize(&data[..12]).unwrap_err(), StateError::Corrupt);
+        data[16] = 5;
+        assert_eq!(Scheduler::deserialize(&data).unwrap_err(), StateError::Corrupt);
+    }
+
+    /// Returns the type of every pending event together with the time until it fired.
+    fn fire_all_events(scheduler: &mut Scheduler) -> Vec<(EventType, u64)> {
+        let start_time = scheduler.current_time;
+        let mut result = Vec::new();
+
+        while scheduler.next_event_timestamp() != u64::MAX {
+            scheduler.skip_to_next_event();
+            while let Some(event) = scheduler.pop_closest() {
+                result.push((event.event_type, event.timestamp - start_time));
+            }
+        }
+
+        result
+    }
 }
@@ -0,0 +1,806 @@
+//! Save state encoding.
+//!
+//! [GameBoyEmulator::save_state](../gb_emu/struct.GameBoyEmulator.html#method.save_state) creates
+//! a snapshot of the entire machine, which every component writes its fields to in a fixed order
+//! through a [StateWriter]. The snapshot starts with a header containing the format version,
+//! the emulated model and the global checksum of the ROM, followed by the payload length and an
+//! Adler-32 checksum of the payload. This allows most snapshots which wouldn't restore correctly
+//! to be rejected before a single component is touched, should a component still reject its
+//! part the emulator is rolled back to how it was before the load.
+//!
+//! The cartridge RAM is stored at the end of the payload, followed by its length, so that
+//! [GameBoyEmulator::save_state_compact](../gb_emu/struct.GameBoyEmulator.html#method.save_state_compact)
+//! can compress it separately from the rest. Such a compact state is meant for keeping many
+//! states around (e.g, rewind), and is accepted by `load_state` just like a full state.
+//!
+//! The compact format run length encodes the cartridge RAM, as most of it is usually untouched
+//! (and thus one long run of the same byte), and LZ compresses the rest of the state.
+
+use std::borrow::Cow;
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+
+use crate::gb_emu::GameBoyModel;
+
+/// The version of the save state format, should be incremented whenever any component changes
+/// what it writes to a [StateWriter].
+pub const SAVE_STATE_VERSION: u16 = 7;
+const SAVE_STATE_MAGIC: [u8; 4] = *b"RBSS";
+const COMPACT_STATE_MAGIC: [u8; 4] = *b"RBSC";
+/// Magic, version, model, ROM checksum, payload length and payload checksum.
+const HEADER_SIZE: usize = 4 + 2 + 1 + 2 + 4 + 4;
+
+/// The smallest back-reference worth encoding, anything shorter is stored as literals.
+const MIN_MATCH: usize = 4;
+const MAX_MATCH: usize = 0x7F + MIN_MATCH;
+const MAX_LITERALS: usize = 0x80;
+/// The furthest back a match can reference, limited by the 16 bit offset.
+const WINDOW_SIZE: usize = u16::MAX as usize;
+const HASH_BITS: u32 = 15;
+/// Neither of the encodings turns a single byte into more than this many bytes, anything claiming
+/// otherwise is corrupt (and shouldn't make us allocate gigabytes).
+const MAX_EXPANSION: usize = 0x80;
+
+/// Trades CPU time for size when creating a [CompactState].
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum CompressionLevel {
+    /// Only considers the most recent match, suitable for creating states every frame.
+    Fast,
+    #[default]
+    Default,
+    /// Searches the most candidates for the longest match.
+    Best,
+}
+
+impl CompressionLevel {
+    /// The amount of earlier positions considered when looking for a match.
+    fn search_depth(self) -> usize {
+        match self {
+            CompressionLevel::Fast => 1,
+            CompressionLevel::Default => 16,
+            CompressionLevel::Best => 256,
+        }
+    }
+}
+
+/// A save state as created by a [StateWriter], split into the cartridge RAM and everything else.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct FullState {
+    /// The header and serialized state of the machine, excluding the cartridge RAM.
+    pub machine: Vec<u8>,
+    pub cartridge_ram: Vec<u8>,
+}
+
+impl FullState {
+    /// Split the `data`, returns `None` if the cartridge RAM length at the end is out of range.
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        let (rest, ram_length) = split_ram_length(data)?;
+        let (machine, cartridge_ram) = rest.split_at(rest.len().checked_sub(ram_length)?);
+
+        Some(FullState {
+            machine: machine.to_vec(),
+            cartridge_ram: cartridge_ram.to_vec(),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(self.machine.len() + self.cartridge_ram.len() + 4);
+        result.extend_from_slice(&self.machine);
+        result.extend_from_slice(&self.cartridge_ram);
+        result.extend_from_slice(&(self.cartridge_ram.len() as u32).to_le_bytes());
+        result
+    }
+}
+
+/// A compressed [FullState], see the module documentation for the format.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct CompactState {
+    machine_length: usize,
+    machine: Vec<u8>,
+    cartridge_ram_length: usize,
+    cartridge_ram: Vec<u8>,
+}
+
+impl CompactState {
+    pub fn new(state: &FullState, level: CompressionLevel) -> Self {
+        CompactState {
+            machine_length: state.machine.len(),
+            machine: lz_compress(&state.machine, level),
+            cartridge_ram_length: state.cartridge_ram.len(),
+            cartridge_ram: rle_encode(&state.cartridge_ram),
+        }
+    }
+
+    /// Decompress the state, returns `None` if the compressed data is corrupt.
+    pub fn to_full_state(&self) -> Option<FullState> {
+        let machine = lz_decompress(&self.machine, self.machine_length)?;
+        let cartridge_ram = rle_decode(&self.cartridge_ram, self.cartridge_ram_length)?;
+
+        Some(FullState { machine, cartridge_ram })
+    }
+
+    /// The magic, followed by the decompressed length, compressed length and compressed data of
+    /// first the machine and then the cartridge RAM.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut result = COMPACT_STATE_MAGIC.to_vec();
+
+        let parts = [
+            (self.machine_length, &self.machine),
+            (self.cartridge_ram_length, &self.cartridge_ram),
+        ];
+
+        for &(length, data) in parts.iter() {
+            result.extend_from_slice(&(length as u32).to_le_bytes());
+            result.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            result.extend_from_slice(data);
+        }
+
+        result
+    }
+
+    /// Parse the data created by [to_bytes](#method.to_bytes), which still has to be decompressed.
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        let mut data = data.strip_prefix(&COMPACT_STATE_MAGIC[..])?;
+        let (machine_length, machine) = read_compressed_part(&mut data)?;
+        let (cartridge_ram_length, cartridge_ram) = read_compressed_part(&mut data)?;
+
+        if !data.is_empty() {
+            return None;
+        }
+
+        Some(CompactState {
+            machine_length,
+            machine,
+            cartridge_ram_length,
+            cartridge_ram,
+        })
+    }
+}
+
+/// Returns the uncompressed form of the state `data`, which may be either a full or compact state.
+pub(crate) fn decompress_state(data: &[u8]) -> Result<Cow<'_, [u8]>, StateError> {
+    if !data.starts_with(&COMPACT_STATE_MAGIC) {
+        return Ok(Cow::Borrowed(data));
+    }
+
+    CompactState::from_bytes(data)
+        .and_then(|state| state.to_full_state())
+        .map(|state| Cow::Owned(state.to_bytes()))
+        .ok_or(StateError::Corrupt)
+}
+
+fn read_compressed_part(data: &mut &[u8]) -> Option<(usize, Vec<u8>)> {
+    let length = u32::from_le_bytes(data.get(0..4)?.try_into().ok()?) as usize;
+    let compressed_length = u32::from_le_bytes(data.get(4..8)?.try_into().ok()?) as usize;
+    let compressed = data.get(8..8 + compressed_length)?.to_vec();
+
+    if length > compressed_length * MAX_EXPANSION {
+        return None;
+    }
+    *data = &data[8 + compressed_length..];
+
+    Some((length, compressed))
+}
+
+/// Split off the cartridge RAM length stored at the end of a state.
+fn split_ram_length(data: &[u8]) -> Option<(&[u8], usize)> {
+    let (rest, length) = data.split_at(data.len().checked_sub(4)?);
+    Some((rest, u32::from_le_bytes(length.try_into().ok()?) as usize))
+}
+
+/// Returned when a save state can't be loaded.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StateError {
+    /// The data doesn't start with a save state header.
+    InvalidHeader,
+    /// The state was created by a different version of the emulator.
+    UnsupportedVersion { version: u16 },
+    /// The state was created for a different ROM.
+    RomMismatch,
+    /// The state was created while emulating a different model.
+    ModelMismatch,
+    /// The state was truncated or otherwise damaged.
+    Corrupt,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::InvalidHeader => write!(f, "The data is not a save state"),
+            StateError::UnsupportedVersion { version } => write!(
+                f,
+                "The save state has version {}, while only version {} is supported",
+                version, SAVE_STATE_VERSION
+            ),
+            StateError::RomMismatch => write!(f, "The save state was created for a different ROM"),
+            StateError::ModelMismatch => write!(f, "The save state was created for a different Game Boy model"),
+            StateError::Corrupt => write!(f, "The save state is corrupt"),
+        }
+    }
+}
+
+impl Error for StateError {}
+
+/// Implemented by every component which is part of a save state.
+///
+/// Only the emulation state is saved, anything configured through the `EmulatorOptions` (e.g,
+/// the audio output settings) is kept as is when loading a state.
+pub(crate) trait SaveState {
+    fn save_state(&self, state: &mut StateWriter);
+
+    /// Restore the fields in the same order as they were written by `save_state()`.
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError>;
+}
+
+/// Writes the little endian state of the components after the save state header.
+pub(crate) struct StateWriter {
+    data: Vec<u8>,
+    cartridge_ram: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new(model: GameBoyModel, rom_checksum: u16) -> Self {
+        let mut data = Vec::with_capacity(0x40000);
+        data.extend_from_slice(&SAVE_STATE_MAGIC);
+        data.extend_from_slice(&SAVE_STATE_VERSION.to_le_bytes());
+        data.push(model as u8);
+        data.extend_from_slice(&rom_checksum.to_le_bytes());
+        // Payload length and checksum, filled in by `finish()`.
+        data.extend_from_slice(&[0; 8]);
+
+        StateWriter {
+            data,
+            cartridge_ram: Vec::new(),
+        }
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_usize(&mut self, value: usize) {
+        self.write_u64(value as u64);
+    }
+
+    pub fn write_f32(&mut self, value: f32) {
+        self.write_u32(value.to_bits());
+    }
+
+    /// Write a fixed size `data`, the reader is expected to know its length.
+    pub fn write_bytes(&mut self, data: &[u8]) {
+        self.data.extend_from_slice(data);
+    }
+
+    /// Write a variably sized `data`, prefixed with its length.
+    pub fn write_vec(&mut self, data: &[u8]) {
+        self.write_u32(data.len() as u32);
+        self.write_bytes(data);
+    }
+
+    /// Store the external RAM of the cartridge, which is written after all other components.
+    pub fn write_cartridge_ram(&mut self, ram: &[u8]) {
+        self.cartridge_ram.extend_from_slice(ram);
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        let ram_length = self.cartridge_ram.len() as u32;
+        self.data.append(&mut self.cartridge_ram);
+        self.write_u32(ram_length);
+
+        let payload = &self.data[HEADER_SIZE..];
+        let length = (payload.len() as u32).to_le_bytes();
+        let checksum = adler32(payload).to_le_bytes();
+
+        self.data[HEADER_SIZE - 8..HEADER_SIZE - 4].copy_from_slice(&length);
+        self.data[HEADER_SIZE - 4..HEADER_SIZE].copy_from_slice(&checksum);
+        self.data
+    }
+}
+
+/// Reads the state written by a [StateWriter], any read past the end results in [StateError::Corrupt].
+pub(crate) struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    cartridge_ram: Option<&'a [u8]>,
+}
+
+impl<'a> StateReader<'a> {
+    /// Validate the header of the `data`, only once this succeeds should any component be loaded.
+    pub fn new(data: &'a [u8], model: GameBoyModel, rom_checksum: u16) -> Result<Self, StateError> {
+        if data.len() < HEADER_SIZE || data[0..4] != SAVE_STATE_MAGIC {
+            return Err(StateError::InvalidHeader);
+        }
+        let mut result = StateReader {
+            data,
+            position: 4,
+            cartridge_ram: None,
+        };
+
+        let version = result.read_u16()?;
+        if version != SAVE_STATE_VERSION {
+            return Err(StateError::UnsupportedVersion { version });
+        }
+        if result.read_u8()? != model as u8 {
+            return Err(StateError::ModelMismatch);
+        }
+        if result.read_u16()? != rom_checksum {
+            return Err(StateError::RomMismatch);
+        }
+        let length = result.read_u32()? as usize;
+        let checksum = result.read_u32()?;
+        let payload = &data[HEADER_SIZE..];
+
+        if payload.len() != length || adler32(payload) != checksum {
+            return Err(StateError::Corrupt);
+        }
+
+        let (rest, ram_length) = split_ram_length(payload).ok_or(StateError::Corrupt)?;
+        let ram_start = rest.len().checked_sub(ram_length).ok_or(StateError::Corrupt)?;
+        result.data = &data[..HEADER_SIZE + ram_start];
+        result.cartridge_ram = Some(&rest[ram_start..]);
+
+        Ok(result)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, StateError> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, StateError> {
+        let mut bytes = [0; 2];
+        self.read_into(&mut bytes)?;
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, StateError> {
+        let mut bytes = [0; 4];
+        self.read_into(&mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, StateError> {
+        let mut bytes = [0; 8];
+        self.read_into(&mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub fn read_usize(&mut self) -> Result<usize, StateError> {
+        Ok(self.read_u64()? as usize)
+    }
+
+    pub fn read_f32(&mut self) -> Result<f32, StateError> {
+        Ok(f32::from_bits(self.read_u32()?))
+    }
+
+    pub fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], StateError> {
+        let result = self.data.get(self.position..self.position + length).ok_or(StateError::Corrupt)?;
+        self.position += length;
+        Ok(result)
+    }
+
+    /// Fill the entire `target` with the next bytes.
+    pub fn read_into(&mut self, target: &mut [u8]) -> Result<(), StateError> {
+        target.copy_from_slice(self.read_bytes(target.len())?);
+        Ok(())
+    }
+
+    /// Read data written by [StateWriter::write_vec].
+    pub fn read_vec(&mut self) -> Result<&'a [u8], StateError> {
+        let length = self.read_u32()? as usize;
+        self.read_bytes(length)
+    }
+
+    /// Read the data written by [StateWriter::write_cartridge_ram], can only be called once.
+    pub fn read_cartridge_ram(&mut self) -> Result<&'a [u8], StateError> {
+        self.cartridge_ram.take().ok_or(StateError::Corrupt)
+    }
+
+    /// Returns an error if not all data was read, which would indicate a mismatch between
+    /// what the components wrote and read.
+    pub fn finish(self) -> Result<(), StateError> {
+        let ram_read = self.cartridge_ram.is_none_or(|ram| ram.is_empty());
+
+        if self.position == self.data.len() && ram_read {
+            Ok(())
+        } else {
+            Err(StateError::Corrupt)
+        }
+    }
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD_ADLER: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+
+    // Process in chunks small enough that the sums can't overflow before the modulo.
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD_ADLER;
+        b %= MOD_ADLER;
+    }
+
+    (b << 16) | a
+}
+
+/// Run length encode `data`, using the same scheme as the Game Boy Printer.
+///
+/// A control byte with bit 7 set is followed by a single byte which is repeated
+/// `(control & 0x7F) + 2` times, otherwise it's followed by `control + 1` literal bytes.
+fn rle_encode(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::new();
+    let mut literal_start = 0;
+    let mut i = 0;
+
+    while i < data.len() {
+        let run_length = data[i..].iter().take(0x7F + 2).take_while(|&&b| b == data[i]).count();
+
+        if run_length >= 2 {
+            push_literals(&mut result, &data[literal_start..i]);
+            result.push(0x80 | (run_length - 2) as u8);
+            result.push(data[i]);
+            i += run_length;
+            literal_start = i;
+        } else {
+            i += 1;
+        }
+    }
+
+    push_literals(&mut result, &data[literal_start..]);
+    result
+}
+
+/// Decompress the `data` created by [rle_encode] into `output`.
+///
+/// Returns `None` if the data ends in the middle of a run, in which case everything before it
+/// (and as much of a literal run as there is) is still decompressed.
+pub(crate) fn rle_decode_into(data: &[u8], output: &mut Vec<u8>) -> Option<()> {
+    let mut i = 0;
+
+    while i < data.len() {
+        let control = data[i] as usize;
+        i += 1;
+
+        if (control & 0x80) != 0 {
+            let value = *data.get(i)?;
+            output.extend(std::iter::repeat_n(value, (control & 0x7F) + 2));
+            i += 1;
+        } else {
+            let end = i + control + 1;
+            output.extend_from_slice(&data[i..end.min(data.len())]);
+            if end > data.len() {
+                return None;
+            }
+            i = end;
+        }
+    }
+
+    Some(())
+}
+
+fn rle_decode(data: &[u8], length: usize) -> Option<Vec<u8>> {
+    let mut result = Vec::with_capacity(length);
+    rle_decode_into(data, &mut result)?;
+
+    if result.len() == length {
+        Some(result)
+    } else {
+        None
+    }
+}
+
+/// Compress `data` with a simple LZ77 scheme.
+///
+/// A control byte with bit 7 set is a back-reference of `(control & 0x7F) + 4` bytes, followed
+/// by the (little endian) `u16` distance to copy from. Otherwise it's followed by `control + 1`
+/// literal bytes.
+fn lz_compress(data: &[u8], level: CompressionLevel) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len() / 2);
+    // The most recent position for every hash, and the previous position with the same hash.
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut previous = vec![usize::MAX; data.len()];
+    let mut literal_start = 0;
+    let mut i = 0;
+
+    while i < data.len() {
+        let (match_length, distance) = find_match(data, i, &head, &previous, level.search_depth());
+
+        if match_length >= MIN_MATCH {
+            push_literals(&mut result, &data[literal_start..i]);
+            result.push(0x80 | (match_length - MIN_MATCH) as u8);
+            result.extend_from_slice(&(distance as u16).to_le_bytes());
+
+            for position in i..i + match_length {
+                insert_position(data, position, &mut head, &mut previous);
+            }
+            i += match_length;
+            literal_start = i;
+        } else {
+            insert_position(data, i, &mut head, &mut previous);
+            i += 1;
+        }
+    }
+
+    push_literals(&mut result, &data[literal_start..]);
+    result
+}
+
+fn lz_decompress(data: &[u8], length: usize) -> Option<Vec<u8>> {
+    let mut result = Vec::with_capacity(length);
+    let mut i = 0;
+
+    while i < data.len() {
+        let control = data[i] as usize;
+        i += 1;
+
+        if (control & 0x80) != 0 {
+            let distance = u16::from_le_bytes([*data.get(i)?, *data.get(i + 1)?]) as usize;
+            i += 2;
+            if distance == 0 || distance > result.len() {
+                return None;
+            }
+            // The source may overlap with what we're writing, so copy byte by byte.
+            let start = result.len() - distance;
+            for offset in 0..(control & 0x7F) + MIN_MATCH {
+                result.push(result[start + offset]);
+            }
+        } else {
+            result.extend_from_slice(data.get(i..i + control + 1)?);
+            i += control + 1;
+        }
+    }
+
+    if result.len() == length {
+        Some(result)
+    } else {
+        None
+    }
+}
+
+/// Returns the length and distance of the longest match for `data[position..]`.
+fn find_match(data: &[u8], position: usize, head: &[usize], previous: &[usize], depth: usize) -> (usize, usize) {
+    if position + MIN_MATCH > data.len() {
+        return (0, 0);
+    }
+    let max_length = (data.len() - position).min(MAX_MATCH);
+    let mut best = (0, 0);
+    let mut candidate = head[hash(&data[position..])];
+
+    for _ in 0..depth {
+        if candidate == usize::MAX || position - candidate > WINDOW_SIZE {
+            break;
+        }
+        let length = data[candidate..]
+            .iter()
+            .zip(&data[position..position + max_length])
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        if length > best.0 {
+            best = (length, position - candidate);
+            if length == max_length {
+                break;
+            }
+        }
+        candidate = previous[candidate];
+    }
+
+    best
+}
+
+fn insert_position(data: &[u8], position: usize, head: &mut [usize], previous: &mut [usize]) {
+    if position + MIN_MATCH <= data.len() {
+        let hash = hash(&data[position..]);
+        previous[position] = head[hash];
+        head[hash] = position;
+    }
+}
+
+fn push_literals(output: &mut Vec<u8>, literals: &[u8]) {
+    for chunk in literals.chunks(MAX_LITERALS) {
+        output.push((chunk.len() - 1) as u8);
+        output.extend_from_slice(chunk);
+    }
+}
+
+#[inline]
+fn hash(data: &[u8]) -> usize {
+    let value = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+    (value.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gb_emu::tests::TestRom;
+    use crate::gb_emu::GameBoyEmulator;
+    use crate::hardware::mmu::MemoryMapper;
+    use crate::hardware::ppu::palette::{DisplayColour, DisplayColourPreset};
+    use crate::state::*;
+    use crate::EmulatorOptionsBuilder;
+
+    #[test]
+    fn test_compact_state_round_trip() {
+        // MBC1 + RAM + Battery, with 32KB of RAM.
+        let rom = || TestRom::new().with_bytes(0x147, &[0x03]).with_bytes(0x149, &[0x03]);
+        // Just some code which writes a pattern to the work ram.
+        let program = [0x21, 0x00, 0xC0, 0x7D, 0x22, 0x18, 0xFC];
+        let mut emulator = rom().with_program(&program).emulator();
+        emulator.cpu.mmu.write_byte(0x0000, 0x0A);
+        emulator.cpu.mmu.write_byte(0xA010, 0x42);
+        emulator.run_to_vblank();
+        let full_state = emulator.save_state();
+        let frame = emulator.frame_buffer().to_vec();
+
+        for &level in [CompressionLevel::Fast, CompressionLevel::Default, CompressionLevel::Best].iter() {
+            let compact = emulator.save_state_compact(level);
+            assert!(compact.len() < full_state.len() / 10);
+
+            let mut restored = rom().with_program(&program).emulator();
+            restored.load_state(&compact).unwrap();
+            assert_eq!(restored.save_state(), full_state);
+            assert_eq!(restored.frame_buffer().to_vec(), frame);
+            assert_eq!(restored.cpu.mmu.cartridge().unwrap().ram()[0x10], 0x42);
+        }
+
+        let mut damaged = emulator.save_state_compact(CompressionLevel::Fast);
+        damaged.truncate(damaged.len() - 1);
+        assert_eq!(emulator.load_state(&damaged), Err(StateError::Corrupt));
+    }
+
+    /// Only present when the commercial ROMs have been placed in the `roms` directory.
+    const TETRIS_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../roms/Tetris.gb");
+
+    /// A ROM which continuously writes to the tile data while scrolling, with the timer enabled.
+    fn save_state_rom() -> TestRom {
+        let program = [
+            0x3E, 0x05, 0xE0, 0x07, 0x21, 0x00, 0x80, 0x7D, 0x80, 0x22, 0xF0, 0x44, 0xE0, 0x43, 0x7C, 0xFE, 0x98,
+            0x20, 0xF4, 0x26, 0x80, 0x04, 0x18, 0xEF,
+        ];
+        let colours = DisplayColour::from_preset(DisplayColourPreset::Grayscale);
+
+        TestRom::new()
+            .with_program(&[0xC3, 0x50, 0x01])
+            .with_bytes(0x150, &program)
+            .with_options(EmulatorOptionsBuilder::new().with_display_colour(colours))
+    }
+
+    fn run_frames(emulator: &mut GameBoyEmulator, frames: usize) {
+        (0..frames).for_each(|_| emulator.run_to_vblank());
+    }
+
+    #[test]
+    fn test_save_state_round_trip() {
+        let mut emulator = save_state_rom().emulator();
+        run_frames(&mut emulator, 100);
+        let state = emulator.save_state();
+        let frame = emulator.frame_buffer().to_vec();
+
+        run_frames(&mut emulator, 20);
+        let later_frame = emulator.frame_buffer().to_vec();
+        let later_cycles = emulator.cycles_performed();
+        assert_ne!(later_frame, frame);
+
+        emulator.load_state(&state).unwrap();
+        assert_eq!(emulator.frame_buffer().to_vec(), frame);
+        assert_eq!(emulator.save_state(), state);
+
+        // With the scheduler restored the emulation should continue exactly as before.
+        let mut restored = save_state_rom().emulator();
+        restored.load_state(&state).unwrap();
+        for emulator in [&mut emulator, &mut restored].iter_mut() {
+            run_frames(emulator, 20);
+            assert_eq!(emulator.frame_buffer().to_vec(), later_frame);
+            assert_eq!(emulator.cycles_performed(), later_cycles);
+        }
+    }
+
+    #[test]
+    fn test_save_state_tetris() {
+        let rom = match std::fs::read(TETRIS_PATH) {
+            Ok(rom) => rom,
+            Err(_) => {
+                println!("Skipping, {} is not present", TETRIS_PATH);
+                return;
+            }
+        };
+        let mut emulator = GameBoyEmulator::new(&rom, EmulatorOptionsBuilder::new().build());
+        run_frames(&mut emulator, 1000);
+        let state = emulator.save_state();
+        let frame = emulator.frame_buffer().to_vec();
+
+        run_frames(&mut emulator, 100);
+        let later_frame = emulator.frame_buffer().to_vec();
+        emulator.load_state(&state).unwrap();
+        assert_eq!(emulator.frame_buffer().to_vec(), frame);
+
+        run_frames(&mut emulator, 100);
+        assert_eq!(emulator.frame_buffer().to_vec(), later_frame);
+    }
+
+    #[test]
+    fn test_save_state_rejected() {
+        let mut emulator = save_state_rom().emulator();
+        run_frames(&mut emulator, 1);
+        let state = emulator.save_state();
+        let cycles = emulator.cycles_performed();
+
+        let mut old_version = state.clone();
+        old_version[4] = 0;
+        let mut other_emulator = save_state_rom().with_bytes(0x14E, &[0x12]).emulator();
+        let mut damaged = state.clone();
+        *damaged.last_mut().unwrap() ^= 0xFF;
+
+        assert_eq!(emulator.load_state(&[0; 4]), Err(StateError::InvalidHeader));
+        assert_eq!(
+            emulator.load_state(&old_version),
+            Err(StateError::UnsupportedVersion { version: 0 })
+        );
+        assert_eq!(other_emulator.load_state(&state), Err(StateError::RomMismatch));
+        assert_eq!(emulator.load_state(&state[..state.len() - 1]), Err(StateError::Corrupt));
+        assert_eq!(emulator.load_state(&damaged), Err(StateError::Corrupt));
+        assert_eq!(emulator.cycles_performed(), cycles);
+    }
+
+    #[test]
+    fn test_failed_load_rolls_back() {
+        let mut emulator = save_state_rom().emulator();
+        run_frames(&mut emulator, 1);
+        // Cut the state off in the middle of the components, with a valid header.
+        let mut truncated = emulator.save_state();
+        truncated.truncate(HEADER_SIZE + 0x100);
+        // Without any cartridge RAM.
+        truncated.extend_from_slice(&0u32.to_le_bytes());
+        let payload = &truncated[HEADER_SIZE..];
+        let length = (payload.len() as u32).to_le_bytes();
+        let checksum = adler32(payload).to_le_bytes();
+        truncated[HEADER_SIZE - 8..HEADER_SIZE - 4].copy_from_slice(&length);
+        truncated[HEADER_SIZE - 4..HEADER_SIZE].copy_from_slice(&checksum);
+
+        run_frames(&mut emulator, 1);
+        let state = emulator.save_state();
+
+        assert_eq!(emulator.load_state(&truncated), Err(StateError::Corrupt));
+        assert_eq!(emulator.save_state(), state);
+    }
+
+    #[test]
+    fn test_levels_trade_size() {
+        let data: Vec<u8> = (0..0x4000u32).map(|i| (((i * 7) % 251) ^ (i / 97)) as u8).collect();
+        let fast = lz_compress(&data, CompressionLevel::Fast);
+        let best = lz_compress(&data, CompressionLevel::Best);
+
+        assert!(best.len() <= fast.len());
+        assert_eq!(lz_decompress(&fast, data.len()).unwrap(), data);
+        assert_eq!(lz_decompress(&best, data.len()).unwrap(), data);
+    }
+
+    #[test]
+    fn test_corrupt_data() {
+        assert_eq!(lz_decompress(&[0x80, 0x01, 0x00], 4), None);
+        assert_eq!(rle_decode(&[0x05, 0x01], 6), None);
+        assert_eq!(rle_decode(&rle_encode(&[1, 2, 2, 2, 3]), 5), Some(vec![1, 2, 2, 2, 3]));
+    }
+}
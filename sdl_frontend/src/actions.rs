@@ -1,6 +1,9 @@
 use directories::ProjectDirs;
+use rustyboi_core::crash_report::CrashReport;
 use rustyboi_core::gb_emu::GameBoyEmulator;
 use rustyboi_core::hardware::cartridge::header::CartridgeHeader;
+use rustyboi_core::hardware::cartridge::Cartridge;
+use rustyboi_core::patch::apply_patch;
 
 use rustyboi_core::{EmulatorOptions, EmulatorOptionsBuilder};
 use std::fs::{create_dir_all, read, File};
@@ -9,33 +12,79 @@ use std::path::Path;
 
 /// Function to call in order to save external ram (in case it's present)
 /// as well as any additional cleanup as required.
-pub fn save_rom(emulator: &GameBoyEmulator) {
-    if let Some(ram) = emulator.battery_ram() {
-        let save_dir = ProjectDirs::from("", "Hirtol", "Rustyboi")
-            .expect("Could not get access to data dir for saving!")
-            .data_dir()
-            .join("saves");
-        create_dir_all(&save_dir);
-        // Really, this expect case shouldn't ever be reached.
-        let title = emulator.game_title().expect("No cartridge loaded, can't save!").trim();
-
-        let mut save_file =
-            File::create(save_dir.join(format!("{}.save", title))).expect("Could not create the save file");
-        save_file.write(ram);
-
-        log::debug!(
-            "Finished saving the external ram with size: {} successfully!",
-            ram.len()
-        );
+///
+/// The save callback (see `save_battery_ram()`) is the only writer of the save file, this just
+/// flushes any writes it hasn't seen yet.
+pub fn save_rom(emulator: &mut GameBoyEmulator) {
+    emulator.shutdown();
+}
+
+/// Write the provided battery `ram` to the save file for the game with `title`.
+pub fn save_battery_ram(title: &str, ram: &[u8]) {
+    let save_dir = ProjectDirs::from("", "Hirtol", "Rustyboi")
+        .expect("Could not get access to data dir for saving!")
+        .data_dir()
+        .join("saves");
+    create_dir_all(&save_dir);
+
+    let mut save_file =
+        File::create(save_dir.join(format!("{}.save", title))).expect("Could not create the save file");
+    save_file.write(ram);
+
+    log::debug!(
+        "Finished saving the external ram with size: {} successfully!",
+        ram.len()
+    );
+}
+
+/// Write the save `state` of the game with `title` to its save state file.
+pub fn save_state_file(title: &str, state: &[u8]) {
+    let state_dir = ProjectDirs::from("", "Hirtol", "Rustyboi")
+        .expect("Could not get access to data dir for saving!")
+        .data_dir()
+        .join("states");
+    create_dir_all(&state_dir);
+
+    match File::create(state_dir.join(format!("{}.state", title))).and_then(|mut file| file.write_all(state)) {
+        Ok(_) => log::info!("Saved state for {}", title),
+        Err(e) => log::error!("Could not save state for {} due to: {:?}", title, e),
+    }
+}
+
+/// Read the save state file of the game with `title`, if there is one.
+pub fn find_save_state(title: &str) -> Option<Vec<u8>> {
+    let state_dir = ProjectDirs::from("", "Hirtol", "Rustyboi")
+        .expect("Could not get access to data dir for saving!")
+        .data_dir()
+        .join("states");
+
+    read(state_dir.join(format!("{}.state", title))).ok()
+}
+
+/// Write the `report` to the data dir so that it can be attached to a bug report.
+pub fn save_crash_report(report: &CrashReport) {
+    let report_dir = ProjectDirs::from("", "Hirtol", "Rustyboi")
+        .expect("Could not get access to data dir for saving!")
+        .data_dir()
+        .join("crash_reports");
+    create_dir_all(&report_dir);
+    let title = report.game_title.as_deref().unwrap_or("unknown");
+    let report_path = report_dir.join(format!("{}_{}.txt", title, report.cycles_performed));
+
+    match File::create(&report_path).and_then(|mut file| file.write_all(report.to_string().as_bytes())) {
+        Ok(_) => log::error!("Emulator crashed, saved crash report to: {:?}", report_path),
+        Err(e) => log::error!("Emulator crashed, but failed to save crash report due to: {:?}", e),
     }
 }
 
 /// Create an emulator for the ROM provided by `rom_path`.
 /// In case the file provided is not a rom the program will *probably* crash.
 ///
-/// Any external ram will also automatically be loaded if present.
+/// Any external ram will also automatically be loaded if present, as will an IPS/BPS patch
+/// with the same name as the ROM (e.g, `game.ips` for `game.gb`).
 pub fn create_emulator(rom_path: impl AsRef<Path>, options: EmulatorOptions) -> GameBoyEmulator {
     let rom = read(rom_path.as_ref()).expect(&format!("Could not open ROM file {:?}!", rom_path.as_ref()));
+    let rom = patch_rom(rom_path.as_ref(), rom);
     let saved_ram = find_saved_ram(find_rom_name(&rom));
 
     log::info!(
@@ -44,11 +93,54 @@ pub fn create_emulator(rom_path: impl AsRef<Path>, options: EmulatorOptions) ->
         saved_ram.is_some()
     );
 
-    let emu_options = EmulatorOptionsBuilder::from(options).with_saved_ram(saved_ram).build();
+    let emu_options = EmulatorOptionsBuilder::from(options)
+        .with_saved_ram(saved_ram)
+        .with_upgrade_cgb_only_roms(true)
+        .build();
 
     GameBoyEmulator::new(&rom, emu_options)
 }
 
+/// Apply the first patch found next to the `rom_path`, if any.
+/// Should the patch fail to apply the unpatched `rom` is returned instead.
+fn patch_rom(rom_path: &Path, rom: Vec<u8>) -> Vec<u8> {
+    let patch_path = ["ips", "bps"]
+        .iter()
+        .map(|extension| rom_path.with_extension(extension))
+        .find(|path| path.exists());
+
+    let patch_path = match patch_path {
+        Some(path) => path,
+        None => return rom,
+    };
+
+    match read(&patch_path).map(|patch| apply_patch(&rom, &patch)) {
+        Ok(Ok(patched)) => {
+            log::info!("Applied patch {:?}", patch_path);
+            patched
+        }
+        Ok(Err(e)) => {
+            log::error!("Could not apply patch {:?} due to: {}", patch_path, e);
+            rom
+        }
+        Err(e) => {
+            log::error!("Could not open patch {:?} due to: {:?}", patch_path, e);
+            rom
+        }
+    }
+}
+
+/// Returns whether the ROM at `rom_path` can be read and uses a cartridge type we support.
+pub fn is_supported_rom(rom_path: impl AsRef<Path>) -> bool {
+    match read(rom_path.as_ref()) {
+        Ok(rom) => rom.get(0x147).map_or(false, |&cartridge_type| Cartridge::is_supported(cartridge_type)),
+        Err(e) => {
+            log::warn!("Could not open ROM file {:?} due to: {:?}", rom_path.as_ref(), e);
+            false
+        }
+    }
+}
+
 pub fn find_saved_ram(name: impl AsRef<str>) -> Option<Vec<u8>> {
     let save_dir = ProjectDirs::from("", "Hirtol", "Rustyboi")
         .expect("Could not get access to data dir for saving!")
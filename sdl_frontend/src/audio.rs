@@ -5,6 +5,8 @@ use sdl2::audio::{AudioQueue, AudioSpecDesired};
 use sdl2::AudioSubsystem;
 use std::time::Duration;
 
+pub mod time_stretch;
+
 pub struct AudioPlayer {
     awaiting_audio: bool,
     paused: bool,
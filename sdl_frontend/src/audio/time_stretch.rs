@@ -0,0 +1,112 @@
+//! A simple WSOLA (Waveform Similarity Overlap-Add) time-stretcher.
+//!
+//! Used during fast forward to shorten the emulator's audio to real-time length without
+//! the pitch shift which would occur by just playing it back faster.
+
+/// Expected to operate on interleaved stereo samples, as produced by the APU.
+const CHANNELS: usize = 2;
+/// Length of a single (windowed) segment in frames, ~23ms at 44.1kHz.
+const WINDOW_FRAMES: usize = 1024;
+/// The segments overlap by half, so this is both the output hop size and the overlap length.
+const HOP_FRAMES: usize = WINDOW_FRAMES / 2;
+/// How far (in frames) we may deviate from the nominal segment position to find a better fit.
+const SEEK_FRAMES: usize = 128;
+
+pub struct TimeStretcher {
+    speed: f64,
+    window: Vec<f32>,
+    /// Interleaved input samples which have not been fully consumed yet.
+    input: Vec<f32>,
+    /// The nominal start (in frames) of the next segment within `input`.
+    analysis_position: f64,
+    /// The windowed second half of the previous segment, to be overlapped with the next one.
+    previous_tail: Vec<f32>,
+    /// The input which naturally followed the previous segment, used to find the most similar
+    /// next segment. `None` if there was no previous segment.
+    reference: Option<Vec<f32>>,
+}
+
+impl TimeStretcher {
+    /// Create a new `TimeStretcher` which will shorten its input by the given `speed` factor.
+    pub fn new(speed: f64) -> Self {
+        // A periodic Hann window sums to exactly `1.0` when overlapped by half, so no
+        // further normalisation is needed.
+        let window = (0..WINDOW_FRAMES)
+            .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / WINDOW_FRAMES as f32).cos())
+            .collect();
+
+        TimeStretcher {
+            speed: speed.max(1.0),
+            window,
+            input: Vec::with_capacity(WINDOW_FRAMES * CHANNELS * 8),
+            analysis_position: SEEK_FRAMES as f64,
+            previous_tail: vec![0.0; HOP_FRAMES * CHANNELS],
+            reference: None,
+        }
+    }
+
+    pub fn push_samples(&mut self, samples: &[f32]) {
+        self.input.extend_from_slice(samples);
+    }
+
+    /// Stretch as much of the pushed input as possible and append the result to `output`.
+    ///
+    /// Any input which can't be processed yet is kept around for the next call.
+    pub fn drain_into(&mut self, output: &mut Vec<f32>) {
+        let input_frames = self.input.len() / CHANNELS;
+
+        while (self.analysis_position as usize) + SEEK_FRAMES + WINDOW_FRAMES <= input_frames {
+            let start = self.best_segment_start(self.analysis_position as usize);
+            let segment = &self.input[start * CHANNELS..(start + WINDOW_FRAMES) * CHANNELS];
+
+            for i in 0..HOP_FRAMES * CHANNELS {
+                let weight = self.window[i / CHANNELS];
+                output.push(self.previous_tail[i] + segment[i] * weight);
+            }
+
+            let second_half = &segment[HOP_FRAMES * CHANNELS..];
+            for (i, (tail, sample)) in self.previous_tail.iter_mut().zip(second_half).enumerate() {
+                *tail = sample * self.window[HOP_FRAMES + i / CHANNELS];
+            }
+
+            let natural_continuation = &self.input[(start + HOP_FRAMES) * CHANNELS..(start + WINDOW_FRAMES) * CHANNELS];
+            match self.reference.as_mut() {
+                Some(reference) => reference.copy_from_slice(natural_continuation),
+                None => self.reference = Some(natural_continuation.to_vec()),
+            }
+
+            self.analysis_position += HOP_FRAMES as f64 * self.speed;
+        }
+
+        // Drop the input we'll never look at again.
+        let consumed_frames = (self.analysis_position as usize).saturating_sub(SEEK_FRAMES).min(input_frames);
+        self.input.drain(..consumed_frames * CHANNELS);
+        self.analysis_position -= consumed_frames as f64;
+    }
+
+    /// Find the segment start within `SEEK_FRAMES` of `nominal_start` which most closely
+    /// resembles the input that followed the previous segment, so that overlapping the two
+    /// doesn't cause audible phase cancellation.
+    fn best_segment_start(&self, nominal_start: usize) -> usize {
+        let reference = match &self.reference {
+            Some(reference) => reference,
+            None => return nominal_start,
+        };
+
+        let lowest_start = nominal_start.saturating_sub(SEEK_FRAMES);
+        let mut best_start = nominal_start;
+        let mut best_correlation = f32::MIN;
+
+        for candidate in lowest_start..=nominal_start + SEEK_FRAMES {
+            let candidate_samples = &self.input[candidate * CHANNELS..(candidate + HOP_FRAMES) * CHANNELS];
+            let correlation: f32 = candidate_samples.iter().zip(reference.iter()).map(|(a, b)| a * b).sum();
+
+            if correlation > best_correlation {
+                best_correlation = correlation;
+                best_start = candidate;
+            }
+        }
+
+        best_start
+    }
+}
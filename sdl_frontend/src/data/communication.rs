@@ -1,7 +1,10 @@
 use crate::data::state::DisplayColourConfigurable;
+use crate::gameboy::FastForward;
 use rustyboi_core::gb_emu::GameBoyModel;
+use rustyboi_core::hardware::cartridge::BankInfo;
 use rustyboi_core::hardware::ppu::debugging_features::PaletteDebugInfo;
-use rustyboi_core::InputKey;
+use rustyboi_core::hardware::ppu::palette::RGB;
+use rustyboi_core::{InputKey, TimerState};
 
 /// Represents a notification for the emulator thread to execute when possible.
 #[derive(Debug)]
@@ -11,9 +14,23 @@ pub enum EmulatorNotification {
     /// Pass the audio buffer back and forth to avoid constant heap allocation
     AudioRequest(Vec<f32>),
     ExtraAudioRequest,
+    /// Start fast forwarding with the given settings, or stop doing so if `None`.
+    FastForward(Option<FastForward>),
+    /// Reset the emulator, as if the reset button were pressed.
+    Reset,
+    /// Stop running frames, or resume doing so, while still answering all other notifications.
+    Pause(bool),
+    /// Run a single frame while paused, which is sent like any other frame.
+    FrameStep,
+    /// Write the current state to the save state file of the game.
+    SaveState,
+    /// Restore the state from the save state file of the game, if there is one.
+    LoadState,
     ExitRequest,
     Debug(DebugMessage),
     ChangeDisplayColour(DisplayColourConfigurable),
+    /// Mute, or unmute, the given voice (`0-3` for voice 1-4) of the APU.
+    MaskAudioChannel(usize, bool),
 }
 
 #[derive(Debug, Clone, PartialOrd, PartialEq)]
@@ -28,6 +45,12 @@ pub enum EmulatorResponse {
 pub enum DebugMessage {
     Mode(Option<GameBoyModel>),
     Palette(Option<PaletteDebugInfo>),
+    Timer(Option<TimerState>),
+    /// The most recently completed frame.
+    Screen(Option<Vec<RGB>>),
+    /// The most recently completed frame, with an outline around every sprite.
+    SpriteBoxes(Option<Vec<RGB>>),
+    Banks(Option<BankInfo>),
 }
 
 impl Into<EmulatorNotification> for DebugMessage {
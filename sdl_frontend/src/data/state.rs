@@ -1,4 +1,4 @@
-use crate::KIRBY_DISPLAY_COLOURS;
+use crate::{DEFAULT_MAX_CATCH_UP_FRAMES, KIRBY_DISPLAY_COLOURS};
 use nanoserde::{DeJson, SerJson};
 use rustyboi_core::hardware::ppu::palette::DisplayColour;
 
@@ -7,6 +7,8 @@ use rustyboi_core::hardware::ppu::palette::DisplayColour;
 pub struct AppEmulatorState {
     /// Whether the emulation is currently paused.
     pub emulator_paused: bool,
+    /// Whether we should advance a single frame while paused.
+    pub frame_step: bool,
     /// Whether we should fast forward at our set fast_forward_rate
     pub fast_forward: bool,
     /// Whether the emulation should run unbounded
@@ -21,6 +23,7 @@ impl AppEmulatorState {
     pub fn reset(&mut self) {
         self.awaiting_debug = false;
         self.emulator_paused = false;
+        self.frame_step = false;
     }
 }
 
@@ -31,6 +34,16 @@ pub struct AppState {
     pub fast_forward_rate: u64,
     pub audio_mute: bool,
     pub audio_volume: f32,
+    /// Whether to time-stretch the audio while fast forwarding, instead of letting it pitch up.
+    pub preserve_turbo_pitch: bool,
+    /// Whether to drop the audio while fast forwarding entirely, takes precedence over
+    /// `preserve_turbo_pitch`.
+    #[nserde(default)]
+    pub skip_fast_forward_audio: bool,
+    /// The maximum amount of frames to run at once when catching up after the host stalled,
+    /// any time beyond that is dropped.
+    #[nserde(default)]
+    pub max_catch_up_frames: u64,
     pub custom_display_colour: DisplayColourConfigurable,
 }
 
@@ -40,6 +53,9 @@ impl Default for AppState {
             fast_forward_rate: 2,
             audio_mute: false,
             audio_volume: 0.0,
+            preserve_turbo_pitch: false,
+            skip_fast_forward_audio: false,
+            max_catch_up_frames: DEFAULT_MAX_CATCH_UP_FRAMES,
             custom_display_colour: DisplayColourConfigurable::default(),
         }
     }
@@ -1,10 +1,15 @@
 use core::option::Option::Some;
 use std::path::Path;
 use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
 use crossbeam::channel::*;
 
-use crate::actions::{create_emulator, save_rom};
+use crate::actions::{
+    create_emulator, find_save_state, save_battery_ram, save_crash_report, save_rom, save_state_file,
+};
+use crate::audio::time_stretch::TimeStretcher;
+use crate::link::{run_to_vblank_linked, SharedLink};
 use rustyboi_core::gb_emu::GameBoyEmulator;
 use rustyboi_core::hardware::ppu::palette::RGB;
 use rustyboi_core::hardware::ppu::FRAMEBUFFER_SIZE;
@@ -12,28 +17,67 @@ use rustyboi_core::{EmulatorOptions, InputKey};
 
 use crate::data::communication::{DebugMessage, EmulatorNotification, EmulatorResponse};
 
+/// The settings used while fast forwarding, see `GameboyRunner::set_fast_forward()`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FastForward {
+    /// The amount of frames to run for every real-time frame.
+    pub rate: u64,
+    /// Whether to time-stretch the audio to play at normal speed, otherwise it's played sped up
+    /// (and thus pitched up).
+    pub preserve_pitch: bool,
+    /// Whether to drop the audio produced while fast forwarding entirely.
+    pub skip_audio: bool,
+}
+
+impl FastForward {
+    pub const MAX_RATE: u64 = 100;
+
+    /// Create the settings, with the `rate` clamped to `1..=MAX_RATE`.
+    pub fn new(rate: u64, preserve_pitch: bool, skip_audio: bool) -> Self {
+        FastForward {
+            rate: rate.max(1).min(Self::MAX_RATE),
+            preserve_pitch,
+            skip_audio,
+        }
+    }
+}
+
 pub struct GameboyRunner {
     current_thread: Option<JoinHandle<()>>,
+    fast_forward: Option<FastForward>,
+    paused: bool,
+    serial_link: Option<SharedLink>,
     pub frame_receiver: Receiver<[RGB; FRAMEBUFFER_SIZE]>,
     pub request_sender: Sender<EmulatorNotification>,
     pub response_receiver: Receiver<EmulatorResponse>,
 }
 
 impl GameboyRunner {
-    pub fn new(rom_path: impl AsRef<Path>, options: EmulatorOptions) -> GameboyRunner {
+    pub fn new(rom_path: impl AsRef<Path>, options: EmulatorOptions, serial_link: Option<SharedLink>) -> GameboyRunner {
         log::info!("Starting new thread for emulator with options: {:#X?}", options);
         let (frame_sender, frame_receiver) = bounded(1);
         let (request_sender, request_receiver) = unbounded::<EmulatorNotification>();
         let (response_sender, response_receiver) = unbounded::<EmulatorResponse>();
         let title = rom_path.as_ref().to_str().unwrap().to_string();
+        let thread_link = serial_link.clone();
         let emulator_thread = std::thread::spawn(move || {
             // Has to be allocated on this separate stack or else we get a stack overflow :D
             let mut emulator = create_emulator(title, options);
-            run_emulator(&mut emulator, frame_sender, response_sender, request_receiver);
-            save_rom(&emulator);
+            if let Some(link) = &thread_link {
+                emulator.connect_serial_link(Box::new(link.clone()));
+            }
+            // Save shortly after the game does, so we don't lose anything if we're force-quit.
+            if let Some(game_title) = emulator.game_title().map(|title| title.trim().to_string()) {
+                emulator.set_save_callback(Box::new(move |ram| save_battery_ram(&game_title, ram)));
+            }
+            run_emulator(&mut emulator, thread_link, frame_sender, response_sender, request_receiver);
+            save_rom(&mut emulator);
         });
         GameboyRunner {
             current_thread: Some(emulator_thread),
+            fast_forward: None,
+            paused: false,
+            serial_link,
             frame_receiver,
             request_sender,
             response_receiver,
@@ -44,6 +88,11 @@ impl GameboyRunner {
         self.current_thread.is_some()
     }
 
+    /// The link cable connected to the emulator, to keep it connected for the next ROM.
+    pub fn serial_link(&self) -> Option<SharedLink> {
+        self.serial_link.clone()
+    }
+
     pub fn handle_input(&self, key: InputKey, pressed: bool) {
         //TODO: Error handling
         if pressed {
@@ -53,6 +102,61 @@ impl GameboyRunner {
         }
     }
 
+    /// Start fast forwarding with the given settings, or stop doing so if `None`.
+    ///
+    /// Can be called every frame, the emulator thread is only notified on a change.
+    pub fn set_fast_forward(&mut self, fast_forward: Option<FastForward>) {
+        if fast_forward != self.fast_forward {
+            self.request_sender.send(EmulatorNotification::FastForward(fast_forward));
+            self.fast_forward = fast_forward;
+        }
+    }
+
+    /// Pause or resume the emulator thread, can be called every frame.
+    ///
+    /// The frames which were already underway when pausing are still sent, see `try_recv_frame()`.
+    pub fn set_paused(&mut self, paused: bool) {
+        if paused != self.paused {
+            self.request_sender.send(EmulatorNotification::Pause(paused));
+            self.paused = paused;
+        }
+    }
+
+    /// Run a single frame while paused, which can be received with `try_recv_frame()`.
+    pub fn frame_step(&self) {
+        if self.paused {
+            self.request_sender.send(EmulatorNotification::FrameStep);
+        }
+    }
+
+    /// Returns a frame sent by the emulator thread if there is one, without blocking.
+    pub fn try_recv_frame(&self) -> Option<[RGB; FRAMEBUFFER_SIZE]> {
+        self.frame_receiver.try_recv().ok()
+    }
+
+    /// Run (by receiving them) the frames owed according to the `budget`, each owed frame being
+    /// multiplied by the fast forward rate, if any. `on_frame` is called for every received frame.
+    ///
+    /// # Returns
+    ///
+    /// The amount of frames that were run.
+    pub fn run_with_budget(&self, budget: &mut FrameBudget, mut on_frame: impl FnMut(&[RGB; FRAMEBUFFER_SIZE])) -> u64 {
+        let speed_multiplier = self.fast_forward.map_or(1, |fast_forward| fast_forward.rate);
+        let frames_to_run = budget.owed_frames() * speed_multiplier;
+
+        for _ in 0..frames_to_run {
+            match self.frame_receiver.recv() {
+                Ok(frame) => on_frame(&frame),
+                Err(e) => {
+                    log::error!("Failed to receive frame due to: {:?}", e);
+                    return 0;
+                }
+            }
+        }
+
+        frames_to_run
+    }
+
     /// Stops the current emulator thread and blocks until it has completed.
     ///
     /// Commands the emulator thread to save the current saves to disk as well.
@@ -66,26 +170,129 @@ impl GameboyRunner {
     }
 }
 
+/// Keeps track of how far behind real time the emulation is, so that we can catch up after
+/// the host stalled without trying to emulate an unbounded amount of frames at once.
+pub struct FrameBudget {
+    frame_duration: Duration,
+    max_frames: u64,
+    owed_time: Duration,
+    last_update: Instant,
+}
+
+impl FrameBudget {
+    pub fn new(frame_duration: Duration, max_frames: u64) -> Self {
+        FrameBudget {
+            frame_duration,
+            max_frames: max_frames.max(1),
+            owed_time: Duration::from_secs(0),
+            last_update: Instant::now(),
+        }
+    }
+
+    pub fn set_max_frames(&mut self, max_frames: u64) {
+        self.max_frames = max_frames.max(1);
+    }
+
+    /// Returns the amount of frames owed since the last call, always at least one so that
+    /// regular frame pacing is unaffected.
+    ///
+    /// Capped at `max_frames`, any time beyond that is dropped to prevent a spiral of death.
+    pub fn owed_frames(&mut self) -> u64 {
+        let now = Instant::now();
+        self.owed_time += now - self.last_update;
+        self.last_update = now;
+
+        let owed = (self.owed_time.as_nanos() / self.frame_duration.as_nanos()).max(1) as u64;
+
+        if owed > self.max_frames {
+            log::debug!("Dropping {} frames to catch up with real time", owed - self.max_frames);
+            self.owed_time = Duration::from_secs(0);
+            self.max_frames
+        } else {
+            self.owed_time = self
+                .owed_time
+                .checked_sub(self.frame_duration * owed as u32)
+                .unwrap_or_else(|| Duration::from_secs(0));
+            owed
+        }
+    }
+
+    /// Forget about any owed time, for example after having been paused.
+    pub fn reset(&mut self) {
+        self.owed_time = Duration::from_secs(0);
+        self.last_update = Instant::now();
+    }
+}
+
 fn run_emulator(
     emulator: &mut GameBoyEmulator,
+    serial_link: Option<SharedLink>,
     frame_sender: Sender<[RGB; FRAMEBUFFER_SIZE]>,
     response_sender: Sender<EmulatorResponse>,
     notification_receiver: Receiver<EmulatorNotification>,
 ) {
+    let mut time_stretcher: Option<TimeStretcher> = None;
+    let mut skip_audio = false;
+    let mut crash_reported = false;
+    let mut paused = false;
+    let mut frame_step = false;
+
+    let run_frame = |emulator: &mut GameBoyEmulator| match &serial_link {
+        Some(link) => {
+            if let Err(e) = run_to_vblank_linked(emulator, link) {
+                log::warn!("Link cable transfer failed: {}", e);
+            }
+        }
+        None => emulator.run_to_vblank(),
+    };
+
     'emu_loop: loop {
-        emulator.run_to_vblank();
+        if !paused || std::mem::take(&mut frame_step) {
+            if paused {
+                emulator.frame_step();
+            } else {
+                run_frame(emulator);
+            }
+
+            if !crash_reported {
+                if let Some(report) = emulator.crash_report() {
+                    save_crash_report(&report);
+                    crash_reported = true;
+                }
+            }
 
-        if let Err(e) = frame_sender.send(emulator.frame_buffer().clone()) {
-            log::error!("Failed to transfer framebuffer due to: {:?}", e);
-            break 'emu_loop;
+            if let Some(stretcher) = time_stretcher.as_mut() {
+                stretcher.push_samples(emulator.audio_buffer());
+                emulator.clear_audio_buffer();
+            }
+
+            if let Err(e) = frame_sender.send(emulator.frame_buffer().clone()) {
+                log::error!("Failed to transfer framebuffer due to: {:?}", e);
+                break 'emu_loop;
+            }
         }
 
-        while let Ok(notification) = notification_receiver.try_recv() {
+        // While paused we block until there's something to do, such as a frame step.
+        let blocking_notification = if paused {
+            match notification_receiver.recv() {
+                Ok(notification) => Some(notification),
+                Err(_) => break 'emu_loop,
+            }
+        } else {
+            None
+        };
+
+        for notification in blocking_notification.into_iter().chain(notification_receiver.try_iter()) {
             match notification {
                 EmulatorNotification::KeyDown(key) => emulator.handle_input(key, true),
                 EmulatorNotification::KeyUp(key) => emulator.handle_input(key, false),
                 EmulatorNotification::AudioRequest(mut audio_buffer) => {
-                    audio_buffer.extend(emulator.audio_buffer().iter());
+                    // While skipping audio we simply return an empty buffer, the player will play silence.
+                    if let Some(stretcher) = time_stretcher.as_mut() {
+                        stretcher.drain_into(&mut audio_buffer);
+                    } else if !skip_audio {
+                        audio_buffer.extend(emulator.audio_buffer().iter());
+                    }
                     if let Err(e) = response_sender.send(EmulatorResponse::Audio(audio_buffer)) {
                         log::error!("Failed to transfer audio buffer due to: {:?}", e);
                         break 'emu_loop;
@@ -93,8 +300,21 @@ fn run_emulator(
                     emulator.clear_audio_buffer();
                 }
                 EmulatorNotification::ExtraAudioRequest => {
-                    emulator.run_to_vblank();
-                    if let Err(e) = response_sender.send(EmulatorResponse::Audio(emulator.audio_buffer().to_vec())) {
+                    if !paused {
+                        run_frame(emulator);
+                    }
+                    let audio_buffer = if skip_audio || paused {
+                        Vec::new()
+                    } else if let Some(stretcher) = time_stretcher.as_mut() {
+                        let mut audio_buffer = Vec::new();
+                        stretcher.push_samples(emulator.audio_buffer());
+                        emulator.clear_audio_buffer();
+                        stretcher.drain_into(&mut audio_buffer);
+                        audio_buffer
+                    } else {
+                        emulator.audio_buffer().to_vec()
+                    };
+                    if let Err(e) = response_sender.send(EmulatorResponse::Audio(audio_buffer)) {
                         log::error!("Failed to transfer extra audio buffer due to: {:?}", e);
                         break 'emu_loop;
                     }
@@ -104,6 +324,31 @@ fn run_emulator(
                         break 'emu_loop;
                     }
                 }
+                EmulatorNotification::FastForward(fast_forward) => {
+                    skip_audio = fast_forward.map_or(false, |fast_forward| fast_forward.skip_audio);
+                    time_stretcher = fast_forward
+                        .filter(|fast_forward| fast_forward.preserve_pitch && !fast_forward.skip_audio)
+                        .map(|fast_forward| TimeStretcher::new(fast_forward.rate as f64));
+                }
+                EmulatorNotification::Reset => emulator.reset(),
+                EmulatorNotification::Pause(pause) => paused = pause,
+                EmulatorNotification::FrameStep => frame_step = paused,
+                EmulatorNotification::SaveState => {
+                    if let Some(title) = emulator.game_title() {
+                        save_state_file(title.trim(), &emulator.save_state());
+                    }
+                }
+                EmulatorNotification::LoadState => {
+                    let title = emulator.game_title().map(|title| title.trim().to_string());
+                    match title.and_then(|title| find_save_state(&title)) {
+                        Some(state) => {
+                            if let Err(e) = emulator.load_state(&state) {
+                                log::error!("Could not load the save state due to: {}", e);
+                            }
+                        }
+                        None => log::warn!("There is no save state to load"),
+                    }
+                }
                 EmulatorNotification::ExitRequest => {
                     break 'emu_loop;
                 }
@@ -114,6 +359,9 @@ fn run_emulator(
                         new_palette.dmg_sprite_colour_1.into(),
                     );
                 }
+                EmulatorNotification::MaskAudioChannel(channel, masked) => {
+                    emulator.set_audio_channel_masked(channel, masked);
+                }
             }
         }
         // Since we know that in the common runtime the emulator thread will run in lockstep
@@ -136,6 +384,19 @@ fn handle_debug_request(
         DebugMessage::Mode(_) => {
             response = response_sender.send(DebugMessage::Mode(emulator.emulator_mode().into()).into());
         }
+        DebugMessage::Timer(_) => {
+            response = response_sender.send(DebugMessage::Timer(emulator.timer_state().into()).into());
+        }
+        DebugMessage::Screen(_) => {
+            response = response_sender.send(DebugMessage::Screen(emulator.frame_buffer().to_vec().into()).into());
+        }
+        DebugMessage::SpriteBoxes(_) => {
+            let frame = emulator.render_with_sprite_boxes();
+            response = response_sender.send(DebugMessage::SpriteBoxes(frame.into()).into());
+        }
+        DebugMessage::Banks(_) => {
+            response = response_sender.send(DebugMessage::Banks(emulator.bank_info()).into());
+        }
     }
 
     if let Err(e) = response {
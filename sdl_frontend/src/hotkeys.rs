@@ -0,0 +1,155 @@
+//! Configurable hotkeys for the actions of the frontend, as opposed to the joypad keys.
+//!
+//! Every binding is a combination of SDL key names separated by `+` (e.g, `Left Ctrl+R`), where
+//! the action is triggered once the last key is pressed while all others are held.
+
+use std::collections::HashSet;
+
+use nanoserde::{DeJson, SerJson};
+use sdl2::keyboard::Keycode;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum EmulatorAction {
+    /// Fast forward for as long as the binding is held.
+    FastForward,
+    ToggleFastForward,
+    ToggleUnbounded,
+    TogglePause,
+    /// Advance a single frame, only while paused.
+    FrameStep,
+    Reset,
+    /// Write the current state of the emulator to the save state slot of the game.
+    SaveState,
+    /// Restore the emulator from the save state slot of the game, if any.
+    LoadState,
+    OpenDebugger,
+    ToggleFullscreen,
+    /// Apply the display colours from the config to the running emulator.
+    ReloadDisplayColour,
+}
+
+/// The persisted bindings for every `EmulatorAction`, an empty binding disables the action.
+#[derive(Debug, Clone, SerJson, DeJson)]
+pub struct HotkeyConfig {
+    pub fast_forward: String,
+    pub toggle_fast_forward: String,
+    pub toggle_unbounded: String,
+    pub toggle_pause: String,
+    pub frame_step: String,
+    pub reset: String,
+    pub save_state: String,
+    pub load_state: String,
+    pub open_debugger: String,
+    pub toggle_fullscreen: String,
+    pub reload_display_colour: String,
+}
+
+impl Default for HotkeyConfig {
+    fn default() -> Self {
+        HotkeyConfig {
+            fast_forward: "Left Shift".to_string(),
+            toggle_fast_forward: "Left Ctrl+Left Shift".to_string(),
+            toggle_unbounded: "U".to_string(),
+            toggle_pause: "P".to_string(),
+            frame_step: "N".to_string(),
+            reset: "Left Ctrl+R".to_string(),
+            save_state: "F5".to_string(),
+            load_state: "F8".to_string(),
+            open_debugger: "K".to_string(),
+            toggle_fullscreen: "F11".to_string(),
+            reload_display_colour: "R".to_string(),
+        }
+    }
+}
+
+pub struct Hotkeys {
+    bindings: Vec<(Vec<Keycode>, EmulatorAction)>,
+    held_keys: HashSet<Keycode>,
+    /// The bindings (as indices into `bindings`) which were triggered and haven't been broken
+    /// since, so that releasing a key only ends the actions which were actually started.
+    active_bindings: Vec<usize>,
+}
+
+impl Hotkeys {
+    /// Parse all bindings in the `config`, invalid bindings are logged and ignored.
+    pub fn new(config: &HotkeyConfig) -> Self {
+        use EmulatorAction::*;
+        let config_bindings = [
+            (&config.fast_forward, FastForward),
+            (&config.toggle_fast_forward, ToggleFastForward),
+            (&config.toggle_unbounded, ToggleUnbounded),
+            (&config.toggle_pause, TogglePause),
+            (&config.frame_step, FrameStep),
+            (&config.reset, Reset),
+            (&config.save_state, SaveState),
+            (&config.load_state, LoadState),
+            (&config.open_debugger, OpenDebugger),
+            (&config.toggle_fullscreen, ToggleFullscreen),
+            (&config.reload_display_colour, ReloadDisplayColour),
+        ];
+
+        let mut bindings: Vec<_> = config_bindings
+            .iter()
+            .filter(|(combo, _)| !combo.trim().is_empty())
+            .filter_map(|&(combo, action)| match parse_combo(combo) {
+                Some(keys) => Some((keys, action)),
+                None => {
+                    log::warn!("Ignoring invalid hotkey: {} for {:?}", combo, action);
+                    None
+                }
+            })
+            .collect();
+        // The most specific combo should win, so that `Left Ctrl+R` doesn't also trigger `R`.
+        bindings.sort_by_key(|(keys, _)| std::cmp::Reverse(keys.len()));
+
+        Hotkeys {
+            bindings,
+            held_keys: HashSet::new(),
+            active_bindings: Vec::new(),
+        }
+    }
+
+    /// Should be called for every key press, returns the action whose combo was just completed.
+    ///
+    /// Repeated presses of an already held key are ignored.
+    pub fn key_down(&mut self, key: Keycode) -> Option<EmulatorAction> {
+        if !self.held_keys.insert(key) {
+            return None;
+        }
+        let held_keys = &self.held_keys;
+
+        let index = self
+            .bindings
+            .iter()
+            .position(|(keys, _)| keys.last() == Some(&key) && keys.iter().all(|k| held_keys.contains(k)))?;
+        self.active_bindings.push(index);
+
+        Some(self.bindings[index].1)
+    }
+
+    /// Should be called for every key release, returns all actions whose combo was triggered by
+    /// `key_down()` and was just broken, for actions like `FastForward` which last while held.
+    ///
+    /// A combo which was shadowed by a longer one (e.g, `Left Shift` by `Left Ctrl+Left Shift`)
+    /// was never triggered, and is therefore not returned either.
+    pub fn key_up(&mut self, key: Keycode) -> Vec<EmulatorAction> {
+        let bindings = &self.bindings;
+        let mut result = Vec::new();
+
+        self.active_bindings.retain(|&index| {
+            let (keys, action) = &bindings[index];
+            let broken = keys.contains(&key);
+            if broken {
+                result.push(*action);
+            }
+            !broken
+        });
+
+        self.held_keys.remove(&key);
+        result
+    }
+}
+
+fn parse_combo(combo: &str) -> Option<Vec<Keycode>> {
+    combo.split('+').map(|name| Keycode::from_name(name.trim())).collect()
+}
@@ -0,0 +1,201 @@
+//! A link cable between two emulator instances over TCP, e.g. for two player Tetris.
+//!
+//! Either side can drive the clock. Whenever the local game starts a transfer with its internal
+//! clock the byte is sent to the other side, which answers with the byte its game shifted out in
+//! exchange. Every message is two bytes: its kind and the transferred byte.
+//!
+//! Should the other side not answer, the game receives `0xFF` like with a disconnected cable, and
+//! the failure is returned by [run_to_vblank_linked].
+
+use std::fmt;
+use std::io;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crossbeam::channel::*;
+use crossbeam::select;
+use rustyboi_core::gb_emu::GameBoyEmulator;
+use rustyboi_core::hardware::cpu::HaltReason;
+use rustyboi_core::SerialLink;
+
+use crate::options::AppOptions;
+
+/// The amount of cycles between checks for transfers driven by the other side, which is blocked
+/// until we answer.
+pub const POLL_INTERVAL: u64 = 456;
+/// How long to wait for the other side to answer a transfer before assuming it's gone.
+const REPLY_TIMEOUT: Duration = Duration::from_millis(100);
+
+const TRANSFER: u8 = 0;
+const REPLY: u8 = 1;
+
+pub type SharedLink = Arc<Mutex<TcpSerialLink>>;
+
+/// Why a transfer driven by the local game didn't get an answer.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LinkError {
+    /// The other side didn't answer within `REPLY_TIMEOUT`.
+    Timeout,
+    Disconnected,
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkError::Timeout => write!(f, "The other side of the link cable didn't answer in time"),
+            LinkError::Disconnected => write!(f, "The link cable was disconnected"),
+        }
+    }
+}
+
+impl std::error::Error for LinkError {}
+
+pub struct TcpSerialLink {
+    stream: TcpStream,
+    transfers: Receiver<u8>,
+    replies: Receiver<u8>,
+    /// The first failed transfer since the last `take_error()`.
+    error: Option<LinkError>,
+}
+
+impl TcpSerialLink {
+    /// Wait for the other side to connect to the provided `address`.
+    pub fn host(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        log::info!("Waiting for the other side of the link cable on: {}", listener.local_addr()?);
+        let (stream, peer) = listener.accept()?;
+        log::info!("Link cable connected to: {}", peer);
+
+        Self::new(stream)
+    }
+
+    /// Connect to the other side, which should already be hosting on the provided `address`.
+    pub fn connect(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        log::info!("Link cable connected to: {}", stream.peer_addr()?);
+
+        Self::new(stream)
+    }
+
+    fn new(stream: TcpStream) -> io::Result<Self> {
+        // Every exchange blocks the emulator driving the clock, so we can't wait for more data.
+        stream.set_nodelay(true)?;
+        let mut reader = stream.try_clone()?;
+        let (transfer_sender, transfers) = unbounded();
+        let (reply_sender, replies) = unbounded();
+
+        std::thread::spawn(move || {
+            let mut message = [0; 2];
+
+            while reader.read_exact(&mut message).is_ok() {
+                let sender = if message[0] == TRANSFER { &transfer_sender } else { &reply_sender };
+                if sender.send(message[1]).is_err() {
+                    return;
+                }
+            }
+
+            log::warn!("The link cable was disconnected");
+        });
+
+        Ok(TcpSerialLink {
+            stream,
+            transfers,
+            replies,
+            error: None,
+        })
+    }
+
+    /// Answer every transfer the other side drove since the last call.
+    pub fn poll(&mut self, emulator: &mut GameBoyEmulator) {
+        while let Ok(received) = self.transfers.try_recv() {
+            let sent = emulator.external_serial_transfer(received);
+            self.send(REPLY, sent);
+        }
+    }
+
+    /// Returns the first transfer which failed since the last call, if any.
+    pub fn take_error(&mut self) -> Option<LinkError> {
+        self.error.take()
+    }
+
+    fn send(&mut self, kind: u8, value: u8) {
+        if let Err(e) = self.stream.write_all(&[kind, value]) {
+            log::warn!("Failed to send over the link cable due to: {}", e);
+        }
+    }
+
+    /// Remember the `error` for `take_error()`, and answer the game like a disconnected cable.
+    fn fail(&mut self, error: LinkError) -> u8 {
+        self.error.get_or_insert(error);
+        0xFF
+    }
+}
+
+impl SerialLink for TcpSerialLink {
+    fn transfer(&mut self, out: u8) -> u8 {
+        // Replies which arrived after we stopped waiting would otherwise answer this transfer.
+        while self.replies.try_recv().is_ok() {}
+        self.send(TRANSFER, out);
+
+        loop {
+            select! {
+                recv(self.replies) -> reply => match reply {
+                    Ok(reply) => return reply,
+                    Err(_) => return self.fail(LinkError::Disconnected),
+                },
+                recv(self.transfers) -> transfer => match transfer {
+                    // Both sides drive the clock, so neither is listening to the other.
+                    Ok(_) => self.send(REPLY, 0xFF),
+                    Err(_) => return self.fail(LinkError::Disconnected),
+                },
+                default(REPLY_TIMEOUT) => return self.fail(LinkError::Timeout),
+            }
+        }
+    }
+}
+
+/// Set up the link cable requested by the command line `options`, if any.
+///
+/// Hosting blocks until the other side connects.
+pub fn link_from_options(options: &AppOptions) -> Option<SharedLink> {
+    let link = if let Some(address) = &options.link_host {
+        TcpSerialLink::host(address)
+    } else if let Some(address) = &options.link_connect {
+        TcpSerialLink::connect(address)
+    } else {
+        return None;
+    };
+
+    match link {
+        Ok(link) => Some(Arc::new(Mutex::new(link))),
+        Err(e) => {
+            log::error!("Failed to set up the link cable due to: {}", e);
+            None
+        }
+    }
+}
+
+/// Run the `emulator` to the next V-blank like `run_to_vblank()`, while answering the transfers
+/// driven by the other side of the `link` in between.
+///
+/// The frame is always completed, but if any transfer driven by the local game failed in the
+/// meantime the first failure is returned.
+pub fn run_to_vblank_linked(emulator: &mut GameBoyEmulator, link: &SharedLink) -> Result<(), LinkError> {
+    let mut next_poll = 0;
+
+    loop {
+        let (vblank, _) = emulator.emulate_cycle();
+
+        if emulator.cycles_performed() >= next_poll {
+            link.lock().unwrap().poll(emulator);
+            next_poll = emulator.cycles_performed() + POLL_INTERVAL;
+        }
+
+        // Nothing advances in STOP mode, so there's no V-blank to wait for.
+        if vblank || emulator.halt_reason() == Some(HaltReason::Stopped) {
+            return link.lock().unwrap().take_error().map_or(Ok(()), Err);
+        }
+    }
+}
@@ -18,10 +18,13 @@ use data::storage::{FileStorage, Storage};
 use options::AppOptions;
 use rustyboi_core::{EmulatorOptionsBuilder, InputKey};
 use rustyboi_core::gb_emu::GameBoyModel::{CGB, DMG};
-use rustyboi_core::hardware::ppu::FRAMEBUFFER_SIZE;
+use rustyboi_core::gb_emu::FRAMES_PER_SECOND;
+use rustyboi_core::hardware::ppu::{FRAMEBUFFER_SIZE, RESOLUTION_HEIGHT, RESOLUTION_WIDTH};
 use rustyboi_core::hardware::ppu::palette::{DisplayColour, RGB};
+use rustyboi_core::osd;
 
-use crate::gameboy::GameboyRunner;
+use crate::gameboy::{FastForward, FrameBudget, GameboyRunner};
+use crate::hotkeys::{EmulatorAction, HotkeyConfig, Hotkeys};
 use crate::rendering::imgui::ImguiBoi;
 use crate::rendering::immediate::ImmediateGui;
 use crate::rendering::Renderer;
@@ -33,6 +36,8 @@ mod rendering;
 mod actions;
 mod data;
 mod options;
+mod hotkeys;
+mod link;
 
 const KIRBY_DISPLAY_COLOURS: DisplayColour = DisplayColour {
     black: RGB(44, 44, 150),
@@ -42,9 +47,12 @@ const KIRBY_DISPLAY_COLOURS: DisplayColour = DisplayColour {
 };
 
 const CONFIG_FILENAME: &str = "config.json";
+const HOTKEYS_FILENAME: &str = "hotkeys.json";
 const FPS: u64 = 60;
 const FRAME_DELAY: Duration = Duration::from_nanos(1_000_000_000u64 / FPS);
 const FAST_FORWARD_MULTIPLIER: u32 = 40;
+/// The maximum amount of frames we'll run in one go to catch up after the host stalled.
+const DEFAULT_MAX_CATCH_UP_FRAMES: u64 = 4;
 // 0.25*44100*4 = 250 ms of delay at worst, average ~100 ms
 const MAX_AUDIO_SAMPLES: u32 = 44100;
 const MIN_AUDIO_SAMPLES: u32 = 12000;
@@ -52,7 +60,12 @@ const AUDIO_FREQUENCY: i32 = 44100;
 
 static GLOBAL_APP_STATE: Lazy<Mutex<AppState>> = Lazy::new(|| {
     let file_storage = FileStorage::new().unwrap();
-    Mutex::new(file_storage.get_value(CONFIG_FILENAME).unwrap_or_default())
+    let mut app_state: AppState = file_storage.get_value(CONFIG_FILENAME).unwrap_or_default();
+    // Configs from before the setting existed leave it at 0.
+    if app_state.max_catch_up_frames == 0 {
+        app_state.max_catch_up_frames = DEFAULT_MAX_CATCH_UP_FRAMES;
+    }
+    Mutex::new(app_state)
 });
 
 fn main() {
@@ -91,9 +104,11 @@ fn main() {
         //.with_boot_rom(Some(_bootrom_file_dmg))
         .with_mode(DMG)
         .with_display_colour(KIRBY_DISPLAY_COLOURS)
+        .with_sample_rate(AUDIO_FREQUENCY as u32)
         .build();
 
-    let mut gameboy_runner = GameboyRunner::new(_cpu_test, emu_opts);
+    let serial_link = link::link_from_options(&options);
+    let mut gameboy_runner = GameboyRunner::new(_cpu_test, emu_opts, serial_link);
 
     let mut audio_player = AudioPlayer::new(&audio_subsystem, Duration::from_millis(100));
 
@@ -103,8 +118,13 @@ fn main() {
 
     let mut last_update_time: Instant = Instant::now();
 
+    let mut frame_budget = FrameBudget::new(FRAME_DELAY, DEFAULT_MAX_CATCH_UP_FRAMES);
+
     let mut emulation_state = AppEmulatorState::default();
 
+    let hotkey_config: HotkeyConfig = file_storage.get_value(HOTKEYS_FILENAME).unwrap_or_default();
+    let mut hotkeys = Hotkeys::new(&hotkey_config);
+
     let mut most_recent_frame: [RGB; FRAMEBUFFER_SIZE] = [RGB::default(); FRAMEBUFFER_SIZE];
 
     if !GLOBAL_APP_STATE.lock().unwrap().audio_mute {
@@ -123,6 +143,19 @@ fn main() {
             }
         }
 
+        if let Some(gui) = renderer.immediate_gui.as_mut() {
+            for notification in gui.take_notifications() {
+                // The pause state is owned by us, so that the hotkeys and the GUI stay in sync.
+                match notification {
+                    EmulatorNotification::Pause(paused) => emulation_state.emulator_paused = paused,
+                    EmulatorNotification::FrameStep => emulation_state.frame_step = emulation_state.emulator_paused,
+                    notification => {
+                        gameboy_runner.request_sender.send(notification);
+                    }
+                }
+            }
+        }
+
         let ticks = timer.ticks() as i32;
 
         for event in event_pump.poll_iter() {
@@ -132,29 +165,66 @@ fn main() {
                 &mut audio_player,
                 &mut emulation_state,
                 &mut renderer,
+                &mut hotkeys,
             ) {
                 break 'mainloop;
             }
         }
 
-        let frames_to_go = if emulation_state.fast_forward {
-            GLOBAL_APP_STATE
-                .lock()
-                .expect("Failed to lock in fast forward")
-                .fast_forward_rate
-        } else {
-            1
-        };
-
-        // I should really figure out proper audio syncing ._.
-        if emulation_state.unbounded || emulation_state.fast_forward || !audio_player.has_too_many_samples() {
-            for _ in 0..frames_to_go {
-                if !emulation_state.emulator_paused {
-                    most_recent_frame = gameboy_runner.frame_receiver.recv().unwrap();
-                }
-                renderer.render_main_window(&most_recent_frame);
+        {
+            let app_state = GLOBAL_APP_STATE.lock().expect("Failed to lock in fast forward");
+            frame_budget.set_max_frames(app_state.max_catch_up_frames);
+
+            let fast_forward = if emulation_state.fast_forward {
+                Some(FastForward::new(
+                    app_state.fast_forward_rate,
+                    app_state.preserve_turbo_pitch,
+                    app_state.skip_fast_forward_audio,
+                ))
+            } else {
+                None
+            };
+            gameboy_runner.set_fast_forward(fast_forward);
+        }
+
+        gameboy_runner.set_paused(emulation_state.emulator_paused);
+        if let Some(gui) = renderer.immediate_gui.as_mut() {
+            gui.set_paused(emulation_state.emulator_paused);
+        }
+
+        if emulation_state.emulator_paused {
+            // We don't want to catch up on all the time we spent paused.
+            frame_budget.reset();
+
+            if std::mem::take(&mut emulation_state.frame_step) {
+                gameboy_runner.frame_step();
+                loop_cycles += 1;
             }
-            loop_cycles += frames_to_go;
+            // Either the stepped frame, or one which was already underway when we paused.
+            while let Some(frame) = gameboy_runner.try_recv_frame() {
+                most_recent_frame = frame;
+            }
+
+            let mut paused_frame = most_recent_frame;
+            osd::draw_text(
+                &mut paused_frame,
+                RESOLUTION_WIDTH,
+                RESOLUTION_HEIGHT,
+                2,
+                2,
+                "PAUSED",
+                KIRBY_DISPLAY_COLOURS.black,
+            );
+            renderer.render_main_window(&paused_frame);
+        } else if emulation_state.unbounded || emulation_state.fast_forward || !audio_player.has_too_many_samples() {
+            // I should really figure out proper audio syncing ._.
+            loop_cycles += gameboy_runner.run_with_budget(&mut frame_budget, |frame| {
+                most_recent_frame = *frame;
+                renderer.render_main_window(frame);
+            });
+        } else {
+            // We're ahead of the audio, so we certainly don't owe any frames.
+            frame_budget.reset();
         }
 
         while let Ok(response) = gameboy_runner.response_receiver.try_recv() {
@@ -178,7 +248,7 @@ fn main() {
             renderer.main_window.window_mut().set_title(
                 format!(
                     "RustyBoi - [{:.2} FPS | {}%]",
-                    frames_rendered, (frames_rendered / 59.725 * 100.0) as usize
+                    frames_rendered, (frames_rendered / FRAMES_PER_SECOND * 100.0) as usize
                 )
                 .as_str(),
             );
@@ -198,6 +268,8 @@ fn main() {
     }
 
     file_storage.save_value(CONFIG_FILENAME, GLOBAL_APP_STATE.lock().unwrap().deref());
+    // Saved so that there's a file with the defaults to edit.
+    file_storage.save_value(HOTKEYS_FILENAME, &hotkey_config);
 }
 
 fn handle_events(
@@ -206,6 +278,7 @@ fn handle_events(
     audio_player: &mut AudioPlayer,
     app_state: &mut AppEmulatorState,
     renderer: &mut Renderer<ImguiBoi>,
+    hotkeys: &mut Hotkeys,
 ) -> bool {
     if handle_debug_window_events(&event, renderer) {
         return true;
@@ -239,7 +312,11 @@ fn handle_events(
             renderer.main_window.window_mut().raise();
         }
         Event::DropFile { filename, .. } => {
-            if filename.ends_with(".gb") || filename.ends_with(".gbc") {
+            if !(filename.ends_with(".gb") || filename.ends_with(".gbc")) {
+                warn!("Attempted opening of file: {} which is not a GameBoy rom!", filename);
+            } else if !actions::is_supported_rom(&filename) {
+                warn!("Attempted opening of file: {} which uses an unsupported cartridge type!", filename);
+            } else {
                 debug!("Opening file: {}", filename);
 
                 app_state.reset();
@@ -251,10 +328,12 @@ fn handle_events(
                     .with_bg_display_colour(options.custom_display_colour.dmg_bg_colour.into())
                     .with_sp0_display_colour(options.custom_display_colour.dmg_sprite_colour_0.into())
                     .with_sp1_display_colour(options.custom_display_colour.dmg_sprite_colour_1.into())
+                    .with_sample_rate(AUDIO_FREQUENCY as u32)
                     .build();
-                *gameboy_runner = GameboyRunner::new(&filename, emu_opts);
-            } else {
-                warn!("Attempted opening of file: {} which is not a GameBoy rom!", filename);
+                *gameboy_runner = GameboyRunner::new(&filename, emu_opts, gameboy_runner.serial_link());
+                if let Some(gui) = renderer.immediate_gui.as_mut() {
+                    gui.emulator_replaced();
+                }
             }
         }
         Event::KeyDown {
@@ -262,42 +341,10 @@ fn handle_events(
             window_id: 1,
             ..
         } => {
-            if let Some(input_key) = keycode_to_input(key) {
+            if let Some(action) = hotkeys.key_down(key) {
+                handle_action(action, gameboy_runner, app_state, renderer);
+            } else if let Some(input_key) = keycode_to_input(key) {
                 gameboy_runner.handle_input(input_key, true);
-            } else {
-                match key {
-                    Keycode::LShift => app_state.fast_forward = true,
-                    Keycode::U => app_state.unbounded = !app_state.unbounded,
-                    Keycode::P => app_state.emulator_paused = !app_state.emulator_paused,
-                    Keycode::K => renderer.setup_immediate_gui("Rustyboi Debugging").unwrap(),
-                    Keycode::F11 => renderer.toggle_main_window_fullscreen(),
-                    Keycode::R => {
-                        //TODO: Remove once we have UI interaction.
-                        gameboy_runner
-                            .request_sender
-                            .send(EmulatorNotification::ChangeDisplayColour(
-                                GLOBAL_APP_STATE.lock().unwrap().custom_display_colour,
-                            ));
-                    }
-                    // Keycode::O => println!("{:#?}", notifier.oam()),
-                    // Keycode::L => {
-                    //     let mut true_image_buffer = vec![0u8; 768*8*8*3];
-                    //
-                    //     for (i, colour) in notifier.vram_tiles().iter().enumerate() {
-                    //         let offset = i * 3;
-                    //         true_image_buffer[offset] = colour.0;
-                    //         true_image_buffer[offset + 1] = colour.1;
-                    //         true_image_buffer[offset + 2] = colour.2;
-                    //     }
-                    //     let temp_buffer: ImageBuffer<image::Rgb<u8>, Vec<u8>> =
-                    //         image::ImageBuffer::from_raw(128, 384, true_image_buffer).unwrap();
-                    //     let temp_buffer = image::imageops::resize(&temp_buffer, 256, 768, FilterType::Nearest);
-                    //     temp_buffer
-                    //         .save(format!("vram_dump.png"))
-                    //         .unwrap();
-                    // }
-                    _ => {}
-                }
             }
         }
         Event::KeyUp {
@@ -305,13 +352,11 @@ fn handle_events(
             window_id: 1,
             ..
         } => {
+            if hotkeys.key_up(key).contains(&EmulatorAction::FastForward) {
+                app_state.fast_forward = false;
+            }
             if let Some(input_key) = keycode_to_input(key) {
                 gameboy_runner.handle_input(input_key, false);
-            } else {
-                match key {
-                    Keycode::LShift => app_state.fast_forward = false,
-                    _ => {}
-                }
             }
         }
         _ => {}
@@ -320,6 +365,40 @@ fn handle_events(
     true
 }
 
+fn handle_action(
+    action: EmulatorAction,
+    gameboy_runner: &mut GameboyRunner,
+    app_state: &mut AppEmulatorState,
+    renderer: &mut Renderer<ImguiBoi>,
+) {
+    match action {
+        EmulatorAction::FastForward => app_state.fast_forward = true,
+        EmulatorAction::ToggleFastForward => app_state.fast_forward = !app_state.fast_forward,
+        EmulatorAction::ToggleUnbounded => app_state.unbounded = !app_state.unbounded,
+        EmulatorAction::TogglePause => app_state.emulator_paused = !app_state.emulator_paused,
+        EmulatorAction::FrameStep => app_state.frame_step = app_state.emulator_paused,
+        EmulatorAction::Reset => {
+            gameboy_runner.request_sender.send(EmulatorNotification::Reset);
+        }
+        EmulatorAction::SaveState => {
+            gameboy_runner.request_sender.send(EmulatorNotification::SaveState);
+        }
+        EmulatorAction::LoadState => {
+            gameboy_runner.request_sender.send(EmulatorNotification::LoadState);
+        }
+        EmulatorAction::OpenDebugger => renderer.setup_immediate_gui("Rustyboi Debugging").unwrap(),
+        EmulatorAction::ToggleFullscreen => renderer.toggle_main_window_fullscreen(),
+        EmulatorAction::ReloadDisplayColour => {
+            //TODO: Remove once we have UI interaction.
+            gameboy_runner
+                .request_sender
+                .send(EmulatorNotification::ChangeDisplayColour(
+                    GLOBAL_APP_STATE.lock().unwrap().custom_display_colour,
+                ));
+        }
+    }
+}
+
 fn handle_debug_window_events(event: &Event, renderer: &mut Renderer<ImguiBoi>) -> bool {
     if let Some(gui) = &mut renderer.immediate_gui {
         let second_window_id = renderer.debug_window.as_ref().unwrap().id();
@@ -17,4 +17,10 @@ pub struct AppOptions {
     /// If provided will run a benchmark on the provided rom, and then exit.
     #[options()]
     pub benchmark: bool,
+    /// Host a link cable on the provided address (e.g, 0.0.0.0:5000), waits for the other side
+    #[options(no_short)]
+    pub link_host: Option<String>,
+    /// Connect a link cable to the other side hosting on the provided address
+    #[options(no_short)]
+    pub link_connect: Option<String>,
 }
@@ -2,12 +2,24 @@ use imgui::*;
 use sdl2::keyboard::Scancode;
 
 use rustyboi_core::hardware::ppu::palette::RGB;
+use rustyboi_core::hardware::ppu::{RESOLUTION_HEIGHT, RESOLUTION_WIDTH};
+
+use crate::rendering::imgui::screen::ScreenTexture;
 
 use crate::rendering::imgui::state::{DebugState, GuiState, Notification};
 use std::time::Duration;
 
-pub fn create_main_menu_bar(state: &mut GuiState, ui: &Ui) {
+pub fn create_main_menu_bar(state: &mut GuiState, ui: &Ui, debug_state: &mut DebugState) {
     ui.main_menu_bar(|| {
+        ui.menu(im_str!("Emulation"), true, || {
+            let mut paused = debug_state.paused;
+            if MenuItem::new(im_str!("Pause")).build_with_ref(ui, &mut paused) {
+                debug_state.requested_pause = Some(paused);
+            }
+            if MenuItem::new(im_str!("Step Frame")).enabled(debug_state.paused).build(ui) {
+                debug_state.requested_frame_step = true;
+            }
+        });
         ui.menu(im_str!("Debug"), true, || {
             if MenuItem::new(im_str!("ImGui Metrics")).build_with_ref(ui, &mut state.show_metrics) {}
             MenuItem::new(im_str!("Settings"))
@@ -21,6 +33,14 @@ pub fn create_main_menu_bar(state: &mut GuiState, ui: &Ui) {
             MenuItem::new(im_str!("Palette View"))
                 .shortcut(im_str!("Ctrl+P"))
                 .build_with_ref(ui, &mut state.palette_window);
+            MenuItem::new(im_str!("Timer View"))
+                .shortcut(im_str!("Ctrl+T"))
+                .build_with_ref(ui, &mut state.timer_window);
+            MenuItem::new(im_str!("Bank View")).build_with_ref(ui, &mut state.banks_window);
+            MenuItem::new(im_str!("Audio View")).build_with_ref(ui, &mut state.audio_window);
+            MenuItem::new(im_str!("Screen View"))
+                .shortcut(im_str!("Ctrl+G"))
+                .build_with_ref(ui, &mut state.screen_window);
             MenuItem::new(im_str!("VRAM View"))
                 .shortcut(im_str!("Ctrl+Q"))
                 .build_with_ref(ui, &mut state.tile_display);
@@ -40,6 +60,12 @@ pub fn main_menu_shortcuts(state: &mut GuiState, ui: &Ui) {
     if ui.io().key_ctrl && ui.is_key_pressed(Scancode::P as u32) {
         state.palette_window = !state.palette_window;
     }
+    if ui.io().key_ctrl && ui.is_key_pressed(Scancode::T as u32) {
+        state.timer_window = !state.timer_window;
+    }
+    if ui.io().key_ctrl && ui.is_key_pressed(Scancode::G as u32) {
+        state.screen_window = !state.screen_window;
+    }
     if ui.io().key_ctrl && ui.is_key_pressed(Scancode::Q as u32) {
         state.tile_display = !state.tile_display;
     }
@@ -117,6 +143,102 @@ pub fn render_palette_view(state: &mut GuiState, ui: &Ui, debug_state: &mut Debu
     }
 }
 
+pub fn render_timer_view(state: &mut GuiState, ui: &Ui, debug_state: &DebugState) {
+    if state.timer_window {
+        let timer = &debug_state.timer;
+        Window::new(im_str!("Timer View"))
+            .size(size_a(ui, [12.0, 9.0]), Condition::Appearing)
+            .opened(&mut state.timer_window)
+            .build(ui, || {
+                ui.text(format!("DIV:  {:#04X}", timer.div));
+                ui.text(format!("TIMA: {:#04X}", timer.tima));
+                ui.text(format!("TMA:  {:#04X}", timer.tma));
+                ui.separator();
+                ui.text(format!("Enabled: {}", timer.enabled));
+                ui.text(format!("Frequency: {} Hz", timer.frequency));
+            });
+    }
+}
+
+pub fn render_banks_view(state: &mut GuiState, ui: &Ui, debug_state: &DebugState) {
+    if state.banks_window {
+        Window::new(im_str!("Bank View"))
+            .size(size_a(ui, [14.0, 5.0]), Condition::Appearing)
+            .opened(&mut state.banks_window)
+            .build(ui, || match &debug_state.banks {
+                Some(banks) => {
+                    ui.text(format!("ROM bank {}/{}", banks.current_rom_bank, banks.rom_banks));
+                    ui.text(format!("RAM bank {}/{}", banks.current_ram_bank, banks.ram_banks));
+                }
+                None => ui.text("No cartridge loaded"),
+            });
+    }
+}
+
+pub fn render_audio_view(state: &mut GuiState, ui: &Ui, debug_state: &mut DebugState) {
+    if state.audio_window {
+        let channel_masks = &mut debug_state.channel_masks;
+        Window::new(im_str!("Audio View"))
+            .size(size_a(ui, [12.0, 9.0]), Condition::Appearing)
+            .opened(&mut state.audio_window)
+            .build(ui, || {
+                let mut solo = None;
+
+                for channel in 0..channel_masks.len() {
+                    let mut enabled = !channel_masks[channel];
+                    if ui.checkbox(&im_str!("Voice {}", channel + 1), &mut enabled) {
+                        channel_masks[channel] = !enabled;
+                    }
+                    ui.same_line(0.0);
+                    if ui.small_button(&im_str!("Solo##{}", channel)) {
+                        solo = Some(channel);
+                    }
+                }
+
+                if let Some(solo) = solo {
+                    for (channel, masked) in channel_masks.iter_mut().enumerate() {
+                        *masked = channel != solo;
+                    }
+                }
+
+                if ui.small_button(im_str!("Unmute all")) {
+                    *channel_masks = [false; 4];
+                }
+            });
+    }
+}
+
+pub fn render_screen_view(state: &mut GuiState, ui: &Ui, screen_texture: Option<&ScreenTexture>) {
+    if state.screen_window {
+        let sprite_boxes = &mut state.sprite_boxes;
+        Window::new(im_str!("Screen View"))
+            .size(size_a(ui, [22.0, 20.0]), Condition::Appearing)
+            .opened(&mut state.screen_window)
+            .build(ui, || {
+                ui.checkbox(im_str!("Sprite boxes"), sprite_boxes);
+
+                if let Some(texture) = screen_texture {
+                    // Scale by an integer factor where possible, to keep the pixels sharp.
+                    let available = ui.content_region_avail();
+                    let max_scale =
+                        (available[0] / RESOLUTION_WIDTH as f32).min(available[1] / RESOLUTION_HEIGHT as f32);
+                    let scale = if max_scale >= 1.0 { max_scale.floor() } else { max_scale.max(0.1) };
+                    Image::new(
+                        texture.texture_id(),
+                        [RESOLUTION_WIDTH as f32 * scale, RESOLUTION_HEIGHT as f32 * scale],
+                    )
+                    .build(ui);
+
+                    if texture.divisor() > 1 {
+                        ui.text(format!("Reduced to 1/{} resolution to keep up", texture.divisor()));
+                    }
+                } else {
+                    ui.text("Waiting for the next frame...");
+                }
+            });
+    }
+}
+
 #[inline(always)]
 fn show_palettes_column(ui: &Ui, notification: &mut Notification, palettes: &Vec<[RGB; 4]>, name_prefix: &str) {
     //TODO: Figure out why we get a stack overflow if we don't inline this?!
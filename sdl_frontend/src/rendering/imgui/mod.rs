@@ -10,8 +10,9 @@ use sdl2::VideoSubsystem;
 use font::COUSINE_REGULAR_UNCOMPRESSED_DATA;
 use crate::data::storage::{FileStorage, Storage};
 
-use crate::data::communication::DebugMessage;
+use crate::data::communication::{DebugMessage, EmulatorNotification};
 use crate::rendering::imgui::interface::*;
+use crate::rendering::imgui::screen::{DynamicResolution, ScreenTexture};
 use crate::rendering::imgui::settings::render_settings;
 use crate::rendering::imgui::state::{DebugState, GuiState};
 use crate::rendering::immediate::ImmediateGui;
@@ -19,6 +20,7 @@ use crate::rendering::immediate::ImmediateGui;
 mod animate;
 mod font;
 mod interface;
+mod screen;
 mod settings;
 mod state;
 
@@ -33,6 +35,11 @@ pub struct ImguiBoi {
     pub input_handler: ImguiSdl2,
     gui_state: GuiState,
     debug_state: DebugState,
+    /// Lazily created once the first frame for the screen view arrives.
+    screen_texture: Option<ScreenTexture>,
+    screen_resolution: DynamicResolution,
+    /// The voice masks the emulator was last notified of, see `take_notifications()`.
+    sent_channel_masks: [bool; 4],
     storage: Arc<FileStorage>,
 }
 
@@ -59,6 +66,9 @@ impl ImguiBoi {
             input_handler,
             gui_state: state,
             debug_state: DebugState::default(),
+            screen_texture: None,
+            screen_resolution: DynamicResolution::new(),
+            sent_channel_masks: [false; 4],
             storage,
         }
     }
@@ -89,6 +99,22 @@ impl ImmediateGui for ImguiBoi {
             result.push(Palette(None));
         }
 
+        if self.gui_state.timer_window {
+            result.push(Timer(None));
+        }
+
+        if self.gui_state.banks_window {
+            result.push(Banks(None));
+        }
+
+        if self.gui_state.screen_window {
+            if self.gui_state.sprite_boxes {
+                result.push(SpriteBoxes(None));
+            } else {
+                result.push(Screen(None));
+            }
+        }
+
         Some(result)
     }
 
@@ -96,23 +122,63 @@ impl ImmediateGui for ImguiBoi {
         match debug_response {
             DebugMessage::Palette(info) => self.debug_state.palette = info.unwrap_or_default(),
             DebugMessage::Mode(mode) => self.debug_state.current_emu_mode = mode.unwrap(),
+            DebugMessage::Timer(timer) => self.debug_state.timer = timer.unwrap_or_default(),
+            DebugMessage::Screen(frame) | DebugMessage::SpriteBoxes(frame) => self.debug_state.screen = frame,
+            DebugMessage::Banks(banks) => self.debug_state.banks = banks,
+        }
+    }
+
+    fn take_notifications(&mut self) -> Vec<EmulatorNotification> {
+        let masks = self.debug_state.channel_masks;
+        let mut notifications: Vec<EmulatorNotification> = (0..masks.len())
+            .filter(|&channel| masks[channel] != self.sent_channel_masks[channel])
+            .map(|channel| EmulatorNotification::MaskAudioChannel(channel, masks[channel]))
+            .collect();
+
+        if let Some(paused) = self.debug_state.requested_pause.take() {
+            notifications.push(EmulatorNotification::Pause(paused));
+        }
+        if std::mem::take(&mut self.debug_state.requested_frame_step) {
+            notifications.push(EmulatorNotification::FrameStep);
         }
+
+        self.sent_channel_masks = masks;
+        notifications
+    }
+
+    fn emulator_replaced(&mut self) {
+        // A new emulator starts with all voices enabled, so any muted voice has to be sent again.
+        self.sent_channel_masks = [false; 4];
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        self.debug_state.paused = paused;
     }
 
     fn prepare_render(&mut self, delta_time: f32, host_window: &sdl2::video::Window, mouse_state: &MouseState) {
         self.input_handler.prepare_frame(self.imgui_context.io_mut(), host_window, mouse_state);
         self.imgui_context.io_mut().delta_time = delta_time;
+        self.screen_resolution.update(delta_time);
     }
 
     fn render(&mut self, host_window: &sdl2::video::Window) {
+        if let Some(frame) = self.debug_state.screen.take() {
+            let divisor = self.screen_resolution.divisor();
+            self.screen_texture.get_or_insert_with(ScreenTexture::new).update(&frame, divisor);
+        }
+
         let ui = self.imgui_context.frame();
         ui.show_demo_window(&mut true);
 
         {
-            create_main_menu_bar(&mut self.gui_state, &ui);
+            create_main_menu_bar(&mut self.gui_state, &ui, &mut self.debug_state);
             render_notification(&mut self.debug_state, &ui);
             render_metrics(&mut self.gui_state, &ui);
             render_palette_view(&mut self.gui_state, &ui, &mut self.debug_state);
+            render_timer_view(&mut self.gui_state, &ui, &self.debug_state);
+            render_banks_view(&mut self.gui_state, &ui, &self.debug_state);
+            render_audio_view(&mut self.gui_state, &ui, &mut self.debug_state);
+            render_screen_view(&mut self.gui_state, &ui, self.screen_texture.as_ref());
             render_settings(&mut self.gui_state, &ui, &mut self.debug_state);
         }
 
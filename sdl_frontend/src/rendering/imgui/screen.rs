@@ -0,0 +1,151 @@
+use gl::types::GLuint;
+use imgui::TextureId;
+
+use rustyboi_core::hardware::ppu::palette::RGB;
+use rustyboi_core::hardware::ppu::{FRAMEBUFFER_SIZE, RESOLUTION_HEIGHT, RESOLUTION_WIDTH};
+
+/// The frame time the debug window should stay within, any slower and the screen view's
+/// resolution is lowered.
+const TARGET_FRAME_TIME: f32 = 1.0 / 60.0;
+/// The lowest resolution is `1/MAX_DIVISOR` of the native one, which has to divide both sides.
+const MAX_DIVISOR: usize = 4;
+
+/// Picks the resolution of the screen view based on the recent frame times of the debug window,
+/// halving it while the window is too slow and doubling it again once there's headroom.
+#[derive(Debug, Copy, Clone)]
+pub struct DynamicResolution {
+    divisor: usize,
+    average_frame_time: f32,
+}
+
+impl DynamicResolution {
+    pub fn new() -> Self {
+        DynamicResolution {
+            divisor: 1,
+            average_frame_time: TARGET_FRAME_TIME,
+        }
+    }
+
+    /// Should be called every frame with the time it took in seconds.
+    pub fn update(&mut self, delta_time: f32) {
+        // An exponential moving average, so a single hitch doesn't change the resolution.
+        self.average_frame_time = self.average_frame_time * 0.9 + delta_time * 0.1;
+
+        let new_divisor = if self.average_frame_time > TARGET_FRAME_TIME * 1.25 {
+            (self.divisor * 2).min(MAX_DIVISOR)
+        } else if self.average_frame_time < TARGET_FRAME_TIME * 0.75 {
+            (self.divisor / 2).max(1)
+        } else {
+            self.divisor
+        };
+
+        if new_divisor != self.divisor {
+            log::debug!("Changing the screen view resolution to 1/{}", new_divisor);
+            self.divisor = new_divisor;
+            // Give the new resolution a chance to settle before changing it again.
+            self.average_frame_time = TARGET_FRAME_TIME;
+        }
+    }
+
+    /// The factor the native resolution should be divided by.
+    pub fn divisor(&self) -> usize {
+        self.divisor
+    }
+}
+
+/// An OpenGL texture containing the most recent emulator frame, to be displayed in an imgui `Image`.
+///
+/// Requires the debug window's OpenGL context to be current whenever it's used or dropped.
+pub struct ScreenTexture {
+    id: GLuint,
+    /// The native resolution divided by this is the size of the texture.
+    divisor: usize,
+}
+
+impl ScreenTexture {
+    pub fn new() -> Self {
+        let mut id = 0;
+        unsafe {
+            gl::GenTextures(1, &mut id);
+            gl::BindTexture(gl::TEXTURE_2D, id);
+            // We want sharp pixels when scaling up.
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as _);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as _);
+        }
+
+        let mut result = ScreenTexture { id, divisor: 0 };
+        result.allocate(1);
+        result
+    }
+
+    /// Upload the provided `framebuffer` to the texture, at `1/divisor` of the native resolution.
+    pub fn update(&mut self, framebuffer: &[RGB], divisor: usize) {
+        if framebuffer.len() != FRAMEBUFFER_SIZE {
+            log::warn!("Received framebuffer of invalid size: {}", framebuffer.len());
+            return;
+        }
+        if divisor != self.divisor {
+            self.allocate(divisor);
+        }
+
+        let (width, height) = (RESOLUTION_WIDTH / divisor, RESOLUTION_HEIGHT / divisor);
+        let pixels: Vec<RGB> = if divisor == 1 {
+            framebuffer.to_vec()
+        } else {
+            (0..width * height)
+                .map(|i| framebuffer[(i / width) * divisor * RESOLUTION_WIDTH + (i % width) * divisor])
+                .collect()
+        };
+
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, self.id);
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl::TexSubImage2D(
+                gl::TEXTURE_2D,
+                0,
+                0,
+                0,
+                width as _,
+                height as _,
+                gl::RGB,
+                gl::UNSIGNED_BYTE,
+                pixels.as_ptr() as *const _,
+            );
+        }
+    }
+
+    /// The factor the native resolution is currently divided by.
+    pub fn divisor(&self) -> usize {
+        self.divisor
+    }
+
+    fn allocate(&mut self, divisor: usize) {
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, self.id);
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::RGB8 as _,
+                (RESOLUTION_WIDTH / divisor) as _,
+                (RESOLUTION_HEIGHT / divisor) as _,
+                0,
+                gl::RGB,
+                gl::UNSIGNED_BYTE,
+                std::ptr::null(),
+            );
+        }
+        self.divisor = divisor;
+    }
+
+    pub fn texture_id(&self) -> TextureId {
+        TextureId::from(self.id as usize)
+    }
+}
+
+impl Drop for ScreenTexture {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteTextures(1, &self.id);
+        }
+    }
+}
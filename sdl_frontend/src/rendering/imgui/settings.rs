@@ -7,6 +7,7 @@ use std::str::FromStr;
 use std::time::Duration;
 
 use crate::data::state::{DisplayColourConfigurable, DisplayColourDTO};
+use crate::gameboy::FastForward;
 
 const SUB_MENUS: [&str; 3] = ["General", "Audio", "Display"];
 
@@ -58,7 +59,7 @@ fn create_settings(ui: &Ui, state: &mut GuiState, debug_state: &mut DebugState)
                 .build()
             {
                 if let Ok(new_multiplier) = u64::from_str(input.as_ref()) {
-                    if (1..=100).contains(&new_multiplier) {
+                    if (1..=FastForward::MAX_RATE).contains(&new_multiplier) {
                         GLOBAL_APP_STATE.lock().unwrap().fast_forward_rate = new_multiplier;
                     } else {
                         debug_state.notification = Notification::with_duration(
@@ -71,6 +72,20 @@ fn create_settings(ui: &Ui, state: &mut GuiState, debug_state: &mut DebugState)
                     debug_state.notification = Notification::new("Only integers are valid!", ui);
                 }
             }
+
+            ui.text("Max Catch-up Frames:");
+            ui.same_line(0.0);
+            show_help_marker(ui, "The maximum amount of frames emulated at once after a hitch.\
+            \nAny time beyond that is dropped, preventing audio/video runaway.");
+            ui.same_line_with_spacing(0.0, size(ui, 2.0));
+            let mut max_frames = GLOBAL_APP_STATE.lock().unwrap().max_catch_up_frames as i32;
+            if Slider::new(im_str!("##hidelabel MaxCatchUp"))
+                .range(1..=30)
+                .flags(SliderFlags::CLAMP_ON_INPUT)
+                .build(ui, &mut max_frames)
+            {
+                GLOBAL_APP_STATE.lock().unwrap().max_catch_up_frames = max_frames as u64;
+            }
         }
         "Audio" => {
             let mut global_state = GLOBAL_APP_STATE.lock().unwrap();
@@ -79,6 +94,18 @@ fn create_settings(ui: &Ui, state: &mut GuiState, debug_state: &mut DebugState)
             ui.same_line(0.0);
             right_align(ui, 2.0);
             ui.checkbox(im_str!("##hidelabel AudioMute"), &mut global_state.audio_mute);
+            ui.text("Preserve Fast Forward Pitch:");
+            ui.same_line(0.0);
+            show_help_marker(ui, "Time-stretches the audio while fast forwarding instead of pitching it up.");
+            ui.same_line(0.0);
+            right_align(ui, 2.0);
+            ui.checkbox(im_str!("##hidelabel PreserveTurboPitch"), &mut global_state.preserve_turbo_pitch);
+            ui.text("Mute Fast Forward:");
+            ui.same_line(0.0);
+            show_help_marker(ui, "Drops the audio while fast forwarding instead of playing it.");
+            ui.same_line(0.0);
+            right_align(ui, 2.0);
+            ui.checkbox(im_str!("##hidelabel SkipFastForwardAudio"), &mut global_state.skip_fast_forward_audio);
             ui.text("Volume:");
             ui.same_line(0.0);
             right_align(ui, 13.0);
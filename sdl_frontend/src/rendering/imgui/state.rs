@@ -2,7 +2,10 @@ use crate::rendering::imgui::animate::{formulas::Quadratic, FadeAnimation};
 use imgui::Ui;
 use nanoserde::{DeJson, SerJson};
 use rustyboi_core::gb_emu::GameBoyModel;
+use rustyboi_core::hardware::cartridge::BankInfo;
 use rustyboi_core::hardware::ppu::debugging_features::PaletteDebugInfo;
+use rustyboi_core::hardware::ppu::palette::RGB;
+use rustyboi_core::TimerState;
 use std::time::Duration;
 
 use crate::rendering::imgui::settings::SettingScreenState;
@@ -12,6 +15,13 @@ pub struct GuiState {
     pub show_metrics: bool,
     pub show_settings: bool,
     pub palette_window: bool,
+    pub timer_window: bool,
+    pub banks_window: bool,
+    #[nserde(default)]
+    pub audio_window: bool,
+    pub screen_window: bool,
+    /// Outline all sprites in the screen view.
+    pub sprite_boxes: bool,
     pub tile_display: bool,
     pub execution_log: bool,
     pub setting_state: SettingScreenState,
@@ -27,6 +37,19 @@ impl GuiState {
 pub struct DebugState {
     pub current_emu_mode: GameBoyModel,
     pub palette: PaletteDebugInfo,
+    pub timer: TimerState,
+    /// `None` if no cartridge is loaded.
+    pub banks: Option<BankInfo>,
+    /// A new frame for the screen view, yet to be uploaded.
+    pub screen: Option<Vec<RGB>>,
+    /// The voices muted in the audio view, `0-3` for voice 1-4.
+    pub channel_masks: [bool; 4],
+    /// Whether the emulator is paused, as last told by the app.
+    pub paused: bool,
+    /// A pause or resume requested from the menu, yet to be sent.
+    pub requested_pause: Option<bool>,
+    /// Whether a frame step was requested from the menu, yet to be sent.
+    pub requested_frame_step: bool,
     pub notification: Notification,
 }
 
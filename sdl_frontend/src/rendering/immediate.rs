@@ -1,6 +1,6 @@
 use sdl2::mouse::MouseState;
 
-use crate::data::communication::DebugMessage;
+use crate::data::communication::{DebugMessage, EmulatorNotification};
 use crate::data::storage::FileStorage;
 use sdl2::event::Event;
 use std::sync::Arc;
@@ -26,6 +26,18 @@ pub trait ImmediateGui {
     /// Fulfills the GUI's request presented at `query_emulator`.
     fn fulfill_query(&mut self, debug_response: DebugMessage);
 
+    /// Returns the changes made in the GUI (e.g, muting a voice) which the emulator should apply,
+    /// since the last call.
+    fn take_notifications(&mut self) -> Vec<EmulatorNotification>;
+
+    /// Should be called after a new emulator was started (e.g, for another ROM), which has none of
+    /// the changes returned by `take_notifications()` applied yet.
+    fn emulator_replaced(&mut self);
+
+    /// Tells the GUI whether the emulator is currently paused, so that it can offer to
+    /// step a frame. Pausing from the GUI is requested through `take_notifications()`.
+    fn set_paused(&mut self, paused: bool);
+
     fn prepare_render(&mut self, delta_time: f32, host_window: &sdl2::video::Window, mouse_state: &MouseState);
 
     fn render(&mut self, host_window: &sdl2::video::Window);
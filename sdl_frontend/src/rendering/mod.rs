@@ -64,9 +64,10 @@ where
 
     /// Closes the debug window, and drops any contexts that were present.
     pub fn close_immediate_gui(&mut self) {
+        // The GUI has to be dropped first, as it still needs the OpenGL context to clean up.
+        self.immediate_gui = None;
         self.gl_context = None;
         self.debug_window = None;
-        self.immediate_gui = None;
     }
 
     /// Render a new frame in the main window.
@@ -0,0 +1,220 @@
+//! Writing the framebuffer of a test ROM to a PNG, optionally composited onto a [Bezel].
+
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use image::imageops::FilterType;
+use image::{ImageBuffer, RgbImage};
+use rustyboi_core::hardware::ppu::palette::RGB;
+use rustyboi_core::hardware::ppu::{RESOLUTION_HEIGHT, RESOLUTION_WIDTH};
+use rustyboi_core::scaler::scale_framebuffer_aspect;
+
+/// The area of a [Bezel] image in which the screen is drawn, in pixels.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ScreenRect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl FromStr for ScreenRect {
+    type Err = BezelError;
+
+    /// Parse a rect in the `x,y,width,height` format.
+    fn from_str(rect: &str) -> Result<Self, Self::Err> {
+        let values = rect
+            .split(',')
+            .map(|value| value.trim().parse())
+            .collect::<Result<Vec<usize>, _>>()
+            .map_err(|_| BezelError::InvalidScreenRect)?;
+
+        match values[..] {
+            [x, y, width, height] => Ok(ScreenRect { x, y, width, height }),
+            _ => Err(BezelError::InvalidScreenRect),
+        }
+    }
+}
+
+/// Returned by [Bezel::new] if the image or screen rect are invalid.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BezelError {
+    /// The image data isn't an exact multiple of the provided width.
+    InvalidImageSize,
+    /// The screen rect is empty, or doesn't fit within the image.
+    InvalidScreenRect,
+}
+
+impl fmt::Display for BezelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BezelError::InvalidImageSize => write!(f, "The bezel image doesn't match its width"),
+            BezelError::InvalidScreenRect => write!(f, "The screen rect doesn't fit within the bezel image"),
+        }
+    }
+}
+
+impl Error for BezelError {}
+
+/// A border image (e.g, a handheld frame) which exported screenshots can be composited onto.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bezel {
+    image: Vec<RGB>,
+    width: usize,
+    height: usize,
+    screen_rect: ScreenRect,
+}
+
+impl Bezel {
+    /// Create a bezel from the `image` (row major, `width` pixels per row), where the screen
+    /// will be drawn in the `screen_rect` cutout.
+    pub fn new(image: Vec<RGB>, width: usize, screen_rect: ScreenRect) -> Result<Self, BezelError> {
+        if width == 0 || image.len() % width != 0 {
+            return Err(BezelError::InvalidImageSize);
+        }
+        let height = image.len() / width;
+
+        if screen_rect.width == 0
+            || screen_rect.height == 0
+            || screen_rect.x + screen_rect.width > width
+            || screen_rect.y + screen_rect.height > height
+        {
+            return Err(BezelError::InvalidScreenRect);
+        }
+
+        Ok(Bezel {
+            image,
+            width,
+            height,
+            screen_rect,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns a copy of the bezel image with the `framebuffer` scaled into its screen cutout,
+    /// see `scale_framebuffer_aspect()`. Any part of the cutout not covered by the screen is black.
+    pub fn composite(&self, framebuffer: &[RGB]) -> Vec<RGB> {
+        let rect = self.screen_rect;
+        let screen = scale_framebuffer_aspect(framebuffer, rect.width, rect.height, RGB::default());
+        let mut result = self.image.clone();
+
+        for (y, row) in screen.chunks_exact(rect.width).enumerate() {
+            let start = (rect.y + y) * self.width + rect.x;
+            result[start..start + rect.width].copy_from_slice(row);
+        }
+
+        result
+    }
+}
+
+/// How the screenshots are written, by default the framebuffer at twice the native resolution.
+#[derive(Debug, Clone, Default)]
+pub struct ScreenshotExport {
+    bezel: Option<Bezel>,
+}
+
+impl ScreenshotExport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Composite every screenshot onto the bezel `image`, with the screen scaled into the
+    /// `screen_rect` cutout.
+    pub fn with_bezel(mut self, image: &RgbImage, screen_rect: ScreenRect) -> Result<Self, BezelError> {
+        let pixels = image.pixels().map(|pixel| RGB(pixel[0], pixel[1], pixel[2])).collect();
+        self.bezel = Some(Bezel::new(pixels, image.width() as usize, screen_rect)?);
+        Ok(self)
+    }
+
+    /// Renders and saves the provided framebuffer to the `path`.
+    pub fn save(&self, framebuffer: &[RGB], path: impl AsRef<Path>) -> image::ImageResult<()> {
+        let image = match &self.bezel {
+            Some(bezel) => to_image(&bezel.composite(framebuffer), bezel.width(), bezel.height()),
+            None => {
+                let image = to_image(framebuffer, RESOLUTION_WIDTH, RESOLUTION_HEIGHT);
+                let (width, height) = (RESOLUTION_WIDTH as u32 * 2, RESOLUTION_HEIGHT as u32 * 2);
+                image::imageops::resize(&image, width, height, FilterType::Nearest)
+            }
+        };
+
+        image.save(path)
+    }
+}
+
+fn to_image(pixels: &[RGB], width: usize, height: usize) -> RgbImage {
+    let raw = pixels.iter().flat_map(|colour| vec![colour.0, colour.1, colour.2]).collect();
+
+    ImageBuffer::from_raw(width as u32, height as u32, raw).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::{Bezel, BezelError, ScreenRect, ScreenshotExport};
+    use image::RgbImage;
+    use rustyboi_core::hardware::ppu::palette::RGB;
+    use rustyboi_core::hardware::ppu::FRAMEBUFFER_SIZE;
+
+    const BAR: RGB = RGB(1, 2, 3);
+    const WHITE: RGB = RGB(255, 255, 255);
+
+    #[test]
+    fn test_bezel_composite() {
+        let mut framebuffer = [WHITE; FRAMEBUFFER_SIZE];
+        framebuffer[0] = RGB(0, 0, 0);
+        let screen_rect = ScreenRect {
+            x: 20,
+            y: 10,
+            width: 320,
+            height: 288,
+        };
+        let bezel = Bezel::new(vec![BAR; 400 * 300], 400, screen_rect).unwrap();
+        let result = bezel.composite(&framebuffer);
+
+        assert_eq!((bezel.width(), bezel.height()), (400, 300));
+        assert_eq!(result.len(), 400 * 300);
+        assert_eq!(result[10 * 400 + 19], BAR);
+        assert_eq!(result[9 * 400 + 20], BAR);
+        assert_eq!(result[10 * 400 + 20], RGB(0, 0, 0));
+        assert_eq!(result[11 * 400 + 21], RGB(0, 0, 0));
+        assert_eq!(result[10 * 400 + 22], WHITE);
+        assert_eq!(result[297 * 400 + 339], WHITE);
+        assert_eq!(result[297 * 400 + 340], BAR);
+        assert_eq!(result[298 * 400 + 339], BAR);
+    }
+
+    #[test]
+    fn test_bezel_validation() {
+        let rect = |x, y, width, height| ScreenRect { x, y, width, height };
+        let bezel = |length, width, screen_rect| Bezel::new(vec![BAR; length], width, screen_rect);
+
+        assert_eq!(bezel(100, 0, rect(0, 0, 1, 1)), Err(BezelError::InvalidImageSize));
+        assert_eq!(bezel(101, 10, rect(0, 0, 1, 1)), Err(BezelError::InvalidImageSize));
+        assert_eq!(bezel(100, 10, rect(0, 0, 0, 1)), Err(BezelError::InvalidScreenRect));
+        assert_eq!(bezel(100, 10, rect(5, 0, 6, 1)), Err(BezelError::InvalidScreenRect));
+        assert_eq!(bezel(100, 10, rect(0, 5, 1, 6)), Err(BezelError::InvalidScreenRect));
+        assert!(bezel(100, 10, rect(5, 5, 5, 5)).is_ok());
+
+        assert_eq!("5, 5,5,5".parse(), Ok(rect(5, 5, 5, 5)));
+        assert_eq!("5,5,5".parse::<ScreenRect>(), Err(BezelError::InvalidScreenRect));
+        assert_eq!("5,5,5,a".parse::<ScreenRect>(), Err(BezelError::InvalidScreenRect));
+    }
+
+    #[test]
+    fn test_with_bezel() {
+        let image = RgbImage::new(200, 180);
+
+        let export = ScreenshotExport::new().with_bezel(&image, "20,18,160,144".parse().unwrap());
+        assert!(export.is_ok());
+        let export = ScreenshotExport::new().with_bezel(&image, "100,18,160,144".parse().unwrap());
+        assert_eq!(export.unwrap_err(), BezelError::InvalidScreenRect);
+    }
+}
@@ -10,14 +10,13 @@ use std::io;
 use std::path::{Path, PathBuf};
 
 use crate::display::TEST_COLOURS;
+use crate::export::{ScreenRect, ScreenshotExport};
 use rustyboi_core::EmulatorOptionsBuilder;
 use std::ffi::{OsStr, OsString};
 
 use crate::options::AppOptions;
 use blake2::{Blake2s, Digest};
-use image::ImageBuffer;
 use rustyboi_core::gb_emu::{GameBoyEmulator, GameBoyModel};
-use rustyboi_core::hardware::ppu::FRAMEBUFFER_SIZE;
 use std::thread::spawn;
 use std::time::Instant;
 
@@ -25,13 +24,14 @@ use anyhow::*;
 use std::collections::{HashMap, HashSet};
 
 use gumdrop::Options;
-use image::imageops::FilterType;
 use rustyboi_core::gb_emu::GameBoyModel::{CGB, DMG};
-use rustyboi_core::hardware::ppu::palette::RGB;
+use rustyboi_core::hardware::ppu::palette::{PaletteTables, RGB};
 use std::sync::Arc;
 
 mod display;
+mod export;
 mod options;
+mod validate;
 
 const TESTING_PATH_OLD: &str = "testing_frames/old/";
 const TESTING_PATH_CHANGED: &str = "testing_frames/changed/";
@@ -43,6 +43,12 @@ fn main() -> anyhow::Result<()> {
     let options: AppOptions = AppOptions::parse_args_default_or_exit();
     let current_time = Instant::now();
 
+    if options.validate {
+        validate::validate_roms(&options.test_path, &options.validation_output)?;
+        println!("Took: {:?}", current_time.elapsed());
+        return Ok(());
+    }
+
     // Clean out old files.
     remove_dir_all(TESTING_PATH_OLD);
     remove_dir_all(TESTING_PATH_CHANGED);
@@ -55,9 +61,10 @@ fn main() -> anyhow::Result<()> {
     create_dir_all(TESTING_PATH_CHANGED);
 
     let old_hashes = calculate_hashes(TESTING_PATH_OLD).unwrap_or_default();
+    let export = Arc::new(screenshot_export(&options)?);
 
-    run_test_roms(&options.test_path, &options.dmg_boot_rom, DMG);
-    run_test_roms(&options.test_path, &options.cgb_boot_rom, CGB);
+    run_test_roms(&options.test_path, &options.dmg_boot_rom, DMG, &export);
+    run_test_roms(&options.test_path, &options.cgb_boot_rom, CGB, &export);
 
     let new_hashes = calculate_hashes(TESTING_PATH_NEW).unwrap_or_default();
 
@@ -84,7 +91,26 @@ fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
-fn run_test_roms(test_path: impl AsRef<str>, bootrom: impl AsRef<Path>, emulator_mode: GameBoyModel) {
+/// Create the screenshot settings, with the bezel from the `options` if one was provided.
+fn screenshot_export(options: &AppOptions) -> anyhow::Result<ScreenshotExport> {
+    match (&options.bezel, &options.bezel_screen) {
+        (Some(bezel), Some(screen_rect)) => {
+            let image = image::open(bezel).with_context(|| format!("Could not open bezel {}", bezel))?;
+            let screen_rect: ScreenRect = screen_rect.parse()?;
+
+            Ok(ScreenshotExport::new().with_bezel(&image.to_rgb8(), screen_rect)?)
+        }
+        (Some(_), None) => Err(anyhow!("A bezel requires the screen area to be set with --bezel-screen")),
+        _ => Ok(ScreenshotExport::new()),
+    }
+}
+
+fn run_test_roms(
+    test_path: impl AsRef<str>,
+    bootrom: impl AsRef<Path>,
+    emulator_mode: GameBoyModel,
+    export: &Arc<ScreenshotExport>,
+) {
     let boot_file = if bootrom.as_ref().exists() {
         read(bootrom.as_ref()).ok()
     } else {
@@ -92,7 +118,7 @@ fn run_test_roms(test_path: impl AsRef<str>, bootrom: impl AsRef<Path>, emulator
     };
 
     if !test_path.as_ref().is_empty() {
-        run_path(test_path.as_ref(), boot_file.clone(), emulator_mode);
+        run_path(test_path.as_ref(), boot_file.clone(), emulator_mode, export);
     }
 }
 
@@ -100,15 +126,23 @@ fn run_test_roms(test_path: impl AsRef<str>, bootrom: impl AsRef<Path>, emulator
 /// all test roms and running them for ~600 frames, or a custom amount if set via config.
 ///
 /// But it works!
-fn run_path(path: impl AsRef<str>, boot_rom_vec: Option<Vec<u8>>, emulator_mode: GameBoyModel) {
+fn run_path(
+    path: impl AsRef<str>,
+    boot_rom_vec: Option<Vec<u8>>,
+    emulator_mode: GameBoyModel,
+    export: &Arc<ScreenshotExport>,
+) {
     let file_extension = if emulator_mode.is_dmg() { ".gb" } else { ".gbc" };
     let tests = list_files_with_extensions(path.as_ref(), file_extension).unwrap();
     let custom_list = Arc::new(get_custom_list("custom_test_cycles.txt"));
+    let palette_tables = Arc::new(PaletteTables::new(TEST_COLOURS, TEST_COLOURS, TEST_COLOURS));
     let wait_group = crossbeam::sync::WaitGroup::new();
 
     for path in tests {
         let boot_rom = boot_rom_vec.clone();
         let list_copy = custom_list.clone();
+        let palette_tables = palette_tables.clone();
+        let export = export.clone();
         let wg = wait_group.clone();
 
         spawn(move || {
@@ -116,7 +150,8 @@ fn run_path(path: impl AsRef<str>, boot_rom_vec: Option<Vec<u8>>, emulator_mode:
             let mut frames_to_render = 600;
             let mut options_builder = EmulatorOptionsBuilder::new()
                 .with_boot_rom(boot_rom)
-                .with_display_colour(TEST_COLOURS);
+                .with_display_colour(TEST_COLOURS)
+                .with_palette_tables(palette_tables);
 
             options_builder = if emulator_mode.is_dmg() {
                 options_builder.with_mode(DMG)
@@ -145,7 +180,7 @@ fn run_path(path: impl AsRef<str>, boot_rom_vec: Option<Vec<u8>>, emulator_mode:
                 file_stem.to_str().unwrap(),
                 if emulator_mode.is_dmg() { "dmg" } else { "cgb" }
             );
-            save_image(emu.frame_buffer(), file_path);
+            save_image(&export, emu.frame_buffer(), file_path);
             drop(wg);
         });
     }
@@ -222,22 +257,11 @@ fn calculate_hashes(directory: impl AsRef<Path>) -> anyhow::Result<HashMap<OsStr
 }
 
 /// Renders and saves the provided framebuffer to the `file_name`.
-fn save_image(framebuffer: &[RGB], file_name: impl AsRef<str>) {
-    let mut true_image_buffer = vec![0u8; FRAMEBUFFER_SIZE * 3];
-
-    for (i, colour) in framebuffer.iter().enumerate() {
-        let offset = i * 3;
-        true_image_buffer[offset] = colour.0;
-        true_image_buffer[offset + 1] = colour.1;
-        true_image_buffer[offset + 2] = colour.2;
-    }
+fn save_image(export: &ScreenshotExport, framebuffer: &[RGB], file_name: impl AsRef<str>) {
     let path = format!("{}{}", TESTING_PATH_NEW, file_name.as_ref());
     create_dir_all(Path::new(&path).parent().unwrap());
 
-    let temp_buffer: ImageBuffer<image::Rgb<u8>, Vec<u8>> =
-        image::ImageBuffer::from_raw(160, 144, true_image_buffer).unwrap();
-    let temp_buffer = image::imageops::resize(&temp_buffer, 320, 288, FilterType::Nearest);
-    temp_buffer.save(path).unwrap();
+    export.save(framebuffer, path).unwrap();
 }
 
 /// Returns the entries from the provided `filename` in the format:
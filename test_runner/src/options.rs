@@ -16,4 +16,16 @@ pub struct AppOptions {
     /// The path to the CGB bootrom
     #[options(default = "roms/cgb_bios.bin")]
     pub cgb_boot_rom: String,
+    /// Only validate the ROMs in the test path, and write a CSV report instead of screenshots.
+    #[options(no_short)]
+    pub validate: bool,
+    /// The path of the CSV report written by `--validate`
+    #[options(no_short, default = "rom_validation.csv")]
+    pub validation_output: String,
+    /// The path to a bezel image to composite all screenshots onto, requires `--bezel-screen`
+    #[options(no_short)]
+    pub bezel: Option<String>,
+    /// The area of the bezel in which to draw the screen, as `x,y,width,height`
+    #[options(no_short)]
+    pub bezel_screen: Option<String>,
 }
@@ -0,0 +1,127 @@
+//! A quick compatibility report across a ROM set, without running the full screenshot suite.
+//!
+//! Every ROM is checked for a valid header, Nintendo logo, and a supported MBC, after which it's run
+//! until it first polls the joypad. The results are written as a CSV with the columns:
+//! `file, title, mbc, logo_valid, supported, boots`.
+
+use std::fs::{read, write};
+use std::panic::{self, catch_unwind, AssertUnwindSafe};
+use std::path::Path;
+
+use rustyboi_core::gb_emu::GameBoyEmulator;
+use rustyboi_core::gb_emu::GameBoyModel::{CGB, DMG};
+use rustyboi_core::hardware::cartridge::header::CartridgeHeader;
+use rustyboi_core::hardware::cartridge::Cartridge;
+use rustyboi_core::EmulatorOptionsBuilder;
+
+use crate::list_files_with_extensions;
+
+/// The maximum amount of frames a ROM gets to poll the joypad before it's considered not to boot.
+const MAX_BOOT_FRAMES: u32 = 600;
+
+struct ValidationResult {
+    file: String,
+    title: String,
+    mbc: String,
+    logo_valid: bool,
+    supported: bool,
+    boots: bool,
+}
+
+/// Validate all `.gb` and `.gbc` ROMs in the provided `path`, and write the results to `output`.
+pub fn validate_roms(path: impl AsRef<Path>, output: impl AsRef<Path>) -> anyhow::Result<()> {
+    let mut roms = list_files_with_extensions(path.as_ref(), ".gb")?;
+    roms.extend(list_files_with_extensions(path.as_ref(), ".gbc")?);
+    roms.sort();
+
+    let mut csv = String::from("file,title,mbc,logo_valid,supported,boots\n");
+
+    for rom_path in roms {
+        let file = rom_path.to_string_lossy().into_owned();
+        let cgb = rom_path.extension().is_some_and(|ext| ext == "gbc");
+        let result = validate_rom(file, &read(&rom_path)?, cgb);
+
+        if !result.boots {
+            println!("ROM doesn't boot: {} ({})", result.file, result.mbc);
+        }
+
+        csv.push_str(&format!(
+            "{},{},{},{},{},{}\n",
+            csv_field(&result.file),
+            csv_field(&result.title),
+            csv_field(&result.mbc),
+            result.logo_valid,
+            result.supported,
+            result.boots
+        ));
+    }
+
+    write(output, csv)?;
+
+    Ok(())
+}
+
+fn validate_rom(file: String, rom: &[u8], cgb: bool) -> ValidationResult {
+    let mut result = ValidationResult {
+        file,
+        title: String::new(),
+        mbc: String::from("Invalid"),
+        logo_valid: false,
+        supported: false,
+        boots: false,
+    };
+
+    // The header parsing panics on invalid cartridge types and titles, which we just want to report.
+    let header = if rom.len() >= 0x150 {
+        catch_quietly(|| CartridgeHeader::new(rom))
+    } else {
+        None
+    };
+
+    let header = match header {
+        Some(header) => header,
+        None => return result,
+    };
+
+    result.title = header.title.clone();
+    result.mbc = format!("{:?}", header.cartridge_type);
+    result.logo_valid = header.logo_valid();
+    result.supported = Cartridge::is_supported(header.cartridge_type as u8);
+
+    if !result.supported {
+        return result;
+    }
+
+    let options = EmulatorOptionsBuilder::new()
+        .with_mode(if cgb { CGB } else { DMG })
+        .with_upgrade_cgb_only_roms(true)
+        .build();
+
+    result.boots = catch_quietly(|| match GameBoyEmulator::try_new(rom, options) {
+        Ok(mut emulator) => emulator.run_to_first_input_poll(MAX_BOOT_FRAMES).is_ok(),
+        Err(_) => false,
+    })
+    .unwrap_or(false);
+
+    result
+}
+
+/// Run `f`, returning `None` if it panicked. The default panic hook is silenced in the meantime,
+/// as a broken ROM is an expected result and shouldn't spam the output with backtraces.
+fn catch_quietly<T>(f: impl FnOnce() -> T) -> Option<T> {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let result = catch_unwind(AssertUnwindSafe(f)).ok();
+    panic::set_hook(hook);
+
+    result
+}
+
+/// Quote the `value` if it contains any characters which would break the CSV.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}